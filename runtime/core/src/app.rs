@@ -1,7 +1,7 @@
 use std::path::PathBuf;
 
 use crate::config::{
-    compile_cache_from_path, load_from_path, CompileError, CompiledCache, Diagnostic, LoadError,
+    compile_loaded, load_from_path, CompileError, CompiledCache, Diagnostic, LoadError,
     LoadedConfig,
 };
 use thiserror::Error;
@@ -25,7 +25,7 @@ impl AppState {
     pub fn initialize(config_path: impl Into<PathBuf>) -> Result<Self, AppStateError> {
         let path = config_path.into();
         let loaded = load_from_path(&path)?;
-        let compiled = compile_cache_from_path(&path)?;
+        let compiled = compile_loaded(&loaded)?;
         Ok(Self {
             config_path: path,
             loaded,
@@ -33,9 +33,21 @@ impl AppState {
         })
     }
 
+    /// Loads and validates `config_path` without compiling a cache, e.g. to show an
+    /// editor's diagnostics before the config is committed. Warnings and infos are
+    /// in `diagnostics`; a config with errors is a [`LoadError::Validation`].
+    pub fn validate_only(config_path: impl Into<PathBuf>) -> Result<LoadedConfig, AppStateError> {
+        Ok(load_from_path(config_path.into())?)
+    }
+
+    /// Compiles a fresh cache from the loaded config, without re-reading the file.
+    pub fn compile(&self) -> Result<CompiledCache, AppStateError> {
+        Ok(compile_loaded(&self.loaded)?)
+    }
+
     pub fn reload(&mut self) -> Result<(), AppStateError> {
         let loaded = load_from_path(&self.config_path)?;
-        let compiled = compile_cache_from_path(&self.config_path)?;
+        let compiled = compile_loaded(&loaded)?;
         self.loaded = loaded;
         self.compiled = compiled;
         Ok(())
@@ -45,6 +57,13 @@ impl AppState {
         &self.config_path
     }
 
+    /// The config file followed by every file it includes.
+    pub fn watched_paths(&self) -> Vec<PathBuf> {
+        std::iter::once(self.config_path.clone())
+            .chain(self.loaded.includes.iter().cloned())
+            .collect()
+    }
+
     pub fn diagnostics(&self) -> &[Diagnostic] {
         &self.compiled.diagnostics
     }
@@ -57,6 +76,7 @@ impl AppState {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::DiagnosticSeverity;
     use std::fs;
 
     fn sample_config() -> String {
@@ -90,7 +110,18 @@ scripts: {}
         let app = AppState::initialize(config_path.clone()).expect("initialize");
         assert_eq!(app.compiled.bundle.macros.len(), 1);
         assert_eq!(app.compiled.bundle.macros[0].id, "ready");
-        assert!(app.diagnostics().is_empty());
+        // The untriggered draft is only noted as unused.
+        let paths: Vec<_> = app
+            .diagnostics()
+            .iter()
+            .filter(|d| !d.lint)
+            .map(|d| d.path.as_str())
+            .collect();
+        assert_eq!(paths, vec!["macros.draft"]);
+        assert_eq!(
+            app.diagnostics().iter().find(|d| !d.lint).unwrap().severity,
+            DiagnosticSeverity::Info
+        );
 
         // modify draft to ready with valid macro data and reload
         let new_config = r#"version: 1
@@ -119,4 +150,69 @@ scripts: {}
         app.reload().expect("reload");
         assert_eq!(app.compiled.bundle.macros.len(), 2);
     }
+
+    fn sorted(diagnostics: &[Diagnostic]) -> Vec<(String, String, DiagnosticSeverity)> {
+        let mut list: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.path.clone(), d.message.clone(), d.severity))
+            .collect();
+        list.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+        list
+    }
+
+    #[test]
+    fn single_parse_matches_separate_load_and_compile() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let config_path = dir.path().join("config.yaml");
+        let config = sample_config().replace(
+            "    status: ready\n",
+            "    status: ready\n    cooldown_ms: 90000\n",
+        );
+        fs::write(&config_path, config).expect("write config");
+
+        let loaded = load_from_path(&config_path).expect("load");
+        let compiled = crate::config::compile_cache_from_path(&config_path).expect("compile");
+        let app = AppState::initialize(&config_path).expect("initialize");
+
+        assert_eq!(sorted(&app.loaded.diagnostics), sorted(&loaded.diagnostics));
+        assert_eq!(sorted(app.diagnostics()), sorted(&compiled.diagnostics));
+        assert_eq!(app.diagnostics().iter().filter(|d| !d.lint).count(), 2);
+        let mut bundle = app.compiled.bundle.clone();
+        bundle.header.generated_at = compiled.bundle.header.generated_at;
+        assert_eq!(bundle, compiled.bundle);
+    }
+
+    #[test]
+    fn validate_only_reports_warnings_without_compiling() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let config_path = dir.path().join("config.yaml");
+        let config = sample_config().replace(
+            "    status: ready\n",
+            "    status: ready\n    cooldown_ms: 90000\n",
+        );
+        fs::write(&config_path, config).expect("write config");
+
+        let loaded = AppState::validate_only(&config_path).expect("validate");
+        let warnings: Vec<_> = loaded
+            .diagnostics
+            .iter()
+            .filter(|d| d.severity == DiagnosticSeverity::Warning)
+            .map(|d| d.path.as_str())
+            .collect();
+        assert_eq!(warnings, vec!["macros.ready.cooldown_ms"]);
+
+        let app = AppState::initialize(&config_path).expect("initialize");
+        let compiled = app.compile().expect("compile");
+        assert_eq!(compiled.bundle.macros, app.compiled.bundle.macros);
+        assert_eq!(
+            compiled.bundle.header.source_hash,
+            app.compiled.bundle.header.source_hash
+        );
+
+        fs::write(&config_path, "version: 1\nmacros:\n  a:\n    status: ready\n    steps:\n      - type: pause\n        ms: 0\n").expect("rewrite config");
+        assert!(matches!(
+            AppState::validate_only(&config_path),
+            Err(AppStateError::Load(LoadError::Validation(_)))
+        ));
+    }
 }
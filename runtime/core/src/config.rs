@@ -1,15 +1,25 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::Path;
 use std::{fs, path::PathBuf};
 
 use bincode;
 use cache_builder::{
-    build_from_path as builder_build_from_path, build_from_str as builder_build_from_str,
-    BuildError,
+    build_from_config as builder_build_from_config, build_from_path as builder_build_from_path,
+    build_from_str as builder_build_from_str, bundle_from_config, decode_bundle, decode_payload,
+    encode_bundle, is_json_cache, parse_tap_behavior, payload_checksum, unix_now, BuildError,
+    BuildOutput,
 };
-use cache_format::CacheBundle;
-use config_validator::schema::{Config, Macro, MacroStatus};
+use cache_format::{
+    CacheBundle, CacheDefaults, CacheHeader, CompressionKind, DeviceLayout, ErrorPolicy,
+    ExecutionHooks, LayoutPage, LayoutWidget, MacroEntry, MacroStep, MidiTrigger, MidiTriggerType,
+    MouseButton, WidgetAction, CACHE_VERSION,
+};
+pub use cache_format::{DEFAULT_DOUBLE_TAP_WINDOW_MS, DEFAULT_TAP_HOLD_TIMEOUT_MS};
+use config_validator::schema::{Config, Defaults, Macro, MacroStatus};
+pub use config_validator::DiagnosticTarget;
 use config_validator::{
-    parse_config_str, validate_config, ConfigError, Location, Severity, ValidationIssue,
+    parse_config_str, parse_config_with_includes, validate_config, ConfigError, Location, Severity,
+    ValidationIssue,
 };
 use thiserror::Error;
 
@@ -20,6 +30,16 @@ pub enum DiagnosticSeverity {
     Info,
 }
 
+impl From<DiagnosticSeverity> for Severity {
+    fn from(value: DiagnosticSeverity) -> Self {
+        match value {
+            DiagnosticSeverity::Error => Severity::Error,
+            DiagnosticSeverity::Warning => Severity::Warning,
+            DiagnosticSeverity::Info => Severity::Info,
+        }
+    }
+}
+
 impl From<Severity> for DiagnosticSeverity {
     fn from(value: Severity) -> Self {
         match value {
@@ -36,13 +56,35 @@ pub struct Diagnostic {
     pub message: String,
     pub location: Option<Location>,
     pub severity: DiagnosticSeverity,
+    pub target: DiagnosticTarget,
+    /// Style-only hint; see `config_validator::ValidationIssue::lint`.
+    pub lint: bool,
 }
 
+/// Renders a diagnostic in the same format as the config tooling CLIs.
+pub fn format_diagnostic(diagnostic: &Diagnostic) -> String {
+    let mut issue = ValidationIssue::new(
+        diagnostic.path.clone(),
+        diagnostic.message.clone(),
+        diagnostic.severity.into(),
+    );
+    issue.location = diagnostic.location;
+    config_validator::format_diagnostic(&issue)
+}
+
+/// Theme used when `global.defaults.display.theme` is unset.
+pub const DEFAULT_THEME: &str = "dark";
+
 #[derive(Debug)]
 pub struct LoadedConfig {
     pub path: Option<PathBuf>,
     pub config: Config,
     pub diagnostics: Vec<Diagnostic>,
+    /// Files merged in through `include`, in merge order.
+    pub includes: Vec<PathBuf>,
+    /// Text of the root config file, which diagnostics locations and the cache's
+    /// `source_hash` refer to.
+    pub source: String,
 }
 
 impl LoadedConfig {
@@ -52,6 +94,35 @@ impl LoadedConfig {
             .iter()
             .filter(|(_, macro_def)| macro_def.status == MacroStatus::Ready)
     }
+
+    pub fn tap_hold_timeout_ms(&self) -> u64 {
+        self.defaults()
+            .and_then(|defaults| defaults.tap_hold_timeout_ms)
+            .unwrap_or(DEFAULT_TAP_HOLD_TIMEOUT_MS)
+    }
+
+    pub fn theme(&self) -> &str {
+        self.defaults()
+            .and_then(|defaults| defaults.display.as_ref())
+            .and_then(|display| display.theme.as_deref())
+            .unwrap_or(DEFAULT_THEME)
+    }
+
+    /// Global macro run-time cap; `None` means macros run unbounded.
+    pub fn max_macro_duration_ms(&self) -> Option<u64> {
+        self.defaults()
+            .and_then(|defaults| defaults.max_macro_duration_ms)
+    }
+
+    pub fn double_tap_window_ms(&self) -> u64 {
+        self.defaults()
+            .and_then(|defaults| defaults.double_tap_window_ms)
+            .unwrap_or(DEFAULT_DOUBLE_TAP_WINDOW_MS)
+    }
+
+    fn defaults(&self) -> Option<&Defaults> {
+        self.config.global.as_ref()?.defaults.as_ref()
+    }
 }
 
 #[derive(Debug, Error)]
@@ -62,18 +133,34 @@ pub enum LoadError {
     Parse(#[from] ConfigError),
     #[error("Validation errors prevented loading")]
     Validation(Vec<Diagnostic>),
+    #[error("No config file found among candidates: {0:?}")]
+    NotFound(Vec<PathBuf>),
 }
 
 pub fn load_from_path(path: impl AsRef<Path>) -> Result<LoadedConfig, LoadError> {
     let path_ref = path.as_ref();
     let content = fs::read_to_string(path_ref)?;
-    let mut loaded = load_from_str(&content)?;
+    let (config, includes) = parse_config_with_includes(path_ref, &content)?;
+    let mut loaded = load_config(config, &content)?;
     loaded.path = Some(path_ref.to_path_buf());
+    loaded.includes = includes;
     Ok(loaded)
 }
 
+/// Loads the first candidate path that exists. The chosen path is recorded in
+/// `LoadedConfig::path`; an existing but invalid file is an error rather than skipped.
+pub fn load_from_first_existing(candidates: &[PathBuf]) -> Result<LoadedConfig, LoadError> {
+    match candidates.iter().find(|path| path.is_file()) {
+        Some(path) => load_from_path(path),
+        None => Err(LoadError::NotFound(candidates.to_vec())),
+    }
+}
+
 pub fn load_from_str(content: &str) -> Result<LoadedConfig, LoadError> {
-    let config = parse_config_str(content)?;
+    load_config(parse_config_str(content)?, content)
+}
+
+fn load_config(config: Config, content: &str) -> Result<LoadedConfig, LoadError> {
     let diagnostics = convert_issues(validate_config(&config, content));
 
     if diagnostics
@@ -87,6 +174,8 @@ pub fn load_from_str(content: &str) -> Result<LoadedConfig, LoadError> {
         path: None,
         config,
         diagnostics,
+        includes: Vec::new(),
+        source: content.to_string(),
     })
 }
 
@@ -97,6 +186,113 @@ pub struct CompiledCache {
     pub bytes: Vec<u8>,
 }
 
+impl CompiledCache {
+    /// `global.defaults` as compiled into the bundle.
+    pub fn defaults(&self) -> &CacheDefaults {
+        &self.bundle.defaults
+    }
+
+    /// What changed since `old`, so a reload can report "2 macros updated" rather
+    /// than refreshing everything. Every list is sorted.
+    pub fn diff(&self, old: &CompiledCache) -> CacheDiff {
+        let macros = |cache: &CompiledCache| -> BTreeMap<String, MacroEntry> {
+            cache
+                .bundle
+                .macros
+                .iter()
+                .map(|entry| (entry.id.clone(), entry.clone()))
+                .collect()
+        };
+        let (new_macros, old_macros) = (macros(self), macros(old));
+        let device_ids = |cache: &CompiledCache| -> BTreeSet<String> {
+            cache
+                .bundle
+                .devices
+                .iter()
+                .map(|device| device.id.clone())
+                .collect()
+        };
+        let (new_devices, old_devices) = (device_ids(self), device_ids(old));
+        let (new_widgets, old_widgets) = (widget_actions(self), widget_actions(old));
+
+        let mut changed_widgets: Vec<_> = new_widgets
+            .iter()
+            .filter(|(key, actions)| old_widgets.get(*key).is_some_and(|old| old != *actions))
+            .map(|((device, _, widget), _)| (device.clone(), widget.clone()))
+            .collect();
+        changed_widgets.sort();
+        changed_widgets.dedup();
+        CacheDiff {
+            added_macros: keys_missing_from(&new_macros, &old_macros),
+            removed_macros: keys_missing_from(&old_macros, &new_macros),
+            changed_macros: new_macros
+                .iter()
+                .filter(|(id, entry)| old_macros.get(*id).is_some_and(|old| old != *entry))
+                .map(|(id, _)| id.clone())
+                .collect(),
+            added_devices: new_devices.difference(&old_devices).cloned().collect(),
+            removed_devices: old_devices.difference(&new_devices).cloned().collect(),
+            changed_widgets,
+        }
+    }
+}
+
+/// Differences between two [`CompiledCache`]s; see [`CompiledCache::diff`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheDiff {
+    pub added_macros: Vec<String>,
+    pub removed_macros: Vec<String>,
+    /// Macros in both caches whose entry differs in any field.
+    pub changed_macros: Vec<String>,
+    pub added_devices: Vec<String>,
+    pub removed_devices: Vec<String>,
+    /// `(device_id, widget_id)` of widgets in both caches whose action, off action
+    /// or double-tap action differs.
+    pub changed_widgets: Vec<(String, String)>,
+}
+
+impl CacheDiff {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+type WidgetActions = (
+    Option<WidgetAction>,
+    Option<WidgetAction>,
+    Option<WidgetAction>,
+);
+
+/// Actions of every widget, keyed by `(device_id, page_name, widget_id)`.
+fn widget_actions(cache: &CompiledCache) -> BTreeMap<(String, String, String), WidgetActions> {
+    cache
+        .bundle
+        .devices
+        .iter()
+        .flat_map(|device| {
+            device.pages.iter().flat_map(move |page| {
+                page.widgets.iter().map(move |widget| {
+                    (
+                        (device.id.clone(), page.name.clone(), widget.id.clone()),
+                        (
+                            widget.action.clone(),
+                            widget.off_action.clone(),
+                            widget.double_tap_action.clone(),
+                        ),
+                    )
+                })
+            })
+        })
+        .collect()
+}
+
+fn keys_missing_from<V>(map: &BTreeMap<String, V>, other: &BTreeMap<String, V>) -> Vec<String> {
+    map.keys()
+        .filter(|key| !other.contains_key(*key))
+        .cloned()
+        .collect()
+}
+
 #[derive(Debug, Error)]
 pub enum CompileError {
     #[error("Validation errors prevented cache build")]
@@ -105,6 +301,10 @@ pub enum CompileError {
     Serialize(bincode::Error),
     #[error("Cache build failed: {0}")]
     Build(BuildError),
+    #[error("Cache file is corrupt: {0}")]
+    Corrupt(String),
+    #[error("Cache format version {found} is not supported (expected {expected})")]
+    UnsupportedVersion { found: u32, expected: u32 },
 }
 
 pub fn compile_cache_from_path(path: impl AsRef<Path>) -> Result<CompiledCache, CompileError> {
@@ -123,10 +323,34 @@ pub fn compile_cache_from_path(path: impl AsRef<Path>) -> Result<CompiledCache,
 }
 
 pub fn compile_cache_from_str(content: &str) -> Result<CompiledCache, CompileError> {
-    match builder_build_from_str(content) {
+    encode_output(builder_build_from_str(content))
+}
+
+/// Validates and compiles an already-parsed `config`, read from `source`.
+pub fn compile_cache_from_config(
+    config: &Config,
+    source: &str,
+) -> Result<CompiledCache, CompileError> {
+    encode_output(builder_build_from_config(config, source, unix_now()))
+}
+
+/// Compiles an already-loaded config without reading, parsing or validating it
+/// again; the cache carries the load's diagnostics.
+pub fn compile_loaded(loaded: &LoadedConfig) -> Result<CompiledCache, CompileError> {
+    let bundle = bundle_from_config(&loaded.config, &loaded.source, unix_now());
+    let bytes = encode_bundle(&bundle).map_err(encode_error)?;
+    Ok(CompiledCache {
+        bundle,
+        diagnostics: loaded.diagnostics.clone(),
+        bytes,
+    })
+}
+
+fn encode_output(built: Result<BuildOutput, BuildError>) -> Result<CompiledCache, CompileError> {
+    match built {
         Ok(output) => {
             let diagnostics = convert_issues(output.diagnostics);
-            let bytes = bincode::serialize(&output.bundle).map_err(CompileError::Serialize)?;
+            let bytes = encode_bundle(&output.bundle).map_err(encode_error)?;
             Ok(CompiledCache {
                 bundle: output.bundle,
                 diagnostics,
@@ -138,6 +362,613 @@ pub fn compile_cache_from_str(content: &str) -> Result<CompiledCache, CompileErr
     }
 }
 
+fn encode_error(err: BuildError) -> CompileError {
+    match err {
+        BuildError::Serialize(err) => CompileError::Serialize(err),
+        err => CompileError::Build(err),
+    }
+}
+
+/// Reads a built `.cache` file, decompressing it when its header says so.
+pub fn read_cache_from_path(path: impl AsRef<Path>) -> Result<CompiledCache, CompileError> {
+    let bytes = fs::read(path).map_err(|err| CompileError::Build(BuildError::Io(err)))?;
+    read_cache_bytes(bytes)
+}
+
+pub fn read_cache_bytes(bytes: Vec<u8>) -> Result<CompiledCache, CompileError> {
+    let bundle = load_cache_bytes(&bytes)?;
+    Ok(CompiledCache {
+        bundle,
+        diagnostics: Vec::new(),
+        bytes,
+    })
+}
+
+/// Surfaces corruption found while reading a cache as `CompileError::Corrupt`.
+fn read_error(err: BuildError) -> CompileError {
+    match err {
+        BuildError::Corrupt(reason) => CompileError::Corrupt(reason),
+        err => CompileError::Build(err),
+    }
+}
+
+/// Rewrites a cache's bytes from one format version into a later one.
+type Migration = fn(&[u8]) -> Result<Vec<u8>, CompileError>;
+
+/// Keyed by the version each migration upgrades from; applied in sequence until
+/// the bytes reach `CACHE_VERSION`. Version 1 converts straight to the current layout.
+const MIGRATIONS: &[(u32, Migration)] = &[
+    (1, migrate_v1),
+    (2, migrate_v2),
+    (3, migrate_v3),
+    (4, migrate_v4),
+    (5, migrate_v5),
+];
+
+/// Decodes a JSON or bincode cache of the current format version, migrating older
+/// bincode versions first.
+pub fn load_cache_bytes(bytes: &[u8]) -> Result<CacheBundle, CompileError> {
+    if is_json_cache(bytes) {
+        let bundle = decode_bundle(bytes).map_err(CompileError::Build)?;
+        if bundle.header.version != CACHE_VERSION {
+            return Err(CompileError::UnsupportedVersion {
+                found: bundle.header.version,
+                expected: CACHE_VERSION,
+            });
+        }
+        return Ok(bundle);
+    }
+    let mut migrated = None;
+    loop {
+        let current = migrated.as_deref().unwrap_or(bytes);
+        let found: u32 = bincode::deserialize(current)
+            .map_err(|_| CompileError::Corrupt("truncated header".into()))?;
+        if found == CACHE_VERSION {
+            return decode_bundle(current).map_err(read_error);
+        }
+        let Some((_, migrate)) = MIGRATIONS.iter().find(|(from, _)| *from == found) else {
+            return Err(CompileError::UnsupportedVersion {
+                found,
+                expected: CACHE_VERSION,
+            });
+        };
+        migrated = Some(migrate(current)?);
+    }
+}
+
+/// Header layout before `compression` and `payload_crc` existed.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct HeaderV1 {
+    version: u32,
+    source_hash: u64,
+    generated_at: u64,
+}
+
+/// The version 1 bundle as the first cache builder wrote it. These types are frozen
+/// copies of that layout; bincode encodes enum variants by index, so even the
+/// enums must not pick up later variants.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct BundleV1 {
+    header: HeaderV1,
+    devices: Vec<DeviceLayoutV1>,
+    macros: Vec<MacroEntryV1>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct DeviceLayoutV1 {
+    id: String,
+    hardware_id: Option<String>,
+    pages: Vec<LayoutPageV1>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct LayoutPageV1 {
+    name: String,
+    widgets: Vec<LayoutWidgetV1>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct LayoutWidgetV1 {
+    id: String,
+    tap_behavior: Option<String>,
+    action: Option<WidgetActionV1>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+enum WidgetActionV1 {
+    Macro { id: String },
+    Script { id: String },
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct MacroEntryV1 {
+    id: String,
+    description: Option<String>,
+    tags: Vec<String>,
+    trigger: Option<MidiTriggerV1>,
+    steps: Vec<MacroStepV1>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct MidiTriggerV1 {
+    r#type: MidiTriggerTypeV1,
+    number: u8,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+enum MidiTriggerTypeV1 {
+    Note,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+enum MacroStepV1 {
+    Keystroke { keys: Vec<String> },
+    Pause { ms: u64 },
+}
+
+/// Decodes a version 1 bundle and re-encodes it in the current format. Fields
+/// added since get the values the builder uses when a config leaves them unset.
+fn migrate_v1(bytes: &[u8]) -> Result<Vec<u8>, CompileError> {
+    let old: BundleV1 = bincode::deserialize(bytes)
+        .map_err(|_| CompileError::Corrupt("truncated v1 bundle".into()))?;
+    let convert_action = |action: WidgetActionV1| match action {
+        WidgetActionV1::Macro { id } => WidgetAction::Macro { id },
+        WidgetActionV1::Script { id } => WidgetAction::Script { id },
+    };
+    let devices = old
+        .devices
+        .into_iter()
+        .map(|device| DeviceLayout {
+            id: device.id,
+            hardware_id: device.hardware_id,
+            pages: device
+                .pages
+                .into_iter()
+                .map(|page| LayoutPage {
+                    name: page.name,
+                    widgets: page
+                        .widgets
+                        .into_iter()
+                        .map(|widget| LayoutWidget {
+                            id: widget.id,
+                            behavior: parse_tap_behavior(widget.tap_behavior.as_deref()),
+                            tap_behavior: widget.tap_behavior,
+                            action: widget.action.map(convert_action),
+                            mode: None,
+                            off_action: None,
+                            double_tap_action: None,
+                            double_tap_window_ms: DEFAULT_DOUBLE_TAP_WINDOW_MS,
+                        })
+                        .collect(),
+                })
+                .collect(),
+            reserved_notes: Vec::new(),
+        })
+        .collect();
+    let macros = old
+        .macros
+        .into_iter()
+        .map(|entry| MacroEntry {
+            id: entry.id,
+            description: entry.description,
+            tags: entry.tags,
+            trigger: entry.trigger.map(|trigger| MidiTrigger {
+                r#type: match trigger.r#type {
+                    MidiTriggerTypeV1::Note => MidiTriggerType::Note,
+                },
+                number: trigger.number,
+                transport: None,
+                channel: None,
+                threshold: None,
+            }),
+            steps: entry
+                .steps
+                .into_iter()
+                .map(|step| match step {
+                    MacroStepV1::Keystroke { keys } => MacroStep::Keystroke { keys },
+                    MacroStepV1::Pause { ms } => MacroStep::Pause { ms, label: None },
+                })
+                .collect(),
+            max_duration_ms: None,
+            on_error: None,
+            cooldown_ms: None,
+        })
+        .collect();
+    let bundle = CacheBundle {
+        header: CacheHeader {
+            version: CACHE_VERSION,
+            source_hash: old.header.source_hash,
+            generated_at: old.header.generated_at,
+            compression: CompressionKind::None,
+            payload_crc: 0,
+        },
+        devices,
+        macros,
+        scripts: Vec::new(),
+        profiles: Vec::new(),
+        hooks: ExecutionHooks::default(),
+        defaults: CacheDefaults::default(),
+    };
+    encode_bundle(&bundle).map_err(encode_error)
+}
+
+/// `CacheDefaults` layout before `allow_commands` existed.
+#[derive(serde::Serialize)]
+struct DefaultsV3 {
+    tap_hold_timeout_ms: u64,
+    theme: Option<String>,
+}
+
+/// Version 3 appended `defaults`; v2 bundles get the built-in defaults. v2 steps
+/// are a prefix of the v3 step layout, so the rest of the payload is kept as is.
+fn migrate_v2(bytes: &[u8]) -> Result<Vec<u8>, CompileError> {
+    let defaults = DefaultsV3 {
+        tap_hold_timeout_ms: DEFAULT_TAP_HOLD_TIMEOUT_MS,
+        theme: None,
+    };
+    append_to_payload(bytes, 2, &defaults)
+}
+
+/// Version 4 appended `defaults.allow_commands`, and inserted `RunCommand` ahead of
+/// the v3 `KeyDown`, `KeyUp` and `RandomPause` steps. v3 bundles keep commands
+/// disabled and have their steps re-encoded in the v4 layout.
+fn migrate_v3(bytes: &[u8]) -> Result<Vec<u8>, CompileError> {
+    rewrite_payload(bytes, 3, |payload| {
+        let mut migrated = map_macros(payload, 3, |old: MacroEntryV4<MacroStepV3>| MacroEntryV4 {
+            id: old.id,
+            description: old.description,
+            tags: old.tags,
+            trigger: old.trigger,
+            steps: old.steps.into_iter().map(step_v3_to_v4).collect(),
+            max_duration_ms: old.max_duration_ms,
+            on_error: old.on_error,
+        })?;
+        migrated.extend(bincode::serialize(&false).map_err(CompileError::Serialize)?);
+        Ok(migrated)
+    })
+}
+
+/// Version 5 added `MacroEntry::cooldown_ms`; v4 macros get no cooldown.
+fn migrate_v4(bytes: &[u8]) -> Result<Vec<u8>, CompileError> {
+    rewrite_payload(bytes, 4, |payload| {
+        map_macros(payload, 4, |old: MacroEntryV4<MacroStepV4>| MacroEntryV5 {
+            id: old.id,
+            description: old.description,
+            tags: old.tags,
+            trigger: old.trigger,
+            steps: old.steps,
+            max_duration_ms: old.max_duration_ms,
+            on_error: old.on_error,
+            cooldown_ms: None,
+        })
+    })
+}
+
+/// Version 6 moved `KeyDown`, `KeyUp` and `RunCommand` after `RandomPause`; v5
+/// steps are re-encoded in the current layout.
+fn migrate_v5(bytes: &[u8]) -> Result<Vec<u8>, CompileError> {
+    rewrite_payload(bytes, 5, |payload| {
+        map_macros(payload, 5, |old: MacroEntryV5| MacroEntry {
+            id: old.id,
+            description: old.description,
+            tags: old.tags,
+            trigger: old.trigger,
+            steps: old.steps.into_iter().map(step_from_v4).collect(),
+            max_duration_ms: old.max_duration_ms,
+            on_error: old.on_error,
+            cooldown_ms: old.cooldown_ms,
+        })
+    })
+}
+
+/// Re-encodes the `devices` and `macros` at the front of a version `from` payload,
+/// passing each macro through `convert`; the rest of the payload is kept as is.
+fn map_macros<Old, New>(
+    payload: Vec<u8>,
+    from: u32,
+    convert: impl FnMut(Old) -> New,
+) -> Result<Vec<u8>, CompileError>
+where
+    Old: serde::de::DeserializeOwned,
+    New: serde::Serialize,
+{
+    let mut rest = payload.as_slice();
+    let devices: Vec<DeviceLayout> = bincode::deserialize_from(&mut rest)
+        .map_err(|_| CompileError::Corrupt(format!("truncated v{from} devices")))?;
+    let macros: Vec<Old> = bincode::deserialize_from(&mut rest)
+        .map_err(|_| CompileError::Corrupt(format!("truncated v{from} macros")))?;
+    let macros: Vec<New> = macros.into_iter().map(convert).collect();
+    let mut migrated = bincode::serialize(&devices).map_err(CompileError::Serialize)?;
+    migrated.extend(bincode::serialize(&macros).map_err(CompileError::Serialize)?);
+    migrated.extend_from_slice(rest);
+    Ok(migrated)
+}
+
+/// `MacroEntry` layout before `cooldown_ms` existed, with steps in the layout `S`
+/// of the version being read.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct MacroEntryV4<S> {
+    id: String,
+    description: Option<String>,
+    tags: Vec<String>,
+    trigger: Option<MidiTrigger>,
+    steps: Vec<S>,
+    max_duration_ms: Option<u64>,
+    on_error: Option<ErrorPolicy>,
+}
+
+/// `MacroEntry` layout of v5 caches, with steps still in the v4 layout.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct MacroEntryV5 {
+    id: String,
+    description: Option<String>,
+    tags: Vec<String>,
+    trigger: Option<MidiTrigger>,
+    steps: Vec<MacroStepV4>,
+    max_duration_ms: Option<u64>,
+    on_error: Option<ErrorPolicy>,
+    cooldown_ms: Option<u64>,
+}
+
+/// Frozen step layout of v2 and v3 caches; v2 stops at `WaitForMidi`.
+#[derive(serde::Serialize, serde::Deserialize)]
+enum MacroStepV3 {
+    Keystroke {
+        keys: Vec<String>,
+    },
+    Text {
+        text: String,
+    },
+    MouseMove {
+        x: i32,
+        y: i32,
+        relative: bool,
+    },
+    MouseClick {
+        button: MouseButton,
+    },
+    Pause {
+        ms: u64,
+        label: Option<String>,
+    },
+    Atomic {
+        steps: Vec<MacroStepV3>,
+    },
+    Repeat {
+        count: u32,
+        steps: Vec<MacroStepV3>,
+    },
+    RunMacro {
+        id: String,
+    },
+    RunScript {
+        id: String,
+    },
+    MidiOut {
+        message: Vec<u8>,
+    },
+    WaitForMidi {
+        note: u8,
+        timeout_ms: u64,
+        continue_on_timeout: bool,
+    },
+    VelocityGate {
+        min: u8,
+        max: u8,
+    },
+    KeyDown {
+        key: String,
+    },
+    KeyUp {
+        key: String,
+    },
+    RandomPause {
+        min_ms: u64,
+        max_ms: u64,
+    },
+}
+
+/// Frozen step layout of v4 and v5 caches; v4 stops at `RandomPause`.
+#[derive(serde::Serialize, serde::Deserialize)]
+enum MacroStepV4 {
+    Keystroke {
+        keys: Vec<String>,
+    },
+    Text {
+        text: String,
+    },
+    MouseMove {
+        x: i32,
+        y: i32,
+        relative: bool,
+    },
+    MouseClick {
+        button: MouseButton,
+    },
+    Pause {
+        ms: u64,
+        label: Option<String>,
+    },
+    Atomic {
+        steps: Vec<MacroStepV4>,
+    },
+    Repeat {
+        count: u32,
+        steps: Vec<MacroStepV4>,
+    },
+    RunMacro {
+        id: String,
+    },
+    RunScript {
+        id: String,
+    },
+    MidiOut {
+        message: Vec<u8>,
+    },
+    WaitForMidi {
+        note: u8,
+        timeout_ms: u64,
+        continue_on_timeout: bool,
+    },
+    VelocityGate {
+        min: u8,
+        max: u8,
+    },
+    RunCommand {
+        program: String,
+        args: Vec<String>,
+    },
+    KeyDown {
+        key: String,
+    },
+    KeyUp {
+        key: String,
+    },
+    RandomPause {
+        min_ms: u64,
+        max_ms: u64,
+    },
+    PauseMicros {
+        us: u64,
+    },
+    Reset,
+    SetVar {
+        name: String,
+        value: i64,
+    },
+    If {
+        var: String,
+        equals: i64,
+        then: Vec<MacroStepV4>,
+        else_: Vec<MacroStepV4>,
+    },
+}
+
+fn step_v3_to_v4(step: MacroStepV3) -> MacroStepV4 {
+    let steps = |steps: Vec<MacroStepV3>| steps.into_iter().map(step_v3_to_v4).collect();
+    match step {
+        MacroStepV3::Keystroke { keys } => MacroStepV4::Keystroke { keys },
+        MacroStepV3::Text { text } => MacroStepV4::Text { text },
+        MacroStepV3::MouseMove { x, y, relative } => MacroStepV4::MouseMove { x, y, relative },
+        MacroStepV3::MouseClick { button } => MacroStepV4::MouseClick { button },
+        MacroStepV3::Pause { ms, label } => MacroStepV4::Pause { ms, label },
+        MacroStepV3::Atomic { steps: inner } => MacroStepV4::Atomic {
+            steps: steps(inner),
+        },
+        MacroStepV3::Repeat {
+            count,
+            steps: inner,
+        } => MacroStepV4::Repeat {
+            count,
+            steps: steps(inner),
+        },
+        MacroStepV3::RunMacro { id } => MacroStepV4::RunMacro { id },
+        MacroStepV3::RunScript { id } => MacroStepV4::RunScript { id },
+        MacroStepV3::MidiOut { message } => MacroStepV4::MidiOut { message },
+        MacroStepV3::WaitForMidi {
+            note,
+            timeout_ms,
+            continue_on_timeout,
+        } => MacroStepV4::WaitForMidi {
+            note,
+            timeout_ms,
+            continue_on_timeout,
+        },
+        MacroStepV3::VelocityGate { min, max } => MacroStepV4::VelocityGate { min, max },
+        MacroStepV3::KeyDown { key } => MacroStepV4::KeyDown { key },
+        MacroStepV3::KeyUp { key } => MacroStepV4::KeyUp { key },
+        MacroStepV3::RandomPause { min_ms, max_ms } => MacroStepV4::RandomPause { min_ms, max_ms },
+    }
+}
+
+fn step_from_v4(step: MacroStepV4) -> MacroStep {
+    let steps = |steps: Vec<MacroStepV4>| steps.into_iter().map(step_from_v4).collect();
+    match step {
+        MacroStepV4::Keystroke { keys } => MacroStep::Keystroke { keys },
+        MacroStepV4::Text { text } => MacroStep::Text { text },
+        MacroStepV4::MouseMove { x, y, relative } => MacroStep::MouseMove { x, y, relative },
+        MacroStepV4::MouseClick { button } => MacroStep::MouseClick { button },
+        MacroStepV4::Pause { ms, label } => MacroStep::Pause { ms, label },
+        MacroStepV4::Atomic { steps: inner } => MacroStep::Atomic {
+            steps: steps(inner),
+        },
+        MacroStepV4::Repeat {
+            count,
+            steps: inner,
+        } => MacroStep::Repeat {
+            count,
+            steps: steps(inner),
+        },
+        MacroStepV4::RunMacro { id } => MacroStep::RunMacro { id },
+        MacroStepV4::RunScript { id } => MacroStep::RunScript { id },
+        MacroStepV4::MidiOut { message } => MacroStep::MidiOut { message },
+        MacroStepV4::WaitForMidi {
+            note,
+            timeout_ms,
+            continue_on_timeout,
+        } => MacroStep::WaitForMidi {
+            note,
+            timeout_ms,
+            continue_on_timeout,
+        },
+        MacroStepV4::VelocityGate { min, max } => MacroStep::VelocityGate { min, max },
+        MacroStepV4::RunCommand { program, args } => MacroStep::RunCommand { program, args },
+        MacroStepV4::KeyDown { key } => MacroStep::KeyDown { key },
+        MacroStepV4::KeyUp { key } => MacroStep::KeyUp { key },
+        MacroStepV4::RandomPause { min_ms, max_ms } => MacroStep::RandomPause { min_ms, max_ms },
+        MacroStepV4::PauseMicros { us } => MacroStep::PauseMicros { us },
+        MacroStepV4::Reset => MacroStep::Reset,
+        MacroStepV4::SetVar { name, value } => MacroStep::SetVar { name, value },
+        MacroStepV4::If {
+            var,
+            equals,
+            then,
+            else_,
+        } => MacroStep::If {
+            var,
+            equals,
+            then: steps(then),
+            else_: steps(else_),
+        },
+    }
+}
+
+/// Re-encodes a version `from` cache as `from + 1`, uncompressed, with `tail`
+/// serialized after the existing payload.
+fn append_to_payload(
+    bytes: &[u8],
+    from: u32,
+    tail: &impl serde::Serialize,
+) -> Result<Vec<u8>, CompileError> {
+    rewrite_payload(bytes, from, |mut payload| {
+        payload.extend(bincode::serialize(tail).map_err(CompileError::Serialize)?);
+        Ok(payload)
+    })
+}
+
+/// Re-encodes a version `from` cache as `from + 1`, uncompressed, with its
+/// decoded payload passed through `rewrite`.
+fn rewrite_payload(
+    bytes: &[u8],
+    from: u32,
+    rewrite: impl FnOnce(Vec<u8>) -> Result<Vec<u8>, CompileError>,
+) -> Result<Vec<u8>, CompileError> {
+    let header: CacheHeader = bincode::deserialize(bytes)
+        .map_err(|_| CompileError::Corrupt(format!("truncated v{from} header")))?;
+    let split = bincode::serialized_size(&header).map_err(CompileError::Serialize)? as usize;
+    let payload = decode_payload(&header, &bytes[split..]).map_err(read_error)?;
+    let payload = rewrite(payload)?;
+    let header = CacheHeader {
+        version: from + 1,
+        compression: CompressionKind::None,
+        payload_crc: payload_checksum(&payload),
+        ..header
+    };
+    let mut migrated = bincode::serialize(&header).map_err(CompileError::Serialize)?;
+    migrated.extend(payload);
+    Ok(migrated)
+}
+
 fn convert_issues(issues: Vec<ValidationIssue>) -> Vec<Diagnostic> {
     issues.into_iter().map(convert_issue).collect()
 }
@@ -148,13 +979,226 @@ fn convert_issue(issue: ValidationIssue) -> Diagnostic {
         message: issue.message,
         location: issue.location,
         severity: DiagnosticSeverity::from(issue.severity),
+        target: issue.target,
+        lint: issue.lint,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use cache_format::MacroStep;
+    use std::fs;
+
+    #[test]
+    fn global_accessors_read_present_settings() {
+        let yaml = r#"version: 1
+global:
+  defaults:
+    tap_hold_timeout_ms: 250
+    display:
+      theme: "light"
+    max_macro_duration_ms: 5000
+    double_tap_window_ms: 180
+devices: {}
+macros: {}
+"#;
+        let loaded = load_from_str(yaml).expect("load");
+        assert_eq!(loaded.tap_hold_timeout_ms(), 250);
+        assert_eq!(loaded.theme(), "light");
+        assert_eq!(loaded.max_macro_duration_ms(), Some(5000));
+        assert_eq!(loaded.double_tap_window_ms(), 180);
+    }
+
+    #[test]
+    fn global_accessors_fall_back_to_defaults() {
+        for yaml in [
+            "version: 1\ndevices: {}\nmacros: {}\n",
+            "version: 1\nglobal:\n  defaults:\n    display: {}\ndevices: {}\nmacros: {}\n",
+        ] {
+            let loaded = load_from_str(yaml).expect("load");
+            assert_eq!(loaded.tap_hold_timeout_ms(), DEFAULT_TAP_HOLD_TIMEOUT_MS);
+            assert_eq!(loaded.theme(), DEFAULT_THEME);
+            assert_eq!(loaded.max_macro_duration_ms(), None);
+            assert_eq!(loaded.double_tap_window_ms(), DEFAULT_DOUBLE_TAP_WINDOW_MS);
+        }
+    }
+
+    #[test]
+    fn format_diagnostic_matches_cli_format() {
+        let diag = Diagnostic {
+            path: "macros.a".into(),
+            message: "Macro has no steps".into(),
+            location: Some(Location { line: 3, column: 3 }),
+            severity: DiagnosticSeverity::Info,
+            target: DiagnosticTarget::Other,
+            lint: false,
+        };
+        assert_eq!(
+            format_diagnostic(&diag),
+            "[info] macros.a: Macro has no steps (line 3, column 3)"
+        );
+    }
+
+    #[test]
+    fn reads_compressed_and_plain_caches() {
+        let yaml = "version: 1\ndevices: {}\nmacros:\n  copy:\n    status: ready\n    trigger: { type: note, number: 60 }\n    steps:\n      - type: keystroke\n        keys: [\"Ctrl\", \"C\"]\n";
+        let compiled = compile_cache_from_str(yaml).expect("compile");
+        let plain = read_cache_bytes(compiled.bytes.clone()).expect("read plain");
+        assert_eq!(plain.bundle, compiled.bundle);
+
+        let mut bundle = compiled.bundle.clone();
+        bundle.header.compression = cache_format::CompressionKind::Zstd;
+        let bytes = encode_bundle(&bundle).expect("encode");
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("config.cache");
+        fs::write(&path, bytes).expect("write cache");
+        let read = read_cache_from_path(&path).expect("read compressed");
+        assert_eq!(read.bundle, bundle);
+    }
+
+    #[test]
+    fn flipped_payload_byte_is_reported_as_corrupt() {
+        let yaml = "version: 1\ndevices: {}\nmacros:\n  copy:\n    status: ready\n    trigger: { type: note, number: 60 }\n    steps:\n      - type: keystroke\n        keys: [\"Ctrl\", \"C\"]\n";
+        let mut bytes = compile_cache_from_str(yaml).expect("compile").bytes;
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+        assert!(matches!(
+            read_cache_bytes(bytes.clone()),
+            Err(CompileError::Corrupt(_))
+        ));
+        bytes.truncate(last / 2);
+        assert!(matches!(
+            read_cache_bytes(bytes),
+            Err(CompileError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn migrates_old_caches_and_rejects_unknown_versions() {
+        // Built from `config/sample.yaml` by the original version 1 cache builder.
+        let v1 = include_bytes!("../tests/fixtures/sample_v1.cache");
+        let old: HeaderV1 = bincode::deserialize(v1).unwrap();
+        assert_eq!(old.version, 1);
+        let bundle = load_cache_bytes(v1).expect("migrate v1");
+        let sample = compile_cache_from_str(include_str!("../../../config/sample.yaml"))
+            .expect("compile sample");
+        assert_eq!(bundle.header.version, CACHE_VERSION);
+        assert_eq!(bundle.header.source_hash, old.source_hash);
+        assert_eq!(bundle.header.generated_at, old.generated_at);
+        assert_eq!(bundle.devices, sample.bundle.devices);
+        assert_eq!(bundle.macros, sample.bundle.macros);
+        assert!(bundle.scripts.is_empty());
+        assert_eq!(bundle.defaults, CacheDefaults::default());
+
+        // Each built from its `.yaml` by the last cache builder writing that version;
+        // the v2 one is zstd-compressed.
+        let fixtures: [(&[u8], &str, u32); 4] = [
+            (
+                include_bytes!("../tests/fixtures/layout_v2.cache"),
+                include_str!("../tests/fixtures/layout_v2.yaml"),
+                2,
+            ),
+            (
+                include_bytes!("../tests/fixtures/layout_v3.cache"),
+                include_str!("../tests/fixtures/layout_v3.yaml"),
+                3,
+            ),
+            (
+                include_bytes!("../tests/fixtures/layout_v4.cache"),
+                include_str!("../tests/fixtures/layout_v4.yaml"),
+                4,
+            ),
+            (
+                include_bytes!("../tests/fixtures/layout_v5.cache"),
+                include_str!("../tests/fixtures/layout_v5.yaml"),
+                5,
+            ),
+        ];
+        for (bytes, yaml, version) in fixtures {
+            let old: CacheHeader = bincode::deserialize(bytes).unwrap();
+            assert_eq!(old.version, version);
+            let bundle = load_cache_bytes(bytes).expect("migrate");
+            assert_eq!(bundle.header.version, CACHE_VERSION);
+            assert_eq!(bundle.header.source_hash, old.source_hash);
+            let expected = compile_cache_from_str(yaml).expect("compile").bundle;
+            assert_eq!(
+                CacheBundle {
+                    header: expected.header.clone(),
+                    ..bundle
+                },
+                expected,
+                "v{version} fixture"
+            );
+        }
+
+        let yaml = "version: 1\ndevices: {}\nmacros:\n  copy:\n    status: ready\n    trigger: { type: note, number: 60 }\n    steps:\n      - type: keystroke\n        keys: [\"Ctrl\", \"C\"]\n";
+        let mut future = compile_cache_from_str(yaml).expect("compile").bytes;
+        future[..4].copy_from_slice(&99u32.to_le_bytes());
+        assert!(matches!(
+            load_cache_bytes(&future),
+            Err(CompileError::UnsupportedVersion {
+                found: 99,
+                expected: CACHE_VERSION
+            })
+        ));
+    }
+
+    #[test]
+    fn sniffs_json_caches() {
+        let yaml = "version: 1\ndevices: {}\nmacros:\n  copy:\n    status: ready\n    trigger: { type: note, number: 60 }\n    steps:\n      - type: keystroke\n        keys: [\"Ctrl\", \"C\"]\n";
+        let dir = tempfile::tempdir().expect("temp dir");
+        let config_path = dir.path().join("config.yaml");
+        fs::write(&config_path, yaml).expect("write config");
+        let (output, bytes) = cache_builder::build_from_path_json(&config_path).expect("build");
+        assert!(bytes.starts_with(b"{\n"));
+
+        let cache_path = dir.path().join("config.cache");
+        fs::write(&cache_path, bytes).expect("write cache");
+        let read = read_cache_from_path(&cache_path).expect("read json");
+        assert_eq!(read.bundle, output.bundle);
+    }
+
+    #[test]
+    fn json_and_yaml_configs_compile_to_the_same_bundle() {
+        let yaml = "version: 1\ndevices: {}\nmacros:\n  copy:\n    status: ready\n    trigger: { type: note, number: 60 }\n    steps:\n      - type: keystroke\n        keys: [\"Ctrl\", \"C\"]\n";
+        let json = r#"{"version": 1, "devices": {}, "macros": {"copy": {"status": "ready",
+            "trigger": {"type": "note", "number": 60},
+            "steps": [{"type": "keystroke", "keys": ["Ctrl", "C"]}]}}}"#;
+        let dir = tempfile::tempdir().expect("temp dir");
+        let yaml_path = dir.path().join("config.yaml");
+        let json_path = dir.path().join("config.json");
+        fs::write(&yaml_path, yaml).expect("write yaml");
+        fs::write(&json_path, json).expect("write json");
+
+        let from_yaml = compile_cache_from_path(&yaml_path).expect("compile yaml");
+        let mut from_json = compile_cache_from_path(&json_path).expect("compile json");
+        // Only the header's source hash and timestamp may differ.
+        from_json.bundle.header.source_hash = from_yaml.bundle.header.source_hash;
+        from_json.bundle.header.generated_at = from_yaml.bundle.header.generated_at;
+        assert_eq!(from_json.bundle, from_yaml.bundle);
+        assert!(load_from_path(&json_path).is_ok());
+
+        fs::write(&json_path, "{\"version\": 1,}").expect("rewrite json");
+        assert!(matches!(
+            load_from_path(&json_path),
+            Err(LoadError::Parse(ConfigError::Json(_)))
+        ));
+    }
+
+    #[test]
+    fn global_defaults_survive_the_cache_round_trip() {
+        let yaml = "version: 1\nglobal:\n  defaults:\n    display:\n      theme: light\ndevices: {}\nmacros: {}\n";
+        let compiled = compile_cache_from_str(yaml).expect("compile");
+        let read = read_cache_bytes(compiled.bytes).expect("read");
+        assert_eq!(
+            read.defaults(),
+            &CacheDefaults {
+                tap_hold_timeout_ms: DEFAULT_TAP_HOLD_TIMEOUT_MS,
+                theme: Some("light".into()),
+                allow_commands: false,
+            }
+        );
+    }
 
     #[test]
     fn loads_with_ready_macro_only() {
@@ -179,7 +1223,23 @@ scripts: {}
         let loaded = load_from_str(yaml).expect("should load");
         let ready: Vec<_> = loaded.ready_macros().map(|(id, _)| id.clone()).collect();
         assert_eq!(ready, vec!["ready_macro".to_string()]);
-        assert!(loaded.diagnostics.is_empty());
+        // The only note is that nothing can start the draft.
+        let paths: Vec<_> = loaded
+            .diagnostics
+            .iter()
+            .filter(|d| !d.lint)
+            .map(|d| d.path.as_str())
+            .collect();
+        assert_eq!(paths, vec!["macros.draft_macro"]);
+        assert_eq!(
+            loaded
+                .diagnostics
+                .iter()
+                .find(|d| !d.lint)
+                .unwrap()
+                .severity,
+            DiagnosticSeverity::Info
+        );
     }
 
     #[test]
@@ -200,8 +1260,9 @@ scripts: {}
         let err = load_from_str(yaml).unwrap_err();
         match err {
             LoadError::Validation(diags) => {
-                assert_eq!(diags.len(), 1);
-                assert_eq!(diags[0].severity, DiagnosticSeverity::Error);
+                let errors: Vec<_> = diags.iter().filter(|d| !d.lint).collect();
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].severity, DiagnosticSeverity::Error);
             }
             other => panic!("unexpected error: {other:?}"),
         }
@@ -230,7 +1291,22 @@ scripts: {}
         let compiled = compile_cache_from_str(yaml).expect("compile");
         assert_eq!(compiled.bundle.macros.len(), 1);
         assert_eq!(compiled.bundle.macros[0].id, "ready");
-        assert!(compiled.diagnostics.is_empty());
+        let paths: Vec<_> = compiled
+            .diagnostics
+            .iter()
+            .filter(|d| !d.lint)
+            .map(|d| d.path.as_str())
+            .collect();
+        assert_eq!(paths, vec!["macros.draft"]);
+        assert_eq!(
+            compiled
+                .diagnostics
+                .iter()
+                .find(|d| !d.lint)
+                .unwrap()
+                .severity,
+            DiagnosticSeverity::Info
+        );
         match &compiled.bundle.macros[0].steps[0] {
             MacroStep::Keystroke { keys } => assert_eq!(keys, &vec!["A".to_string()]),
             _ => panic!("unexpected step"),
@@ -260,4 +1336,84 @@ scripts: {}
             other => panic!("unexpected error: {other:?}"),
         }
     }
+
+    #[test]
+    fn load_from_first_existing_skips_missing_candidates() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let missing = dir.path().join("missing.yaml");
+        let present = dir.path().join("present.yaml");
+        fs::write(
+            &present,
+            "version: 1\ndevices: {}\nmacros: {}\nscripts: {}\n",
+        )
+        .expect("write config");
+
+        let candidates = vec![missing.clone(), present.clone()];
+        let loaded = load_from_first_existing(&candidates).expect("should load");
+        assert_eq!(loaded.path.as_deref(), Some(present.as_path()));
+
+        match load_from_first_existing(&candidates[..1]).unwrap_err() {
+            LoadError::NotFound(paths) => assert_eq!(paths, vec![missing]),
+            other => panic!("unexpected error: {other:?}"),
+        }
+    }
+
+    const DIFF_BASE: &str = r#"version: 1
+devices:
+  pad:
+    hardware_id: "usb:pad"
+    pages:
+      - name: main
+        widgets:
+          - id: rec
+            action: { type: macro, ref: record }
+macros:
+  record:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["R"]
+  stop:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["S"]
+"#;
+
+    #[test]
+    fn diff_reports_a_changed_macro() {
+        let old = compile_cache_from_str(DIFF_BASE).expect("compile old");
+        let new =
+            compile_cache_from_str(&DIFF_BASE.replace(r#"keys: ["S"]"#, r#"keys: ["Space"]"#))
+                .expect("compile new");
+        assert_eq!(
+            new.diff(&old),
+            CacheDiff {
+                changed_macros: vec!["stop".into()],
+                ..CacheDiff::default()
+            }
+        );
+        assert!(old.diff(&old).is_empty());
+    }
+
+    #[test]
+    fn diff_reports_a_changed_widget_action() {
+        let old = compile_cache_from_str(DIFF_BASE).expect("compile old");
+        let new = compile_cache_from_str(&DIFF_BASE.replace("ref: record", "ref: stop"))
+            .expect("compile new");
+        assert_eq!(
+            new.diff(&old),
+            CacheDiff {
+                changed_widgets: vec![("pad".into(), "rec".into())],
+                ..CacheDiff::default()
+            }
+        );
+
+        let renamed = compile_cache_from_str(&DIFF_BASE.replace("pad:", "launchpad:"))
+            .expect("compile renamed");
+        let diff = renamed.diff(&old);
+        assert_eq!(diff.added_devices, vec!["launchpad"]);
+        assert_eq!(diff.removed_devices, vec!["pad"]);
+        assert!(diff.changed_widgets.is_empty());
+    }
 }
@@ -1,6 +1,8 @@
 //! Virtual Console manager applying cache layouts and exposing diagnostics for UI/runtime subsystems.
 
-use crate::config::{CompiledCache, Diagnostic, DiagnosticSeverity};
+use crate::config::{
+    CompiledCache, Diagnostic, DiagnosticSeverity, DiagnosticTarget, DEFAULT_THEME,
+};
 use cache_format::{DeviceLayout, LayoutPage, LayoutWidget};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -17,6 +19,7 @@ pub struct ConsoleManager {
     pub macro_count: usize,
     pub devices: Vec<DeviceLayout>,
     pub diagnostics: Vec<Diagnostic>,
+    theme: Option<String>,
     widget_warning_cache: Vec<WidgetWarning>,
 }
 
@@ -29,9 +32,15 @@ impl ConsoleManager {
         self.macro_count = cache.bundle.macros.len();
         self.devices = cache.bundle.devices.clone();
         self.diagnostics = cache.diagnostics.clone();
+        self.theme = cache.defaults().theme.clone();
         self.rebuild_warning_cache();
     }
 
+    /// `global.defaults.display.theme` from the applied cache, or `DEFAULT_THEME`.
+    pub fn theme(&self) -> &str {
+        self.theme.as_deref().unwrap_or(DEFAULT_THEME)
+    }
+
     pub fn pages_for_device(&self, device_id: &str) -> Option<&[LayoutPage]> {
         self.devices
             .iter()
@@ -56,6 +65,19 @@ impl ConsoleManager {
             })
     }
 
+    /// Index-based lookup; reaches pages that share a name with an earlier page.
+    pub fn widgets_for_page_index(
+        &self,
+        device_id: &str,
+        page_index: usize,
+    ) -> Option<&[LayoutWidget]> {
+        self.devices
+            .iter()
+            .find(|device| device.id == device_id)
+            .and_then(|device| device.pages.get(page_index))
+            .map(|page| page.widgets.as_slice())
+    }
+
     pub fn widget_warnings(
         &self,
         device_id: &str,
@@ -78,41 +100,34 @@ impl ConsoleManager {
             if diag.severity != DiagnosticSeverity::Warning {
                 continue;
             }
-            if let Some(warning) = self.parse_widget_warning(diag) {
+            if let Some(warning) = self.widget_warning(diag) {
                 self.widget_warning_cache.push(warning);
             }
         }
     }
 
-    fn parse_widget_warning(&self, diag: &Diagnostic) -> Option<WidgetWarning> {
-        let path = &diag.path;
-        let devices_prefix = "devices.";
-        let device_start = path.find(devices_prefix)? + devices_prefix.len();
-        let device_end = path[device_start..].find('.')? + device_start;
-        let device_id = &path[device_start..device_end];
-
-        let pages_marker = "pages[";
-        let pages_start = path[device_end..].find(pages_marker)? + device_end + pages_marker.len();
-        let pages_end = path[pages_start..].find(']')? + pages_start;
-        let page_index = path[pages_start..pages_end].parse::<usize>().ok()?;
-
-        let widgets_marker = ".widgets.";
-        let widget_start =
-            path[pages_end..].find(widgets_marker)? + pages_end + widgets_marker.len();
-        let widget_id = &path[widget_start..];
+    fn widget_warning(&self, diag: &Diagnostic) -> Option<WidgetWarning> {
+        let DiagnosticTarget::Widget {
+            device,
+            page_index,
+            widget_id,
+        } = &diag.target
+        else {
+            return None;
+        };
 
         let page_name = self
             .devices
             .iter()
-            .find(|d| d.id == device_id)
-            .and_then(|device| device.pages.get(page_index))
+            .find(|d| d.id == *device)
+            .and_then(|layout| layout.pages.get(*page_index))
             .map(|page| page.name.clone());
 
         Some(WidgetWarning {
-            device_id: device_id.to_string(),
-            page_index,
+            device_id: device.clone(),
+            page_index: *page_index,
             page_name,
-            widget_id: widget_id.to_string(),
+            widget_id: widget_id.clone(),
             message: diag.message.clone(),
         })
     }
@@ -122,7 +137,8 @@ impl ConsoleManager {
 mod tests {
     use super::*;
     use cache_format::{
-        CacheBundle, CacheHeader, DeviceLayout, LayoutPage, LayoutWidget, MacroEntry, WidgetAction,
+        CacheBundle, CacheDefaults, CacheHeader, CompressionKind, DeviceLayout, ExecutionHooks,
+        LayoutPage, LayoutWidget, MacroEntry, TapBehavior, WidgetAction,
     };
 
     fn sample_cache(count: usize) -> CompiledCache {
@@ -134,6 +150,9 @@ mod tests {
                 tags: vec![],
                 trigger: None,
                 steps: vec![],
+                max_duration_ms: None,
+                on_error: None,
+                cooldown_ms: None,
             });
         }
         let bundle = CacheBundle {
@@ -141,6 +160,8 @@ mod tests {
                 version: cache_format::CACHE_VERSION,
                 source_hash: 0,
                 generated_at: 0,
+                compression: CompressionKind::None,
+                payload_crc: 0,
             },
             devices: vec![DeviceLayout {
                 id: "launchpad".into(),
@@ -150,11 +171,21 @@ mod tests {
                     widgets: vec![LayoutWidget {
                         id: "pad_1".into(),
                         tap_behavior: Some("tap".into()),
+                        behavior: TapBehavior::Tap,
                         action: Some(WidgetAction::Macro { id: "m0".into() }),
+                        mode: None,
+                        off_action: None,
+                        double_tap_action: None,
+                        double_tap_window_ms: 300,
                     }],
                 }],
+                reserved_notes: vec![],
             }],
             macros,
+            scripts: vec![],
+            profiles: vec![],
+            hooks: ExecutionHooks::default(),
+            defaults: CacheDefaults::default(),
         };
         CompiledCache {
             bundle,
@@ -177,6 +208,17 @@ mod tests {
             .expect("widgets");
         assert_eq!(widgets.len(), 1);
         assert_eq!(widgets[0].id, "pad_1");
+        assert_eq!(
+            manager.widgets_for_page_index("launchpad", 0),
+            Some(widgets)
+        );
+        assert_eq!(manager.widgets_for_page_index("launchpad", 1), None);
+        assert_eq!(manager.theme(), DEFAULT_THEME);
+
+        let mut themed = sample_cache(0);
+        themed.bundle.defaults.theme = Some("light".into());
+        manager.apply_cache(&themed);
+        assert_eq!(manager.theme(), "light");
     }
 
     #[test]
@@ -189,6 +231,12 @@ mod tests {
                     .into(),
             location: None,
             severity: DiagnosticSeverity::Warning,
+            target: DiagnosticTarget::Widget {
+                device: "launchpad".into(),
+                page_index: 0,
+                widget_id: "pad_1".into(),
+            },
+            lint: false,
         });
 
         let mut manager = ConsoleManager::new();
@@ -1,30 +1,276 @@
 //! Macro execution engine placeholder.
 
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
 
 use crate::config::CompiledCache;
-use cache_format::{MacroEntry, MacroStep};
-use tokio::sync::Mutex;
+use crate::layers::LayerManager;
+use crate::midi::output::MidiOutManager;
+use cache_format::{
+    ActionMode, CrossingDirection, ErrorPolicy, ExecutionHooks, MacroEntry, MacroStep, MidiTrigger,
+    MidiTriggerType, MouseButton, ScriptEntry, TapBehavior, ThresholdCrossing, TransportKind,
+    WidgetAction,
+};
+use futures::future::{BoxFuture, FutureExt};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::{Mutex, MutexGuard};
 use tokio::task;
+use tokio_util::sync::CancellationToken;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ActionLog {
     Keystroke(Vec<String>),
-    Pause(u64),
+    KeyDown(String),
+    KeyUp(String),
+    Text(String),
+    MouseMove {
+        x: i32,
+        y: i32,
+        relative: bool,
+    },
+    MouseClick(MouseButton),
+    /// Pause duration in milliseconds plus the optional step label.
+    Pause(u64, Option<String>),
+    /// Duration of a `PauseMicros` step in microseconds.
+    PauseMicros(u64),
+    /// The macro exceeded its `max_duration_ms` and was cut short.
+    TimedOut(String),
+    /// A `WaitForMidi` step received its note.
+    MidiReceived(u8),
+    /// A `WaitForMidi` step gave up waiting for its note.
+    MidiWaitTimedOut(u8),
+    /// A step failed and the macro's `on_error` policy ran this fallback macro.
+    Fallback(String),
+    /// A step could not run; the message says why.
+    Error(String),
+    /// This script ran and exited successfully.
+    Script(String),
+    /// A `RunCommand` step's program ran and exited successfully.
+    Command(String),
+    /// Raw bytes of a `MidiOut` step.
+    MidiOut(Vec<u8>),
+    /// This background run was cancelled or restarted before it finished.
+    Cancelled(String),
+    /// A `VelocityGate` step stopped the macro; carries the triggering velocity.
+    VelocityGated(u8),
+    /// A `Reset` step or a cancellation released held keys and silenced MIDI output.
+    Reset,
+    /// A `SetVar` step stored this value in the named variable.
+    SetVar(String, i64),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MidiEvent {
     pub note: u8,
     pub velocity: u8,
+    /// Channel nibble (0–15) of the status byte; 0 for system messages.
+    pub channel: u8,
+    pub kind: MidiEventKind,
+}
+
+impl MidiEvent {
+    /// Velocity `VelocityGate` steps see: a note-on's velocity or a control
+    /// change's value. Other events carry none.
+    pub fn trigger_velocity(&self) -> Option<u8> {
+        match self.kind {
+            MidiEventKind::NoteOn | MidiEventKind::ControlChange => Some(self.velocity),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MidiEventKind {
+    NoteOn,
+    /// Note release, including note-ons sent with velocity 0.
+    NoteOff,
+    /// Control change; `note` is the controller number and `velocity` its value.
+    ControlChange,
+    /// Program change; `note` is the program number and `velocity` is unused.
+    ProgramChange,
+    /// System real-time transport message; `note` and `velocity` are unused.
+    Transport(TransportKind),
+    /// 14-bit pitch wheel position (0–16383, centre 8192); `note` and `velocity` are unused.
+    PitchBend(u16),
+    /// Complete System Exclusive message, `0xF0` through `0xF7` inclusive, e.g. an
+    /// identity reply or a layout change; `note` and `velocity` are unused.
+    SysEx {
+        data: Vec<u8>,
+    },
+}
+
+/// A `NoteRange` trigger; exact note triggers take precedence over it.
+#[derive(Debug)]
+struct NoteRangeTrigger {
+    low: u8,
+    high: u8,
+    channel: Option<u8>,
+    macro_id: String,
+}
+
+impl NoteRangeTrigger {
+    fn matches(&self, event: &MidiEvent) -> bool {
+        event.kind == MidiEventKind::NoteOn
+            && (self.low..=self.high).contains(&event.note)
+            && self.channel.is_none_or(|channel| channel == event.channel)
+    }
+
+    /// Sort key among overlapping ranges: channel-bound first, then narrower
+    /// ranges, then macro id.
+    fn rank(&self) -> (bool, u8, &str) {
+        (
+            self.channel.is_none(),
+            self.high.saturating_sub(self.low),
+            &self.macro_id,
+        )
+    }
+}
+
+/// A `Chord` trigger; `fired` stays set until one of its notes is released, so
+/// holding the chord fires it once.
+#[derive(Debug)]
+struct ChordWatch {
+    notes: Vec<u8>,
+    channel: Option<u8>,
+    window: Duration,
+    macro_id: String,
+    fired: bool,
+}
+
+impl ChordWatch {
+    fn listens_to(&self, channel: u8, note: u8) -> bool {
+        self.notes.contains(&note) && self.channel.is_none_or(|ch| ch == channel)
+    }
+}
+
+/// Lookup key shared by compiled triggers and incoming events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum TriggerKey {
+    Note(u8),
+    ControlChange(u8),
+    ProgramChange(u8),
+    Transport(TransportKind),
+}
+
+impl TriggerKey {
+    fn for_trigger(trigger: &MidiTrigger) -> Option<Self> {
+        match trigger.r#type {
+            MidiTriggerType::Note => Some(TriggerKey::Note(trigger.number)),
+            MidiTriggerType::ControlChange => Some(TriggerKey::ControlChange(trigger.number)),
+            MidiTriggerType::ProgramChange => Some(TriggerKey::ProgramChange(trigger.number)),
+            MidiTriggerType::Transport => trigger.transport.map(TriggerKey::Transport),
+            MidiTriggerType::PitchBend
+            | MidiTriggerType::NoteRange { .. }
+            | MidiTriggerType::Chord { .. } => None,
+        }
+    }
+
+    /// Continuous-value events have no fixed key; see [`ThresholdWatch`].
+    /// Note triggers fire on the press edge, so releases never match.
+    fn for_event(event: &MidiEvent) -> Option<Self> {
+        match event.kind {
+            MidiEventKind::NoteOn => Some(TriggerKey::Note(event.note)),
+            MidiEventKind::NoteOff => None,
+            MidiEventKind::ControlChange => Some(TriggerKey::ControlChange(event.note)),
+            MidiEventKind::ProgramChange => Some(TriggerKey::ProgramChange(event.note)),
+            MidiEventKind::Transport(kind) => Some(TriggerKey::Transport(kind)),
+            MidiEventKind::PitchBend(_) | MidiEventKind::SysEx { .. } => None,
+        }
+    }
+}
+
+/// Distance a value must travel back past a threshold before it can fire again,
+/// so jitter around the crossing point doesn't retrigger.
+const THRESHOLD_HYSTERESIS: u16 = 256;
+
+/// Top of the 14-bit pitch wheel range.
+const PITCH_BEND_MAX: u16 = 16383;
+
+/// Crossing detector for a single threshold trigger.
+#[derive(Debug)]
+struct ThresholdWatch {
+    crossing: ThresholdCrossing,
+    /// `None` watches every channel.
+    channel: Option<u8>,
+    macro_id: String,
+    /// Set once the value has been seen clear of the threshold on the approach side.
+    armed: bool,
+}
+
+impl ThresholdWatch {
+    fn new(crossing: ThresholdCrossing, channel: Option<u8>, macro_id: String) -> Self {
+        Self {
+            crossing,
+            channel,
+            macro_id,
+            armed: false,
+        }
+    }
+
+    /// Where the value must return to before the watch arms. Near either end of
+    /// the wheel the hysteresis shrinks to half the room left on the approach
+    /// side, so the arm point is always reachable short of the end stop.
+    fn arm_point(&self) -> u16 {
+        let threshold = self.crossing.value;
+        match self.crossing.direction {
+            CrossingDirection::Rising => {
+                threshold - THRESHOLD_HYSTERESIS.min(threshold.div_ceil(2))
+            }
+            CrossingDirection::Falling => {
+                let room = PITCH_BEND_MAX.saturating_sub(threshold);
+                threshold + THRESHOLD_HYSTERESIS.min(room.div_ceil(2))
+            }
+        }
+    }
+
+    /// Feeds the latest value; returns true when it completes a crossing.
+    fn observe(&mut self, value: u16) -> bool {
+        let threshold = self.crossing.value;
+        let (past, clear) = match self.crossing.direction {
+            CrossingDirection::Rising => (value >= threshold, value <= self.arm_point()),
+            CrossingDirection::Falling => (value <= threshold, value >= self.arm_point()),
+        };
+        if self.armed && past {
+            self.armed = false;
+            return true;
+        }
+        if clear {
+            self.armed = true;
+        }
+        false
+    }
 }
 
+/// Injects key input. Every method's `Err` carries a reason for the action log.
 #[async_trait::async_trait]
 pub trait KeySender: Send + Sync {
-    async fn send_keystroke(&self, keys: &[String]);
+    async fn send_keystroke(&self, keys: &[String]) -> Result<(), String>;
+
+    /// Types `text` literally. The default sends one keystroke per character.
+    async fn type_text(&self, text: &str) -> Result<(), String> {
+        for ch in text.chars() {
+            self.send_keystroke(&[ch.to_string()]).await?;
+        }
+        Ok(())
+    }
+
+    /// Presses `key` and leaves it held. The default does nothing.
+    async fn key_down(&self, _key: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Releases a key pressed by [`KeySender::key_down`]. The default does nothing.
+    async fn key_up(&self, _key: &str) -> Result<(), String> {
+        Ok(())
+    }
 }
 
+#[derive(Default)]
 pub struct LoggingKeySender;
 
 impl LoggingKeySender {
@@ -35,212 +281,4549 @@ impl LoggingKeySender {
 
 #[async_trait::async_trait]
 impl KeySender for LoggingKeySender {
-    async fn send_keystroke(&self, _keys: &[String]) {}
+    async fn send_keystroke(&self, _keys: &[String]) -> Result<(), String> {
+        Ok(())
+    }
 }
 
-pub struct EnigoKeySender;
+/// One call a [`RecordingKeySender`] received.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SentInput {
+    Keystroke(Vec<String>),
+    KeyDown(String),
+    KeyUp(String),
+    Text(String),
+}
 
-impl EnigoKeySender {
+/// Keeps every input it is sent with the time it arrived, so tests can assert
+/// exactly what reached the sender and when.
+#[derive(Debug, Default)]
+pub struct RecordingKeySender {
+    sent: std::sync::Mutex<Vec<(Instant, SentInput)>>,
+}
+
+impl RecordingKeySender {
     pub fn new() -> Self {
-        Self
+        Self::default()
+    }
+
+    /// Every input so far, oldest first.
+    pub fn recorded(&self) -> Vec<(Instant, SentInput)> {
+        self.sent
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    fn record(&self, input: SentInput) -> Result<(), String> {
+        self.sent
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push((Instant::now(), input));
+        Ok(())
     }
 }
 
 #[async_trait::async_trait]
-impl KeySender for EnigoKeySender {
-    async fn send_keystroke(&self, keys: &[String]) {
-        let keys = keys.to_vec();
-        let _ = task::spawn_blocking(move || send_keys_blocking(keys)).await;
+impl KeySender for RecordingKeySender {
+    async fn send_keystroke(&self, keys: &[String]) -> Result<(), String> {
+        self.record(SentInput::Keystroke(keys.to_vec()))
+    }
+
+    async fn type_text(&self, text: &str) -> Result<(), String> {
+        self.record(SentInput::Text(text.to_string()))
+    }
+
+    async fn key_down(&self, key: &str) -> Result<(), String> {
+        self.record(SentInput::KeyDown(key.to_string()))
+    }
+
+    async fn key_up(&self, key: &str) -> Result<(), String> {
+        self.record(SentInput::KeyUp(key.to_string()))
     }
 }
 
+/// An input event performed by an [`EnigoWorker`].
 #[derive(Debug)]
-pub struct Executor<T: KeySender + 'static> {
-    macros: HashMap<String, MacroEntry>,
-    triggers: HashMap<u8, String>,
-    pub last_actions: Vec<ActionLog>,
-    key_sender: Arc<T>,
+enum EnigoCommand {
+    Keystroke(Vec<String>),
+    Text(String),
+    KeyEdge { key: enigo::Key, down: bool },
+    MoveMouse { x: i32, y: i32, relative: bool },
+    Click(enigo::MouseButton),
 }
 
-impl<T: KeySender + 'static> Executor<T> {
-    pub fn new(key_sender: Arc<T>) -> Self {
+type EnigoReply = tokio::sync::oneshot::Sender<Result<(), String>>;
+
+/// A thread owning one `enigo::Enigo` for as long as its sender lives. `Enigo` is
+/// not `Send`, so every event is handed to this thread and performed there.
+#[derive(Debug)]
+struct EnigoWorker {
+    commands: std::sync::mpsc::Sender<(EnigoCommand, EnigoReply)>,
+}
+
+impl EnigoWorker {
+    /// Starts the thread and waits until its `Enigo` exists.
+    fn spawn() -> Result<Self, String> {
+        let (commands, received) = std::sync::mpsc::channel::<(EnigoCommand, EnigoReply)>();
+        let (ready_tx, ready) = std::sync::mpsc::channel::<Result<(), String>>();
+        std::thread::Builder::new()
+            .name("enigo-input".into())
+            .spawn(move || {
+                let mut enigo = match std::panic::catch_unwind(enigo::Enigo::new) {
+                    Ok(enigo) => enigo,
+                    Err(_) => {
+                        let _ = ready_tx.send(Err("enigo panicked during initialization".into()));
+                        return;
+                    }
+                };
+                let _ = ready_tx.send(Ok(()));
+                for (command, reply) in received {
+                    let done = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                        perform_enigo(&mut enigo, command)
+                    }));
+                    let _ =
+                        reply.send(done.map_err(|_| "enigo panicked while sending input".into()));
+                }
+            })
+            .map_err(|err| format!("could not start the input thread: {err}"))?;
+        ready
+            .recv()
+            .map_err(|_| "the input thread exited during startup".to_string())??;
+        Ok(Self { commands })
+    }
+
+    async fn run(&self, command: EnigoCommand) -> Result<(), String> {
+        let stopped = || "the input thread has stopped".to_string();
+        let (reply, result) = tokio::sync::oneshot::channel();
+        self.commands
+            .send((command, reply))
+            .map_err(|_| stopped())?;
+        result.await.map_err(|_| stopped())?
+    }
+}
+
+fn perform_enigo(enigo: &mut enigo::Enigo, command: EnigoCommand) {
+    use enigo::{KeyboardControllable, MouseControllable};
+    match command {
+        EnigoCommand::Keystroke(keys) => press_keys(enigo, &keys),
+        EnigoCommand::Text(text) => enigo.key_sequence(&text),
+        EnigoCommand::KeyEdge { key, down: true } => enigo.key_down(key),
+        EnigoCommand::KeyEdge { key, down: false } => enigo.key_up(key),
+        EnigoCommand::MoveMouse {
+            x,
+            y,
+            relative: true,
+        } => enigo.mouse_move_relative(x, y),
+        EnigoCommand::MoveMouse {
+            x,
+            y,
+            relative: false,
+        } => enigo.mouse_move_to(x, y),
+        EnigoCommand::Click(button) => enigo.mouse_click(button),
+    }
+}
+
+/// An [`EnigoWorker`] started by the first send. A failed start is remembered, so
+/// later sends fail at once with the same reason instead of retrying.
+#[derive(Debug)]
+struct EnigoBackend {
+    init: fn() -> Result<(), String>,
+    worker: OnceLock<Result<EnigoWorker, String>>,
+}
+
+impl EnigoBackend {
+    fn new(init: fn() -> Result<(), String>) -> Self {
         Self {
-            macros: HashMap::new(),
-            triggers: HashMap::new(),
-            last_actions: Vec::new(),
-            key_sender,
+            init,
+            worker: OnceLock::new(),
         }
     }
 
-    pub fn apply_cache(&mut self, cache: &CompiledCache) {
-        self.macros = cache
-            .bundle
-            .macros
-            .iter()
-            .cloned()
-            .map(|entry| (entry.id.clone(), entry))
-            .collect();
-        self.triggers.clear();
-        for entry in self.macros.values() {
-            if let Some(trigger) = &entry.trigger {
-                self.triggers.insert(trigger.number, entry.id.clone());
-            }
+    fn error(&self) -> Option<&str> {
+        self.worker.get()?.as_ref().err().map(String::as_str)
+    }
+
+    /// `kind` names the backend in the error of a failed start, e.g. "Keystroke".
+    async fn run(&self, kind: &str, command: EnigoCommand) -> Result<(), String> {
+        let worker = self
+            .worker
+            .get_or_init(|| (self.init)().and_then(|()| EnigoWorker::spawn()));
+        match worker {
+            Ok(worker) => worker.run(command).await,
+            Err(err) => Err(format!("{kind} backend unavailable: {err}")),
         }
     }
+}
 
-    pub async fn execute_midi_event(&mut self, event: MidiEvent) -> bool {
-        if let Some(id) = self.triggers.get(&event.note).cloned() {
-            self.execute_macro(&id).await
-        } else {
-            false
+/// Sends keystrokes through `enigo`. The backend is started once; if that fails
+/// (e.g. no display server), every send reports why instead of panicking.
+#[derive(Debug)]
+pub struct EnigoKeySender {
+    backend: EnigoBackend,
+}
+
+impl EnigoKeySender {
+    pub fn new() -> Self {
+        Self::with_initializer(probe_enigo)
+    }
+
+    /// Uses `init` instead of probing `enigo`, so the degraded path can be exercised.
+    pub fn with_initializer(init: fn() -> Result<(), String>) -> Self {
+        Self {
+            backend: EnigoBackend::new(init),
         }
     }
 
-    pub async fn execute_macro(&mut self, id: &str) -> bool {
-        let Some(entry) = self.macros.get(id) else {
-            return false;
+    /// Why the backend is unavailable, once initialization has been attempted.
+    pub fn init_error(&self) -> Option<&str> {
+        self.backend.error()
+    }
+
+    /// Presses (`down`) or releases a single key; unknown names are ignored.
+    async fn send_key_edge(&self, key: &str, down: bool) -> Result<(), String> {
+        let Some(key) = map_key(key) else {
+            return Ok(());
         };
-        self.last_actions.clear();
-        for step in &entry.steps {
-            match step {
-                MacroStep::Keystroke { keys } => {
-                    self.key_sender.send_keystroke(keys).await;
-                    self.last_actions.push(ActionLog::Keystroke(keys.clone()))
-                }
-                MacroStep::Pause { ms } => {
-                    self.last_actions.push(ActionLog::Pause(*ms));
-                    tokio::time::sleep(std::time::Duration::from_millis(*ms)).await;
-                }
-            }
-        }
-        true
+        self.backend
+            .run("Keystroke", EnigoCommand::KeyEdge { key, down })
+            .await
     }
 }
 
-pub type SharedExecutor<T> = Arc<Mutex<Executor<T>>>;
+impl Default for EnigoKeySender {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-#[cfg(not(test))]
-pub type DefaultKeySender = EnigoKeySender;
+#[async_trait::async_trait]
+impl KeySender for EnigoKeySender {
+    async fn send_keystroke(&self, keys: &[String]) -> Result<(), String> {
+        self.backend
+            .run("Keystroke", EnigoCommand::Keystroke(keys.to_vec()))
+            .await
+    }
 
-#[cfg(test)]
-pub type DefaultKeySender = LoggingKeySender;
+    async fn type_text(&self, text: &str) -> Result<(), String> {
+        self.backend
+            .run("Keystroke", EnigoCommand::Text(text.to_string()))
+            .await
+    }
 
-fn send_keys_blocking(keys: Vec<String>) {
-    use enigo::{Enigo, Key, KeyboardControllable};
+    async fn key_down(&self, key: &str) -> Result<(), String> {
+        self.send_key_edge(key, true).await
+    }
 
-    if keys.is_empty() {
-        return;
+    async fn key_up(&self, key: &str) -> Result<(), String> {
+        self.send_key_edge(key, false).await
     }
+}
 
-    let mut enigo = Enigo::new();
-    let mut modifiers: Vec<Key> = Vec::new();
+/// Pointer counterpart to [`KeySender`].
+#[async_trait::async_trait]
+pub trait MouseSender: Send + Sync + std::fmt::Debug {
+    /// Moves to `(x, y)`, or by that offset when `relative` is set.
+    async fn move_mouse(&self, x: i32, y: i32, relative: bool) -> Result<(), String>;
+    async fn click(&self, button: MouseButton) -> Result<(), String>;
+}
 
-    for key_str in keys.iter().take(keys.len().saturating_sub(1)) {
-        if let Some(key) = map_key(key_str) {
-            enigo.key_down(key.clone());
-            modifiers.push(key);
-        }
+#[derive(Debug, Default)]
+pub struct LoggingMouseSender;
+
+#[async_trait::async_trait]
+impl MouseSender for LoggingMouseSender {
+    async fn move_mouse(&self, _x: i32, _y: i32, _relative: bool) -> Result<(), String> {
+        Ok(())
     }
 
-    if let Some(last_str) = keys.last() {
-        if let Some(last_key) = map_key(last_str) {
-            enigo.key_click(last_key);
+    async fn click(&self, _button: MouseButton) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Drives the pointer through `enigo`, degrading like [`EnigoKeySender`].
+#[derive(Debug)]
+pub struct EnigoMouseSender {
+    backend: EnigoBackend,
+}
+
+impl EnigoMouseSender {
+    pub fn new() -> Self {
+        Self {
+            backend: EnigoBackend::new(probe_enigo),
         }
     }
+}
 
-    for key in modifiers.into_iter().rev() {
-        enigo.key_up(key);
+impl Default for EnigoMouseSender {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-fn map_key(input: &str) -> Option<enigo::Key> {
-    use enigo::Key;
-    match input.to_ascii_lowercase().as_str() {
-        "ctrl" | "control" => Some(Key::Control),
-        "alt" => Some(Key::Alt),
-        "shift" => Some(Key::Shift),
-        "meta" | "cmd" | "command" | "super" => Some(Key::Meta),
-        "enter" | "return" => Some(Key::Return),
-        "space" | "spacebar" => Some(Key::Space),
-        "tab" => Some(Key::Tab),
-        "esc" | "escape" => Some(Key::Escape),
-        s if s.len() == 1 => {
-            let ch = s.chars().next().unwrap();
-            Some(Key::Layout(ch))
-        }
-        _ => None,
+#[async_trait::async_trait]
+impl MouseSender for EnigoMouseSender {
+    async fn move_mouse(&self, x: i32, y: i32, relative: bool) -> Result<(), String> {
+        self.backend
+            .run("Mouse", EnigoCommand::MoveMouse { x, y, relative })
+            .await
+    }
+
+    async fn click(&self, button: MouseButton) -> Result<(), String> {
+        let button = match button {
+            MouseButton::Left => enigo::MouseButton::Left,
+            MouseButton::Right => enigo::MouseButton::Right,
+            MouseButton::Middle => enigo::MouseButton::Middle,
+        };
+        self.backend.run("Mouse", EnigoCommand::Click(button)).await
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use cache_format::{CacheBundle, CacheHeader, MacroEntry, MidiTrigger, MidiTriggerType};
+/// Runs bundled scripts for `RunScript` steps and script widget actions.
+#[async_trait::async_trait]
+pub trait ScriptRunner: Send + Sync + std::fmt::Debug {
+    /// Runs `script` to completion; `Err` carries a reason for the action log.
+    async fn run_script(&self, script: &ScriptEntry) -> Result<(), String>;
+}
 
-    struct MockSender;
+#[derive(Debug, Default)]
+pub struct LoggingScriptRunner;
 
-    #[async_trait::async_trait]
-    impl KeySender for MockSender {
-        async fn send_keystroke(&self, _keys: &[String]) {}
+#[async_trait::async_trait]
+impl ScriptRunner for LoggingScriptRunner {
+    async fn run_script(&self, _script: &ScriptEntry) -> Result<(), String> {
+        Ok(())
     }
+}
 
-    fn sample_cache() -> CompiledCache {
-        let bundle = CacheBundle {
-            header: CacheHeader {
-                version: cache_format::CACHE_VERSION,
-                source_hash: 1,
-                generated_at: 1,
-            },
-            devices: vec![],
-            macros: vec![MacroEntry {
-                id: "macro_a".into(),
-                description: None,
-                tags: vec![],
-                trigger: Some(MidiTrigger {
-                    r#type: MidiTriggerType::Note,
-                    number: 60,
-                }),
-                steps: vec![
-                    MacroStep::Keystroke {
-                        keys: vec!["Ctrl".into(), "S".into()],
-                    },
-                    MacroStep::Pause { ms: 10 },
-                ],
-            }],
-        };
-        CompiledCache {
-            bundle,
-            diagnostics: vec![],
-            bytes: vec![],
+/// Runs script bodies as shell commands (`sh -c`, or `cmd /C` on Windows).
+/// Scripts tagged with any language other than `shell` are refused.
+#[derive(Debug, Default)]
+pub struct ShellScriptRunner;
+
+#[async_trait::async_trait]
+impl ScriptRunner for ShellScriptRunner {
+    async fn run_script(&self, script: &ScriptEntry) -> Result<(), String> {
+        if let Some(language) = script.language.as_deref().filter(|lang| *lang != "shell") {
+            return Err(format!("no runner for `{language}` scripts"));
+        }
+        let body = script.body.clone();
+        let status = task::spawn_blocking(move || shell_command(&body).status())
+            .await
+            .map_err(|err| err.to_string())?
+            .map_err(|err| format!("failed to start shell: {err}"))?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(format!("script exited with {status}"))
         }
     }
+}
 
-    #[tokio::test]
-    async fn executes_macro_actions() {
-        let cache = sample_cache();
-        let mut executor = Executor::new(Arc::new(MockSender));
-        executor.apply_cache(&cache);
-        let result = executor.execute_macro("macro_a").await;
-        assert!(result);
-        assert_eq!(
-            executor.last_actions,
-            vec![
-                ActionLog::Keystroke(vec!["Ctrl".into(), "S".into()]),
-                ActionLog::Pause(10)
-            ]
-        );
+fn shell_command(body: &str) -> std::process::Command {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut command = std::process::Command::new(shell);
+    command.args([flag, body]);
+    command
+}
+
+fn probe_enigo() -> Result<(), String> {
+    if cfg!(target_os = "linux")
+        && std::env::var_os("DISPLAY").is_none()
+        && std::env::var_os("WAYLAND_DISPLAY").is_none()
+    {
+        return Err("no display server found (DISPLAY and WAYLAND_DISPLAY are unset)".into());
     }
+    Ok(())
+}
 
-    #[tokio::test]
-    async fn midi_event_dispatches_macro() {
-        let cache = sample_cache();
-        let mut executor = Executor::new(Arc::new(MockSender));
-        executor.apply_cache(&cache);
-        let event = MidiEvent {
-            note: 60,
+/// Live executor state that is not part of the cache, for persisting across restarts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutorState {
+    pub active_profile: Option<String>,
+    /// `(device_id, widget_id)` of every latching widget currently latched on.
+    pub latched_widgets: Vec<(String, String)>,
+    /// `(device_id, page_name)` of every device's active page.
+    #[serde(default)]
+    pub active_pages: Vec<(String, String)>,
+    /// See [`Executor::set_enabled`].
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+    /// Every variable a `SetVar` step has set.
+    #[serde(default)]
+    pub variables: BTreeMap<String, i64>,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+impl Default for ExecutorState {
+    fn default() -> Self {
+        Self {
+            active_profile: None,
+            latched_widgets: Vec::new(),
+            active_pages: Vec::new(),
+            enabled: enabled_by_default(),
+            variables: BTreeMap::new(),
+        }
+    }
+}
+
+/// Where a macro run is, for highlighting the running step in a UI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecProgress {
+    Started {
+        id: String,
+    },
+    /// Step `index` of macro `id` is about to run. `id` names a hook or fallback
+    /// while one of those runs; nested `RunMacro` steps are not reported.
+    Step {
+        id: String,
+        index: usize,
+    },
+    /// `ok` is false when the run logged an error, timed out or was cancelled.
+    Finished {
+        id: String,
+        ok: bool,
+    },
+}
+
+/// How many [`ExecutionRecord`]s an executor keeps unless told otherwise.
+pub const DEFAULT_HISTORY_CAPACITY: usize = 128;
+
+/// One finished macro run, direct or in the background, for recent-activity views.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecutionRecord {
+    pub macro_id: String,
+    pub started_at: SystemTime,
+    pub action_count: usize,
+    /// False when the run logged an [`ActionLog::Error`], timed out or was
+    /// cancelled, even if `on_error` let it continue or ran a fallback.
+    pub success: bool,
+}
+
+/// The most recent runs, oldest first, at most `capacity` of them. Shared with
+/// background runs, which record themselves when they end.
+#[derive(Debug)]
+struct History {
+    records: VecDeque<ExecutionRecord>,
+    capacity: usize,
+}
+
+impl History {
+    fn push(&mut self, record: ExecutionRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+}
+
+/// A widget's actions plus its latch and double-tap state.
+#[derive(Debug)]
+struct WidgetBinding {
+    mode: Option<ActionMode>,
+    action: Option<WidgetAction>,
+    off_action: Option<WidgetAction>,
+    latched: bool,
+    double_tap_action: Option<WidgetAction>,
+    double_tap_window: Duration,
+    /// First press of a possible double tap, not yet acted on.
+    pending_tap: Option<Instant>,
+}
+
+impl WidgetBinding {
+    /// Action for a single press. Latching widgets alternate between `action` and
+    /// `off_action`; every other mode runs `action`.
+    fn press_action(&mut self) -> Option<WidgetAction> {
+        match self.mode {
+            Some(ActionMode::Latching) => {
+                self.latched = !self.latched;
+                if self.latched {
+                    self.action.clone()
+                } else {
+                    self.off_action.clone()
+                }
+            }
+            Some(ActionMode::Momentary) | None => self.action.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Executor<T: KeySender + 'static> {
+    macros: Arc<HashMap<String, MacroEntry>>,
+    scripts: Arc<HashMap<String, ScriptEntry>>,
+    /// Keyed by trigger and channel; a `None` channel matches any channel.
+    triggers: HashMap<(TriggerKey, Option<u8>), String>,
+    /// Consulted only when no exact trigger matches, in [`NoteRangeTrigger::rank`] order.
+    note_ranges: Vec<NoteRangeTrigger>,
+    chords: Vec<ChordWatch>,
+    /// Press time of every note currently down, keyed by `(channel, note)`.
+    held_notes: HashMap<(u8, u8), Instant>,
+    /// Last value of every controller seen, keyed by `(channel, controller)`.
+    cc_values: HashMap<(u8, u8), u8>,
+    bend_watches: Vec<ThresholdWatch>,
+    /// Keyed by `(device_id, page_index, widget_id)`; only the active page's respond.
+    widgets: HashMap<(String, usize, String), WidgetBinding>,
+    layers: LayerManager,
+    hooks: ExecutionHooks,
+    profiles: HashMap<String, Vec<String>>,
+    /// When set, only this profile's macros respond to MIDI triggers.
+    active_profile: Option<String>,
+    /// Log of the last run [`Executor::execute_macro`] finished; background runs
+    /// keep their own log, returned by [`Executor::join_macro`].
+    pub last_actions: Vec<ActionLog>,
+    key_sender: Arc<T>,
+    mouse_sender: Arc<dyn MouseSender>,
+    script_runner: Arc<dyn ScriptRunner>,
+    skip_pauses: bool,
+    /// `global.defaults.tap_hold_timeout_ms` from the applied cache.
+    tap_hold_timeout: Duration,
+    /// `global.defaults.allow_commands` from the applied cache.
+    allow_commands: bool,
+    /// Held by `Atomic` steps so their key output never interleaves with another run.
+    send_lock: Arc<Mutex<()>>,
+    /// Source that `WaitForMidi` steps listen on; without one they time out at once.
+    midi_events: Option<broadcast::Sender<MidiEvent>>,
+    /// Destination for `MidiOut` steps; without one they are only logged.
+    midi_out: Option<Arc<MidiOutManager>>,
+    /// Keys pressed by `KeyDown` steps and not yet released, oldest first.
+    held_keys: Arc<std::sync::Mutex<Vec<String>>>,
+    /// Values written by `SetVar` steps and read by `If` steps; kept across reloads.
+    variables: Arc<std::sync::Mutex<HashMap<String, i64>>>,
+    /// Receives [`ExecProgress`] for every run; without one nothing is reported.
+    progress: Option<broadcast::Sender<ExecProgress>>,
+    retrigger_policy: RetriggerPolicy,
+    run_in_background: bool,
+    /// Velocity of the event being dispatched, for `VelocityGate` steps.
+    event_velocity: Option<u8>,
+    /// Note of the note-on being dispatched, e.g. the pad a range trigger saw.
+    event_note: Option<u8>,
+    /// Background runs by macro id.
+    running: HashMap<String, RunningMacro>,
+    /// Draws `RandomPause` durations; seed it for reproducible runs.
+    rng: Arc<std::sync::Mutex<StdRng>>,
+    /// Cleared to ignore MIDI triggers; shared so it can flip without the executor lock.
+    enabled: Arc<AtomicBool>,
+    history: Arc<std::sync::Mutex<History>>,
+    /// Last triggered start of each macro with a `cooldown_ms`.
+    last_fired: HashMap<String, Instant>,
+}
+
+impl<T: KeySender + 'static> Executor<T> {
+    pub fn new(key_sender: Arc<T>) -> Self {
+        Self {
+            macros: Arc::default(),
+            scripts: Arc::default(),
+            triggers: HashMap::new(),
+            note_ranges: Vec::new(),
+            chords: Vec::new(),
+            held_notes: HashMap::new(),
+            cc_values: HashMap::new(),
+            bend_watches: Vec::new(),
+            widgets: HashMap::new(),
+            layers: LayerManager::new(),
+            hooks: ExecutionHooks::default(),
+            profiles: HashMap::new(),
+            active_profile: None,
+            last_actions: Vec::new(),
+            key_sender,
+            mouse_sender: Arc::new(DefaultMouseSender::default()),
+            script_runner: Arc::new(DefaultScriptRunner::default()),
+            skip_pauses: false,
+            tap_hold_timeout: Duration::from_millis(cache_format::DEFAULT_TAP_HOLD_TIMEOUT_MS),
+            allow_commands: false,
+            send_lock: Arc::new(Mutex::new(())),
+            midi_events: None,
+            midi_out: None,
+            held_keys: Arc::default(),
+            variables: Arc::default(),
+            progress: None,
+            retrigger_policy: RetriggerPolicy::default(),
+            run_in_background: false,
+            event_velocity: None,
+            event_note: None,
+            running: HashMap::new(),
+            rng: Arc::new(std::sync::Mutex::new(StdRng::from_entropy())),
+            enabled: Arc::new(AtomicBool::new(true)),
+            history: Arc::new(std::sync::Mutex::new(History {
+                records: VecDeque::new(),
+                capacity: DEFAULT_HISTORY_CAPACITY,
+            })),
+            last_fired: HashMap::new(),
+        }
+    }
+
+    pub fn set_mouse_sender(&mut self, mouse_sender: Arc<dyn MouseSender>) {
+        self.mouse_sender = mouse_sender;
+    }
+
+    pub fn set_script_runner(&mut self, script_runner: Arc<dyn ScriptRunner>) {
+        self.script_runner = script_runner;
+    }
+
+    pub fn set_midi_output(&mut self, midi_out: Arc<MidiOutManager>) {
+        self.midi_out = Some(midi_out);
+    }
+
+    /// Reports every run's [`ExecProgress`] to `progress`.
+    pub fn set_progress_sender(&mut self, progress: broadcast::Sender<ExecProgress>) {
+        self.progress = Some(progress);
+    }
+
+    /// Lets `WaitForMidi` steps observe incoming MIDI from `events`.
+    pub fn set_midi_source(&mut self, events: broadcast::Sender<MidiEvent>) {
+        self.midi_events = Some(events);
+    }
+
+    pub fn send_lock(&self) -> Arc<Mutex<()>> {
+        self.send_lock.clone()
+    }
+
+    /// Shares an atomic-step lock with other executors driving the same output.
+    pub fn set_send_lock(&mut self, lock: Arc<Mutex<()>>) {
+        self.send_lock = lock;
+    }
+
+    /// How long a press must be held before it counts as a hold rather than a tap.
+    pub fn tap_hold_timeout(&self) -> Duration {
+        self.tap_hold_timeout
+    }
+
+    /// Current value of a `SetVar` variable; 0 if nothing has set it.
+    pub fn variable(&self, name: &str) -> i64 {
+        lock_variables(&self.variables)
+            .get(name)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Log pauses without sleeping, for deterministic dry runs.
+    pub fn set_skip_pauses(&mut self, skip: bool) {
+        self.skip_pauses = skip;
+    }
+
+    /// While disabled, [`Executor::execute_midi_event`] ignores every event.
+    /// Direct [`Executor::execute_macro`] calls still run.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// The flag behind [`Executor::set_enabled`], for toggling it without locking
+    /// the executor.
+    pub fn enabled_flag(&self) -> Arc<AtomicBool> {
+        self.enabled.clone()
+    }
+
+    /// Reseeds the generator behind `RandomPause` steps so their durations repeat.
+    pub fn set_random_seed(&mut self, seed: u64) {
+        *self
+            .rng
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = StdRng::seed_from_u64(seed);
+    }
+
+    /// Id of the macro `event` would fire, if any. Threshold and chord triggers,
+    /// and the control-change edge, depend on earlier events and are only
+    /// resolved by [`Executor::resolve_event`].
+    /// A trigger bound to the event's channel wins over a channel-less one, and
+    /// exact triggers win over note ranges.
+    pub fn macro_for_event(&self, event: &MidiEvent) -> Option<&str> {
+        let key = TriggerKey::for_event(event)?;
+        self.triggers
+            .get(&(key, Some(event.channel)))
+            .or_else(|| self.triggers.get(&(key, None)))
+            .or_else(|| {
+                self.note_ranges
+                    .iter()
+                    .find(|range| range.matches(event))
+                    .map(|range| &range.macro_id)
+            })
+            .map(String::as_str)
+    }
+
+    /// Id of the macro `event` fires, updating threshold-crossing and held-note state.
+    /// A note-on completing a chord fires the chord instead of the note's own trigger.
+    /// A control change fires only when its controller leaves 0, so a button
+    /// release or a knob sweep fires once rather than on every step.
+    pub fn resolve_event(&mut self, event: &MidiEvent) -> Option<String> {
+        match event.kind {
+            MidiEventKind::NoteOn => {
+                let now = Instant::now();
+                self.held_notes.insert((event.channel, event.note), now);
+                self.complete_chord(event.channel, event.note, now)
+                    .or_else(|| self.macro_for_event(event).map(str::to_string))
+            }
+            MidiEventKind::NoteOff => {
+                self.release_note(event.channel, event.note);
+                None
+            }
+            MidiEventKind::ControlChange => {
+                let previous = self
+                    .cc_values
+                    .insert((event.channel, event.note), event.velocity);
+                if event.velocity == 0 || previous.is_some_and(|value| value != 0) {
+                    return None;
+                }
+                self.macro_for_event(event).map(str::to_string)
+            }
+            MidiEventKind::PitchBend(value) => {
+                let mut fired = None;
+                let channel = event.channel;
+                for watch in &mut self.bend_watches {
+                    if watch.channel.is_some_and(|ch| ch != channel) {
+                        continue;
+                    }
+                    if watch.observe(value) && fired.is_none() {
+                        fired = Some(watch.macro_id.clone());
+                    }
+                }
+                fired
+            }
+            _ => self.macro_for_event(event).map(str::to_string),
+        }
+    }
+
+    /// First unfired chord that `note` completes: all its notes held, none pressed
+    /// longer than the chord's window before `now`.
+    fn complete_chord(&mut self, channel: u8, note: u8, now: Instant) -> Option<String> {
+        let held = &self.held_notes;
+        let chord = self.chords.iter_mut().find(|chord| {
+            !chord.fired
+                && chord.listens_to(channel, note)
+                && chord.notes.iter().all(|note| {
+                    held.get(&(channel, *note))
+                        .is_some_and(|pressed| now.duration_since(*pressed) <= chord.window)
+                })
+        })?;
+        chord.fired = true;
+        Some(chord.macro_id.clone())
+    }
+
+    /// Forgets a released note and re-arms every chord that used it.
+    fn release_note(&mut self, channel: u8, note: u8) {
+        self.held_notes.remove(&(channel, note));
+        for chord in &mut self.chords {
+            if chord.listens_to(channel, note) {
+                chord.fired = false;
+            }
+        }
+    }
+
+    pub fn snapshot_state(&self) -> ExecutorState {
+        let mut latched_widgets: Vec<_> = self
+            .widgets
+            .iter()
+            .filter(|(_, binding)| binding.latched)
+            .map(|((device, _, widget), _)| (device.clone(), widget.clone()))
+            .collect();
+        latched_widgets.sort();
+        latched_widgets.dedup();
+        ExecutorState {
+            active_profile: self.active_profile.clone(),
+            latched_widgets,
+            active_pages: self.layers.active_pages(),
+            enabled: self.is_enabled(),
+            variables: lock_variables(&self.variables)
+                .iter()
+                .map(|(name, value)| (name.clone(), *value))
+                .collect(),
+        }
+    }
+
+    /// Reapplies a snapshot on top of the current cache. Entries that no longer
+    /// exist (a removed profile, widget or page) are dropped.
+    pub fn restore_state(&mut self, state: ExecutorState) {
+        self.active_profile = state
+            .active_profile
+            .filter(|name| self.profiles.contains_key(name));
+        for (device, page) in &state.active_pages {
+            self.layers.switch_to(device, page);
+        }
+        self.set_enabled(state.enabled);
+        *lock_variables(&self.variables) = state.variables.into_iter().collect();
+        for ((device, _, widget), binding) in &mut self.widgets {
+            binding.latched = binding.mode == Some(ActionMode::Latching)
+                && state
+                    .latched_widgets
+                    .iter()
+                    .any(|(d, w)| d == device && w == widget);
+        }
+        self.rebuild_triggers();
+    }
+
+    pub fn active_profile(&self) -> Option<&str> {
+        self.active_profile.as_deref()
+    }
+
+    /// Enables only the triggers of `name`'s macros. Returns false (leaving the
+    /// current set untouched) when no such profile exists.
+    pub fn activate_profile(&mut self, name: &str) -> bool {
+        if !self.profiles.contains_key(name) {
+            return false;
+        }
+        self.active_profile = Some(name.to_string());
+        self.rebuild_triggers();
+        true
+    }
+
+    /// Re-enables every macro's trigger.
+    pub fn clear_profile(&mut self) {
+        self.active_profile = None;
+        self.rebuild_triggers();
+    }
+
+    fn rebuild_triggers(&mut self) {
+        let members = self
+            .active_profile
+            .as_ref()
+            .and_then(|name| self.profiles.get(name));
+        self.triggers.clear();
+        self.note_ranges.clear();
+        self.chords.clear();
+        self.bend_watches.clear();
+        for entry in self.macros.values() {
+            if members.is_some_and(|ids| !ids.contains(&entry.id)) {
+                continue;
+            }
+            let Some(trigger) = entry.trigger.as_ref() else {
+                continue;
+            };
+            if let Some(crossing) = trigger
+                .threshold
+                .filter(|_| trigger.r#type == MidiTriggerType::PitchBend)
+            {
+                self.bend_watches.push(ThresholdWatch::new(
+                    crossing,
+                    trigger.channel,
+                    entry.id.clone(),
+                ));
+            }
+            match &trigger.r#type {
+                MidiTriggerType::NoteRange { low, high } => {
+                    self.note_ranges.push(NoteRangeTrigger {
+                        low: *low,
+                        high: *high,
+                        channel: trigger.channel,
+                        macro_id: entry.id.clone(),
+                    })
+                }
+                MidiTriggerType::Chord { notes, window_ms } => self.chords.push(ChordWatch {
+                    notes: notes.clone(),
+                    channel: trigger.channel,
+                    window: Duration::from_millis(*window_ms),
+                    macro_id: entry.id.clone(),
+                    fired: false,
+                }),
+                _ => {}
+            }
+            if let Some(key) = TriggerKey::for_trigger(trigger) {
+                self.triggers
+                    .insert((key, trigger.channel), entry.id.clone());
+            }
+        }
+        self.note_ranges.sort_by(|a, b| a.rank().cmp(&b.rank()));
+        self.chords.sort_by(|a, b| a.macro_id.cmp(&b.macro_id));
+        // Deterministic order when several watches cross on the same event.
+        self.bend_watches
+            .sort_by(|a, b| a.macro_id.cmp(&b.macro_id));
+    }
+
+    pub fn apply_cache(&mut self, cache: &CompiledCache) {
+        self.macros = Arc::new(
+            cache
+                .bundle
+                .macros
+                .iter()
+                .cloned()
+                .map(|entry| (entry.id.clone(), entry))
+                .collect(),
+        );
+        self.scripts = Arc::new(
+            cache
+                .bundle
+                .scripts
+                .iter()
+                .cloned()
+                .map(|script| (script.id.clone(), script))
+                .collect(),
+        );
+        self.hooks = cache.bundle.hooks.clone();
+        self.tap_hold_timeout = Duration::from_millis(cache.defaults().tap_hold_timeout_ms);
+        self.allow_commands = cache.defaults().allow_commands;
+        self.layers.apply_cache(cache);
+        // Toggles stay latched across a reload while their widget still exists.
+        let latched = self.snapshot_state().latched_widgets;
+        self.widgets.clear();
+        for device in &cache.bundle.devices {
+            let widgets =
+                device.pages.iter().enumerate().flat_map(|(index, page)| {
+                    page.widgets.iter().map(move |widget| (index, widget))
+                });
+            for (page_index, widget) in widgets {
+                // An explicit `mode` wins; otherwise `tap_behavior` picks one.
+                let mode = widget.mode.or(match widget.behavior {
+                    TapBehavior::Tap => None,
+                    TapBehavior::Hold => Some(ActionMode::Momentary),
+                    TapBehavior::Toggle => Some(ActionMode::Latching),
+                });
+                let was_latched = latched
+                    .iter()
+                    .any(|(d, w)| *d == device.id && *w == widget.id);
+                self.widgets
+                    .entry((device.id.clone(), page_index, widget.id.clone()))
+                    .or_insert_with(|| WidgetBinding {
+                        mode,
+                        action: widget.action.clone(),
+                        off_action: widget.off_action.clone(),
+                        latched: was_latched && mode == Some(ActionMode::Latching),
+                        double_tap_action: widget.double_tap_action.clone(),
+                        double_tap_window: Duration::from_millis(widget.double_tap_window_ms),
+                        pending_tap: None,
+                    });
+            }
+        }
+        self.profiles = cache
+            .bundle
+            .profiles
+            .iter()
+            .map(|profile| (profile.name.clone(), profile.macros.clone()))
+            .collect();
+        // A reload keeps the active profile as long as it still exists.
+        if self
+            .active_profile
+            .as_ref()
+            .is_some_and(|name| !self.profiles.contains_key(name))
+        {
+            self.active_profile = None;
+        }
+        self.rebuild_triggers();
+    }
+
+    /// Runs the macro `event` triggers. When that macro is a widget's `action`, the
+    /// event presses the widget instead, and the matching note-off releases it.
+    /// [`MidiEvent::trigger_velocity`] reaches `VelocityGate` steps, and a note-on's
+    /// note and velocity reach `RunCommand` steps through the environment.
+    pub async fn execute_midi_event(&mut self, event: MidiEvent) -> bool {
+        if !self.is_enabled() {
+            return false;
+        }
+        self.event_velocity = event.trigger_velocity();
+        self.event_note = (event.kind == MidiEventKind::NoteOn).then_some(event.note);
+        let ran = self.dispatch_midi_event(event).await;
+        self.event_velocity = None;
+        self.event_note = None;
+        ran
+    }
+
+    async fn dispatch_midi_event(&mut self, event: MidiEvent) -> bool {
+        if event.kind == MidiEventKind::NoteOff {
+            self.release_note(event.channel, event.note);
+            let press = MidiEvent {
+                kind: MidiEventKind::NoteOn,
+                ..event
+            };
+            let Some((device_id, widget_id)) = self
+                .macro_for_event(&press)
+                .and_then(|id| self.widget_for_macro(id))
+            else {
+                return false;
+            };
+            return self.release_widget(&device_id, &widget_id).await;
+        }
+        let Some(id) = self.resolve_event(&event) else {
+            return false;
+        };
+        match self.widget_for_macro(&id) {
+            Some((device_id, widget_id)) => self.press_widget(&device_id, &widget_id).await,
+            None => self.start_macro(&id).await,
+        }
+    }
+
+    /// First active-page widget (by device, then widget id) whose press action is
+    /// macro `id`.
+    fn widget_for_macro(&self, id: &str) -> Option<(String, String)> {
+        self.widgets
+            .iter()
+            .filter(|((device, page, _), binding)| {
+                self.layers.active_page(device) == Some(*page)
+                    && matches!(&binding.action, Some(WidgetAction::Macro { id: action }) if action == id)
+            })
+            .map(|((device, _, widget), _)| (device.clone(), widget.clone()))
+            .min()
+    }
+
+    /// Key of widget `widget_id` on `device_id`'s active page.
+    fn active_widget_key(
+        &self,
+        device_id: &str,
+        widget_id: &str,
+    ) -> Option<(String, usize, String)> {
+        let page = self.layers.active_page(device_id)?;
+        Some((device_id.to_string(), page, widget_id.to_string()))
+    }
+
+    /// Active page of every device, plus [`PageChanged`](crate::layers::PageChanged)
+    /// events for the UI and MIDI-out feedback.
+    pub fn layers(&self) -> &LayerManager {
+        &self.layers
+    }
+
+    /// Activates `page_name` on `device_id`, as a `PageSwitch` widget would.
+    pub fn switch_page(&mut self, device_id: &str, page_name: &str) -> bool {
+        self.layers.switch_to(device_id, page_name)
+    }
+
+    /// Handles a widget press. Latching widgets alternate between `action` and
+    /// `off_action`; every other mode runs `action`. Returns true if a macro ran or
+    /// the page switched. Widgets off their device's active page do not respond.
+    ///
+    /// Widgets with a `double_tap_action` hold the first press back: a second press
+    /// within the window runs the double-tap action, otherwise
+    /// [`Executor::fire_expired_taps`] runs the single tap once the window passes.
+    pub async fn press_widget(&mut self, device_id: &str, widget_id: &str) -> bool {
+        self.press_widget_at(device_id, widget_id, Instant::now())
+            .await
+    }
+
+    async fn press_widget_at(&mut self, device_id: &str, widget_id: &str, now: Instant) -> bool {
+        let Some(binding) = self
+            .active_widget_key(device_id, widget_id)
+            .and_then(|key| self.widgets.get_mut(&key))
+        else {
+            return false;
+        };
+        if binding.double_tap_action.is_some() {
+            match binding.pending_tap.replace(now) {
+                Some(first) if now.duration_since(first) <= binding.double_tap_window => {
+                    binding.pending_tap = None;
+                    let action = binding.double_tap_action.clone();
+                    return self.run_widget_action(action).await;
+                }
+                // A stale first press that was never flushed still counts as a single tap.
+                Some(_) => {}
+                None => return false,
+            }
+        }
+        let action = binding.press_action();
+        self.run_widget_action(action).await
+    }
+
+    /// Runs the single tap of every widget whose double-tap window passed without
+    /// a second press. Call this periodically; returns true if a macro ran.
+    pub async fn fire_expired_taps(&mut self) -> bool {
+        self.fire_expired_taps_at(Instant::now()).await
+    }
+
+    async fn fire_expired_taps_at(&mut self, now: Instant) -> bool {
+        let mut expired: Vec<_> = self
+            .widgets
+            .iter()
+            .filter(|(_, binding)| {
+                binding
+                    .pending_tap
+                    .is_some_and(|first| now.duration_since(first) > binding.double_tap_window)
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        expired.sort();
+        let mut ran = false;
+        for key in expired {
+            let Some(binding) = self.widgets.get_mut(&key) else {
+                continue;
+            };
+            binding.pending_tap = None;
+            let action = binding.press_action();
+            ran |= self.run_widget_action(action).await;
+        }
+        ran
+    }
+
+    /// Handles a widget release; only momentary widgets act on it.
+    pub async fn release_widget(&mut self, device_id: &str, widget_id: &str) -> bool {
+        let binding = self
+            .active_widget_key(device_id, widget_id)
+            .and_then(|key| self.widgets.get(&key));
+        let action = match binding {
+            Some(binding) if binding.mode == Some(ActionMode::Momentary) => {
+                binding.off_action.clone()
+            }
+            _ => None,
+        };
+        self.run_widget_action(action).await
+    }
+
+    async fn run_widget_action(&mut self, action: Option<WidgetAction>) -> bool {
+        match action {
+            Some(WidgetAction::Macro { id }) => self.start_macro(&id).await,
+            Some(WidgetAction::Script { id }) => self.start_script(&id).await,
+            Some(WidgetAction::PageSwitch { device, page }) => self.switch_page(&device, &page),
+            None => false,
+        }
+    }
+
+    /// Runs a bundled script on its own, outside any macro.
+    pub async fn execute_script(&mut self, id: &str) -> bool {
+        if !self.scripts.contains_key(id) {
+            return false;
+        }
+        self.last_actions.clear();
+        run_script(
+            self.script_runner.as_ref(),
+            &self.scripts,
+            id,
+            self.allow_commands,
+            &mut self.last_actions,
+        )
+        .await
+    }
+
+    /// Runs macro `id` as if an event with `velocity` had triggered it.
+    pub async fn execute_macro_at_velocity(&mut self, id: &str, velocity: u8) -> bool {
+        self.event_velocity = Some(velocity);
+        let ran = self.execute_macro(id).await;
+        self.event_velocity = None;
+        ran
+    }
+
+    /// Ids of the loaded macros, sorted.
+    pub fn macro_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.macros.keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Loaded macros tagged `tag`, sorted by id.
+    pub fn macros_with_tag(&self, tag: &str) -> Vec<&MacroEntry> {
+        let mut entries: Vec<_> = self
+            .macros
+            .values()
+            .filter(|entry| entry.tags.iter().any(|t| t == tag))
+            .collect();
+        entries.sort_by(|a, b| a.id.cmp(&b.id));
+        entries
+    }
+
+    pub async fn execute_macro(&mut self, id: &str) -> bool {
+        if !self.macros.contains_key(id) {
+            return false;
+        }
+        self.last_actions.clear();
+        let started_at = SystemTime::now();
+        let task = self.macro_task();
+        let ran = run_macro(&task.context(), &self.hooks, &mut self.last_actions, id).await;
+        lock_history(&self.history).push(ExecutionRecord {
+            macro_id: id.to_string(),
+            started_at,
+            action_count: self.last_actions.len(),
+            success: run_succeeded(&self.last_actions),
+        });
+        ran
+    }
+
+    /// Recent runs, direct and background, oldest first.
+    pub fn history(&self) -> Vec<ExecutionRecord> {
+        lock_history(&self.history)
+            .records
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    pub fn clear_history(&mut self) {
+        lock_history(&self.history).records.clear();
+    }
+
+    /// Keeps at most `capacity` records, dropping the oldest ones beyond it.
+    pub fn set_history_capacity(&mut self, capacity: usize) {
+        let mut history = lock_history(&self.history);
+        history.capacity = capacity;
+        while history.records.len() > capacity {
+            history.records.pop_front();
+        }
+    }
+
+    /// Starts macro `id` as a background task, applying the retrigger policy when a
+    /// run of it is still in flight. Returns false for an unknown macro or an
+    /// ignored retrigger. [`Executor::join_macro`] collects the run's log; a
+    /// restarted run's log is discarded.
+    pub fn trigger_macro(&mut self, id: &str) -> bool {
+        if !self.macros.contains_key(id) {
+            return false;
+        }
+        self.running.retain(|_, run| !run.handle.is_finished());
+        let previous = self.running.remove(id);
+        let (cancel, previous) = match (previous, self.retrigger_policy) {
+            (None, _) => (CancellationToken::new(), None),
+            (Some(run), RetriggerPolicy::Ignore) => {
+                self.running.insert(id.to_string(), run);
+                return false;
+            }
+            (Some(run), RetriggerPolicy::Restart) => {
+                run.cancel.cancel();
+                (CancellationToken::new(), None)
+            }
+            // Queued runs share the token, so cancelling the macro drops them too.
+            (Some(run), RetriggerPolicy::Queue) => (run.cancel, Some(run.handle)),
+        };
+        let task = self.macro_task();
+        let hooks = self.hooks.clone();
+        let history = self.history.clone();
+        let token = cancel.clone();
+        let macro_id = id.to_string();
+        let handle = tokio::spawn(async move {
+            let mut log = Vec::new();
+            let mut started_at = SystemTime::now();
+            let cancelled = {
+                let run = async {
+                    if let Some(previous) = previous {
+                        let _ = previous.await;
+                        started_at = SystemTime::now();
+                    }
+                    run_macro(&task.context(), &hooks, &mut log, &macro_id).await;
+                };
+                // Checked first, so a queued run never starts once cancelled.
+                tokio::select! {
+                    biased;
+                    _ = token.cancelled() => true,
+                    _ = run => false,
+                }
+            };
+            if cancelled {
+                reset(&task.context(), &mut log).await;
+                if let Some(progress) = &task.progress {
+                    let _ = progress.send(ExecProgress::Finished {
+                        id: macro_id.clone(),
+                        ok: false,
+                    });
+                }
+                log.push(ActionLog::Cancelled(macro_id.clone()));
+            }
+            lock_history(&history).push(ExecutionRecord {
+                macro_id,
+                started_at,
+                action_count: log.len(),
+                success: !cancelled && run_succeeded(&log),
+            });
+            log
+        });
+        self.running
+            .insert(id.to_string(), RunningMacro { cancel, handle });
+        true
+    }
+
+    /// True while a background run of `id` (or one queued behind it) is in flight.
+    pub fn is_running(&self, id: &str) -> bool {
+        self.running
+            .get(id)
+            .is_some_and(|run| !run.handle.is_finished())
+    }
+
+    /// Waits for the background run of `id` to end and returns its action log.
+    pub async fn join_macro(&mut self, id: &str) -> Option<Vec<ActionLog>> {
+        let run = self.running.remove(id)?;
+        run.handle.await.ok()
+    }
+
+    /// Stops the background run of `id`, along with any runs queued behind it.
+    /// Returns false if none was in flight.
+    pub fn cancel_macro(&mut self, id: &str) -> bool {
+        match self.running.get(id) {
+            Some(run) if !run.handle.is_finished() => {
+                run.cancel.cancel();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Stops every background run.
+    pub fn cancel_all(&mut self) {
+        for run in self.running.values() {
+            run.cancel.cancel();
+        }
+    }
+
+    /// Waits up to `timeout` for every background run to end, then cancels those
+    /// still going. Returns false if any had to be cancelled.
+    pub async fn wait_for_running(&mut self, timeout: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut finished = true;
+        for (_, mut run) in self.running.drain() {
+            if tokio::time::timeout_at(deadline, &mut run.handle)
+                .await
+                .is_err()
+            {
+                run.cancel.cancel();
+                let _ = run.handle.await;
+                finished = false;
+            }
+        }
+        finished
+    }
+
+    /// Releases every key `KeyDown` steps still hold and sends all-notes-off, as a
+    /// `Reset` step would. Returns what was done.
+    pub async fn release_held_keys(&self) -> Vec<ActionLog> {
+        let mut log = Vec::new();
+        reset(&self.macro_task().context(), &mut log).await;
+        log
+    }
+
+    pub fn retrigger_policy(&self) -> RetriggerPolicy {
+        self.retrigger_policy
+    }
+
+    pub fn set_retrigger_policy(&mut self, policy: RetriggerPolicy) {
+        self.retrigger_policy = policy;
+    }
+
+    /// Runs triggered macros as background tasks via [`Executor::trigger_macro`],
+    /// so a later trigger can restart or cancel them. Off by default: each macro
+    /// runs to completion before the triggering call returns, as dry runs need.
+    pub fn set_run_in_background(&mut self, background: bool) {
+        self.run_in_background = background;
+    }
+
+    /// Runs macro `id` the way triggers and widgets do: in the background when
+    /// enabled, otherwise to completion. A re-trigger within the macro's
+    /// `cooldown_ms` is ignored.
+    async fn start_macro(&mut self, id: &str) -> bool {
+        if !self.cooldown_elapsed(id, Instant::now()) {
+            return false;
+        }
+        if self.run_in_background {
+            self.trigger_macro(id)
+        } else {
+            self.execute_macro(id).await
+        }
+    }
+
+    /// False while macro `id` is within `cooldown_ms` of its last triggered start;
+    /// otherwise records `now` as that start.
+    fn cooldown_elapsed(&mut self, id: &str, now: Instant) -> bool {
+        let Some(cooldown) = self.macros.get(id).and_then(|entry| entry.cooldown_ms) else {
+            return true;
+        };
+        if let Some(last) = self.last_fired.get(id) {
+            if now.duration_since(*last) < Duration::from_millis(cooldown) {
+                return false;
+            }
+        }
+        self.last_fired.insert(id.to_string(), now);
+        true
+    }
+
+    /// Script counterpart to [`Executor::start_macro`]. Background scripts are not
+    /// subject to the retrigger policy and cannot be cancelled.
+    async fn start_script(&mut self, id: &str) -> bool {
+        if !self.run_in_background {
+            return self.execute_script(id).await;
+        }
+        if !self.scripts.contains_key(id) {
+            return false;
+        }
+        let runner = self.script_runner.clone();
+        let scripts = self.scripts.clone();
+        let allow_commands = self.allow_commands;
+        let id = id.to_string();
+        tokio::spawn(async move {
+            run_script(
+                runner.as_ref(),
+                &scripts,
+                &id,
+                allow_commands,
+                &mut Vec::new(),
+            )
+            .await;
+        });
+        true
+    }
+
+    fn macro_task(&self) -> MacroTask<T> {
+        MacroTask {
+            key_sender: self.key_sender.clone(),
+            mouse_sender: self.mouse_sender.clone(),
+            script_runner: self.script_runner.clone(),
+            skip_pauses: self.skip_pauses,
+            allow_commands: self.allow_commands,
+            send_lock: self.send_lock.clone(),
+            midi_events: self.midi_events.clone(),
+            midi_out: self.midi_out.clone(),
+            held_keys: self.held_keys.clone(),
+            variables: self.variables.clone(),
+            progress: self.progress.clone(),
+            macros: self.macros.clone(),
+            scripts: self.scripts.clone(),
+            velocity: self.event_velocity,
+            note: self.event_note,
+            rng: self.rng.clone(),
+        }
+    }
+}
+
+/// Runs macro `id` wrapped in the global hooks, subject to its `max_duration_ms`
+/// and `on_error` policy. Returns false if `id` is unknown.
+async fn run_macro<T: KeySender>(
+    ctx: &StepContext<'_, T>,
+    hooks: &ExecutionHooks,
+    log: &mut Vec<ActionLog>,
+    id: &str,
+) -> bool {
+    let macros = ctx.macros;
+    let Some(entry) = macros.get(id) else {
+        return false;
+    };
+    // Hook macros are never wrapped themselves, so they cannot recurse.
+    let is_hook = [&hooks.on_before, &hooks.on_after]
+        .into_iter()
+        .any(|hook| hook.as_deref() == Some(id));
+    let hook_entry = |hook: &Option<String>| {
+        hook.as_ref()
+            .filter(|_| !is_hook)
+            .and_then(|hook_id| macros.get(hook_id))
+    };
+    let before = hook_entry(&hooks.on_before);
+    let after = hook_entry(&hooks.on_after);
+    let report = |progress: ExecProgress| {
+        if let Some(sender) = ctx.progress {
+            let _ = sender.send(progress);
+        }
+    };
+    report(ExecProgress::Started { id: id.to_string() });
+    let start = log.len();
+
+    for entry in before.into_iter().chain([entry]).chain(after) {
+        let continue_on_error = entry.on_error == Some(ErrorPolicy::Continue);
+        let run = async {
+            // A velocity gate ends the macro without counting as a failure.
+            if run_reported_steps(ctx, log, &entry.id, &entry.steps, continue_on_error).await
+                || matches!(log.last(), Some(ActionLog::VelocityGated(_)))
+            {
+                return;
+            }
+            // Fallbacks run with the default abort policy so they cannot chain.
+            if let Some(ErrorPolicy::RunMacro(fallback_id)) = &entry.on_error {
+                if let Some(fallback) = macros.get(fallback_id) {
+                    log.push(ActionLog::Fallback(fallback_id.clone()));
+                    run_reported_steps(ctx, log, fallback_id, &fallback.steps, false).await;
+                }
+            }
+        };
+        match entry.max_duration_ms {
+            Some(limit) => {
+                let limit = std::time::Duration::from_millis(limit);
+                if tokio::time::timeout(limit, run).await.is_err() {
+                    log.push(ActionLog::TimedOut(entry.id.clone()));
+                }
+            }
+            None => run.await,
+        }
+    }
+    report(ExecProgress::Finished {
+        id: id.to_string(),
+        ok: run_succeeded(&log[start..]),
+    });
+    true
+}
+
+/// [`run_steps`] for the top-level steps of macro `id`, reporting each one as an
+/// [`ExecProgress::Step`] just before it runs.
+async fn run_reported_steps<T: KeySender>(
+    ctx: &StepContext<'_, T>,
+    log: &mut Vec<ActionLog>,
+    id: &str,
+    steps: &[MacroStep],
+    continue_on_error: bool,
+) -> bool {
+    let Some(progress) = ctx.progress else {
+        return run_steps(ctx, log, steps, continue_on_error, 0).await;
+    };
+    for (index, step) in steps.iter().enumerate() {
+        let _ = progress.send(ExecProgress::Step {
+            id: id.to_string(),
+            index,
+        });
+        if !run_steps(ctx, log, std::slice::from_ref(step), continue_on_error, 0).await {
+            return false;
+        }
+    }
+    true
+}
+
+/// Whether a run's log is free of errors and timeouts.
+fn run_succeeded(log: &[ActionLog]) -> bool {
+    !log.iter()
+        .any(|action| matches!(action, ActionLog::Error(_) | ActionLog::TimedOut(_)))
+}
+
+/// What a second trigger does while the macro's previous run is still in flight.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RetriggerPolicy {
+    /// Drop the new trigger.
+    Ignore,
+    /// Cancel the running macro and start it again.
+    Restart,
+    /// Start the new run once the current one ends.
+    #[default]
+    Queue,
+}
+
+/// A background macro run and the token that cancels it.
+#[derive(Debug)]
+struct RunningMacro {
+    cancel: CancellationToken,
+    handle: task::JoinHandle<Vec<ActionLog>>,
+}
+
+/// Owned inputs for running a macro outside the executor, e.g. on a spawned task.
+struct MacroTask<T: KeySender> {
+    key_sender: Arc<T>,
+    mouse_sender: Arc<dyn MouseSender>,
+    script_runner: Arc<dyn ScriptRunner>,
+    skip_pauses: bool,
+    allow_commands: bool,
+    send_lock: Arc<Mutex<()>>,
+    midi_events: Option<broadcast::Sender<MidiEvent>>,
+    midi_out: Option<Arc<MidiOutManager>>,
+    held_keys: Arc<std::sync::Mutex<Vec<String>>>,
+    variables: Arc<std::sync::Mutex<HashMap<String, i64>>>,
+    progress: Option<broadcast::Sender<ExecProgress>>,
+    macros: Arc<HashMap<String, MacroEntry>>,
+    scripts: Arc<HashMap<String, ScriptEntry>>,
+    velocity: Option<u8>,
+    note: Option<u8>,
+    rng: Arc<std::sync::Mutex<StdRng>>,
+}
+
+impl<T: KeySender> MacroTask<T> {
+    fn context(&self) -> StepContext<'_, T> {
+        StepContext {
+            key_sender: self.key_sender.as_ref(),
+            mouse_sender: self.mouse_sender.as_ref(),
+            skip_pauses: self.skip_pauses,
+            allow_commands: self.allow_commands,
+            send_lock: &self.send_lock,
+            holds_send_lock: false,
+            midi_events: self.midi_events.as_ref(),
+            midi_out: self.midi_out.as_deref(),
+            held_keys: &self.held_keys,
+            variables: &self.variables,
+            progress: self.progress.as_ref(),
+            macros: &self.macros,
+            script_runner: self.script_runner.as_ref(),
+            scripts: &self.scripts,
+            velocity: self.velocity,
+            note: self.note,
+            rng: &self.rng,
+        }
+    }
+}
+
+/// Shared, read-only inputs for running a macro's steps.
+struct StepContext<'a, T: KeySender> {
+    key_sender: &'a T,
+    mouse_sender: &'a dyn MouseSender,
+    skip_pauses: bool,
+    allow_commands: bool,
+    send_lock: &'a Mutex<()>,
+    /// Set inside an `Atomic` group, whose run already holds `send_lock`; nested
+    /// groups and sends reuse it instead of waiting on themselves.
+    holds_send_lock: bool,
+    midi_events: Option<&'a broadcast::Sender<MidiEvent>>,
+    midi_out: Option<&'a MidiOutManager>,
+    /// Keys `KeyDown` steps hold, shared by every run so `Reset` releases them all.
+    held_keys: &'a std::sync::Mutex<Vec<String>>,
+    /// Variable store of `SetVar` and `If` steps, shared by every run.
+    variables: &'a std::sync::Mutex<HashMap<String, i64>>,
+    progress: Option<&'a broadcast::Sender<ExecProgress>>,
+    /// Callees for `RunMacro` steps.
+    macros: &'a HashMap<String, MacroEntry>,
+    script_runner: &'a dyn ScriptRunner,
+    scripts: &'a HashMap<String, ScriptEntry>,
+    /// Velocity of the triggering event; `None` for runs nothing triggered.
+    velocity: Option<u8>,
+    /// Note of the triggering note-on, if a note-on triggered the run.
+    note: Option<u8>,
+    rng: &'a std::sync::Mutex<StdRng>,
+}
+
+/// Runs script `id`, logging the outcome; returns false if it is missing, fails
+/// or is refused. Scripts run as shell commands, so like `RunCommand` steps they
+/// need `allow_commands`.
+async fn run_script(
+    runner: &dyn ScriptRunner,
+    scripts: &HashMap<String, ScriptEntry>,
+    id: &str,
+    allow_commands: bool,
+    log: &mut Vec<ActionLog>,
+) -> bool {
+    let Some(script) = scripts.get(id) else {
+        log.push(ActionLog::Error(format!("Script `{id}` is not compiled")));
+        return false;
+    };
+    if !allow_commands {
+        log.push(ActionLog::Error(format!(
+            "Script `{id}` refused: `global.defaults.allow_commands` is not set"
+        )));
+        return false;
+    }
+    match runner.run_script(script).await {
+        Ok(()) => {
+            log.push(ActionLog::Script(id.to_string()));
+            true
+        }
+        Err(reason) => {
+            log.push(ActionLog::Error(format!("Script `{id}` failed: {reason}")));
+            false
+        }
+    }
+}
+
+/// Runs `program` directly with `args` and waits for it, logging the outcome;
+/// returns false when commands are disabled, it cannot start or it exits unsuccessfully.
+/// The triggering note and velocity, when known, are passed as `MIDIMACROS_NOTE`
+/// and `MIDIMACROS_VELOCITY`.
+async fn run_command<T: KeySender>(
+    ctx: &StepContext<'_, T>,
+    program: &str,
+    args: &[String],
+    log: &mut Vec<ActionLog>,
+) -> bool {
+    if !ctx.allow_commands {
+        log.push(ActionLog::Error(format!(
+            "Command `{program}` refused: `global.defaults.allow_commands` is not set"
+        )));
+        return false;
+    }
+    let mut command = tokio::process::Command::new(program);
+    command.args(args);
+    if let Some(note) = ctx.note {
+        command.env("MIDIMACROS_NOTE", note.to_string());
+    }
+    if let Some(velocity) = ctx.velocity {
+        command.env("MIDIMACROS_VELOCITY", velocity.to_string());
+    }
+    match command.status().await {
+        Ok(status) if status.success() => {
+            log.push(ActionLog::Command(program.to_string()));
+            true
+        }
+        Ok(status) => {
+            log.push(ActionLog::Error(format!(
+                "Command `{program}` exited with {status}"
+            )));
+            false
+        }
+        Err(err) => {
+            log.push(ActionLog::Error(format!(
+                "Command `{program}` failed to start: {err}"
+            )));
+            false
+        }
+    }
+}
+
+/// Controller number of the MIDI channel-mode message "all notes off".
+const ALL_NOTES_OFF: u8 = 123;
+
+fn lock_held(held_keys: &std::sync::Mutex<Vec<String>>) -> std::sync::MutexGuard<'_, Vec<String>> {
+    held_keys
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn lock_history(history: &std::sync::Mutex<History>) -> std::sync::MutexGuard<'_, History> {
+    history
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn lock_variables(
+    variables: &std::sync::Mutex<HashMap<String, i64>>,
+) -> std::sync::MutexGuard<'_, HashMap<String, i64>> {
+    variables
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Logs a failed send. Like `MidiOut` feedback, input is best-effort: the macro
+/// goes on.
+fn log_send_error(sent: Result<(), String>, log: &mut Vec<ActionLog>) {
+    if let Err(err) = sent {
+        log.push(ActionLog::Error(err));
+    }
+}
+
+/// Takes `send_lock` for a single send, so it cannot land inside another run's
+/// `Atomic` group. `None` when this run already holds the lock.
+async fn send_guard<'a, T: KeySender>(ctx: &StepContext<'a, T>) -> Option<MutexGuard<'a, ()>> {
+    if ctx.holds_send_lock {
+        None
+    } else {
+        Some(ctx.send_lock.lock().await)
+    }
+}
+
+/// Releases every held key, newest first, then sends all-notes-off on each
+/// channel when a MIDI output is wired.
+async fn reset<T: KeySender>(ctx: &StepContext<'_, T>, log: &mut Vec<ActionLog>) {
+    let held = std::mem::take(&mut *lock_held(ctx.held_keys));
+    let guard = send_guard(ctx).await;
+    for key in held.iter().rev() {
+        let sent = ctx.key_sender.key_up(key).await;
+        log.push(ActionLog::KeyUp(key.clone()));
+        log_send_error(sent, log);
+    }
+    drop(guard);
+    log.push(ActionLog::Reset);
+    if let Some(out) = ctx.midi_out {
+        // Feedback is best-effort; one error is enough to report a closed port.
+        if let Some(Err(err)) = (0..16u8)
+            .map(|channel| out.send(&[0xB0 | channel, ALL_NOTES_OFF, 0]))
+            .find(Result::is_err)
+        {
+            log.push(ActionLog::Error(err.to_string()));
+        }
+    }
+}
+
+/// How deeply `RunMacro` steps may nest before the call is refused.
+const MAX_CALL_DEPTH: usize = 16;
+
+/// Runs `steps`, returning false if a failing step stopped the macro. With
+/// `continue_on_error` a failure is logged and the remaining steps still run.
+/// `depth` counts the `RunMacro` calls leading here.
+fn run_steps<'a, T: KeySender>(
+    ctx: &'a StepContext<'a, T>,
+    log: &'a mut Vec<ActionLog>,
+    steps: &'a [MacroStep],
+    continue_on_error: bool,
+    depth: usize,
+) -> BoxFuture<'a, bool> {
+    async move {
+        for step in steps {
+            match step {
+                MacroStep::Keystroke { keys } => {
+                    let _guard = send_guard(ctx).await;
+                    let sent = ctx.key_sender.send_keystroke(keys).await;
+                    log.push(ActionLog::Keystroke(keys.clone()));
+                    log_send_error(sent, log);
+                }
+                MacroStep::KeyDown { key } => {
+                    let _guard = send_guard(ctx).await;
+                    let sent = ctx.key_sender.key_down(key).await;
+                    let mut held = lock_held(ctx.held_keys);
+                    if !held.contains(key) {
+                        held.push(key.clone());
+                    }
+                    drop(held);
+                    log.push(ActionLog::KeyDown(key.clone()));
+                    log_send_error(sent, log);
+                }
+                MacroStep::KeyUp { key } => {
+                    let _guard = send_guard(ctx).await;
+                    let sent = ctx.key_sender.key_up(key).await;
+                    lock_held(ctx.held_keys).retain(|held| held != key);
+                    log.push(ActionLog::KeyUp(key.clone()));
+                    log_send_error(sent, log);
+                }
+                MacroStep::Reset => reset(ctx, log).await,
+                MacroStep::SetVar { name, value } => {
+                    lock_variables(ctx.variables).insert(name.clone(), *value);
+                    log.push(ActionLog::SetVar(name.clone(), *value));
+                }
+                MacroStep::If {
+                    var,
+                    equals,
+                    then,
+                    else_,
+                } => {
+                    let value = lock_variables(ctx.variables)
+                        .get(var)
+                        .copied()
+                        .unwrap_or(0);
+                    let branch = if value == *equals { then } else { else_ };
+                    if !run_steps(ctx, log, branch, continue_on_error, depth).await {
+                        return false;
+                    }
+                }
+                MacroStep::Text { text } => {
+                    let _guard = send_guard(ctx).await;
+                    let sent = ctx.key_sender.type_text(text).await;
+                    log.push(ActionLog::Text(text.clone()));
+                    log_send_error(sent, log);
+                }
+                MacroStep::MouseMove { x, y, relative } => {
+                    let _guard = send_guard(ctx).await;
+                    let sent = ctx.mouse_sender.move_mouse(*x, *y, *relative).await;
+                    log.push(ActionLog::MouseMove {
+                        x: *x,
+                        y: *y,
+                        relative: *relative,
+                    });
+                    log_send_error(sent, log);
+                }
+                MacroStep::MouseClick { button } => {
+                    let _guard = send_guard(ctx).await;
+                    let sent = ctx.mouse_sender.click(*button).await;
+                    log.push(ActionLog::MouseClick(*button));
+                    log_send_error(sent, log);
+                }
+                MacroStep::Pause { ms, label } => {
+                    log.push(ActionLog::Pause(*ms, label.clone()));
+                    if !ctx.skip_pauses {
+                        tokio::time::sleep(std::time::Duration::from_millis(*ms)).await;
+                    }
+                }
+                MacroStep::RandomPause { min_ms, max_ms } => {
+                    // The guard drops before sleeping. A reversed range (only possible
+                    // in drafts) is sampled as if its ends were swapped.
+                    let ms = ctx
+                        .rng
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .gen_range(*min_ms.min(max_ms)..=*max_ms.max(min_ms));
+                    log.push(ActionLog::Pause(ms, None));
+                    if !ctx.skip_pauses {
+                        tokio::time::sleep(std::time::Duration::from_millis(ms)).await;
+                    }
+                }
+                MacroStep::PauseMicros { us } => {
+                    log.push(ActionLog::PauseMicros(*us));
+                    if !ctx.skip_pauses {
+                        tokio::time::sleep(std::time::Duration::from_micros(*us)).await;
+                    }
+                }
+                MacroStep::Atomic { steps } => {
+                    let _guard = send_guard(ctx).await;
+                    let holding = StepContext {
+                        holds_send_lock: true,
+                        ..*ctx
+                    };
+                    if !run_steps(&holding, log, steps, continue_on_error, depth).await {
+                        return false;
+                    }
+                }
+                MacroStep::Repeat { count, steps } => {
+                    for _ in 0..*count {
+                        if !run_steps(ctx, log, steps, continue_on_error, depth).await {
+                            return false;
+                        }
+                    }
+                }
+                MacroStep::VelocityGate { min, max } => {
+                    if let Some(velocity) = ctx.velocity.filter(|v| !(min..=max).contains(&v)) {
+                        log.push(ActionLog::VelocityGated(velocity));
+                        return false;
+                    }
+                }
+                MacroStep::MidiOut { message } => {
+                    log.push(ActionLog::MidiOut(message.clone()));
+                    // Feedback is best-effort: a closed port never aborts the macro.
+                    if let Some(Err(err)) = ctx.midi_out.map(|out| out.send(message)) {
+                        log.push(ActionLog::Error(err.to_string()));
+                    }
+                }
+                MacroStep::RunScript { id } => {
+                    if !run_script(ctx.script_runner, ctx.scripts, id, ctx.allow_commands, log)
+                        .await
+                        && !continue_on_error
+                    {
+                        return false;
+                    }
+                }
+                MacroStep::RunCommand { program, args } => {
+                    if !run_command(ctx, program, args, log).await
+                        && !continue_on_error
+                    {
+                        return false;
+                    }
+                }
+                MacroStep::RunMacro { id } => {
+                    let ran = match ctx.macros.get(id) {
+                        _ if depth >= MAX_CALL_DEPTH => {
+                            log.push(ActionLog::Error(format!(
+                                "Call to `{id}` exceeds the macro call depth limit of {MAX_CALL_DEPTH}"
+                            )));
+                            false
+                        }
+                        None => {
+                            log.push(ActionLog::Error(format!(
+                                "Called macro `{id}` is not compiled"
+                            )));
+                            false
+                        }
+                        Some(callee) => {
+                            let callee_continues = callee.on_error == Some(ErrorPolicy::Continue);
+                            run_steps(ctx, log, &callee.steps, callee_continues, depth + 1).await
+                        }
+                    };
+                    if !ran && !continue_on_error {
+                        return false;
+                    }
+                }
+                MacroStep::WaitForMidi {
+                    note,
+                    timeout_ms,
+                    continue_on_timeout,
+                } => {
+                    let received = match ctx.midi_events {
+                        Some(events) => wait_for_note(events.subscribe(), *note, *timeout_ms).await,
+                        None => false,
+                    };
+                    if received {
+                        log.push(ActionLog::MidiReceived(*note));
+                    } else {
+                        log.push(ActionLog::MidiWaitTimedOut(*note));
+                        if !continue_on_timeout && !continue_on_error {
+                            return false;
+                        }
+                    }
+                }
+            }
+        }
+        true
+    }
+    .boxed()
+}
+
+async fn wait_for_note(
+    mut events: broadcast::Receiver<MidiEvent>,
+    note: u8,
+    timeout_ms: u64,
+) -> bool {
+    let wait = async {
+        loop {
+            match events.recv().await {
+                Ok(event) if event.kind == MidiEventKind::NoteOn && event.note == note => {
+                    return true
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return false,
+            }
+        }
+    };
+    tokio::time::timeout(std::time::Duration::from_millis(timeout_ms), wait)
+        .await
+        .unwrap_or(false)
+}
+
+pub type SharedExecutor<T> = Arc<Mutex<Executor<T>>>;
+
+#[cfg(not(test))]
+pub type DefaultKeySender = EnigoKeySender;
+
+#[cfg(test)]
+pub type DefaultKeySender = LoggingKeySender;
+
+#[cfg(not(test))]
+pub type DefaultMouseSender = EnigoMouseSender;
+
+#[cfg(test)]
+pub type DefaultMouseSender = LoggingMouseSender;
+
+#[cfg(not(test))]
+pub type DefaultScriptRunner = ShellScriptRunner;
+
+#[cfg(test)]
+pub type DefaultScriptRunner = LoggingScriptRunner;
+
+/// Holds every key but the last as a modifier around a click of the last one.
+fn press_keys(enigo: &mut enigo::Enigo, keys: &[String]) {
+    use enigo::{Key, KeyboardControllable};
+
+    if keys.is_empty() {
+        return;
+    }
+
+    let mut modifiers: Vec<Key> = Vec::new();
+
+    for key_str in keys.iter().take(keys.len().saturating_sub(1)) {
+        if let Some(key) = map_key(key_str) {
+            enigo.key_down(key);
+            modifiers.push(key);
+        }
+    }
+
+    if let Some(last_str) = keys.last() {
+        if let Some(last_key) = map_key(last_str) {
+            enigo.key_click(last_key);
+        }
+    }
+
+    for key in modifiers.into_iter().rev() {
+        enigo.key_up(key);
+    }
+}
+
+fn map_key(input: &str) -> Option<enigo::Key> {
+    use enigo::Key;
+    match input.to_ascii_lowercase().as_str() {
+        "ctrl" | "control" => Some(Key::Control),
+        "alt" => Some(Key::Alt),
+        "shift" => Some(Key::Shift),
+        "meta" | "cmd" | "command" | "super" => Some(Key::Meta),
+        "enter" | "return" => Some(Key::Return),
+        "space" | "spacebar" => Some(Key::Space),
+        "tab" => Some(Key::Tab),
+        "esc" | "escape" => Some(Key::Escape),
+        "up" => Some(Key::UpArrow),
+        "down" => Some(Key::DownArrow),
+        "left" => Some(Key::LeftArrow),
+        "right" => Some(Key::RightArrow),
+        "home" => Some(Key::Home),
+        "end" => Some(Key::End),
+        "pageup" => Some(Key::PageUp),
+        "pagedown" => Some(Key::PageDown),
+        "delete" => Some(Key::Delete),
+        "backspace" => Some(Key::Backspace),
+        #[cfg(any(target_os = "windows", target_os = "linux"))]
+        "insert" => Some(Key::Insert),
+        "volumeup" => Some(Key::VolumeUp),
+        "volumedown" => Some(Key::VolumeDown),
+        "mute" => Some(Key::VolumeMute),
+        // Media transport keys only exist in enigo's Windows and Linux backends;
+        // elsewhere they fall through to `None`. There is no discrete play or pause.
+        #[cfg(any(target_os = "windows", target_os = "linux"))]
+        "play" | "pause" | "playpause" => Some(Key::MediaPlayPause),
+        #[cfg(any(target_os = "windows", target_os = "linux"))]
+        "next" => Some(Key::MediaNextTrack),
+        #[cfg(any(target_os = "windows", target_os = "linux"))]
+        "prev" | "previous" => Some(Key::MediaPrevTrack),
+        #[cfg(any(target_os = "windows", target_os = "linux"))]
+        "stop" => Some(Key::MediaStop),
+        s if s.chars().count() == 1 => s.chars().next().map(Key::Layout),
+        s => s
+            .strip_prefix('f')
+            .and_then(|number| number.parse().ok())
+            .and_then(function_key),
+    }
+}
+
+fn function_key(number: u8) -> Option<enigo::Key> {
+    use enigo::Key;
+    Some(match number {
+        1 => Key::F1,
+        2 => Key::F2,
+        3 => Key::F3,
+        4 => Key::F4,
+        5 => Key::F5,
+        6 => Key::F6,
+        7 => Key::F7,
+        8 => Key::F8,
+        9 => Key::F9,
+        10 => Key::F10,
+        11 => Key::F11,
+        12 => Key::F12,
+        13 => Key::F13,
+        14 => Key::F14,
+        15 => Key::F15,
+        16 => Key::F16,
+        17 => Key::F17,
+        18 => Key::F18,
+        19 => Key::F19,
+        20 => Key::F20,
+        #[cfg(any(target_os = "windows", target_os = "linux"))]
+        21 => Key::F21,
+        #[cfg(any(target_os = "windows", target_os = "linux"))]
+        22 => Key::F22,
+        #[cfg(any(target_os = "windows", target_os = "linux"))]
+        23 => Key::F23,
+        #[cfg(any(target_os = "windows", target_os = "linux"))]
+        24 => Key::F24,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cache_format::{
+        CacheBundle, CacheDefaults, CacheHeader, CompressionKind, ExecutionHooks, MacroEntry,
+    };
+
+    struct MockSender;
+
+    #[test]
+    fn every_validator_key_name_maps() {
+        for name in config_validator::keys::KEY_NAMES {
+            assert!(map_key(name).is_some(), "{name} has no mapping");
+        }
+        #[cfg(any(target_os = "windows", target_os = "linux"))]
+        for name in config_validator::keys::LIMITED_KEY_NAMES {
+            assert!(map_key(name).is_some(), "{name} has no mapping");
+        }
+        assert_eq!(map_key("PageUp"), Some(enigo::Key::PageUp));
+        assert_eq!(map_key("F12"), Some(enigo::Key::F12));
+        assert_eq!(map_key("Mute"), Some(enigo::Key::VolumeMute));
+        #[cfg(any(target_os = "windows", target_os = "linux"))]
+        assert_eq!(map_key("pause"), Some(enigo::Key::MediaPlayPause));
+        assert_eq!(map_key("volume"), None);
+        assert_eq!(map_key("f0"), None);
+        assert_eq!(map_key("f99"), None);
+    }
+
+    #[async_trait::async_trait]
+    impl KeySender for MockSender {
+        async fn send_keystroke(&self, _keys: &[String]) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    fn sample_cache() -> CompiledCache {
+        let bundle = CacheBundle {
+            header: CacheHeader {
+                version: cache_format::CACHE_VERSION,
+                source_hash: 1,
+                generated_at: 1,
+                compression: CompressionKind::None,
+                payload_crc: 0,
+            },
+            devices: vec![],
+            macros: vec![MacroEntry {
+                id: "macro_a".into(),
+                description: None,
+                tags: vec![],
+                trigger: Some(MidiTrigger {
+                    r#type: MidiTriggerType::Note,
+                    number: 60,
+                    transport: None,
+                    threshold: None,
+                    channel: None,
+                }),
+                steps: vec![
+                    MacroStep::Keystroke {
+                        keys: vec!["Ctrl".into(), "S".into()],
+                    },
+                    MacroStep::Pause {
+                        ms: 10,
+                        label: None,
+                    },
+                ],
+                max_duration_ms: None,
+                on_error: None,
+                cooldown_ms: None,
+            }],
+            scripts: vec![],
+            profiles: vec![],
+            hooks: ExecutionHooks::default(),
+            defaults: CacheDefaults::default(),
+        };
+        CompiledCache {
+            bundle,
+            diagnostics: vec![],
+            bytes: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn executes_macro_actions() {
+        let cache = sample_cache();
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        let result = executor.execute_macro("macro_a").await;
+        assert!(result);
+        assert_eq!(
+            executor.last_actions,
+            vec![
+                ActionLog::Keystroke(vec!["Ctrl".into(), "S".into()]),
+                ActionLog::Pause(10, None)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn midi_event_dispatches_macro() {
+        let cache = sample_cache();
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        let event = MidiEvent {
+            note: 60,
+            velocity: 127,
+            channel: 0,
+            kind: MidiEventKind::NoteOn,
+        };
+        assert!(executor.execute_midi_event(event).await);
+        assert_eq!(executor.last_actions.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn background_macros_on_different_pads_run_concurrently() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  left:
+    status: ready
+    trigger: { type: note, number: 60 }
+    steps:
+      - type: keystroke
+        keys: ["L"]
+      - type: pause
+        ms: 60000
+  right:
+    status: ready
+    trigger: { type: note, number: 61 }
+    steps:
+      - type: keystroke
+        keys: ["R"]
+      - type: pause
+        ms: 60000
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        executor.set_run_in_background(true);
+        let press = |note| MidiEvent {
+            note,
             velocity: 127,
+            channel: 0,
+            kind: MidiEventKind::NoteOn,
+        };
+
+        // Neither press waits out the other macro's pause.
+        for note in [60, 61] {
+            let started = tokio::time::timeout(
+                Duration::from_millis(500),
+                executor.execute_midi_event(press(note)),
+            )
+            .await
+            .expect("trigger returns while the macro runs");
+            assert!(started);
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(executor.is_running("left") && executor.is_running("right"));
+
+        executor.cancel_all();
+        for (id, key) in [("left", "L"), ("right", "R")] {
+            let log = executor.join_macro(id).await.expect("run log");
+            assert_eq!(log[0], ActionLog::Keystroke(vec![key.into()]));
+            assert_eq!(log.last(), Some(&ActionLog::Cancelled(id.into())));
+        }
+        assert!(executor.last_actions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn velocity_gate_stops_soft_hits_without_running_the_fallback() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  hard_hit:
+    status: ready
+    trigger: { type: note, number: 60 }
+    on_error: { run_macro: oops }
+    steps:
+      - type: keystroke
+        keys: ["A"]
+      - type: velocity_gate
+        min: 100
+        max: 127
+      - type: keystroke
+        keys: ["B"]
+  oops:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["X"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        let hit = |velocity| MidiEvent {
+            note: 60,
+            velocity,
+            channel: 0,
+            kind: MidiEventKind::NoteOn,
+        };
+
+        assert!(executor.execute_midi_event(hit(40)).await);
+        assert_eq!(
+            executor.last_actions,
+            vec![
+                ActionLog::Keystroke(vec!["A".into()]),
+                ActionLog::VelocityGated(40),
+            ]
+        );
+        assert!(executor.execute_midi_event(hit(110)).await);
+        assert_eq!(executor.last_actions.len(), 2);
+        assert_eq!(
+            executor.last_actions[1],
+            ActionLog::Keystroke(vec!["B".into()])
+        );
+        // Runs nothing triggered pass every gate.
+        assert!(executor.execute_macro("hard_hit").await);
+        assert_eq!(executor.last_actions.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn random_pauses_stay_in_range_and_repeat_for_a_seed() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  humanized:
+    status: ready
+    steps:
+      - type: repeat
+        count: 8
+        steps:
+          - type: random_pause
+            min_ms: 20
+            max_ms: 60
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        executor.set_skip_pauses(true);
+
+        executor.set_random_seed(7);
+        assert!(executor.execute_macro("humanized").await);
+        let first = executor.last_actions.clone();
+        assert_eq!(first.len(), 8);
+        assert!(first
+            .iter()
+            .all(|action| matches!(action, ActionLog::Pause(ms, None) if (20..=60).contains(ms))));
+        executor.set_random_seed(7);
+        assert!(executor.execute_macro("humanized").await);
+        assert_eq!(executor.last_actions, first);
+    }
+
+    #[tokio::test]
+    async fn micro_pauses_are_logged_in_microseconds() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  tight:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["A"]
+      - type: pause_micros
+        us: 250
+      - type: keystroke
+        keys: ["B"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        assert!(executor.execute_macro("tight").await);
+        assert_eq!(
+            executor.last_actions,
+            vec![
+                ActionLog::Keystroke(vec!["A".into()]),
+                ActionLog::PauseMicros(250),
+                ActionLog::Keystroke(vec!["B".into()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn macros_with_tag_returns_every_tagged_macro() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  mute_all:
+    status: ready
+    tags: [live, panic]
+    steps:
+      - type: keystroke
+        keys: ["M"]
+  stop:
+    status: ready
+    tags: [live]
+    steps:
+      - type: keystroke
+        keys: ["S"]
+  save:
+    status: ready
+    tags: [edit]
+    steps:
+      - type: keystroke
+        keys: ["Ctrl", "S"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        let ids: Vec<_> = executor
+            .macros_with_tag("live")
+            .iter()
+            .map(|entry| entry.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["mute_all", "stop"]);
+        assert!(executor.macros_with_tag("missing").is_empty());
+    }
+
+    #[tokio::test]
+    async fn history_records_runs_in_order_up_to_its_capacity() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  one:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["A"]
+  two:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["A"]
+      - type: keystroke
+        keys: ["B"]
+  broken:
+    status: ready
+    steps:
+      - type: run_script
+        id: fails
+scripts:
+  fails: "false"
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.set_script_runner(Arc::new(RecordingRunner::default()));
+        executor.apply_cache(&cache);
+        executor.execute_macro("one").await;
+        executor.execute_macro("two").await;
+        executor.execute_macro("broken").await;
+        assert!(!executor.execute_macro("unknown").await);
+
+        let history = executor.history();
+        let runs: Vec<_> = history
+            .iter()
+            .map(|record| {
+                (
+                    record.macro_id.as_str(),
+                    record.action_count,
+                    record.success,
+                )
+            })
+            .collect();
+        assert_eq!(
+            runs,
+            vec![("one", 1, true), ("two", 2, true), ("broken", 1, false)]
+        );
+        assert_eq!(executor.last_actions.len(), 1);
+
+        executor.set_history_capacity(2);
+        assert_eq!(executor.history()[0].macro_id, "two");
+        executor.execute_macro("one").await;
+        assert_eq!(executor.history().len(), 2);
+        assert_eq!(executor.history()[1].macro_id, "one");
+        executor.clear_history();
+        assert!(executor.history().is_empty());
+    }
+
+    #[tokio::test]
+    async fn progress_reports_each_step_of_a_run() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  two_steps:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["A"]
+      - type: text
+        text: "hi"
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        let (progress, mut events) = broadcast::channel(16);
+        executor.set_progress_sender(progress);
+
+        assert!(executor.execute_macro("two_steps").await);
+        let id = || "two_steps".to_string();
+        let mut seen = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            seen.push(event);
+        }
+        assert_eq!(
+            seen,
+            vec![
+                ExecProgress::Started { id: id() },
+                ExecProgress::Step { id: id(), index: 0 },
+                ExecProgress::Step { id: id(), index: 1 },
+                ExecProgress::Finished { id: id(), ok: true },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn held_keys_log_down_pause_and_up() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  sprint:
+    status: ready
+    steps:
+      - type: key_down
+        key: Shift
+      - type: pause
+        ms: 250
+      - type: key_up
+        key: Shift
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        executor.set_skip_pauses(true);
+        assert!(executor.execute_macro("sprint").await);
+        assert_eq!(
+            executor.last_actions,
+            vec![
+                ActionLog::KeyDown("Shift".into()),
+                ActionLog::Pause(250, None),
+                ActionLog::KeyUp("Shift".into()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn if_steps_branch_on_variables_set_earlier() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  toggle_mode:
+    status: ready
+    steps:
+      - type: if
+        var: mode
+        equals: 0
+        then:
+          - type: set_var
+            name: mode
+            value: 1
+          - type: text
+            text: "on"
+        else:
+          - type: set_var
+            name: mode
+            value: 0
+          - type: text
+            text: "off"
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        assert_eq!(executor.variable("mode"), 0);
+
+        assert!(executor.execute_macro("toggle_mode").await);
+        assert_eq!(
+            executor.last_actions,
+            vec![
+                ActionLog::SetVar("mode".into(), 1),
+                ActionLog::Text("on".into()),
+            ]
+        );
+        assert!(executor.execute_macro("toggle_mode").await);
+        assert_eq!(
+            executor.last_actions,
+            vec![
+                ActionLog::SetVar("mode".into(), 0),
+                ActionLog::Text("off".into()),
+            ]
+        );
+        assert_eq!(executor.variable("mode"), 0);
+    }
+
+    #[tokio::test]
+    async fn variables_survive_a_restore() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  toggle_mode:
+    status: ready
+    steps:
+      - type: if
+        var: mode
+        equals: 0
+        then:
+          - type: set_var
+            name: mode
+            value: 1
+        else:
+          - type: set_var
+            name: mode
+            value: 0
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        assert!(executor.execute_macro("toggle_mode").await);
+        let snapshot = executor.snapshot_state();
+        assert_eq!(snapshot.variables, BTreeMap::from([("mode".into(), 1)]));
+        let persisted = serde_yaml::to_string(&snapshot).expect("serialize");
+
+        let mut restored = Executor::new(Arc::new(MockSender));
+        restored.apply_cache(&cache);
+        restored.restore_state(serde_yaml::from_str(&persisted).expect("deserialize"));
+        assert_eq!(restored.variable("mode"), 1);
+        // The restored value picks the `else` branch, as it would have before.
+        assert!(restored.execute_macro("toggle_mode").await);
+        assert_eq!(
+            restored.last_actions,
+            vec![ActionLog::SetVar("mode".into(), 0)]
+        );
+    }
+
+    // `echo` is a shell builtin rather than a program on Windows.
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn run_command_steps_need_allow_commands() {
+        let macros = r#"devices: {}
+macros:
+  greet:
+    status: ready
+    steps:
+      - type: run_command
+        program: echo
+        args: ["hello"]
+"#;
+        let mut executor = Executor::new(Arc::new(MockSender));
+        let cache = crate::config::compile_cache_from_str(&format!("version: 1\n{macros}"))
+            .expect("compile");
+        executor.apply_cache(&cache);
+        executor.execute_macro("greet").await;
+        assert!(matches!(
+            executor.last_actions.as_slice(),
+            [ActionLog::Error(message)] if message.contains("allow_commands")
+        ));
+
+        let allowed =
+            format!("version: 1\nglobal:\n  defaults:\n    allow_commands: true\n{macros}");
+        let cache = crate::config::compile_cache_from_str(&allowed).expect("compile");
+        executor.apply_cache(&cache);
+        assert!(executor.execute_macro("greet").await);
+        assert_eq!(
+            executor.last_actions,
+            vec![ActionLog::Command("echo".into())]
+        );
+        assert!(executor
+            .history()
+            .last()
+            .is_some_and(|record| record.success));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn run_command_sees_the_triggering_note() {
+        let yaml = r#"version: 1
+global:
+  defaults:
+    allow_commands: true
+devices: {}
+macros:
+  row:
+    status: ready
+    trigger: { type: note_range, low: 36, high: 43 }
+    steps:
+      - type: run_command
+        program: sh
+        args: ["-c", 'test "$MIDIMACROS_NOTE" = 38 && test "$MIDIMACROS_VELOCITY" = 90']
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        let hit = MidiEvent {
+            note: 38,
+            velocity: 90,
+            channel: 0,
+            kind: MidiEventKind::NoteOn,
+        };
+        assert!(executor.execute_midi_event(hit).await);
+        assert_eq!(executor.last_actions, vec![ActionLog::Command("sh".into())]);
+    }
+
+    #[tokio::test]
+    async fn note_ranges_fire_for_notes_no_exact_trigger_claims() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  row:
+    status: ready
+    trigger: { type: note_range, low: 36, high: 43 }
+    steps:
+      - type: keystroke
+        keys: ["R"]
+  corner:
+    status: ready
+    trigger: { type: note, number: 40 }
+    steps:
+      - type: keystroke
+        keys: ["C"]
+  row_ch2:
+    status: ready
+    trigger: { type: note_range, low: 30, high: 50, channel: 2 }
+    steps:
+      - type: keystroke
+        keys: ["2"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        let note_on = |note, channel| MidiEvent {
+            note,
+            velocity: 100,
+            channel,
+            kind: MidiEventKind::NoteOn,
+        };
+
+        assert_eq!(executor.macro_for_event(&note_on(36, 0)), Some("row"));
+        assert_eq!(executor.macro_for_event(&note_on(43, 0)), Some("row"));
+        assert_eq!(executor.macro_for_event(&note_on(40, 0)), Some("corner"));
+        assert_eq!(executor.macro_for_event(&note_on(44, 0)), None);
+        assert_eq!(executor.macro_for_event(&note_on(37, 2)), Some("row_ch2"));
+        let release = MidiEvent {
+            kind: MidiEventKind::NoteOff,
+            ..note_on(37, 0)
+        };
+        assert_eq!(executor.macro_for_event(&release), None);
+
+        assert!(executor.execute_midi_event(note_on(37, 0)).await);
+        assert_eq!(
+            executor.last_actions,
+            vec![ActionLog::Keystroke(vec!["R".into()])]
+        );
+        assert!(!executor.execute_midi_event(note_on(20, 0)).await);
+    }
+
+    #[tokio::test]
+    async fn chords_fire_once_while_their_notes_are_held() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  both:
+    status: ready
+    trigger: { type: chord, notes: [36, 40], window_ms: 5000 }
+    steps:
+      - type: keystroke
+        keys: ["B"]
+  first:
+    status: ready
+    trigger: { type: note, number: 36 }
+    steps:
+      - type: keystroke
+        keys: ["F"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        let event = |note, kind| MidiEvent {
+            note,
+            velocity: 100,
+            channel: 0,
+            kind,
+        };
+
+        assert!(
+            executor
+                .execute_midi_event(event(36, MidiEventKind::NoteOn))
+                .await
+        );
+        assert!(
+            executor
+                .execute_midi_event(event(40, MidiEventKind::NoteOn))
+                .await
+        );
+        // Another note-on while the chord is still held does not fire it again.
+        assert!(
+            !executor
+                .execute_midi_event(event(40, MidiEventKind::NoteOn))
+                .await
+        );
+        let history = executor.history();
+        let runs: Vec<_> = history
+            .iter()
+            .map(|record| record.macro_id.as_str())
+            .collect();
+        assert_eq!(runs, vec!["first", "both"]);
+
+        executor
+            .execute_midi_event(event(36, MidiEventKind::NoteOff))
+            .await;
+        // Releasing a note re-arms the chord; 40 alone is not enough.
+        assert!(
+            !executor
+                .execute_midi_event(event(40, MidiEventKind::NoteOn))
+                .await
+        );
+        assert!(
+            executor
+                .execute_midi_event(event(36, MidiEventKind::NoteOn))
+                .await
+        );
+        assert_eq!(executor.history().len(), 3);
+        assert_eq!(executor.history().last().unwrap().macro_id, "both");
+    }
+
+    #[tokio::test]
+    async fn disabled_executor_ignores_midi_until_re_enabled() {
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&sample_cache());
+        let note = MidiEvent {
+            note: 60,
+            velocity: 100,
+            channel: 0,
+            kind: MidiEventKind::NoteOn,
+        };
+
+        executor.set_enabled(false);
+        assert!(!executor.is_enabled());
+        assert!(!executor.execute_midi_event(note.clone()).await);
+        assert!(executor.last_actions.is_empty());
+
+        executor.enabled_flag().store(true, Ordering::Relaxed);
+        assert!(executor.execute_midi_event(note).await);
+        assert!(!executor.last_actions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn disabled_flag_survives_a_restore() {
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&sample_cache());
+        executor.set_enabled(false);
+        let persisted = serde_yaml::to_string(&executor.snapshot_state()).expect("serialize");
+
+        let mut restored = Executor::new(Arc::new(MockSender));
+        restored.apply_cache(&sample_cache());
+        restored.restore_state(serde_yaml::from_str(&persisted).expect("deserialize"));
+        assert!(!restored.is_enabled());
+        let note = MidiEvent {
+            note: 60,
+            velocity: 100,
+            channel: 0,
+            kind: MidiEventKind::NoteOn,
+        };
+        assert!(!restored.execute_midi_event(note).await);
+
+        // Snapshots written before the flag existed restore as enabled.
+        let state: ExecutorState =
+            serde_yaml::from_str("active_profile: null\nlatched_widgets: []\n")
+                .expect("deserialize");
+        assert_eq!(state, ExecutorState::default());
+        restored.restore_state(state);
+        assert!(restored.is_enabled());
+    }
+
+    #[tokio::test]
+    async fn cooldown_ignores_quick_retriggers() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  bouncy:
+    status: ready
+    cooldown_ms: 60000
+    trigger: { type: note, number: 60 }
+    steps:
+      - type: keystroke
+        keys: ["A"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        let note = MidiEvent {
+            note: 60,
+            velocity: 100,
+            channel: 0,
+            kind: MidiEventKind::NoteOn,
+        };
+
+        assert!(executor.execute_midi_event(note.clone()).await);
+        assert!(!executor.execute_midi_event(note).await);
+        assert_eq!(executor.history().len(), 1);
+        // Direct runs are not rate limited.
+        assert!(executor.execute_macro("bouncy").await);
+
+        let later = Instant::now() + Duration::from_secs(61);
+        assert!(executor.cooldown_elapsed("bouncy", later));
+        assert!(!executor.cooldown_elapsed("bouncy", later + Duration::from_secs(1)));
+    }
+
+    #[tokio::test]
+    async fn pause_label_flows_into_action_log() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  save_dialog:
+    status: ready
+    trigger:
+      type: note
+      number: 62
+    steps:
+      - type: keystroke
+        keys: ["Ctrl", "S"]
+      - type: pause
+        ms: 5
+        label: "wait for dialog"
+scripts: {}
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        assert!(executor.execute_macro("save_dialog").await);
+        assert_eq!(
+            executor.last_actions[1],
+            ActionLog::Pause(5, Some("wait for dialog".into()))
+        );
+    }
+
+    #[tokio::test]
+    async fn global_hooks_bracket_macro_actions() {
+        let yaml = r#"version: 1
+global:
+  on_before: focus
+  on_after: cleanup
+devices: {}
+macros:
+  focus:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["Alt", "1"]
+  cleanup:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["Esc"]
+  copy:
+    status: ready
+    trigger:
+      type: note
+      number: 60
+    steps:
+      - type: keystroke
+        keys: ["Ctrl", "C"]
+scripts: {}
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+
+        assert!(executor.execute_macro("copy").await);
+        assert_eq!(
+            executor.last_actions,
+            vec![
+                ActionLog::Keystroke(vec!["Alt".into(), "1".into()]),
+                ActionLog::Keystroke(vec!["Ctrl".into(), "C".into()]),
+                ActionLog::Keystroke(vec!["Esc".into()]),
+            ]
+        );
+
+        assert!(executor.execute_macro("cleanup").await);
+        assert_eq!(
+            executor.last_actions,
+            vec![ActionLog::Keystroke(vec!["Esc".into()])]
+        );
+    }
+
+    #[tokio::test]
+    async fn macro_exceeding_timeout_is_cut_short() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  runaway:
+    status: ready
+    max_duration_ms: 20
+    trigger:
+      type: note
+      number: 60
+    steps:
+      - type: keystroke
+        keys: ["A"]
+      - type: pause
+        ms: 10000
+      - type: keystroke
+        keys: ["B"]
+scripts: {}
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+
+        let started = std::time::Instant::now();
+        assert!(executor.execute_macro("runaway").await);
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+        assert_eq!(
+            executor.last_actions,
+            vec![
+                ActionLog::Keystroke(vec!["A".into()]),
+                ActionLog::Pause(10000, None),
+                ActionLog::TimedOut("runaway".into()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn enigo_sender_degrades_when_backend_fails() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static INIT_CALLS: AtomicUsize = AtomicUsize::new(0);
+        fn failing_init() -> Result<(), String> {
+            INIT_CALLS.fetch_add(1, Ordering::SeqCst);
+            Err("no display".into())
+        }
+
+        let unavailable = "Keystroke backend unavailable: no display".to_string();
+        let sender = EnigoKeySender::with_initializer(failing_init);
+        assert_eq!(sender.init_error(), None);
+        assert_eq!(
+            sender.send_keystroke(&["A".into()]).await,
+            Err(unavailable.clone())
+        );
+        assert_eq!(sender.key_down("Shift").await, Err(unavailable.clone()));
+        assert_eq!(sender.init_error(), Some("no display"));
+        assert_eq!(INIT_CALLS.load(Ordering::SeqCst), 1);
+
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  copy:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["Ctrl", "C"]
+      - type: text
+        text: "ok"
+"#;
+        let mut executor = Executor::new(Arc::new(sender));
+        executor.apply_cache(&crate::config::compile_cache_from_str(yaml).expect("compile"));
+        assert!(executor.execute_macro("copy").await);
+        assert_eq!(
+            executor.last_actions,
+            vec![
+                ActionLog::Keystroke(vec!["Ctrl".into(), "C".into()]),
+                ActionLog::Error(unavailable.clone()),
+                ActionLog::Text("ok".into()),
+                ActionLog::Error(unavailable),
+            ]
+        );
+        assert_eq!(INIT_CALLS.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn transport_event_dispatches_transport_macro() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  record:
+    status: ready
+    trigger: { type: transport, transport: start }
+    steps:
+      - type: keystroke
+        keys: ["R"]
+scripts: {}
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+
+        let transport = |kind| MidiEvent {
+            note: 0,
+            velocity: 0,
+            channel: 0,
+            kind: MidiEventKind::Transport(kind),
+        };
+        assert!(
+            !executor
+                .execute_midi_event(transport(TransportKind::Stop))
+                .await
+        );
+        assert!(
+            executor
+                .execute_midi_event(transport(TransportKind::Start))
+                .await
+        );
+        assert_eq!(
+            executor.last_actions,
+            vec![ActionLog::Keystroke(vec!["R".into()])]
+        );
+    }
+
+    /// Input sent to `sender` so far: each keystroke joined with `+`, other
+    /// calls prefixed with their kind.
+    fn sent_keys(sender: &RecordingKeySender) -> Vec<String> {
+        sender
+            .recorded()
+            .into_iter()
+            .map(|(_, input)| match input {
+                SentInput::Keystroke(keys) => keys.join("+"),
+                SentInput::KeyDown(key) => format!("down {key}"),
+                SentInput::KeyUp(key) => format!("up {key}"),
+                SentInput::Text(text) => format!("text {text}"),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn recording_sender_keeps_keystrokes_in_order_with_timing() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  copy_paste:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["Ctrl", "C"]
+      - type: pause
+        ms: 20
+      - type: keystroke
+        keys: ["Ctrl", "V"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let sender = Arc::new(RecordingKeySender::new());
+        let mut executor = Executor::new(sender.clone());
+        executor.apply_cache(&cache);
+
+        assert!(executor.execute_macro("copy_paste").await);
+        let recorded = sender.recorded();
+        assert_eq!(
+            recorded.iter().map(|(_, input)| input).collect::<Vec<_>>(),
+            [
+                &SentInput::Keystroke(vec!["Ctrl".into(), "C".into()]),
+                &SentInput::Keystroke(vec!["Ctrl".into(), "V".into()]),
+            ]
+        );
+        assert!(recorded[1].0 - recorded[0].0 >= Duration::from_millis(20));
+    }
+
+    #[tokio::test]
+    async fn recording_sender_keeps_key_edges_and_text_apart() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  shout:
+    status: ready
+    steps:
+      - type: key_down
+        key: Shift
+      - type: text
+        text: "hi"
+      - type: key_up
+        key: Shift
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let sender = Arc::new(RecordingKeySender::new());
+        let mut executor = Executor::new(sender.clone());
+        executor.apply_cache(&cache);
+
+        assert!(executor.execute_macro("shout").await);
+        assert_eq!(sent_keys(&sender), ["down Shift", "text hi", "up Shift"]);
+    }
+
+    #[tokio::test]
+    async fn atomic_groups_do_not_interleave() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  a:
+    status: ready
+    steps:
+      - type: atomic
+        steps:
+          - type: keystroke
+            keys: ["1"]
+          - type: pause
+            ms: 20
+          - type: keystroke
+            keys: ["2"]
+  b:
+    status: ready
+    steps:
+      - type: atomic
+        steps:
+          - type: keystroke
+            keys: ["3"]
+          - type: pause
+            ms: 20
+          - type: keystroke
+            keys: ["4"]
+scripts: {}
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let sender = Arc::new(RecordingKeySender::new());
+        let mut first = Executor::new(sender.clone());
+        let mut second = Executor::new(sender.clone());
+        first.apply_cache(&cache);
+        second.apply_cache(&cache);
+        second.set_send_lock(first.send_lock());
+
+        let (ran_a, ran_b) = tokio::join!(first.execute_macro("a"), second.execute_macro("b"));
+        assert!(ran_a && ran_b);
+        let sent = sent_keys(&sender);
+        assert!(
+            sent == ["1", "2", "3", "4"] || sent == ["3", "4", "1", "2"],
+            "interleaved output: {sent:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn key_sends_wait_for_a_background_atomic_group() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  a:
+    status: ready
+    steps:
+      - type: atomic
+        steps:
+          - type: keystroke
+            keys: ["1"]
+          - type: pause
+            ms: 20
+          - type: keystroke
+            keys: ["2"]
+  b:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["3"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let sender = Arc::new(RecordingKeySender::new());
+        let mut executor = Executor::new(sender.clone());
+        executor.apply_cache(&cache);
+
+        assert!(executor.trigger_macro("a"));
+        assert!(executor.trigger_macro("b"));
+        executor.join_macro("a").await.expect("run log");
+        executor.join_macro("b").await.expect("run log");
+        assert_eq!(sent_keys(&sender), ["1", "2", "3"]);
+    }
+
+    #[tokio::test]
+    async fn nested_atomic_groups_reuse_the_held_lock() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  outer:
+    status: ready
+    steps:
+      - type: atomic
+        steps:
+          - type: keystroke
+            keys: ["A"]
+          - type: atomic
+            steps:
+              - type: keystroke
+                keys: ["B"]
+          - type: run_macro
+            id: inner
+  inner:
+    status: ready
+    steps:
+      - type: atomic
+        steps:
+          - type: keystroke
+            keys: ["C"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+
+        let ran = tokio::time::timeout(Duration::from_secs(1), executor.execute_macro("outer"))
+            .await
+            .expect("nested atomic groups do not deadlock");
+        assert!(ran);
+        assert_eq!(
+            executor.last_actions,
+            vec![
+                ActionLog::Keystroke(vec!["A".into()]),
+                ActionLog::Keystroke(vec!["B".into()]),
+                ActionLog::Keystroke(vec!["C".into()]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn text_step_types_each_character_by_default() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  greet:
+    status: ready
+    steps:
+      - type: text
+        text: "hé✓"
+"#;
+        /// Forwards only keystrokes, so `type_text` falls back to the default.
+        struct KeystrokesOnly(RecordingKeySender);
+
+        #[async_trait::async_trait]
+        impl KeySender for KeystrokesOnly {
+            async fn send_keystroke(&self, keys: &[String]) -> Result<(), String> {
+                self.0.send_keystroke(keys).await
+            }
+        }
+
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let sender = Arc::new(KeystrokesOnly(RecordingKeySender::new()));
+        let mut executor = Executor::new(sender.clone());
+        executor.apply_cache(&cache);
+
+        assert!(executor.execute_macro("greet").await);
+        assert_eq!(executor.last_actions, vec![ActionLog::Text("hé✓".into())]);
+        assert_eq!(sent_keys(&sender.0), ["h", "é", "✓"]);
+    }
+
+    #[derive(Debug, Default)]
+    struct RecordingMouse {
+        calls: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl MouseSender for RecordingMouse {
+        async fn move_mouse(&self, x: i32, y: i32, relative: bool) -> Result<(), String> {
+            let kind = if relative { "by" } else { "to" };
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("move {kind} {x},{y}"));
+            Ok(())
+        }
+
+        async fn click(&self, button: MouseButton) -> Result<(), String> {
+            self.calls.lock().unwrap().push(format!("click {button:?}"));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn mouse_steps_reach_mouse_sender() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  select:
+    status: ready
+    steps:
+      - type: mouse_move
+        x: 100
+        y: 200
+      - type: mouse_move
+        x: -5
+        y: 0
+        relative: true
+      - type: mouse_click
+        button: left
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mouse = Arc::new(RecordingMouse::default());
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.set_mouse_sender(mouse.clone());
+        executor.apply_cache(&cache);
+
+        assert!(executor.execute_macro("select").await);
+        assert_eq!(
+            *mouse.calls.lock().unwrap(),
+            ["move to 100,200", "move by -5,0", "click Left"]
+        );
+        assert_eq!(
+            executor.last_actions.last(),
+            Some(&ActionLog::MouseClick(MouseButton::Left))
+        );
+    }
+
+    #[tokio::test]
+    async fn run_macro_steps_call_into_other_macros() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  save_and_close:
+    status: ready
+    steps:
+      - type: run_macro
+        id: save
+      - type: keystroke
+        keys: ["Ctrl", "W"]
+  save:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["Ctrl", "S"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+
+        assert!(executor.execute_macro("save_and_close").await);
+        assert_eq!(
+            executor.last_actions,
+            vec![
+                ActionLog::Keystroke(vec!["Ctrl".into(), "S".into()]),
+                ActionLog::Keystroke(vec!["Ctrl".into(), "W".into()]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn recursive_run_macro_stops_at_depth_limit() {
+        // The validator rejects cycles, so build the loop straight into the cache.
+        let mut cache = sample_cache();
+        cache.bundle.macros[0].steps = vec![MacroStep::RunMacro {
+            id: "macro_a".into(),
+        }];
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+
+        executor.execute_macro("macro_a").await;
+        assert_eq!(executor.last_actions.len(), 1);
+        assert!(matches!(
+            &executor.last_actions[0],
+            ActionLog::Error(message) if message.contains("depth limit of 16")
+        ));
+    }
+
+    #[tokio::test]
+    async fn repeat_step_flattens_iterations_into_last_actions() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  roll:
+    status: ready
+    steps:
+      - type: repeat
+        count: 3
+        steps:
+          - type: keystroke
+            keys: ["D"]
+          - type: pause
+            ms: 10
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.set_skip_pauses(true);
+        executor.apply_cache(&cache);
+
+        assert!(executor.execute_macro("roll").await);
+        let iteration = [
+            ActionLog::Keystroke(vec!["D".into()]),
+            ActionLog::Pause(10, None),
+        ];
+        assert_eq!(executor.last_actions, vec![iteration.to_vec(); 3].concat());
+    }
+
+    /// Succeeds for every script except `fails`, recording what it was asked to run.
+    #[derive(Debug, Default)]
+    struct RecordingRunner {
+        bodies: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl ScriptRunner for RecordingRunner {
+        async fn run_script(&self, script: &ScriptEntry) -> Result<(), String> {
+            self.bodies.lock().unwrap().push(script.body.clone());
+            if script.id == "fails" {
+                Err("exit status 1".into())
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn scripts_run_from_steps_and_widget_actions() {
+        let yaml = r#"version: 1
+global:
+  defaults:
+    allow_commands: true
+devices:
+  pad:
+    hardware_id: "usb:pad"
+    pages:
+      - name: main
+        widgets:
+          - id: build_button
+            action: { type: script, ref: build }
+macros:
+  deploy:
+    status: ready
+    steps:
+      - type: run_script
+        id: build
+      - type: run_script
+        id: fails
+      - type: keystroke
+        keys: ["Enter"]
+scripts:
+  build: "make all"
+  fails: "false"
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let runner = Arc::new(RecordingRunner::default());
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.set_script_runner(runner.clone());
+        executor.apply_cache(&cache);
+
+        assert!(executor.press_widget("pad", "build_button").await);
+        assert_eq!(
+            executor.last_actions,
+            vec![ActionLog::Script("build".into())]
+        );
+
+        executor.execute_macro("deploy").await;
+        assert_eq!(
+            executor.last_actions,
+            vec![
+                ActionLog::Script("build".into()),
+                ActionLog::Error("Script `fails` failed: exit status 1".into()),
+            ]
+        );
+        assert_eq!(
+            *runner.bodies.lock().unwrap(),
+            ["make all", "make all", "false"]
+        );
+    }
+
+    #[tokio::test]
+    async fn scripts_are_refused_without_allow_commands() {
+        let yaml = r#"version: 1
+devices:
+  pad:
+    hardware_id: "usb:pad"
+    pages:
+      - name: main
+        widgets:
+          - id: build_button
+            action: { type: script, ref: build }
+macros:
+  deploy:
+    status: ready
+    steps:
+      - type: run_script
+        id: build
+scripts:
+  build: "make all"
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let runner = Arc::new(RecordingRunner::default());
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.set_script_runner(runner.clone());
+        executor.apply_cache(&cache);
+        let refused = ActionLog::Error(
+            "Script `build` refused: `global.defaults.allow_commands` is not set".into(),
+        );
+
+        assert!(!executor.press_widget("pad", "build_button").await);
+        assert_eq!(executor.last_actions, vec![refused.clone()]);
+        executor.execute_macro("deploy").await;
+        assert_eq!(executor.last_actions, vec![refused]);
+        assert!(runner.bodies.lock().unwrap().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn shell_runner_reports_exit_status_and_refuses_other_languages() {
+        let script = |body: &str, language: Option<&str>| ScriptEntry {
+            id: "s".into(),
+            language: language.map(str::to_string),
+            body: body.into(),
+        };
+        let runner = ShellScriptRunner;
+        assert_eq!(runner.run_script(&script("true", None)).await, Ok(()));
+        assert!(runner
+            .run_script(&script("exit 3", Some("shell")))
+            .await
+            .is_err());
+        assert_eq!(
+            runner.run_script(&script("print(1)", Some("python"))).await,
+            Err("no runner for `python` scripts".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn midi_out_steps_log_and_survive_a_closed_port() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  flash:
+    status: ready
+    steps:
+      - type: midi_out
+        message: [0x90, 36, 127]
+      - type: keystroke
+        keys: ["F"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        assert!(executor.execute_macro("flash").await);
+        assert_eq!(
+            executor.last_actions,
+            vec![
+                ActionLog::MidiOut(vec![0x90, 36, 127]),
+                ActionLog::Keystroke(vec!["F".into()]),
+            ]
+        );
+
+        executor.set_midi_output(Arc::new(MidiOutManager::new("test")));
+        assert!(executor.execute_macro("flash").await);
+        assert_eq!(
+            executor.last_actions[1],
+            ActionLog::Error("No MIDI output port is open".into())
+        );
+        assert_eq!(executor.last_actions.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn activating_profile_limits_responding_macros() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  save:
+    status: ready
+    trigger: { type: note, number: 60 }
+    steps:
+      - type: keystroke
+        keys: ["Ctrl", "S"]
+  undo:
+    status: ready
+    trigger: { type: note, number: 61 }
+    steps:
+      - type: keystroke
+        keys: ["Ctrl", "Z"]
+profiles:
+  photoshop: [undo]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        let note = |note| MidiEvent {
+            note,
+            velocity: 100,
+            channel: 0,
+            kind: MidiEventKind::NoteOn,
+        };
+        assert_eq!(executor.macro_for_event(&note(60)), Some("save"));
+
+        assert!(!executor.activate_profile("daw"));
+        assert!(executor.activate_profile("photoshop"));
+        assert_eq!(executor.active_profile(), Some("photoshop"));
+        assert!(!executor.execute_midi_event(note(60)).await);
+        assert!(executor.execute_midi_event(note(61)).await);
+
+        executor.clear_profile();
+        assert_eq!(executor.macro_for_event(&note(60)), Some("save"));
+    }
+
+    #[tokio::test]
+    async fn pitch_bend_fires_once_per_crossing_with_hysteresis() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  bend_up:
+    status: ready
+    trigger: { type: pitch_bend, threshold: 12000 }
+    steps:
+      - type: keystroke
+        keys: ["U"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        let bend = |value| MidiEvent {
+            note: 0,
+            velocity: 0,
+            channel: 0,
+            kind: MidiEventKind::PitchBend(value),
+        };
+        let fired: Vec<_> = [8192, 12500, 11900, 12100, 9000, 12000]
+            .into_iter()
+            .map(|value| executor.resolve_event(&bend(value)))
+            .collect();
+        let up = Some("bend_up".to_string());
+        assert_eq!(fired, vec![None, up.clone(), None, None, None, up]);
+    }
+
+    #[tokio::test]
+    async fn pitch_bend_thresholds_near_the_ends_still_arm() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  bend_low:
+    status: ready
+    trigger: { type: pitch_bend, threshold: 100 }
+    steps:
+      - type: keystroke
+        keys: ["L"]
+  bend_high:
+    status: ready
+    trigger: { type: pitch_bend, threshold: 16300, direction: falling }
+    steps:
+      - type: keystroke
+        keys: ["H"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        let bend = |value| MidiEvent {
+            note: 0,
+            velocity: 0,
+            channel: 0,
+            kind: MidiEventKind::PitchBend(value),
+        };
+        // Rising 100 arms at 50 rather than only at 0.
+        let fired: Vec<_> = [8192, 40, 150, 80, 120, 50, 100]
+            .into_iter()
+            .map(|value| executor.resolve_event(&bend(value)))
+            .collect();
+        let low = Some("bend_low".to_string());
+        assert_eq!(fired, vec![None, None, low.clone(), None, None, None, low]);
+
+        // Falling 16300 arms at 16342 rather than never (16300 + 256 is off the wheel).
+        let fired: Vec<_> = [8192, 16383, 16200, 16320, 16280, PITCH_BEND_MAX, 16300]
+            .into_iter()
+            .map(|value| executor.resolve_event(&bend(value)))
+            .collect();
+        let high = Some("bend_high".to_string());
+        assert_eq!(
+            fired,
+            vec![None, None, high.clone(), None, None, None, high]
+        );
+    }
+
+    fn long_macro_executor(policy: RetriggerPolicy) -> Executor<MockSender> {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  long:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["A"]
+      - type: pause
+        ms: 60000
+      - type: keystroke
+        keys: ["B"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        executor.set_retrigger_policy(policy);
+        executor
+    }
+
+    #[tokio::test]
+    async fn restart_policy_cancels_the_running_macro() {
+        let mut executor = long_macro_executor(RetriggerPolicy::Restart);
+        assert!(executor.trigger_macro("long"));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(executor.is_running("long"));
+
+        assert!(executor.trigger_macro("long"));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(executor.is_running("long"));
+        assert!(executor.cancel_macro("long"));
+        let log = tokio::time::timeout(Duration::from_secs(1), executor.join_macro("long"))
+            .await
+            .expect("cancelled run ends")
+            .expect("run log");
+        assert_eq!(
+            log,
+            vec![
+                ActionLog::Keystroke(vec!["A".into()]),
+                ActionLog::Pause(60000, None),
+                ActionLog::Reset,
+                ActionLog::Cancelled("long".into()),
+            ]
+        );
+        assert!(!executor.is_running("long"));
+        assert!(!executor.cancel_macro("long"));
+    }
+
+    #[tokio::test]
+    async fn cancelling_a_macro_releases_its_held_keys() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  drag:
+    status: ready
+    steps:
+      - type: key_down
+        key: Shift
+      - type: key_down
+        key: Ctrl
+      - type: pause
+        ms: 60000
+      - type: key_up
+        key: Ctrl
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let sender = Arc::new(RecordingKeySender::new());
+        let mut executor = Executor::new(sender.clone());
+        executor.apply_cache(&cache);
+        assert!(executor.trigger_macro("drag"));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(executor.cancel_macro("drag"));
+        let log = tokio::time::timeout(Duration::from_secs(1), executor.join_macro("drag"))
+            .await
+            .expect("cancelled run ends")
+            .expect("run log");
+        assert_eq!(
+            sent_keys(&sender),
+            ["down Shift", "down Ctrl", "up Ctrl", "up Shift"]
+        );
+        assert_eq!(
+            log,
+            vec![
+                ActionLog::KeyDown("Shift".into()),
+                ActionLog::KeyDown("Ctrl".into()),
+                ActionLog::Pause(60000, None),
+                ActionLog::KeyUp("Ctrl".into()),
+                ActionLog::KeyUp("Shift".into()),
+                ActionLog::Reset,
+                ActionLog::Cancelled("drag".into()),
+            ]
+        );
+        assert!(executor.held_keys.lock().unwrap().is_empty());
+    }
+
+    /// Every MIDI message sent through it, for asserting feedback output.
+    #[derive(Clone, Default)]
+    struct CapturedMidi(Arc<std::sync::Mutex<Vec<Vec<u8>>>>);
+
+    impl crate::midi::output::MidiSink for CapturedMidi {
+        fn send(&mut self, message: &[u8]) -> Result<(), String> {
+            self.0.lock().unwrap().push(message.to_vec());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn cancelling_a_macro_sends_all_notes_off_on_every_channel() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  hold:
+    status: ready
+    steps:
+      - type: midi_out
+        message: [0x90, 36, 127]
+      - type: pause
+        ms: 60000
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let captured = CapturedMidi::default();
+        let out = MidiOutManager::new("test");
+        out.connect("capture", Box::new(captured.clone()));
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.set_midi_output(Arc::new(out));
+        executor.apply_cache(&cache);
+        assert!(executor.trigger_macro("hold"));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(executor.cancel_macro("hold"));
+        tokio::time::timeout(Duration::from_secs(1), executor.join_macro("hold"))
+            .await
+            .expect("cancelled run ends")
+            .expect("run log");
+
+        let mut expected = vec![vec![0x90, 36, 127]];
+        expected.extend((0..16u8).map(|channel| vec![0xB0 | channel, ALL_NOTES_OFF, 0]));
+        assert_eq!(*captured.0.lock().unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn ignore_policy_drops_retriggers_and_cancel_all_stops_queues() {
+        let mut executor = long_macro_executor(RetriggerPolicy::Ignore);
+        assert!(executor.trigger_macro("long"));
+        assert!(!executor.trigger_macro("long"));
+        executor.cancel_all();
+        tokio::time::timeout(Duration::from_secs(1), executor.join_macro("long"))
+            .await
+            .expect("cancelled run ends");
+
+        executor.set_retrigger_policy(RetriggerPolicy::Queue);
+        assert!(executor.trigger_macro("long"));
+        assert!(executor.trigger_macro("long"));
+        executor.cancel_all();
+        let log = tokio::time::timeout(Duration::from_secs(1), executor.join_macro("long"))
+            .await
+            .expect("queued run ends")
+            .expect("run log");
+        assert_eq!(
+            log,
+            vec![ActionLog::Reset, ActionLog::Cancelled("long".into())]
+        );
+        assert!(!executor.trigger_macro("missing"));
+    }
+
+    #[tokio::test]
+    async fn background_runs_are_recorded_in_history() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  one:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["1"]
+  two:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["2"]
+  three:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["3"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        executor.set_run_in_background(true);
+        for id in ["one", "two", "three"] {
+            assert!(executor.trigger_macro(id));
+            executor.join_macro(id).await.expect("run log");
+        }
+        let history = executor.history();
+        let runs: Vec<_> = history
+            .iter()
+            .map(|record| {
+                (
+                    record.macro_id.as_str(),
+                    record.action_count,
+                    record.success,
+                )
+            })
+            .collect();
+        assert_eq!(
+            runs,
+            [("one", 1, true), ("two", 1, true), ("three", 1, true)]
+        );
+
+        let mut executor = long_macro_executor(RetriggerPolicy::Ignore);
+        executor.set_run_in_background(true);
+        assert!(executor.trigger_macro("long"));
+        assert!(executor.cancel_macro("long"));
+        tokio::time::timeout(Duration::from_secs(1), executor.join_macro("long"))
+            .await
+            .expect("cancelled run ends");
+        let history = executor.history();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].macro_id, "long");
+        assert!(!history[0].success);
+    }
+
+    #[tokio::test]
+    async fn page_switch_widget_changes_which_widgets_respond() {
+        let yaml = r#"version: 1
+devices:
+  pad:
+    hardware_id: "pad-1"
+    pages:
+      - name: main
+        widgets:
+          - id: shift
+            action: { type: page_switch, device: pad, page: mix }
+          - id: a
+            action: { type: macro, ref: copy }
+      - name: mix
+        widgets:
+          - id: back
+            action: { type: page_switch, device: pad, page: main }
+          - id: a
+            action: { type: macro, ref: mute }
+macros:
+  copy:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["C"]
+  mute:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["M"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        let mut changes = executor.layers().subscribe();
+
+        assert!(!executor.press_widget("pad", "back").await);
+        assert!(executor.press_widget("pad", "shift").await);
+        assert_eq!(executor.layers().active_page_name("pad"), Some("mix"));
+        assert_eq!(changes.try_recv().expect("page changed").page_index, 1);
+
+        assert!(executor.press_widget("pad", "a").await);
+        assert_eq!(
+            executor.last_actions,
+            vec![ActionLog::Keystroke(vec!["M".into()])]
+        );
+        assert!(!executor.press_widget("pad", "shift").await);
+        assert!(executor.press_widget("pad", "back").await);
+        assert_eq!(executor.layers().active_page("pad"), Some(0));
+    }
+
+    #[tokio::test]
+    async fn latching_widget_alternates_actions() {
+        let yaml = r#"version: 1
+devices:
+  pad:
+    hardware_id: "pad-1"
+    pages:
+      - name: main
+        widgets:
+          - id: mute
+            mode: latching
+            action: { type: macro, ref: mute_on }
+            off_action: { type: macro, ref: mute_off }
+macros:
+  mute_on:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["M"]
+  mute_off:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["Shift", "M"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+
+        assert!(executor.press_widget("pad", "mute").await);
+        assert_eq!(
+            executor.last_actions,
+            vec![ActionLog::Keystroke(vec!["M".into()])]
+        );
+        assert!(!executor.release_widget("pad", "mute").await);
+        assert!(executor.press_widget("pad", "mute").await);
+        assert_eq!(
+            executor.last_actions,
+            vec![ActionLog::Keystroke(vec!["Shift".into(), "M".into()])]
+        );
+    }
+
+    #[tokio::test]
+    async fn double_tap_within_window_replaces_single_tap() {
+        let yaml = r#"version: 1
+global:
+  defaults:
+    double_tap_window_ms: 200
+    tap_hold_timeout_ms: 250
+devices:
+  pad:
+    hardware_id: "pad-1"
+    pages:
+      - name: main
+        widgets:
+          - id: play
+            action: { type: macro, ref: play }
+            double_tap_action: { type: macro, ref: stop }
+macros:
+  play:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["Space"]
+  stop:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["Escape"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        assert_eq!(executor.tap_hold_timeout(), Duration::from_millis(250));
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        // Two presses inside the window: only the double-tap action runs.
+        assert!(!executor.press_widget_at("pad", "play", at(0)).await);
+        assert!(executor.press_widget_at("pad", "play", at(150)).await);
+        assert_eq!(
+            executor.last_actions,
+            vec![ActionLog::Keystroke(vec!["Escape".into()])]
+        );
+        assert!(!executor.fire_expired_taps_at(at(1000)).await);
+
+        // A lone press runs the single tap once the window has passed.
+        assert!(!executor.press_widget_at("pad", "play", at(2000)).await);
+        assert!(!executor.fire_expired_taps_at(at(2100)).await);
+        assert!(executor.fire_expired_taps_at(at(2201)).await);
+        assert_eq!(
+            executor.last_actions,
+            vec![ActionLog::Keystroke(vec!["Space".into()])]
+        );
+    }
+
+    #[tokio::test]
+    async fn tap_behavior_drives_note_press_and_release() {
+        let yaml = r#"version: 1
+devices:
+  pad:
+    hardware_id: "pad-1"
+    pages:
+      - name: main
+        widgets:
+          - id: talk
+            tap_behavior: hold
+            action: { type: macro, ref: talk_on }
+            off_action: { type: macro, ref: talk_off }
+          - id: rec
+            tap_behavior: toggle
+            action: { type: macro, ref: rec_on }
+            off_action: { type: macro, ref: rec_off }
+macros:
+  talk_on:
+    status: ready
+    trigger: { type: note, number: 36 }
+    steps:
+      - type: keystroke
+        keys: ["T"]
+  talk_off:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["Shift", "T"]
+  rec_on:
+    status: ready
+    trigger: { type: note, number: 37 }
+    steps:
+      - type: keystroke
+        keys: ["R"]
+  rec_off:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["Shift", "R"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        async fn keys_after(executor: &mut Executor<MockSender>, bytes: [u8; 3]) -> String {
+            let event = crate::midi::input::parse_message(&bytes).expect("note");
+            assert!(executor.execute_midi_event(event).await);
+            match executor.last_actions.as_slice() {
+                [ActionLog::Keystroke(keys)] => keys.join("+"),
+                other => panic!("unexpected actions: {other:?}"),
+            }
+        }
+
+        // Hold: press runs `action`, release runs `off_action`.
+        assert_eq!(keys_after(&mut executor, [0x90, 36, 100]).await, "T");
+        assert_eq!(keys_after(&mut executor, [0x80, 36, 0]).await, "Shift+T");
+        // Toggle: presses alternate; releases do nothing.
+        assert_eq!(keys_after(&mut executor, [0x90, 37, 100]).await, "R");
+        let release = crate::midi::input::parse_message(&[0x80, 37, 0]).expect("note");
+        assert!(!executor.execute_midi_event(release).await);
+        assert_eq!(keys_after(&mut executor, [0x90, 37, 100]).await, "Shift+R");
+    }
+
+    #[tokio::test]
+    async fn wait_for_midi_unblocks_on_awaited_note() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  call:
+    status: ready
+    steps:
+      - type: wait_for_midi
+        note: 64
+        timeout_ms: 2000
+      - type: keystroke
+        keys: ["Enter"]
+  strict:
+    status: ready
+    steps:
+      - type: wait_for_midi
+        note: 64
+        timeout_ms: 10
+      - type: keystroke
+        keys: ["Enter"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let (tx, _) = broadcast::channel(8);
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        executor.set_midi_source(tx.clone());
+
+        let run = tokio::spawn(async move {
+            executor.execute_macro("call").await;
+            executor
+        });
+        while tx.receiver_count() == 0 {
+            tokio::task::yield_now().await;
+        }
+        for note in [63, 64] {
+            tx.send(MidiEvent {
+                note,
+                velocity: 100,
+                channel: 0,
+                kind: MidiEventKind::NoteOn,
+            })
+            .expect("send");
+        }
+        let mut executor = run.await.expect("join");
+        assert_eq!(
+            executor.last_actions,
+            vec![
+                ActionLog::MidiReceived(64),
+                ActionLog::Keystroke(vec!["Enter".into()]),
+            ]
+        );
+
+        // Nothing arrives: the step times out and aborts the rest of the macro.
+        executor.execute_macro("strict").await;
+        assert_eq!(executor.last_actions, vec![ActionLog::MidiWaitTimedOut(64)]);
+    }
+
+    #[tokio::test]
+    async fn on_error_policy_decides_after_a_failing_step() {
+        // With no MIDI source a `wait_for_midi` step fails immediately.
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  keep_going:
+    status: ready
+    on_error: continue
+    steps:
+      - type: wait_for_midi
+        note: 64
+        timeout_ms: 10
+      - type: keystroke
+        keys: ["A"]
+  stop:
+    status: ready
+    on_error: abort
+    steps:
+      - type: wait_for_midi
+        note: 64
+        timeout_ms: 10
+      - type: keystroke
+        keys: ["A"]
+  rescue:
+    status: ready
+    on_error: { run_macro: undo }
+    steps:
+      - type: wait_for_midi
+        note: 64
+        timeout_ms: 10
+      - type: keystroke
+        keys: ["A"]
+  undo:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["Ctrl", "Z"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+
+        executor.execute_macro("keep_going").await;
+        assert_eq!(
+            executor.last_actions,
+            vec![
+                ActionLog::MidiWaitTimedOut(64),
+                ActionLog::Keystroke(vec!["A".into()]),
+            ]
+        );
+
+        executor.execute_macro("stop").await;
+        assert_eq!(executor.last_actions, vec![ActionLog::MidiWaitTimedOut(64)]);
+
+        executor.execute_macro("rescue").await;
+        assert_eq!(
+            executor.last_actions,
+            vec![
+                ActionLog::MidiWaitTimedOut(64),
+                ActionLog::Fallback("undo".into()),
+                ActionLog::Keystroke(vec!["Ctrl".into(), "Z".into()]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn restored_state_matches_snapshot() {
+        let yaml = r#"version: 1
+devices:
+  pad:
+    hardware_id: "pad-1"
+    pages:
+      - name: main
+        widgets:
+          - id: mute
+            mode: latching
+            action: { type: macro, ref: mute_on }
+            off_action: { type: macro, ref: mute_off }
+      - name: mix
+macros:
+  mute_on:
+    status: ready
+    trigger: { type: note, number: 60 }
+    steps:
+      - type: keystroke
+        keys: ["M"]
+  mute_off:
+    status: ready
+    trigger: { type: note, number: 61 }
+    steps:
+      - type: keystroke
+        keys: ["Shift", "M"]
+profiles:
+  live: [mute_off]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        assert!(executor.activate_profile("live"));
+        assert!(executor.press_widget("pad", "mute").await);
+        assert!(executor.switch_page("pad", "mix"));
+
+        let snapshot = executor.snapshot_state();
+        assert_eq!(
+            snapshot,
+            ExecutorState {
+                active_profile: Some("live".into()),
+                latched_widgets: vec![("pad".into(), "mute".into())],
+                active_pages: vec![("pad".into(), "mix".into())],
+                enabled: true,
+                variables: BTreeMap::new(),
+            }
+        );
+        let persisted = serde_yaml::to_string(&snapshot).expect("serialize");
+
+        let mut restored = Executor::new(Arc::new(MockSender));
+        restored.apply_cache(&cache);
+        restored.restore_state(serde_yaml::from_str(&persisted).expect("deserialize"));
+        assert_eq!(restored.snapshot_state(), snapshot);
+        assert_eq!(restored.layers().active_page_name("pad"), Some("mix"));
+        let note = MidiEvent {
+            note: 60,
+            velocity: 100,
+            channel: 0,
+            kind: MidiEventKind::NoteOn,
         };
+        assert_eq!(restored.macro_for_event(&note), None);
+        // Still latched, so the next press runs the off action.
+        assert!(restored.switch_page("pad", "main"));
+        assert!(restored.press_widget("pad", "mute").await);
+        assert_eq!(
+            restored.last_actions,
+            vec![ActionLog::Keystroke(vec!["Shift".into(), "M".into()])]
+        );
+    }
+
+    #[tokio::test]
+    async fn control_change_event_dispatches_cc_macro() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  filter:
+    status: ready
+    trigger: { type: control_change, number: 74 }
+    steps:
+      - type: keystroke
+        keys: ["F"]
+  note_74:
+    status: ready
+    trigger: { type: note, number: 74 }
+    steps:
+      - type: keystroke
+        keys: ["N"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+
+        let event = crate::midi::input::parse_message(&[0xB0, 74, 64]).expect("cc event");
         assert!(executor.execute_midi_event(event).await);
-        assert_eq!(executor.last_actions.len(), 2);
+        assert_eq!(
+            executor.last_actions,
+            vec![ActionLog::Keystroke(vec!["F".into()])]
+        );
+    }
+
+    #[tokio::test]
+    async fn control_change_fires_once_per_press_and_sweep() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  filter:
+    status: ready
+    trigger: { type: control_change, number: 74 }
+    steps:
+      - type: keystroke
+        keys: ["F"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+        let cc = |channel: u8, value: u8| {
+            crate::midi::input::parse_message(&[0xB0 | channel, 74, value])
+        };
+        let fired = |executor: &mut Executor<MockSender>, channel, values: &[u8]| {
+            values
+                .iter()
+                .filter(|value| {
+                    let event = cc(channel, **value).expect("cc event");
+                    executor.resolve_event(&event).is_some()
+                })
+                .count()
+        };
+
+        // A button press then its release.
+        assert_eq!(fired(&mut executor, 0, &[127, 0]), 1);
+        // A knob swept up and back down to 0.
+        assert_eq!(fired(&mut executor, 0, &[1, 20, 64, 127, 90, 30, 0]), 1);
+        // Leaving 0 again fires again; another channel keeps its own value.
+        assert_eq!(fired(&mut executor, 0, &[5]), 1);
+        assert_eq!(fired(&mut executor, 1, &[5, 6]), 1);
+        assert_eq!(fired(&mut executor, 0, &[6, 7]), 0);
+    }
+
+    #[tokio::test]
+    async fn toggles_and_pages_survive_a_reload_while_they_exist() {
+        let config = |extra_widget: &str| {
+            format!(
+                r#"version: 1
+devices:
+  pad:
+    hardware_id: "pad-1"
+    pages:
+      - name: main
+        widgets:
+          - id: mute
+            tap_behavior: toggle
+            action: {{ type: macro, ref: mute_on }}
+            off_action: {{ type: macro, ref: mute_off }}
+{extra_widget}      - name: mix
+macros:
+  mute_on:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["M"]
+  mute_off:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["Shift", "M"]
+"#
+            )
+        };
+        let solo = "          - id: solo\n            tap_behavior: toggle\n            action: { type: macro, ref: mute_on }\n";
+        let before = crate::config::compile_cache_from_str(&config(solo)).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&before);
+        assert!(executor.press_widget("pad", "mute").await);
+        assert!(executor.press_widget("pad", "solo").await);
+        assert!(executor.layers.switch_to("pad", "mix"));
+
+        let after = crate::config::compile_cache_from_str(&config("")).expect("compile");
+        executor.apply_cache(&after);
+        assert_eq!(
+            executor.snapshot_state().latched_widgets,
+            vec![("pad".to_string(), "mute".to_string())]
+        );
+        assert_eq!(executor.layers.active_page_name("pad"), Some("mix"));
+        // Still latched, so the next press runs the off action.
+        assert!(executor.layers.switch_to("pad", "main"));
+        assert!(executor.press_widget("pad", "mute").await);
+        assert_eq!(
+            executor.last_actions,
+            vec![ActionLog::Keystroke(vec!["Shift".into(), "M".into()])]
+        );
+    }
+
+    #[tokio::test]
+    async fn program_change_event_dispatches_program_macro() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  bank_b:
+    status: ready
+    trigger: { type: program_change, number: 5 }
+    steps:
+      - type: keystroke
+        keys: ["B"]
+  note_5:
+    status: ready
+    trigger: { type: note, number: 5 }
+    steps:
+      - type: keystroke
+        keys: ["N"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+
+        let event = crate::midi::input::parse_message(&[0xC0, 5]).expect("program change");
+        assert_eq!(event.kind, MidiEventKind::ProgramChange);
+        assert!(executor.execute_midi_event(event).await);
+        assert_eq!(
+            executor.last_actions,
+            vec![ActionLog::Keystroke(vec!["B".into()])]
+        );
+    }
+
+    #[tokio::test]
+    async fn channel_bound_trigger_wins_over_any_channel() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  drums:
+    status: ready
+    trigger: { type: note, number: 36, channel: 9 }
+    steps:
+      - type: keystroke
+        keys: ["D"]
+  any:
+    status: ready
+    trigger: { type: note, number: 36 }
+    steps:
+      - type: keystroke
+        keys: ["A"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+
+        let on_drums = crate::midi::input::parse_message(&[0x99, 36, 100]).expect("ch 9");
+        assert_eq!(executor.macro_for_event(&on_drums), Some("drums"));
+        let on_other = crate::midi::input::parse_message(&[0x90, 36, 100]).expect("ch 0");
+        assert_eq!(executor.macro_for_event(&on_other), Some("any"));
+    }
+
+    #[tokio::test]
+    async fn note_off_does_not_fire_note_trigger() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  play:
+    status: ready
+    trigger: { type: note, number: 60 }
+    steps:
+      - type: keystroke
+        keys: ["P"]
+"#;
+        let cache = crate::config::compile_cache_from_str(yaml).expect("compile");
+        let mut executor = Executor::new(Arc::new(MockSender));
+        executor.apply_cache(&cache);
+
+        for bytes in [[0x80, 60, 0], [0x90, 60, 0]] {
+            let release = crate::midi::input::parse_message(&bytes).expect("release");
+            assert!(!executor.execute_midi_event(release).await);
+        }
+        let press = crate::midi::input::parse_message(&[0x90, 60, 100]).expect("press");
+        assert!(executor.execute_midi_event(press).await);
     }
 }
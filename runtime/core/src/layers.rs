@@ -0,0 +1,162 @@
+//! Per-device active page tracking for `PageSwitch` widget actions.
+
+use std::collections::HashMap;
+
+use tokio::sync::broadcast;
+
+use crate::config::CompiledCache;
+
+/// A device's active page changing, broadcast to [`LayerManager::subscribe`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageChanged {
+    pub device_id: String,
+    pub page_index: usize,
+    pub page_name: String,
+}
+
+/// Which page of each device is active. Every device starts on its first page.
+#[derive(Debug)]
+pub struct LayerManager {
+    /// Page names per device, in cache order.
+    pages: HashMap<String, Vec<String>>,
+    active: HashMap<String, usize>,
+    events: broadcast::Sender<PageChanged>,
+}
+
+impl Default for LayerManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LayerManager {
+    pub fn new() -> Self {
+        let (events, _) = broadcast::channel(16);
+        Self {
+            pages: HashMap::new(),
+            active: HashMap::new(),
+            events,
+        }
+    }
+
+    /// Takes the cache's pages. A device keeps its active page across a reload as
+    /// long as a page of that name still exists; otherwise it returns to the first.
+    pub fn apply_cache(&mut self, cache: &CompiledCache) {
+        let previous: HashMap<String, String> = self
+            .active
+            .keys()
+            .filter_map(|device| Some((device.clone(), self.active_page_name(device)?.to_string())))
+            .collect();
+        self.pages = cache
+            .bundle
+            .devices
+            .iter()
+            .map(|device| {
+                let names = device.pages.iter().map(|page| page.name.clone()).collect();
+                (device.id.clone(), names)
+            })
+            .collect();
+        self.active = previous
+            .into_iter()
+            .filter_map(|(device, name)| {
+                let index = self.page_index(&device, &name)?;
+                Some((device, index))
+            })
+            .collect();
+    }
+
+    /// Index of `device_id`'s active page, or `None` for an unknown device.
+    pub fn active_page(&self, device_id: &str) -> Option<usize> {
+        self.pages.get(device_id)?;
+        Some(self.active.get(device_id).copied().unwrap_or(0))
+    }
+
+    pub fn active_page_name(&self, device_id: &str) -> Option<&str> {
+        let index = self.active_page(device_id)?;
+        self.pages.get(device_id)?.get(index).map(String::as_str)
+    }
+
+    /// `(device_id, page_name)` of every device's active page, sorted by device.
+    pub fn active_pages(&self) -> Vec<(String, String)> {
+        let mut pages: Vec<_> = self
+            .pages
+            .keys()
+            .filter_map(|device| Some((device.clone(), self.active_page_name(device)?.to_string())))
+            .collect();
+        pages.sort();
+        pages
+    }
+
+    /// Activates the first page of `device_id` named `page_name`. Returns false when
+    /// there is no such page; announces a [`PageChanged`] only when the page changes.
+    pub fn switch_to(&mut self, device_id: &str, page_name: &str) -> bool {
+        let Some(index) = self.page_index(device_id, page_name) else {
+            return false;
+        };
+        if self.active_page(device_id) != Some(index) {
+            self.active.insert(device_id.to_string(), index);
+            let _ = self.events.send(PageChanged {
+                device_id: device_id.to_string(),
+                page_index: index,
+                page_name: page_name.to_string(),
+            });
+        }
+        true
+    }
+
+    /// Page changes from now on, for the UI and MIDI-out feedback.
+    pub fn subscribe(&self) -> broadcast::Receiver<PageChanged> {
+        self.events.subscribe()
+    }
+
+    fn page_index(&self, device_id: &str, page_name: &str) -> Option<usize> {
+        self.pages
+            .get(device_id)?
+            .iter()
+            .position(|name| name == page_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::compile_cache_from_str;
+
+    const CONFIG: &str = r#"version: 1
+devices:
+  pad:
+    hardware_id: "usb:pad"
+    pages:
+      - name: "Main"
+      - name: "Mix"
+macros: {}
+"#;
+
+    #[test]
+    fn switching_pages_updates_the_active_page_and_announces_it() {
+        let cache = compile_cache_from_str(CONFIG).expect("compile");
+        let mut layers = LayerManager::new();
+        layers.apply_cache(&cache);
+        let mut events = layers.subscribe();
+        assert_eq!(layers.active_page_name("pad"), Some("Main"));
+        assert_eq!(layers.active_page("nano"), None);
+
+        assert!(layers.switch_to("pad", "Mix"));
+        assert_eq!(layers.active_page("pad"), Some(1));
+        assert_eq!(
+            events.try_recv().expect("page changed"),
+            PageChanged {
+                device_id: "pad".into(),
+                page_index: 1,
+                page_name: "Mix".into(),
+            }
+        );
+        // Re-selecting the active page is not a change.
+        assert!(layers.switch_to("pad", "Mix"));
+        assert!(events.try_recv().is_err());
+        assert!(!layers.switch_to("pad", "Missing"));
+
+        layers.apply_cache(&cache);
+        assert_eq!(layers.active_page_name("pad"), Some("Mix"));
+    }
+}
@@ -2,19 +2,29 @@ pub mod app;
 pub mod config;
 pub mod console;
 pub mod executor;
+pub mod layers;
 pub mod midi;
 pub mod runtime;
+pub mod simulate;
 pub mod watch;
 
 pub use app::{AppState, AppStateError};
 pub use config::{
-    compile_cache_from_path, compile_cache_from_str, load_from_path, load_from_str, CompileError,
-    CompiledCache, Diagnostic, DiagnosticSeverity, LoadError, LoadedConfig,
+    compile_cache_from_path, compile_cache_from_str, format_diagnostic, load_from_first_existing,
+    load_from_path, load_from_str, CacheDiff, CompileError, CompiledCache, Diagnostic,
+    DiagnosticSeverity, DiagnosticTarget, LoadError, LoadedConfig,
 };
 pub use console::ConsoleManager;
-pub use executor::{ActionLog, DefaultKeySender, Executor, MidiEvent};
+pub use executor::{
+    ActionLog, DefaultKeySender, DefaultMouseSender, DefaultScriptRunner, ExecProgress,
+    ExecutionRecord, Executor, ExecutorState, MidiEvent, MidiEventKind, MouseSender,
+    RecordingKeySender, RetriggerPolicy, ScriptRunner, SentInput,
+};
+pub use layers::{LayerManager, PageChanged};
+pub use midi::input::{list_midi_ports, MidiConnectionEvent, MidiStatus};
 pub use midi::MidiManager;
-pub use runtime::{RuntimeManager, RuntimeManagerError};
+pub use runtime::{RuntimeManager, RuntimeManagerError, RuntimeOptions};
+pub use simulate::{simulate_cache, simulate_str, SimulatedEvent, SimulationReport};
 pub use watch::{watch_config, ReloadEvent, WatchHandle};
 
 pub fn init() {
@@ -1,60 +1,636 @@
-use midir::{Ignore, MidiInput};
-use tokio::sync::broadcast;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use midir::{Ignore, MidiInput, MidiInputConnection};
+use tokio::sync::{broadcast, mpsc, watch};
 use tokio::task::JoinHandle;
 
-use crate::executor::MidiEvent;
+use crate::executor::{MidiEvent, MidiEventKind};
+use cache_format::TransportKind;
+
+/// Connection state published by the MIDI listener.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MidiStatus {
+    Connected { port: String },
+    Disconnected { reason: String },
+}
+
+/// A port connecting or dropping out, broadcast to [`MidiHandle::subscribe_status`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MidiConnectionEvent {
+    Connected { port: String },
+    Disconnected { port: String, reason: String },
+}
+
+/// How often a listener re-enumerates ports to notice unplugs and replugs.
+pub const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_secs(2);
 
 #[derive(Debug)]
 pub struct MidiHandle {
     pub join_handle: JoinHandle<()>,
+    status: watch::Receiver<MidiStatus>,
+    connection_events: broadcast::Sender<MidiConnectionEvent>,
+    /// Tells the threads supervising port connections to close them.
+    stop: Arc<AtomicBool>,
+    connection_threads: Vec<std::thread::Thread>,
+}
+
+impl MidiHandle {
+    /// Handle for a listener that never connected (e.g. headless hosts without MIDI ports).
+    pub fn disconnected(reason: impl Into<String>) -> Self {
+        let (_status_tx, status) = watch::channel(MidiStatus::Disconnected {
+            reason: reason.into(),
+        });
+        Self {
+            join_handle: tokio::spawn(async {}),
+            status,
+            connection_events: broadcast::channel(16).0,
+            stop: Arc::default(),
+            connection_threads: Vec::new(),
+        }
+    }
+
+    /// Latest state; with several ports, the most recent transition on any of them.
+    pub fn status(&self) -> MidiStatus {
+        self.status.borrow().clone()
+    }
+
+    /// Connection transitions from now on, for showing connected/disconnected in a UI.
+    pub fn subscribe_status(&self) -> broadcast::Receiver<MidiConnectionEvent> {
+        self.connection_events.subscribe()
+    }
+
+    /// Closes every port connection and stops forwarding events.
+    pub fn shutdown(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+        for thread in &self.connection_threads {
+            thread.unpark();
+        }
+        self.join_handle.abort();
+    }
+}
+
+/// Forwards events from every port connection into the shared `sender`.
+fn spawn_forwarder(
+    mut events: mpsc::Receiver<MidiEvent>,
+    sender: broadcast::Sender<MidiEvent>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            let _ = sender.send(event);
+        }
+    })
+}
+
+/// What a supervising thread should do after re-enumerating ports.
+#[derive(Debug, PartialEq, Eq)]
+enum PortCheck {
+    /// Connected and the port is still listed.
+    Keep,
+    /// Connected but the port vanished.
+    Lost,
+    /// Not connected and the port is listed at this index.
+    Connect(usize),
+    /// Not connected and the port is still missing.
+    Wait,
+}
+
+fn check_port(names: &[String], wanted: &str, connected: bool) -> PortCheck {
+    let found = names.iter().position(|name| name.contains(wanted));
+    match (connected, found) {
+        (true, Some(_)) => PortCheck::Keep,
+        (true, None) => PortCheck::Lost,
+        (false, Some(index)) => PortCheck::Connect(index),
+        (false, None) => PortCheck::Wait,
+    }
+}
+
+fn connect_port(
+    client_name: &str,
+    port_name: &str,
+    events: mpsc::Sender<MidiEvent>,
+) -> Result<MidiInputConnection<()>, String> {
+    let mut input = MidiInput::new(client_name).map_err(|err| err.to_string())?;
+    input.ignore(Ignore::None);
+    let port = input
+        .ports()
+        .into_iter()
+        .find(|port| input.port_name(port).as_deref() == Ok(port_name))
+        .ok_or_else(|| format!("port `{port_name}` vanished while connecting"))?;
+    let mut sysex = SysExBuffer::default();
+    input
+        .connect(
+            &port,
+            "ai-midimacros",
+            move |_, message, _| {
+                let event = match sysex.feed(message) {
+                    SysExChunk::Other => parse_message(message),
+                    SysExChunk::Partial => None,
+                    SysExChunk::Complete(frame) => parse_message(&frame),
+                };
+                if let Some(event) = event {
+                    let _ = events.blocking_send(event);
+                }
+            },
+            (),
+        )
+        .map_err(|err| err.to_string())
+}
+
+/// What [`SysExBuffer::feed`] made of one callback's bytes.
+#[derive(Debug, PartialEq, Eq)]
+enum SysExChunk {
+    /// Not part of a SysEx message; parse it as it is.
+    Other,
+    /// Buffered until the rest of its SysEx message arrives.
+    Partial,
+    /// Ended a SysEx message, which is returned whole.
+    Complete(Vec<u8>),
+}
+
+/// Joins SysEx messages that a backend delivers split across several callbacks.
+#[derive(Debug, Default)]
+struct SysExBuffer {
+    pending: Option<Vec<u8>>,
+}
+
+impl SysExBuffer {
+    fn feed(&mut self, message: &[u8]) -> SysExChunk {
+        match (message.first(), self.pending.as_mut()) {
+            // A new start byte abandons any unfinished message.
+            (Some(0xF0), _) => self.pending = Some(message.to_vec()),
+            (Some(&byte), Some(frame)) if byte < 0x80 || byte == 0xF7 => {
+                frame.extend_from_slice(message)
+            }
+            // Real-time bytes may interleave with a SysEx message; any other
+            // status byte cuts it short.
+            (Some(&byte), Some(_)) if byte < 0xF8 => {
+                self.pending = None;
+                return SysExChunk::Other;
+            }
+            _ => return SysExChunk::Other,
+        }
+        if self
+            .pending
+            .as_ref()
+            .is_some_and(|frame| frame.ends_with(&[0xF7]))
+        {
+            self.pending
+                .take()
+                .map_or(SysExChunk::Partial, SysExChunk::Complete)
+        } else {
+            SysExChunk::Partial
+        }
+    }
+}
+
+/// Keeps one requested port connected from its own thread.
+struct Supervisor {
+    client_name: String,
+    /// Substring identifying the port across replugs.
+    wanted: String,
+    retry_interval: Duration,
+    events: mpsc::Sender<MidiEvent>,
+    status: watch::Sender<MidiStatus>,
+    connection_events: broadcast::Sender<MidiConnectionEvent>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Supervisor {
+    fn spawn(self) -> std::thread::Thread {
+        std::thread::spawn(move || self.run()).thread().clone()
+    }
+
+    /// Keeps the port connected until stopped, polling every `retry_interval`.
+    /// `midir` has no unplug callback, so a vanished port is noticed by its
+    /// absence from the port list.
+    fn run(self) {
+        let mut connection: Option<(MidiInputConnection<()>, String)> = None;
+        while !self.stop.load(Ordering::SeqCst) {
+            let names = list_midi_ports(&self.client_name).unwrap_or_default();
+            match check_port(&names, &self.wanted, connection.is_some()) {
+                PortCheck::Keep | PortCheck::Wait => {}
+                PortCheck::Lost => {
+                    if let Some((_, port)) = connection.take() {
+                        self.publish(MidiConnectionEvent::Disconnected {
+                            port,
+                            reason: "MIDI port disappeared".into(),
+                        });
+                    }
+                }
+                PortCheck::Connect(index) => {
+                    let port = names[index].clone();
+                    match connect_port(&self.client_name, &port, self.events.clone()) {
+                        Ok(open) => {
+                            self.publish(MidiConnectionEvent::Connected { port: port.clone() });
+                            connection = Some((open, port));
+                        }
+                        Err(err) => self.publish(MidiConnectionEvent::Disconnected {
+                            port,
+                            reason: format!("Failed to open MIDI input: {err}"),
+                        }),
+                    }
+                }
+            }
+            std::thread::park_timeout(self.retry_interval);
+        }
+    }
+
+    fn publish(&self, event: MidiConnectionEvent) {
+        let status = match &event {
+            MidiConnectionEvent::Connected { port } => MidiStatus::Connected { port: port.clone() },
+            MidiConnectionEvent::Disconnected { reason, .. } => MidiStatus::Disconnected {
+                reason: reason.clone(),
+            },
+        };
+        let _ = self.status.send(status);
+        let _ = self.connection_events.send(event);
+    }
+}
+
+/// Decodes a raw MIDI message into an event, ignoring messages no trigger can match.
+pub fn parse_message(message: &[u8]) -> Option<MidiEvent> {
+    // System real-time messages are channel-less; report channel 0.
+    let transport = |kind| MidiEvent {
+        note: 0,
+        velocity: 0,
+        channel: 0,
+        kind: MidiEventKind::Transport(kind),
+    };
+    match *message {
+        [0xFA, ..] => Some(transport(TransportKind::Start)),
+        [0xFB, ..] => Some(transport(TransportKind::Continue)),
+        [0xFC, ..] => Some(transport(TransportKind::Stop)),
+        [0xF0, .., 0xF7] => Some(MidiEvent {
+            note: 0,
+            velocity: 0,
+            channel: 0,
+            kind: MidiEventKind::SysEx {
+                data: message.to_vec(),
+            },
+        }),
+        [status, note, velocity, ..] if status & 0xF0 == 0x90 => Some(MidiEvent {
+            note,
+            velocity,
+            channel: status & 0x0F,
+            // Many devices send note-on with velocity 0 instead of note-off.
+            kind: if velocity == 0 {
+                MidiEventKind::NoteOff
+            } else {
+                MidiEventKind::NoteOn
+            },
+        }),
+        [status, note, velocity, ..] if status & 0xF0 == 0x80 => Some(MidiEvent {
+            note,
+            velocity,
+            channel: status & 0x0F,
+            kind: MidiEventKind::NoteOff,
+        }),
+        [status, controller, value, ..] if status & 0xF0 == 0xB0 => Some(MidiEvent {
+            note: controller,
+            velocity: value,
+            channel: status & 0x0F,
+            kind: MidiEventKind::ControlChange,
+        }),
+        // Program changes are the only two-byte channel message a trigger can match.
+        [status, program, ..] if status & 0xF0 == 0xC0 => Some(MidiEvent {
+            note: program,
+            velocity: 0,
+            channel: status & 0x0F,
+            kind: MidiEventKind::ProgramChange,
+        }),
+        [status, lsb, msb, ..] if status & 0xF0 == 0xE0 => Some(MidiEvent {
+            note: 0,
+            velocity: 0,
+            channel: status & 0x0F,
+            kind: MidiEventKind::PitchBend(u16::from(msb & 0x7F) << 7 | u16::from(lsb & 0x7F)),
+        }),
+        _ => None,
+    }
+}
+
+/// Names of the MIDI input ports currently visible to `client_name`, in port order.
+pub fn list_midi_ports(client_name: &str) -> anyhow::Result<Vec<String>> {
+    let input = MidiInput::new(client_name)?;
+    let names = input
+        .ports()
+        .iter()
+        .map(|port| input.port_name(port))
+        .collect::<Result<_, _>>()?;
+    Ok(names)
 }
 
+/// Index of the first port whose name contains `requested`, or the first port when
+/// no name is requested. `direction` ("input" or "output") only shapes the errors.
+pub(crate) fn select_port(
+    names: &[String],
+    requested: Option<&str>,
+    direction: &str,
+) -> anyhow::Result<usize> {
+    if names.is_empty() {
+        anyhow::bail!("No MIDI {direction} ports available");
+    }
+    let Some(requested) = requested else {
+        return Ok(0);
+    };
+    names
+        .iter()
+        .position(|name| name.contains(requested))
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "No MIDI {direction} port matches `{requested}` (available: {})",
+                names.join(", ")
+            )
+        })
+}
+
+/// Connects to a MIDI input port and forwards parsed events to `sender`,
+/// reconnecting every [`DEFAULT_RETRY_INTERVAL`] after an unplug.
+/// `port_name` picks the first port whose name contains it; `None` takes the first port.
 pub fn spawn_midi_listener<T: Into<String>>(
     client_name: T,
+    port_name: Option<&str>,
+    sender: broadcast::Sender<MidiEvent>,
+) -> anyhow::Result<MidiHandle> {
+    spawn_midi_listener_with_retry(client_name, port_name, DEFAULT_RETRY_INTERVAL, sender)
+}
+
+/// [`spawn_midi_listener`] with a custom port re-enumeration interval. The port
+/// must be present at start; afterwards it may come and go.
+pub fn spawn_midi_listener_with_retry<T: Into<String>>(
+    client_name: T,
+    port_name: Option<&str>,
+    retry_interval: Duration,
     sender: broadcast::Sender<MidiEvent>,
 ) -> anyhow::Result<MidiHandle> {
     let client_name = client_name.into();
-    let mut input = MidiInput::new(client_name.as_str())?;
-    input.ignore(Ignore::None);
+    let names = list_midi_ports(&client_name)?;
+    let index = select_port(&names, port_name, "input")?;
+    // Without a requested name, follow the port that was picked.
+    let wanted = port_name.map_or_else(|| names[index].clone(), str::to_string);
+    spawn_supervisors(client_name, vec![wanted], retry_interval, sender)
+}
 
-    let ports = input.ports();
+/// Connects to every port in `ports` (each matched by substring, as in
+/// [`spawn_midi_listener`]) and funnels all their events into `sender`. Each
+/// port reconnects independently. Fails if any port is missing at start.
+pub fn spawn_midi_listeners<T: Into<String>>(
+    client_name: T,
+    ports: &[String],
+    sender: broadcast::Sender<MidiEvent>,
+) -> anyhow::Result<MidiHandle> {
+    let client_name = client_name.into();
     if ports.is_empty() {
-        anyhow::bail!("No MIDI input ports available");
-    }
-    let port = ports[0].clone();
-
-    let (tx, mut rx) = tokio::sync::mpsc::channel::<MidiEvent>(32);
-
-    std::thread::spawn(move || {
-        let input = input;
-        let _connection = input
-            .connect(
-                &port,
-                "ai-midimacros",
-                move |_, message, _| {
-                    if message.len() >= 2 {
-                        let status = message[0] & 0xF0;
-                        if status == 0x90 && message.len() >= 3 {
-                            let _ = tx.blocking_send(MidiEvent {
-                                note: message[1],
-                                velocity: message[2],
-                            });
-                        }
-                    }
-                },
-                (),
-            )
-            .expect("Failed to open MIDI input");
-        loop {
-            std::thread::park();
-        }
+        anyhow::bail!("No MIDI input ports requested");
+    }
+    let names = list_midi_ports(&client_name)?;
+    for requested in ports {
+        select_port(&names, Some(requested), "input")?;
+    }
+    spawn_supervisors(client_name, ports.to_vec(), DEFAULT_RETRY_INTERVAL, sender)
+}
+
+fn spawn_supervisors(
+    client_name: String,
+    ports: Vec<String>,
+    retry_interval: Duration,
+    sender: broadcast::Sender<MidiEvent>,
+) -> anyhow::Result<MidiHandle> {
+    let (tx, rx) = mpsc::channel::<MidiEvent>(32);
+    let (status_tx, status) = watch::channel(MidiStatus::Disconnected {
+        reason: format!("Connecting to `{}`", ports.join("`, `")),
     });
+    let (connection_events, _) = broadcast::channel(16);
+    let stop = Arc::new(AtomicBool::new(false));
+    let connection_threads = ports
+        .into_iter()
+        .map(|wanted| {
+            Supervisor {
+                client_name: client_name.clone(),
+                wanted,
+                retry_interval,
+                events: tx.clone(),
+                status: status_tx.clone(),
+                connection_events: connection_events.clone(),
+                stop: Arc::clone(&stop),
+            }
+            .spawn()
+        })
+        .collect();
 
-    let join_handle = tokio::spawn(async move {
-        while let Some(event) = rx.recv().await {
-            let _ = sender.send(event);
+    Ok(MidiHandle {
+        join_handle: spawn_forwarder(rx, sender),
+        status,
+        connection_events,
+        stop,
+        connection_threads,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_transport_status_bytes() {
+        for (byte, kind) in [
+            (0xFA, TransportKind::Start),
+            (0xFB, TransportKind::Continue),
+            (0xFC, TransportKind::Stop),
+        ] {
+            let event = parse_message(&[byte]).expect("transport event");
+            assert_eq!(event.kind, MidiEventKind::Transport(kind));
+        }
+    }
+
+    #[test]
+    fn parses_note_on_and_ignores_unknown() {
+        let event = parse_message(&[0x90, 60, 100]).expect("note event");
+        assert_eq!(
+            event,
+            MidiEvent {
+                note: 60,
+                velocity: 100,
+                channel: 0,
+                kind: MidiEventKind::NoteOn,
+            }
+        );
+        assert_eq!(
+            parse_message(&[0xB2, 74, 127]).map(|event| (event.kind, event.channel)),
+            Some((MidiEventKind::ControlChange, 2))
+        );
+        assert_eq!(parse_message(&[0xF8]), None);
+        assert_eq!(parse_message(&[0x90, 60]), None);
+    }
+
+    #[test]
+    fn parses_note_off_and_velocity_zero_note_on() {
+        let event = parse_message(&[0x80, 60, 0]).expect("note off event");
+        assert_eq!(event.kind, MidiEventKind::NoteOff);
+        assert_eq!(event.note, 60);
+        assert_eq!(
+            parse_message(&[0x91, 60, 0]).map(|event| (event.kind, event.channel)),
+            Some((MidiEventKind::NoteOff, 1))
+        );
+    }
+
+    #[test]
+    fn assembles_fourteen_bit_pitch_bend() {
+        for (bytes, value) in [
+            ([0xE0, 0x00, 0x00], 0),
+            ([0xE0, 0x00, 0x40], 8192),
+            ([0xE3, 0x7F, 0x7F], 16383),
+            ([0xE0, 0x01, 0x02], 257),
+        ] {
+            let event = parse_message(&bytes).expect("pitch bend event");
+            assert_eq!(event.kind, MidiEventKind::PitchBend(value));
+            assert_eq!(event.channel, bytes[0] & 0x0F);
+        }
+        assert_eq!(parse_message(&[0xE0, 0x00]), None);
+    }
+
+    #[test]
+    fn forwards_sysex_frames_reassembled_across_callbacks() {
+        let identity = [0xF0, 0x7E, 0x00, 0x06, 0x02, 0x00, 0x20, 0x29, 0xF7];
+        let mut sysex = SysExBuffer::default();
+        let frame = match sysex.feed(&identity) {
+            SysExChunk::Complete(frame) => frame,
+            other => panic!("unexpected chunk: {other:?}"),
+        };
+        let event = parse_message(&frame).expect("sysex event");
+        assert_eq!(
+            event.kind,
+            MidiEventKind::SysEx {
+                data: identity.to_vec()
+            }
+        );
+
+        assert_eq!(sysex.feed(&identity[..4]), SysExChunk::Partial);
+        assert_eq!(sysex.feed(&[0xF8]), SysExChunk::Other);
+        assert_eq!(sysex.feed(&identity[4..7]), SysExChunk::Partial);
+        assert_eq!(
+            sysex.feed(&identity[7..]),
+            SysExChunk::Complete(identity.to_vec())
+        );
+
+        assert_eq!(sysex.feed(&identity[..4]), SysExChunk::Partial);
+        assert_eq!(sysex.feed(&[0x90, 60, 100]), SysExChunk::Other);
+        assert_eq!(sysex.feed(&identity[4..]), SysExChunk::Other);
+        assert_eq!(parse_message(&identity[..4]), None);
+    }
+
+    #[test]
+    fn select_port_matches_substring_and_lists_ports_on_miss() {
+        let names = vec!["Midi Through:0".to_string(), "Launchpad X:1".to_string()];
+        assert_eq!(select_port(&names, None, "input").unwrap(), 0);
+        assert_eq!(select_port(&names, Some("Launchpad"), "input").unwrap(), 1);
+
+        let err = select_port(&names, Some("APC40"), "input")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("`APC40`"), "{err}");
+        assert!(err.contains("Midi Through:0, Launchpad X:1"), "{err}");
+        assert!(select_port(&[], None, "input").is_err());
+    }
+
+    #[tokio::test]
+    async fn forwarder_funnels_every_port_into_one_sender() {
+        let (tx, rx) = mpsc::channel(32);
+        let (sender, mut events) = broadcast::channel(32);
+        let handle = MidiHandle {
+            join_handle: spawn_forwarder(rx, sender),
+            ..MidiHandle::disconnected("test")
+        };
+
+        // Two ports, each feeding the channel from its own callback thread.
+        let ports: Vec<_> = [[0x90, 36, 100], [0x91, 60, 90]]
+            .into_iter()
+            .map(|bytes| {
+                let tx = tx.clone();
+                std::thread::spawn(move || {
+                    let event = parse_message(&bytes).expect("note");
+                    tx.blocking_send(event).expect("send");
+                })
+            })
+            .collect();
+        for port in ports {
+            port.join().expect("port thread");
         }
-    });
 
-    Ok(MidiHandle { join_handle })
+        let mut channels = vec![
+            events.recv().await.expect("event").channel,
+            events.recv().await.expect("event").channel,
+        ];
+        channels.sort();
+        assert_eq!(channels, vec![0, 1]);
+
+        // `tx` is still open, so the forwarder only ends because it was aborted.
+        handle.shutdown();
+        let ended = handle.join_handle.await.expect_err("forwarder aborted");
+        assert!(ended.is_cancelled());
+        drop(tx);
+    }
+
+    #[test]
+    fn check_port_tracks_unplug_and_replug() {
+        let plugged = vec!["Midi Through:0".to_string(), "Launchpad X:1".to_string()];
+        let unplugged = vec!["Midi Through:0".to_string()];
+        assert_eq!(
+            check_port(&plugged, "Launchpad", false),
+            PortCheck::Connect(1)
+        );
+        assert_eq!(check_port(&plugged, "Launchpad", true), PortCheck::Keep);
+        assert_eq!(check_port(&unplugged, "Launchpad", true), PortCheck::Lost);
+        assert_eq!(check_port(&unplugged, "Launchpad", false), PortCheck::Wait);
+    }
+
+    #[tokio::test]
+    async fn supervisor_publishes_to_status_and_subscribers() {
+        let (status_tx, status) = watch::channel(MidiStatus::Disconnected {
+            reason: "starting".into(),
+        });
+        let handle = MidiHandle {
+            status,
+            ..MidiHandle::disconnected("unused")
+        };
+        let supervisor = Supervisor {
+            client_name: "test".into(),
+            wanted: "Launchpad".into(),
+            retry_interval: Duration::from_millis(10),
+            events: mpsc::channel(1).0,
+            status: status_tx,
+            connection_events: handle.connection_events.clone(),
+            stop: Arc::clone(&handle.stop),
+        };
+        let mut updates = handle.subscribe_status();
+
+        supervisor.publish(MidiConnectionEvent::Disconnected {
+            port: "Launchpad X:1".into(),
+            reason: "MIDI port disappeared".into(),
+        });
+        assert_eq!(
+            handle.status(),
+            MidiStatus::Disconnected {
+                reason: "MIDI port disappeared".into()
+            }
+        );
+        supervisor.publish(MidiConnectionEvent::Connected {
+            port: "Launchpad X:1".into(),
+        });
+        assert_eq!(
+            handle.status(),
+            MidiStatus::Connected {
+                port: "Launchpad X:1".into()
+            }
+        );
+        assert!(matches!(
+            updates.recv().await,
+            Ok(MidiConnectionEvent::Disconnected { .. })
+        ));
+        assert!(matches!(
+            updates.recv().await,
+            Ok(MidiConnectionEvent::Connected { .. })
+        ));
+    }
 }
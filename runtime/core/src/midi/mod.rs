@@ -26,7 +26,9 @@ impl MidiManager {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use cache_format::{CacheBundle, CacheHeader, MacroEntry};
+    use cache_format::{
+        CacheBundle, CacheDefaults, CacheHeader, CompressionKind, ExecutionHooks, MacroEntry,
+    };
 
     fn sample_cache() -> CompiledCache {
         let bundle = CacheBundle {
@@ -34,6 +36,8 @@ mod tests {
                 version: cache_format::CACHE_VERSION,
                 source_hash: 0,
                 generated_at: 0,
+                compression: CompressionKind::None,
+                payload_crc: 0,
             },
             devices: vec![],
             macros: vec![MacroEntry {
@@ -42,7 +46,14 @@ mod tests {
                 tags: vec![],
                 trigger: None,
                 steps: vec![],
+                max_duration_ms: None,
+                on_error: None,
+                cooldown_ms: None,
             }],
+            scripts: vec![],
+            profiles: vec![],
+            hooks: ExecutionHooks::default(),
+            defaults: CacheDefaults::default(),
         };
         CompiledCache {
             bundle,
@@ -61,3 +72,4 @@ mod tests {
 }
 
 pub mod input;
+pub mod output;
@@ -0,0 +1,201 @@
+//! MIDI output for controller feedback such as pad LEDs.
+
+use std::sync::Mutex;
+
+use cache_format::MacroStep;
+use midir::{MidiOutput, MidiOutputConnection};
+
+use crate::config::CompiledCache;
+use crate::midi::input::{select_port, MidiStatus};
+
+/// Where a [`MidiOutManager`] writes: a `midir` connection, or a capture in tests.
+pub trait MidiSink: Send {
+    fn send(&mut self, message: &[u8]) -> Result<(), String>;
+}
+
+impl MidiSink for MidiOutputConnection {
+    fn send(&mut self, message: &[u8]) -> Result<(), String> {
+        MidiOutputConnection::send(self, message).map_err(|err| err.to_string())
+    }
+}
+
+/// A single, optionally open, MIDI output connection shared by every macro.
+pub struct MidiOutManager {
+    client_name: String,
+    connection: Mutex<Option<(String, Box<dyn MidiSink>)>>,
+    /// Why the last [`MidiOutManager::open`] failed; cleared once a port opens.
+    last_error: Mutex<Option<String>>,
+}
+
+impl std::fmt::Debug for MidiOutManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MidiOutManager")
+            .field("client_name", &self.client_name)
+            .field("port", &self.port())
+            .finish()
+    }
+}
+
+impl MidiOutManager {
+    /// Starts closed; nothing is sent until [`MidiOutManager::open`] succeeds.
+    pub fn new(client_name: impl Into<String>) -> Self {
+        Self {
+            client_name: client_name.into(),
+            connection: Mutex::new(None),
+            last_error: Mutex::new(None),
+        }
+    }
+
+    /// Connects to the first output port whose name contains `port_name`, or the
+    /// first port for `None`, replacing any open connection. Returns the full port name.
+    pub fn open(&self, port_name: Option<&str>) -> anyhow::Result<String> {
+        let opened = self.try_open(port_name);
+        *self.last_error() = opened.as_ref().err().map(ToString::to_string);
+        opened
+    }
+
+    fn try_open(&self, port_name: Option<&str>) -> anyhow::Result<String> {
+        let output = MidiOutput::new(self.client_name.as_str())?;
+        let ports = output.ports();
+        let names = ports
+            .iter()
+            .map(|port| output.port_name(port))
+            .collect::<Result<Vec<_>, _>>()?;
+        let index = select_port(&names, port_name, "output")?;
+        let connection = output
+            .connect(&ports[index], "ai-midimacros-out")
+            .map_err(|err| anyhow::anyhow!("Failed to open MIDI output: {err}"))?;
+        let name = names[index].clone();
+        self.connect(name.clone(), Box::new(connection));
+        Ok(name)
+    }
+
+    /// Sends to `sink` from now on, reporting `port` as the open port.
+    pub fn connect(&self, port: impl Into<String>, sink: Box<dyn MidiSink>) {
+        *self.lock() = Some((port.into(), sink));
+    }
+
+    /// The open port, or why none is open.
+    pub fn status(&self) -> MidiStatus {
+        match self.port() {
+            Some(port) => MidiStatus::Connected { port },
+            None => MidiStatus::Disconnected {
+                reason: self
+                    .last_error()
+                    .clone()
+                    .unwrap_or_else(|| "No MIDI output port is open".into()),
+            },
+        }
+    }
+
+    /// Name of the open port, if any.
+    pub fn port(&self) -> Option<String> {
+        self.lock().as_ref().map(|(name, _)| name.clone())
+    }
+
+    pub fn send(&self, message: &[u8]) -> anyhow::Result<()> {
+        match self.lock().as_mut() {
+            Some((_, sink)) => sink
+                .send(message)
+                .map_err(|err| anyhow::anyhow!("Failed to send MIDI message: {err}")),
+            None => anyhow::bail!("No MIDI output port is open"),
+        }
+    }
+
+    /// Opens the first output port when the cache has `MidiOut` steps and no port
+    /// is open yet. Failing to open one is returned and kept for
+    /// [`MidiOutManager::status`]; `MidiOut` steps then log an error each.
+    pub fn apply_cache(&self, cache: &CompiledCache) -> anyhow::Result<()> {
+        let sends_midi = cache
+            .bundle
+            .macros
+            .iter()
+            .any(|entry| steps_send_midi(&entry.steps));
+        if !sends_midi || self.port().is_some() {
+            return Ok(());
+        }
+        self.open(None)
+            .map(drop)
+            .map_err(|err| err.context("MIDI output unavailable, MidiOut steps will not be sent"))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<(String, Box<dyn MidiSink>)>> {
+        self.connection
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn last_error(&self) -> std::sync::MutexGuard<'_, Option<String>> {
+        self.last_error
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn steps_send_midi(steps: &[MacroStep]) -> bool {
+    steps.iter().any(|step| match step {
+        MacroStep::MidiOut { .. } => true,
+        MacroStep::Atomic { steps } | MacroStep::Repeat { steps, .. } => steps_send_midi(steps),
+        MacroStep::If { then, else_, .. } => steps_send_midi(then) || steps_send_midi(else_),
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closed_manager_refuses_to_send() {
+        let manager = MidiOutManager::new("test");
+        assert_eq!(manager.port(), None);
+        let err = manager.send(&[0x90, 36, 127]).unwrap_err();
+        assert_eq!(err.to_string(), "No MIDI output port is open");
+    }
+
+    #[test]
+    fn status_keeps_the_last_open_failure() {
+        let manager = MidiOutManager::new("test");
+        assert_eq!(
+            manager.status(),
+            MidiStatus::Disconnected {
+                reason: "No MIDI output port is open".into()
+            }
+        );
+        let err = manager
+            .open(Some("no such port on any test machine"))
+            .unwrap_err();
+        assert_eq!(
+            manager.status(),
+            MidiStatus::Disconnected {
+                reason: err.to_string()
+            }
+        );
+
+        struct Discard;
+        impl MidiSink for Discard {
+            fn send(&mut self, _message: &[u8]) -> Result<(), String> {
+                Ok(())
+            }
+        }
+        manager.connect("capture", Box::new(Discard));
+        assert_eq!(
+            manager.status(),
+            MidiStatus::Connected {
+                port: "capture".into()
+            }
+        );
+    }
+
+    #[test]
+    fn detects_midi_out_in_nested_steps() {
+        let nested = [MacroStep::Repeat {
+            count: 2,
+            steps: vec![MacroStep::MidiOut {
+                message: vec![0x90, 36, 127],
+            }],
+        }];
+        assert!(steps_send_midi(&nested));
+        assert!(!steps_send_midi(&[MacroStep::Text { text: "x".into() }]));
+    }
+}
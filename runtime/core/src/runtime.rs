@@ -1,16 +1,20 @@
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use crate::app::{AppState, AppStateError};
 use crate::config::CompiledCache;
 use crate::console::ConsoleManager;
-use crate::executor::{DefaultKeySender, Executor, MidiEvent, SharedExecutor};
-use crate::midi::input::{spawn_midi_listener, MidiHandle};
+use crate::executor::{DefaultKeySender, ExecProgress, Executor, MidiEvent, SharedExecutor};
+use crate::layers::PageChanged;
+use crate::midi::input::{spawn_midi_listener, MidiHandle, MidiStatus};
+use crate::midi::output::MidiOutManager;
 use crate::midi::MidiManager;
 use crate::watch::{watch_config, ReloadEvent, WatchHandle};
 use notify::Error as NotifyError;
 use tokio::sync::Mutex;
 use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 
 #[derive(thiserror::Error, Debug)]
 pub enum RuntimeManagerError {
@@ -18,8 +22,31 @@ pub enum RuntimeManagerError {
     App(#[from] AppStateError),
     #[error("watch error: {0}")]
     Watch(#[from] NotifyError),
-    #[error("midi error: {0}")]
-    Midi(anyhow::Error),
+}
+
+/// How often held-back single taps are checked against their double-tap window.
+const TAP_FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(25);
+
+/// How long [`RuntimeManager::shutdown_graceful`] lets running macros finish
+/// before cancelling them.
+const GRACEFUL_SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// How [`RuntimeManager::initialize_with`] connects to MIDI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeOptions {
+    /// Substring of the input port to listen on; `None` takes the first port.
+    pub midi_port: Option<String>,
+    /// Name the MIDI input and output clients register under.
+    pub client_name: String,
+}
+
+impl Default for RuntimeOptions {
+    fn default() -> Self {
+        Self {
+            midi_port: None,
+            client_name: "ai-midimacros".into(),
+        }
+    }
 }
 
 pub struct RuntimeManager {
@@ -27,55 +54,111 @@ pub struct RuntimeManager {
     pub midi: Arc<Mutex<MidiManager>>,
     pub console: Arc<Mutex<ConsoleManager>>,
     pub executor: SharedExecutor<DefaultKeySender>,
+    pub midi_out: Arc<MidiOutManager>,
+    progress: tokio::sync::broadcast::Sender<ExecProgress>,
+    /// The executor's enable flag, so toggling never waits on a running macro.
+    enabled: Arc<AtomicBool>,
     watch: WatchHandle,
     midi_handle: MidiHandle,
     listener: JoinHandle<()>,
+    /// Ends the listener loop once the event it is handling is done.
+    stop_listener: CancellationToken,
 }
 
 impl RuntimeManager {
+    /// [`RuntimeManager::initialize_with`] using [`RuntimeOptions::default`].
     pub async fn initialize(config_path: PathBuf) -> Result<Self, RuntimeManagerError> {
+        Self::initialize_with(config_path, RuntimeOptions::default()).await
+    }
+
+    pub async fn initialize_with(
+        config_path: PathBuf,
+        options: RuntimeOptions,
+    ) -> Result<Self, RuntimeManagerError> {
+        Self::initialize_with_listener(config_path, options, spawn_midi_listener).await
+    }
+
+    /// `listen` starts the MIDI listener from the client name and requested port.
+    async fn initialize_with_listener<F>(
+        config_path: PathBuf,
+        options: RuntimeOptions,
+        listen: F,
+    ) -> Result<Self, RuntimeManagerError>
+    where
+        F: FnOnce(
+            String,
+            Option<&str>,
+            tokio::sync::broadcast::Sender<MidiEvent>,
+        ) -> anyhow::Result<MidiHandle>,
+    {
         let app_state = AppState::initialize(config_path.clone())?;
         let (midi_tx, _) = tokio::sync::broadcast::channel(32);
         let midi = Arc::new(Mutex::new(MidiManager::new(midi_tx.clone())));
         let console = Arc::new(Mutex::new(ConsoleManager::new()));
-        let executor = Arc::new(Mutex::new(Executor::new(Arc::new(DefaultKeySender::new()))));
-        let midi_handle = spawn_midi_listener("ai-midimacros", midi_tx.clone())
-            .map_err(RuntimeManagerError::Midi)?;
+        let mut executor = Executor::new(Arc::new(DefaultKeySender::new()));
+        executor.set_midi_source(midi_tx.clone());
+        let midi_out = Arc::new(MidiOutManager::new(options.client_name.clone()));
+        executor.set_midi_output(midi_out.clone());
+        let (progress, _) = tokio::sync::broadcast::channel(64);
+        executor.set_progress_sender(progress.clone());
+        // Lets a retrigger restart or cancel a long macro instead of waiting behind it.
+        executor.set_run_in_background(true);
+        let enabled = executor.enabled_flag();
+        let executor = Arc::new(Mutex::new(executor));
+        // A missing MIDI device is not fatal; callers observe it through `midi_status`.
+        let midi_handle = listen(
+            options.client_name,
+            options.midi_port.as_deref(),
+            midi_tx.clone(),
+        )
+        .unwrap_or_else(|err| MidiHandle::disconnected(err.to_string()));
         let state = Arc::new(Mutex::new(app_state));
 
         {
             let state_guard = state.lock().await;
             apply_cache_to_modules(
-                state_guard.compiled_cache().clone(),
+                state_guard.compiled_cache(),
                 &midi,
                 &console,
                 &executor,
+                &midi_out,
             )
             .await;
         }
 
         let watch = watch_config(config_path, state.clone())?;
         let mut rx = watch.subscribe();
-        let state_clone = state.clone();
         let midi_clone = midi.clone();
         let console_clone = console.clone();
         let executor_clone = executor.clone();
+        let midi_out_clone = midi_out.clone();
         let mut midi_rx_exec = midi_tx.subscribe();
+        let mut tap_flush = tokio::time::interval(TAP_FLUSH_INTERVAL);
+        let stop_listener = CancellationToken::new();
+        let stop = stop_listener.clone();
         let listener = tokio::spawn(async move {
             loop {
                 tokio::select! {
+                    biased;
+                    _ = stop.cancelled() => break,
                     Ok(event) = midi_rx_exec.recv() => {
                         let mut exec = executor_clone.lock().await;
                         let _ = exec.execute_midi_event(event).await;
                     }
+                    _ = tap_flush.tick() => {
+                        let mut exec = executor_clone.lock().await;
+                        let _ = exec.fire_expired_taps().await;
+                    }
                     Ok(event) = rx.recv() => {
-                        if let ReloadEvent::Reloaded = event {
-                            let cache = {
-                                let guard = state_clone.lock().await;
-                                guard.compiled_cache().clone()
-                            };
-                            apply_cache_to_modules(cache, &midi_clone, &console_clone, &executor_clone)
-                                .await;
+                        if let ReloadEvent::Reloaded(cache) = event {
+                            apply_cache_to_modules(
+                                &cache,
+                                &midi_clone,
+                                &console_clone,
+                                &executor_clone,
+                                &midi_out_clone,
+                            )
+                            .await;
                         }
                     }
                     else => break,
@@ -88,9 +171,13 @@ impl RuntimeManager {
             midi,
             console,
             executor,
+            midi_out,
+            progress,
+            enabled,
             watch,
             midi_handle,
             listener,
+            stop_listener,
         })
     }
 
@@ -99,36 +186,123 @@ impl RuntimeManager {
         exec_guard.execute_midi_event(event).await
     }
 
+    /// Runs macro `id` directly, without a MIDI event, and waits for it to finish.
+    /// Returns false for an unknown id.
+    pub async fn run_macro(&self, id: &str) -> bool {
+        self.executor.lock().await.execute_macro(id).await
+    }
+
+    /// Runs every macro tagged `tag` in id order, e.g. a `panic` group, each one
+    /// finishing before the next starts. Returns the ids that ran.
+    pub async fn run_macros_with_tag(&self, tag: &str) -> Vec<String> {
+        let mut exec_guard = self.executor.lock().await;
+        let ids: Vec<String> = exec_guard
+            .macros_with_tag(tag)
+            .into_iter()
+            .map(|entry| entry.id.clone())
+            .collect();
+        let mut ran = Vec::new();
+        for id in ids {
+            if exec_guard.execute_macro(&id).await {
+                ran.push(id);
+            }
+        }
+        ran
+    }
+
+    /// Ids of the macros the executor has loaded, sorted.
+    pub async fn list_macros(&self) -> Vec<String> {
+        self.executor.lock().await.macro_ids()
+    }
+
+    /// Mutes (`false`) or restores every MIDI trigger, e.g. from a panic button.
+    /// Direct [`RuntimeManager::run_macro`] calls are unaffected.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Flips the enabled state and returns the new one.
+    pub fn toggle_enabled(&self) -> bool {
+        !self.enabled.fetch_xor(true, Ordering::Relaxed)
+    }
+
+    /// Start, step and finish reports of every macro run, from now on.
+    pub fn subscribe_progress(&self) -> tokio::sync::broadcast::Receiver<ExecProgress> {
+        self.progress.subscribe()
+    }
+
+    /// Active-page changes from `PageSwitch` widgets, from now on.
+    pub async fn subscribe_page_changes(&self) -> tokio::sync::broadcast::Receiver<PageChanged> {
+        self.executor.lock().await.layers().subscribe()
+    }
+
+    pub fn midi_status(&self) -> MidiStatus {
+        self.midi_handle.status()
+    }
+
+    /// Feedback output port, or why it could not be opened.
+    pub fn midi_out_status(&self) -> MidiStatus {
+        self.midi_out.status()
+    }
+
+    /// Stops every task at once, even mid-macro; keys a macro was holding stay
+    /// down. Prefer [`RuntimeManager::shutdown_graceful`] outside emergencies.
     pub fn shutdown(self) {
         self.watch.join_handle.abort();
         self.listener.abort();
-        self.midi_handle.join_handle.abort();
+        self.midi_handle.shutdown();
+    }
+
+    /// Stops taking MIDI triggers, lets running macros finish (cancelling any still
+    /// going after [`GRACEFUL_SHUTDOWN_TIMEOUT`]), releases held keys and then
+    /// joins the tasks.
+    pub async fn shutdown_graceful(self) {
+        self.set_enabled(false);
+        self.stop_listener.cancel();
+        let _ = self.listener.await;
+        {
+            let mut exec_guard = self.executor.lock().await;
+            exec_guard.wait_for_running(GRACEFUL_SHUTDOWN_TIMEOUT).await;
+            exec_guard.release_held_keys().await;
+        }
+        self.watch.join_handle.abort();
+        let _ = self.watch.join_handle.await;
+        self.midi_handle.shutdown();
     }
 }
 
 async fn apply_cache_to_modules(
-    cache: CompiledCache,
+    cache: &CompiledCache,
     midi: &Arc<Mutex<MidiManager>>,
     console: &Arc<Mutex<ConsoleManager>>,
     executor: &SharedExecutor<DefaultKeySender>,
+    midi_out: &MidiOutManager,
 ) {
+    // A missing output port is not fatal; callers observe it through
+    // `midi_out_status`.
+    midi_out.apply_cache(cache).ok();
     {
         let mut midi_guard = midi.lock().await;
-        midi_guard.apply_cache(&cache);
+        midi_guard.apply_cache(cache);
     }
     {
         let mut console_guard = console.lock().await;
-        console_guard.apply_cache(&cache);
+        console_guard.apply_cache(cache);
     }
     {
         let mut exec_guard = executor.lock().await;
-        exec_guard.apply_cache(&cache);
+        exec_guard.apply_cache(cache);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::executor::MidiEventKind;
     use std::fs;
     use std::time::Duration;
 
@@ -178,6 +352,8 @@ mod tests {
                 .trigger_midi(MidiEvent {
                     note: 66,
                     velocity: 127,
+                    channel: 0,
+                    kind: MidiEventKind::NoteOn,
                 })
                 .await;
             assert!(executed);
@@ -185,4 +361,148 @@ mod tests {
 
         manager.shutdown();
     }
+
+    #[tokio::test]
+    async fn disabling_mutes_midi_triggers() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let config_path = dir.path().join("config.yaml");
+        fs::write(&config_path, sample_config(&[("macro1", "K")])).expect("write config");
+        let manager = RuntimeManager::initialize(config_path).await.expect("init");
+        let note = MidiEvent {
+            note: 66,
+            velocity: 127,
+            channel: 0,
+            kind: MidiEventKind::NoteOn,
+        };
+
+        assert!(!manager.toggle_enabled());
+        assert!(!manager.is_enabled());
+        assert!(!manager.trigger_midi(note.clone()).await);
+        manager.set_enabled(true);
+        assert!(manager.trigger_midi(note).await);
+
+        manager.shutdown();
+    }
+
+    #[tokio::test]
+    async fn options_choose_the_listened_port_and_client_name() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let config_path = dir.path().join("config.yaml");
+        fs::write(&config_path, sample_config(&[("macro1", "K")])).expect("write config");
+        let options = RuntimeOptions {
+            midi_port: Some("Launchpad".into()),
+            client_name: "studio-b".into(),
+        };
+        let requested = Arc::new(std::sync::Mutex::new(None));
+        let seen = requested.clone();
+
+        let manager =
+            RuntimeManager::initialize_with_listener(config_path, options, |client, port, _| {
+                *seen.lock().unwrap() = Some((client, port.map(str::to_string)));
+                Ok(MidiHandle::disconnected("mock listener"))
+            })
+            .await
+            .expect("init");
+        assert_eq!(
+            *requested.lock().unwrap(),
+            Some(("studio-b".to_string(), Some("Launchpad".to_string())))
+        );
+        assert_eq!(
+            manager.midi_status(),
+            MidiStatus::Disconnected {
+                reason: "mock listener".into()
+            }
+        );
+        assert_eq!(RuntimeOptions::default().client_name, "ai-midimacros");
+
+        manager.shutdown();
+    }
+
+    #[tokio::test]
+    async fn failed_listener_start_is_reported_not_fatal() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let config_path = dir.path().join("config.yaml");
+        fs::write(&config_path, sample_config(&[("macro1", "K")])).expect("write config");
+
+        let manager = RuntimeManager::initialize_with_listener(
+            config_path,
+            RuntimeOptions::default(),
+            |_, _, _| Err(anyhow::anyhow!("No MIDI input ports available")),
+        )
+        .await
+        .expect("init despite the listener failing");
+        assert_eq!(
+            manager.midi_status(),
+            MidiStatus::Disconnected {
+                reason: "No MIDI input ports available".into()
+            }
+        );
+        assert!(manager.run_macro("macro1").await);
+
+        manager.shutdown();
+    }
+
+    #[tokio::test]
+    async fn runs_macros_by_id() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let config_path = dir.path().join("config.yaml");
+        fs::write(
+            &config_path,
+            sample_config(&[("beta", "B"), ("alpha", "A")]),
+        )
+        .expect("write config");
+
+        let manager = RuntimeManager::initialize(config_path).await.expect("init");
+        assert_eq!(manager.list_macros().await, vec!["alpha", "beta"]);
+        assert!(manager.run_macro("alpha").await);
+        assert!(!manager.run_macro("gamma").await);
+        assert_eq!(
+            manager.executor.lock().await.last_actions,
+            vec![crate::executor::ActionLog::Keystroke(vec!["A".into()])]
+        );
+
+        manager.shutdown();
+    }
+
+    #[tokio::test]
+    async fn graceful_shutdown_waits_for_running_macros() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let config_path = dir.path().join("config.yaml");
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  slow:
+    status: ready
+    trigger:
+      type: note
+      number: 60
+    steps:
+      - type: pause
+        ms: 300
+"#;
+        fs::write(&config_path, yaml).expect("write config");
+        let manager = RuntimeManager::initialize(config_path).await.expect("init");
+        let mut progress = manager.subscribe_progress();
+        let started = std::time::Instant::now();
+        assert!(
+            manager
+                .trigger_midi(MidiEvent {
+                    note: 60,
+                    velocity: 127,
+                    channel: 0,
+                    kind: MidiEventKind::NoteOn,
+                })
+                .await
+        );
+
+        manager.shutdown_graceful().await;
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        let mut finished = None;
+        while let Ok(event) = progress.try_recv() {
+            if let ExecProgress::Finished { id, ok } = event {
+                finished = Some((id, ok));
+            }
+        }
+        assert_eq!(finished, Some(("slow".to_string(), true)));
+    }
 }
@@ -0,0 +1,128 @@
+//! Dry-run harness that replays MIDI events against a config without touching the keyboard.
+
+use std::sync::Arc;
+
+use crate::config::{compile_cache_from_str, CompileError, CompiledCache};
+use crate::executor::{
+    ActionLog, Executor, LoggingKeySender, LoggingMouseSender, LoggingScriptRunner, MidiEvent,
+};
+
+/// Outcome of replaying a single MIDI event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimulatedEvent {
+    pub event: MidiEvent,
+    /// Macro bound to the event, or `None` if nothing fired.
+    pub fired: Option<String>,
+    pub actions: Vec<ActionLog>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SimulationReport {
+    pub events: Vec<SimulatedEvent>,
+}
+
+impl SimulationReport {
+    /// Ids of every macro that fired, in event order.
+    pub fn fired_macros(&self) -> Vec<&str> {
+        self.events
+            .iter()
+            .filter_map(|event| event.fired.as_deref())
+            .collect()
+    }
+}
+
+/// Compiles `source` and replays `events` through an executor with pauses skipped.
+pub async fn simulate_str(
+    source: &str,
+    events: &[MidiEvent],
+) -> Result<SimulationReport, CompileError> {
+    let cache = compile_cache_from_str(source)?;
+    Ok(simulate_cache(&cache, events).await)
+}
+
+pub async fn simulate_cache(cache: &CompiledCache, events: &[MidiEvent]) -> SimulationReport {
+    let mut executor = Executor::new(Arc::new(LoggingKeySender::new()));
+    executor.set_mouse_sender(Arc::new(LoggingMouseSender));
+    executor.set_script_runner(Arc::new(LoggingScriptRunner));
+    executor.set_skip_pauses(true);
+    executor.apply_cache(cache);
+
+    let mut report = SimulationReport::default();
+    for event in events {
+        let fired = executor.resolve_event(event);
+        let actions = match &fired {
+            Some(id) if execute_at(&mut executor, id, event.trigger_velocity()).await => {
+                executor.last_actions.clone()
+            }
+            _ => Vec::new(),
+        };
+        report.events.push(SimulatedEvent {
+            event: event.clone(),
+            fired,
+            actions,
+        });
+    }
+    report
+}
+
+/// Runs `id` at the event's trigger velocity, so `VelocityGate` steps behave as live.
+async fn execute_at(
+    executor: &mut Executor<LoggingKeySender>,
+    id: &str,
+    velocity: Option<u8>,
+) -> bool {
+    match velocity {
+        Some(velocity) => executor.execute_macro_at_velocity(id, velocity).await,
+        None => executor.execute_macro(id).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::MidiEventKind;
+
+    #[tokio::test]
+    async fn replays_events_and_reports_fired_macros() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  copy:
+    status: ready
+    trigger:
+      type: note
+      number: 60
+    steps:
+      - type: keystroke
+        keys: ["Ctrl", "C"]
+      - type: pause
+        ms: 60000
+scripts: {}
+"#;
+        let events = [
+            MidiEvent {
+                note: 60,
+                velocity: 100,
+                channel: 0,
+                kind: MidiEventKind::NoteOn,
+            },
+            MidiEvent {
+                note: 61,
+                velocity: 100,
+                channel: 0,
+                kind: MidiEventKind::NoteOn,
+            },
+        ];
+        let report = simulate_str(yaml, &events).await.expect("simulate");
+        assert_eq!(report.fired_macros(), vec!["copy"]);
+        assert_eq!(
+            report.events[0].actions,
+            vec![
+                ActionLog::Keystroke(vec!["Ctrl".into(), "C".into()]),
+                ActionLog::Pause(60000, None),
+            ]
+        );
+        assert_eq!(report.events[1].fired, None);
+        assert!(report.events[1].actions.is_empty());
+    }
+}
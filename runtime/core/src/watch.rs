@@ -1,24 +1,29 @@
-use std::path::PathBuf;
-use std::sync::Arc;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
 use std::time::Duration;
 
-use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use tokio::sync::{broadcast, mpsc, Mutex};
 use tokio::task::JoinHandle;
 
 use crate::app::{AppState, AppStateError};
+use crate::config::CompiledCache;
 
+/// Outcome of a reload. `Reloaded` carries the new cache, diagnostics included, so
+/// subscribers need not lock the state again.
 #[derive(Debug, Clone)]
 pub enum ReloadEvent {
-    Reloaded,
+    Reloaded(Arc<CompiledCache>),
     Failed(Arc<AppStateError>),
 }
 
 pub struct WatchHandle {
     pub join_handle: JoinHandle<()>,
     event_tx: broadcast::Sender<ReloadEvent>,
-    /// Keep watcher alive for lifetime of handle.
-    _watcher: RecommendedWatcher,
+    /// Keep watcher alive for lifetime of handle; the task only holds a weak reference.
+    _watcher: Arc<std::sync::Mutex<RecommendedWatcher>>,
 }
 
 impl WatchHandle {
@@ -38,7 +43,15 @@ pub fn watch_config(path: PathBuf, state: Arc<Mutex<AppState>>) -> notify::Resul
         }
     })?;
 
-    watcher.watch(&path, RecursiveMode::NonRecursive)?;
+    // Nothing else holds the state until the task below starts.
+    let paths = state
+        .try_lock()
+        .map(|guard| guard.watched_paths())
+        .unwrap_or_else(|_| vec![path]);
+    let mut watched = WatchedFiles::default();
+    watched.add(&mut watcher, &paths)?;
+    let watcher = Arc::new(std::sync::Mutex::new(watcher));
+    let weak_watcher = Arc::downgrade(&watcher);
 
     let event_tx_clone = event_tx.clone();
     let join_handle = tokio::spawn(async move {
@@ -51,7 +64,7 @@ pub fn watch_config(path: PathBuf, state: Arc<Mutex<AppState>>) -> notify::Resul
                 tokio::select! {
                     Some(event) = notify_rx.recv() => {
                         if let Ok(ev) = event {
-                            if is_relevant(&ev.kind) {
+                            if watched.is_relevant(&ev) {
                                 deadline = Some(tokio::time::Instant::now() + debounce);
                             }
                         } else {
@@ -60,13 +73,15 @@ pub fn watch_config(path: PathBuf, state: Arc<Mutex<AppState>>) -> notify::Resul
                     }
                     _ = tokio::time::sleep_until(next_deadline) => {
                         deadline = None;
-                        reload_state(&state, &event_tx).await;
+                        if let Some(paths) = reload_state(&state, &event_tx).await {
+                            watched.add_weak(&weak_watcher, &paths);
+                        }
                     }
                 }
             } else {
                 match notify_rx.recv().await {
                     Some(Ok(event)) => {
-                        if is_relevant(&event.kind) {
+                        if watched.is_relevant(&event) {
                             deadline = Some(tokio::time::Instant::now() + debounce);
                         }
                     }
@@ -87,21 +102,84 @@ pub fn watch_config(path: PathBuf, state: Arc<Mutex<AppState>>) -> notify::Resul
     })
 }
 
-fn is_relevant(kind: &EventKind) -> bool {
-    matches!(
-        kind,
-        EventKind::Modify(_) | EventKind::Create(_) | EventKind::Remove(_) | EventKind::Other
-    )
+/// The config file and its includes, watched through their directories. Watches
+/// are only ever added; a dropped include just stops mattering to the reload.
+#[derive(Default)]
+struct WatchedFiles {
+    dirs: HashSet<PathBuf>,
+    names: HashSet<OsString>,
 }
 
-async fn reload_state(state: &Arc<Mutex<AppState>>, event_tx: &broadcast::Sender<ReloadEvent>) {
+impl WatchedFiles {
+    /// Watch each file's parent directory rather than the file: editors that save by
+    /// writing a temp file and renaming it over the original replace the inode, which
+    /// would silently end a watch placed on the file itself.
+    fn add(&mut self, watcher: &mut RecommendedWatcher, paths: &[PathBuf]) -> notify::Result<()> {
+        for path in paths {
+            let dir = path
+                .parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            if self.dirs.insert(dir.to_path_buf()) {
+                watcher.watch(dir, RecursiveMode::NonRecursive)?;
+            }
+            if let Some(name) = path.file_name() {
+                self.names.insert(name.to_os_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Picks up includes added by a reload. A directory that cannot be watched is
+    /// reported but leaves the existing watches running.
+    fn add_weak(
+        &mut self,
+        watcher: &Weak<std::sync::Mutex<RecommendedWatcher>>,
+        paths: &[PathBuf],
+    ) {
+        let Some(watcher) = watcher.upgrade() else {
+            return;
+        };
+        let mut guard = watcher
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Err(err) = self.add(&mut guard, paths) {
+            eprintln!("Failed to watch included config: {err}");
+        }
+    }
+
+    /// A change to one of the watched files. The `Remove`+`Create` pair (or rename) of
+    /// an atomic save lands within one debounce window and so triggers a single reload.
+    fn is_relevant(&self, event: &Event) -> bool {
+        let kind_matches = matches!(
+            event.kind,
+            EventKind::Modify(_) | EventKind::Create(_) | EventKind::Remove(_) | EventKind::Other
+        );
+        let touches_config = self.names.is_empty()
+            || event
+                .paths
+                .iter()
+                .any(|p| p.file_name().is_some_and(|name| self.names.contains(name)));
+        kind_matches && (touches_config || event.paths.is_empty())
+    }
+}
+
+/// Reloads the state and announces the outcome. Returns the files to watch after a
+/// successful reload.
+async fn reload_state(
+    state: &Arc<Mutex<AppState>>,
+    event_tx: &broadcast::Sender<ReloadEvent>,
+) -> Option<Vec<PathBuf>> {
     let mut guard = state.lock().await;
     match guard.reload() {
         Ok(_) => {
-            let _ = event_tx.send(ReloadEvent::Reloaded);
+            let cache = Arc::new(guard.compiled_cache().clone());
+            let _ = event_tx.send(ReloadEvent::Reloaded(cache));
+            Some(guard.watched_paths())
         }
         Err(err) => {
             let _ = event_tx.send(ReloadEvent::Failed(Arc::new(err)));
+            None
         }
     }
 }
@@ -165,7 +243,88 @@ scripts: {}
             .await
             .expect("timeout waiting for reload")
             .expect("channel closed");
-        assert!(matches!(event, ReloadEvent::Reloaded));
+        match event {
+            ReloadEvent::Reloaded(cache) => {
+                assert_eq!(cache.bundle.macros.len(), 2);
+                let paths: Vec<_> = cache.diagnostics.iter().map(|d| &d.path).collect();
+                let guard = state.lock().await;
+                let expected: Vec<_> = guard.diagnostics().iter().map(|d| &d.path).collect();
+                assert_eq!(paths, expected);
+                assert!(paths.iter().any(|path| *path == "macros.new_macro.trigger"));
+            }
+            ReloadEvent::Failed(err) => panic!("reload failed: {err}"),
+        }
+        handle.join_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn editing_an_included_file_reloads() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let config_path = dir.path().join("config.yaml");
+        let include_dir = dir.path().join("parts");
+        fs::create_dir(&include_dir).expect("mkdir");
+        let include_path = include_dir.join("macros.yaml");
+        fs::write(
+            &config_path,
+            "version: 1\ninclude: [\"parts/macros.yaml\"]\ndevices: {}\n",
+        )
+        .expect("write config");
+        fs::write(&include_path, "macros: {}\n").expect("write include");
+
+        let state = Arc::new(Mutex::new(
+            AppState::initialize(config_path.clone()).expect("init"),
+        ));
+        assert_eq!(
+            state.lock().await.watched_paths(),
+            vec![config_path.clone(), include_path.clone()]
+        );
+        let handle = watch_config(config_path.clone(), state.clone()).expect("watch");
+        let mut rx = handle.subscribe();
+
+        fs::write(&include_path, sample_config().replace("version: 1\n", ""))
+            .expect("rewrite include");
+
+        let event = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .expect("timeout waiting for reload")
+            .expect("channel closed");
+        match event {
+            ReloadEvent::Reloaded(cache) => assert_eq!(cache.bundle.macros.len(), 1),
+            ReloadEvent::Failed(err) => panic!("reload failed: {err}"),
+        }
+        handle.join_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn atomic_rename_save_reloads_once_and_keeps_watching() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let config_path = dir.path().join("config.yaml");
+        fs::write(&config_path, sample_config()).expect("write config");
+
+        let state = Arc::new(Mutex::new(
+            AppState::initialize(config_path.clone()).expect("init"),
+        ));
+        let handle = watch_config(config_path.clone(), state.clone()).expect("watch");
+        let mut rx = handle.subscribe();
+
+        for _ in 0..2 {
+            // Write-to-temp-then-rename, as many editors save.
+            let temp_path = dir.path().join(".config.yaml.tmp");
+            fs::write(&temp_path, sample_config()).expect("write temp");
+            fs::rename(&temp_path, &config_path).expect("rename over config");
+
+            let event = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+                .await
+                .expect("timeout waiting for reload")
+                .expect("channel closed");
+            assert!(matches!(event, ReloadEvent::Reloaded(_)));
+            assert!(
+                tokio::time::timeout(Duration::from_millis(600), rx.recv())
+                    .await
+                    .is_err(),
+                "atomic save reloaded more than once"
+            );
+        }
         handle.join_handle.abort();
     }
 }
@@ -1,17 +1,28 @@
+pub mod triggers;
+
 use std::fs;
-use std::path::Path;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use cache_format::{
-    CACHE_VERSION, CacheBundle, CacheHeader, DeviceLayout, LayoutPage, LayoutWidget, MacroEntry,
-    MacroStep, MidiTrigger, MidiTriggerType, WidgetAction,
+    ActionMode, CACHE_VERSION, CacheBundle, CacheDefaults, CacheHeader, CacheInconsistency,
+    CompressionKind, CrossingDirection, DEFAULT_CHORD_WINDOW_MS, DEFAULT_DOUBLE_TAP_WINDOW_MS,
+    DEFAULT_TAP_HOLD_TIMEOUT_MS, DeviceLayout, ErrorPolicy, ExecutionHooks, LayoutPage,
+    LayoutWidget, MacroEntry, MacroStep, MidiTrigger, MidiTriggerType, MouseButton, Profile,
+    ScriptEntry, TapBehavior, ThresholdCrossing, TransportKind, WidgetAction,
 };
 use config_validator::schema::{
-    Action, Config, Device, MacroStatus, MacroStep as SchemaMacroStep,
-    MidiTrigger as SchemaTrigger, MidiTriggerType as SchemaTriggerType, Page,
+    Action, ActionMode as SchemaActionMode, Config, CrossingDirection as SchemaDirection, Device,
+    ErrorPolicy as SchemaErrorPolicy, MacroStatus, MacroStep as SchemaMacroStep,
+    MidiTrigger as SchemaTrigger, MidiTriggerType as SchemaTriggerType,
+    MouseButton as SchemaMouseButton, Page, TransportKind as SchemaTransportKind,
     Widget as SchemaWidget,
 };
-use config_validator::{ConfigError, ValidationIssue, parse_config_str, validate_config};
+use config_validator::{
+    ConfigError, ValidationIssue, parse_config_str, parse_config_with_includes, read_source,
+    validate_config,
+};
 use thiserror::Error;
 use xxhash_rust::xxh3::xxh3_64;
 
@@ -19,6 +30,93 @@ use xxhash_rust::xxh3::xxh3_64;
 pub struct BuildOutput {
     pub bundle: CacheBundle,
     pub diagnostics: Vec<ValidationIssue>,
+    pub summary: BuildSummary,
+}
+
+/// Aggregate counts of a build, so tooling need not walk the bundle itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BuildSummary {
+    /// Ready macros compiled into the bundle.
+    pub macro_count: usize,
+    pub device_count: usize,
+    /// Top-level steps across every compiled macro.
+    pub total_steps: usize,
+    pub warnings: usize,
+    pub errors: usize,
+}
+
+impl BuildSummary {
+    pub fn new(bundle: &CacheBundle, diagnostics: &[ValidationIssue]) -> Self {
+        let count = |severity: config_validator::Severity| {
+            diagnostics
+                .iter()
+                .filter(|issue| issue.severity == severity)
+                .count()
+        };
+        Self {
+            macro_count: bundle.macros.len(),
+            device_count: bundle.devices.len(),
+            total_steps: bundle.macros.iter().map(|entry| entry.steps.len()).sum(),
+            warnings: count(config_validator::Severity::Warning),
+            errors: count(config_validator::Severity::Error),
+        }
+    }
+}
+
+impl std::fmt::Display for BuildSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} macros ({} steps), {} devices, {} warnings, {} errors",
+            self.macro_count, self.total_steps, self.device_count, self.warnings, self.errors
+        )
+    }
+}
+
+/// Size summary of a serialized cache, for tracking artifact growth.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildReport {
+    pub macro_count: usize,
+    pub uncompressed_bytes: usize,
+    /// Set only when the cache was written compressed.
+    pub compressed_bytes: Option<usize>,
+}
+
+impl BuildReport {
+    pub fn new(bundle: &CacheBundle, bytes: &[u8]) -> Self {
+        let compressed = bundle.header.compression != CompressionKind::None;
+        let uncompressed_bytes = match bincode::serialized_size(bundle) {
+            Ok(size) if compressed => size as usize,
+            _ => bytes.len(),
+        };
+        Self {
+            macro_count: bundle.macros.len(),
+            uncompressed_bytes,
+            compressed_bytes: compressed.then_some(bytes.len()),
+        }
+    }
+
+    /// Compressed size as a fraction of the uncompressed size.
+    pub fn compression_ratio(&self) -> Option<f64> {
+        let compressed = self.compressed_bytes?;
+        (self.uncompressed_bytes > 0).then(|| compressed as f64 / self.uncompressed_bytes as f64)
+    }
+
+    /// One-line summary used by the CLI, e.g. `3 macros, 412 bytes`.
+    pub fn summary(&self) -> String {
+        let mut text = format!(
+            "{} macros, {} bytes",
+            self.macro_count, self.uncompressed_bytes
+        );
+        if let (Some(compressed), Some(ratio)) = (self.compressed_bytes, self.compression_ratio()) {
+            text.push_str(&format!(
+                ", {} bytes compressed ({:.0}%)",
+                compressed,
+                ratio * 100.0
+            ));
+        }
+        text
+    }
 }
 
 #[derive(Debug, Error)]
@@ -31,22 +129,259 @@ pub enum BuildError {
     Validation(Vec<ValidationIssue>),
     #[error("Serialization error: {0}")]
     Serialize(#[from] bincode::Error),
+    #[error("Format error: {0}")]
+    Format(#[from] serde_yaml::Error),
+    #[error("Bundle inconsistencies introduced after assembly: {0:?}")]
+    Inconsistent(Vec<CacheInconsistency>),
+    #[error("Cache file is corrupt: {0}")]
+    Corrupt(String),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
 }
 
 pub fn build_from_path(path: impl AsRef<Path>) -> Result<(BuildOutput, Vec<u8>), BuildError> {
-    let path_ref = path.as_ref();
-    let content = fs::read_to_string(path_ref)?;
-    let output = build_from_str(&content)?;
-    let bytes = bincode::serialize(&output.bundle)?;
+    let output = build_from_path_with_time(path, unix_now())?;
+    let bytes = encode_bundle(&output.bundle)?;
     Ok((output, bytes))
 }
 
+/// Builds the config at `path` with `generated_at` pinned, so identical sources give
+/// identical caches. The caller encodes the returned bundle.
+pub fn build_from_path_with_time(
+    path: impl AsRef<Path>,
+    generated_at: u64,
+) -> Result<BuildOutput, BuildError> {
+    let (config, content) = read_config(path.as_ref())?;
+    build_from_config(&config, &content, generated_at)
+}
+
+/// Like [`build_from_path`], but zstd-compresses everything after the header.
+pub fn build_from_path_compressed(
+    path: impl AsRef<Path>,
+) -> Result<(BuildOutput, Vec<u8>), BuildError> {
+    let mut output = build_from_path_with_time(path, unix_now())?;
+    output.bundle.header.compression = CompressionKind::Zstd;
+    let bytes = encode_bundle(&output.bundle)?;
+    Ok((output, bytes))
+}
+
+/// Like [`build_from_path`], but writes the bundle as pretty-printed JSON so caches
+/// can be diffed. [`decode_bundle`] reads either form.
+pub fn build_from_path_json(path: impl AsRef<Path>) -> Result<(BuildOutput, Vec<u8>), BuildError> {
+    let output = build_from_path_with_time(path, unix_now())?;
+    let bytes = serde_json::to_vec_pretty(&output.bundle)?;
+    Ok((output, bytes))
+}
+
+/// Builds the configs at `paths` merged in order, so a machine-specific file can
+/// override a shared base; see [`Config::merge`] for the merge rules. Every entry a
+/// later file replaces is reported as an info diagnostic.
+pub fn build_from_paths(paths: &[PathBuf]) -> Result<(BuildOutput, Vec<u8>), BuildError> {
+    let output = build_from_paths_with_time(paths, unix_now())?;
+    let bytes = encode_bundle(&output.bundle)?;
+    Ok((output, bytes))
+}
+
+/// Like [`build_from_paths`], with `generated_at` pinned as in
+/// [`build_from_path_with_time`]. The caller encodes the returned bundle.
+pub fn build_from_paths_with_time(
+    paths: &[PathBuf],
+    generated_at: u64,
+) -> Result<BuildOutput, BuildError> {
+    let mut merged: Option<Config> = None;
+    let mut sources = Vec::with_capacity(paths.len());
+    let mut overrides = Vec::new();
+    for path in paths {
+        let (config, content) = read_config(path)?;
+        sources.push(content);
+        match merged.as_mut() {
+            None => merged = Some(config),
+            Some(base) => overrides.extend(base.merge(config).into_iter().map(|entry| {
+                ValidationIssue::new(
+                    entry,
+                    format!("Overridden by {}", path.display()),
+                    config_validator::Severity::Info,
+                )
+            })),
+        }
+    }
+    let Some(config) = merged else {
+        return Err(BuildError::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "no config paths given",
+        )));
+    };
+    let mut output = build_from_config(&config, &sources.join("\n"), generated_at)?;
+    output.diagnostics.extend(overrides);
+    output.summary = BuildSummary::new(&output.bundle, &output.diagnostics);
+    Ok(output)
+}
+
+/// Reads the config at `path` with its includes merged in, along with its own source.
+fn read_config(path: &Path) -> Result<(Config, String), BuildError> {
+    let content = read_source(path, io::stdin().lock())?;
+    let (config, _) = parse_config_with_includes(path, &content)?;
+    Ok((config, content))
+}
+
+/// True when `bytes` look like a JSON cache rather than bincode.
+pub fn is_json_cache(bytes: &[u8]) -> bool {
+    bytes.first() == Some(&b'{')
+}
+
+/// Serializes a bundle as `.cache` bytes: the bincode header, then the rest of the
+/// bundle encoded as `header.compression` says. The written header always carries
+/// the payload's current checksum.
+pub fn encode_bundle(bundle: &CacheBundle) -> Result<Vec<u8>, BuildError> {
+    let raw = bincode::serialize(bundle)?;
+    let split = bincode::serialized_size(&bundle.header)? as usize;
+    let header = CacheHeader {
+        payload_crc: payload_checksum(&raw[split..]),
+        ..bundle.header.clone()
+    };
+    let mut bytes = bincode::serialize(&header)?;
+    match header.compression {
+        CompressionKind::None => bytes.extend_from_slice(&raw[split..]),
+        CompressionKind::Zstd => bytes.extend(zstd::encode_all(&raw[split..], 0)?),
+    }
+    Ok(bytes)
+}
+
+/// Reads `.cache` bytes written by [`encode_bundle`], decompressing as the header
+/// says and rejecting payloads whose checksum does not match. JSON caches from
+/// [`build_from_path_json`] are parsed as-is.
+pub fn decode_bundle(bytes: &[u8]) -> Result<CacheBundle, BuildError> {
+    if is_json_cache(bytes) {
+        return Ok(serde_json::from_slice(bytes)?);
+    }
+    let header: CacheHeader =
+        bincode::deserialize(bytes).map_err(|_| BuildError::Corrupt("truncated header".into()))?;
+    let split = bincode::serialized_size(&header)? as usize;
+    let mut raw = bytes[..split].to_vec();
+    raw.extend(decode_payload(&header, &bytes[split..])?);
+    Ok(bincode::deserialize(&raw)?)
+}
+
+/// Decompresses the bytes after `header` and checks them against `payload_crc`.
+pub fn decode_payload(header: &CacheHeader, payload: &[u8]) -> Result<Vec<u8>, BuildError> {
+    let raw = match header.compression {
+        CompressionKind::None => payload.to_vec(),
+        CompressionKind::Zstd => zstd::decode_all(payload)
+            .map_err(|err| BuildError::Corrupt(format!("zstd payload: {err}")))?,
+    };
+    let actual = payload_checksum(&raw);
+    if actual != header.payload_crc {
+        return Err(BuildError::Corrupt(format!(
+            "payload checksum {actual:08x} does not match header {:08x}",
+            header.payload_crc
+        )));
+    }
+    Ok(raw)
+}
+
+/// CRC-32 of the bincode payload after `bundle`'s header, as stored in `payload_crc`.
+pub fn payload_crc(bundle: &CacheBundle) -> u32 {
+    let raw = bincode::serialize(bundle).unwrap_or_default();
+    let split = bincode::serialized_size(&bundle.header).unwrap_or_default() as usize;
+    payload_checksum(raw.get(split..).unwrap_or_default())
+}
+
+/// CRC-32 used for `payload_crc`, over an uncompressed payload.
+pub fn payload_checksum(payload: &[u8]) -> u32 {
+    crc32fast::hash(payload)
+}
+
 pub fn build_from_str(content: &str) -> Result<BuildOutput, BuildError> {
+    build_from_str_with_time(content, unix_now())
+}
+
+/// Like [`build_from_str`], but stamps `generated_at` (UNIX seconds) into the header
+/// instead of the current time.
+pub fn build_from_str_with_time(
+    content: &str,
+    generated_at: u64,
+) -> Result<BuildOutput, BuildError> {
     let config = parse_config_str(content)?;
-    build_from_config(&config, content)
+    build_from_config(&config, content, generated_at)
 }
 
-fn build_from_config(config: &Config, source: &str) -> Result<BuildOutput, BuildError> {
+/// Current UNIX time in seconds, the default `generated_at`.
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Loads a bundle for inspection from either a built `.cache` file or a YAML config.
+/// Configs are assembled without the validation gate so reports still work on
+/// configs that would fail to build.
+pub fn load_bundle(path: impl AsRef<Path>) -> Result<CacheBundle, BuildError> {
+    let path_ref = path.as_ref();
+    if path_ref.extension().is_some_and(|ext| ext == "cache") {
+        return decode_bundle(&fs::read(path_ref)?);
+    }
+    let (config, content) = read_config(path_ref)?;
+    Ok(assemble_bundle(&config, &content, unix_now()))
+}
+
+pub fn bundle_from_str(content: &str) -> Result<CacheBundle, BuildError> {
+    let config = parse_config_str(content)?;
+    Ok(assemble_bundle(&config, content, unix_now()))
+}
+
+/// Assembles an already-parsed, already-validated `config` without the validation
+/// gate, stamping `generated_at` into the header.
+pub fn bundle_from_config(config: &Config, source: &str, generated_at: u64) -> CacheBundle {
+    assemble_bundle(config, source, generated_at)
+}
+
+/// Re-emits a config source in canonical form: every mapping is sorted by key and
+/// unset optional fields are dropped, so formatting an already-canonical file is a no-op.
+pub fn canonicalize_str(content: &str) -> Result<String, BuildError> {
+    let config = parse_config_str(content)?;
+    canonicalize_config(&config)
+}
+
+pub fn canonicalize_config(config: &Config) -> Result<String, BuildError> {
+    let value = sort_mappings(serde_yaml::to_value(config)?);
+    Ok(serde_yaml::to_string(&value)?)
+}
+
+fn sort_mappings(value: serde_yaml::Value) -> serde_yaml::Value {
+    use serde_yaml::Value;
+
+    match value {
+        Value::Mapping(map) => {
+            let mut entries: Vec<_> = map
+                .into_iter()
+                .map(|(key, value)| (key, sort_mappings(value)))
+                .collect();
+            entries.sort_by_cached_key(|(key, _)| match key {
+                Value::String(s) => s.clone(),
+                other => serde_yaml::to_string(other).unwrap_or_default(),
+            });
+            Value::Mapping(entries.into_iter().collect())
+        }
+        Value::Sequence(items) => Value::Sequence(items.into_iter().map(sort_mappings).collect()),
+        Value::Tagged(tagged) => {
+            let tagged = *tagged;
+            Value::Tagged(Box::new(serde_yaml::value::TaggedValue {
+                tag: tagged.tag,
+                value: sort_mappings(tagged.value),
+            }))
+        }
+        other => other,
+    }
+}
+
+/// Validates and assembles an already-parsed `config`, read from `source`, with
+/// `generated_at` stamped into the header. The caller encodes the returned bundle.
+pub fn build_from_config(
+    config: &Config,
+    source: &str,
+    generated_at: u64,
+) -> Result<BuildOutput, BuildError> {
     let diagnostics = validate_config(config, source);
     if diagnostics
         .iter()
@@ -55,22 +390,57 @@ fn build_from_config(config: &Config, source: &str) -> Result<BuildOutput, Build
         return Err(BuildError::Validation(diagnostics));
     }
 
-    let bundle = assemble_bundle(config, source);
+    let bundle = assemble_bundle(config, source, generated_at);
+    let summary = BuildSummary::new(&bundle, &diagnostics);
     Ok(BuildOutput {
         bundle,
         diagnostics,
+        summary,
     })
 }
 
-fn assemble_bundle(config: &Config, source: &str) -> CacheBundle {
+/// Builds like [`build_from_str`], then hands the assembled bundle to `extend` so
+/// programmatic pipelines can inject or rewrite entries before serialization.
+/// The bundle is re-checked with [`CacheBundle::validate`] afterwards; any
+/// inconsistency that wasn't already present before `extend` ran is an error.
+pub fn build_from_config_with<F>(
+    config: &Config,
+    source: &str,
+    extend: F,
+) -> Result<BuildOutput, BuildError>
+where
+    F: FnOnce(&mut CacheBundle),
+{
+    let mut output = build_from_config(config, source, unix_now())?;
+    let before = output.bundle.validate();
+    extend(&mut output.bundle);
+    output.bundle.header.payload_crc = payload_crc(&output.bundle);
+    output.summary = BuildSummary::new(&output.bundle, &output.diagnostics);
+    let introduced: Vec<_> = output
+        .bundle
+        .validate()
+        .into_iter()
+        .filter(|issue| !before.contains(issue))
+        .collect();
+    if !introduced.is_empty() {
+        return Err(BuildError::Inconsistent(introduced));
+    }
+    Ok(output)
+}
+
+fn assemble_bundle(config: &Config, source: &str, generated_at: u64) -> CacheBundle {
     let source_hash = xxh3_64(source.as_bytes());
-    let generated_at = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs();
 
-    let devices = convert_devices(&config.devices);
-    let macros = config
+    let defaults = config
+        .global
+        .as_ref()
+        .and_then(|global| global.defaults.as_ref());
+    let double_tap_window_ms = defaults
+        .and_then(|defaults| defaults.double_tap_window_ms)
+        .unwrap_or(DEFAULT_DOUBLE_TAP_WINDOW_MS);
+    let devices = convert_devices(&config.devices, double_tap_window_ms);
+    let default_max_duration = defaults.and_then(|defaults| defaults.max_macro_duration_ms);
+    let mut macros: Vec<_> = config
         .macros
         .iter()
         .filter(|(_, m)| m.status == MacroStatus::Ready)
@@ -78,82 +448,307 @@ fn assemble_bundle(config: &Config, source: &str) -> CacheBundle {
             id: id.clone(),
             description: m.description.clone(),
             tags: m.tags.clone(),
-            trigger: m.trigger.as_ref().map(convert_trigger),
+            trigger: m
+                .trigger
+                .as_ref()
+                .and_then(|t| convert_trigger(t, &config.note_aliases)),
             steps: m.steps.iter().map(convert_macro_step).collect(),
+            max_duration_ms: m.max_duration_ms.or(default_max_duration),
+            on_error: m.on_error.as_ref().map(|policy| match policy {
+                SchemaErrorPolicy::Continue => ErrorPolicy::Continue,
+                SchemaErrorPolicy::Abort => ErrorPolicy::Abort,
+                SchemaErrorPolicy::RunMacro(id) => ErrorPolicy::RunMacro(id.clone()),
+            }),
+            cooldown_ms: m.cooldown_ms,
+        })
+        .collect();
+    macros.sort_by(|a, b| a.id.cmp(&b.id));
+    let referenced_scripts = config.referenced_scripts();
+    let mut scripts: Vec<_> = config
+        .scripts
+        .iter()
+        .filter(|(id, _)| referenced_scripts.contains(id.as_str()))
+        .map(|(id, script)| ScriptEntry {
+            id: id.clone(),
+            language: script.language().map(str::to_string),
+            body: script.body().to_string(),
+        })
+        .collect();
+    scripts.sort_by(|a, b| a.id.cmp(&b.id));
+    let mut profiles: Vec<_> = config
+        .profiles
+        .iter()
+        .map(|(name, members)| Profile {
+            name: name.clone(),
+            macros: members
+                .iter()
+                .filter(|id| {
+                    config
+                        .macros
+                        .get(*id)
+                        .is_some_and(|m| m.status == MacroStatus::Ready)
+                })
+                .cloned()
+                .collect(),
         })
         .collect();
+    profiles.sort_by(|a, b| a.name.cmp(&b.name));
+    let hooks = config
+        .global
+        .as_ref()
+        .map(|global| ExecutionHooks {
+            on_before: global.on_before.clone(),
+            on_after: global.on_after.clone(),
+        })
+        .unwrap_or_default();
 
-    CacheBundle {
+    let mut bundle = CacheBundle {
         header: CacheHeader {
             version: CACHE_VERSION,
             source_hash,
             generated_at,
+            compression: CompressionKind::None,
+            payload_crc: 0,
         },
         devices,
         macros,
-    }
+        scripts,
+        profiles,
+        hooks,
+        defaults: CacheDefaults {
+            tap_hold_timeout_ms: defaults
+                .and_then(|defaults| defaults.tap_hold_timeout_ms)
+                .unwrap_or(DEFAULT_TAP_HOLD_TIMEOUT_MS),
+            theme: defaults
+                .and_then(|defaults| defaults.display.as_ref())
+                .and_then(|display| display.theme.clone()),
+            allow_commands: defaults.is_some_and(|defaults| defaults.allow_commands),
+        },
+    };
+    bundle.header.payload_crc = payload_crc(&bundle);
+    bundle
 }
 
 fn convert_macro_step(step: &SchemaMacroStep) -> MacroStep {
     match step {
         SchemaMacroStep::Keystroke { keys } => MacroStep::Keystroke { keys: keys.clone() },
-        SchemaMacroStep::Pause { ms } => MacroStep::Pause { ms: *ms },
+        SchemaMacroStep::Text { text } => MacroStep::Text { text: text.clone() },
+        SchemaMacroStep::Repeat { count, steps } => MacroStep::Repeat {
+            count: *count,
+            steps: steps.iter().map(convert_macro_step).collect(),
+        },
+        SchemaMacroStep::RunMacro { id } => MacroStep::RunMacro { id: id.clone() },
+        SchemaMacroStep::RunScript { id } => MacroStep::RunScript { id: id.clone() },
+        SchemaMacroStep::MidiOut { message } => MacroStep::MidiOut {
+            message: message.clone(),
+        },
+        SchemaMacroStep::VelocityGate { min, max } => MacroStep::VelocityGate {
+            min: *min,
+            max: *max,
+        },
+        SchemaMacroStep::MouseMove { x, y, relative } => MacroStep::MouseMove {
+            x: *x,
+            y: *y,
+            relative: *relative,
+        },
+        SchemaMacroStep::MouseClick { button } => MacroStep::MouseClick {
+            button: match button {
+                SchemaMouseButton::Left => MouseButton::Left,
+                SchemaMouseButton::Right => MouseButton::Right,
+                SchemaMouseButton::Middle => MouseButton::Middle,
+            },
+        },
+        SchemaMacroStep::RunCommand { program, args } => MacroStep::RunCommand {
+            program: program.clone(),
+            args: args.clone(),
+        },
+        SchemaMacroStep::KeyDown { key } => MacroStep::KeyDown { key: key.clone() },
+        SchemaMacroStep::KeyUp { key } => MacroStep::KeyUp { key: key.clone() },
+        SchemaMacroStep::RandomPause { min_ms, max_ms } => MacroStep::RandomPause {
+            min_ms: *min_ms,
+            max_ms: *max_ms,
+        },
+        SchemaMacroStep::PauseMicros { us } => MacroStep::PauseMicros { us: *us },
+        SchemaMacroStep::Reset => MacroStep::Reset,
+        SchemaMacroStep::SetVar { name, value } => MacroStep::SetVar {
+            name: name.clone(),
+            value: *value,
+        },
+        SchemaMacroStep::If {
+            var,
+            equals,
+            then,
+            else_,
+        } => MacroStep::If {
+            var: var.clone(),
+            equals: *equals,
+            then: then.iter().map(convert_macro_step).collect(),
+            else_: else_.iter().map(convert_macro_step).collect(),
+        },
+        SchemaMacroStep::Pause { ms, label } => MacroStep::Pause {
+            ms: *ms,
+            label: label.clone(),
+        },
+        SchemaMacroStep::Atomic { steps } => MacroStep::Atomic {
+            steps: steps.iter().map(convert_macro_step).collect(),
+        },
+        SchemaMacroStep::WaitForMidi {
+            note,
+            timeout_ms,
+            continue_on_timeout,
+        } => MacroStep::WaitForMidi {
+            note: *note,
+            timeout_ms: *timeout_ms,
+            continue_on_timeout: *continue_on_timeout,
+        },
     }
 }
 
-fn convert_trigger(trigger: &SchemaTrigger) -> MidiTrigger {
-    MidiTrigger {
-        r#type: match trigger.r#type {
-            SchemaTriggerType::Note => MidiTriggerType::Note,
+fn convert_trigger(
+    trigger: &SchemaTrigger,
+    aliases: &std::collections::HashMap<String, u8>,
+) -> Option<MidiTrigger> {
+    let transport = trigger.transport.map(|kind| match kind {
+        SchemaTransportKind::Start => TransportKind::Start,
+        SchemaTransportKind::Stop => TransportKind::Stop,
+        SchemaTransportKind::Continue => TransportKind::Continue,
+    });
+    Some(match trigger.r#type {
+        SchemaTriggerType::Note => MidiTrigger {
+            r#type: MidiTriggerType::Note,
+            number: trigger.resolve_number(aliases)?,
+            transport: None,
+            channel: trigger.channel,
+            threshold: None,
         },
-        number: trigger.number,
-    }
+        SchemaTriggerType::ControlChange => MidiTrigger {
+            r#type: MidiTriggerType::ControlChange,
+            number: trigger.resolve_number(aliases)?,
+            transport: None,
+            channel: trigger.channel,
+            threshold: None,
+        },
+        SchemaTriggerType::ProgramChange => MidiTrigger {
+            r#type: MidiTriggerType::ProgramChange,
+            number: trigger.resolve_number(aliases)?,
+            transport: None,
+            channel: trigger.channel,
+            threshold: None,
+        },
+        SchemaTriggerType::Transport => MidiTrigger {
+            r#type: MidiTriggerType::Transport,
+            number: 0,
+            transport: Some(transport?),
+            channel: None,
+            threshold: None,
+        },
+        SchemaTriggerType::NoteRange => {
+            let (low, high) = (trigger.low?, trigger.high?);
+            MidiTrigger {
+                r#type: MidiTriggerType::NoteRange { low, high },
+                number: low,
+                transport: None,
+                channel: trigger.channel,
+                threshold: None,
+            }
+        }
+        SchemaTriggerType::Chord => MidiTrigger {
+            r#type: MidiTriggerType::Chord {
+                notes: trigger.notes.clone(),
+                window_ms: trigger.window_ms.unwrap_or(DEFAULT_CHORD_WINDOW_MS),
+            },
+            number: *trigger.notes.first()?,
+            transport: None,
+            channel: trigger.channel,
+            threshold: None,
+        },
+        SchemaTriggerType::PitchBend => MidiTrigger {
+            r#type: MidiTriggerType::PitchBend,
+            number: 0,
+            transport: None,
+            channel: trigger.channel,
+            threshold: Some(ThresholdCrossing {
+                value: trigger.threshold?,
+                direction: match trigger.direction {
+                    Some(SchemaDirection::Falling) => CrossingDirection::Falling,
+                    Some(SchemaDirection::Rising) | None => CrossingDirection::Rising,
+                },
+            }),
+        },
+    })
 }
 
-fn convert_devices(devices: &std::collections::HashMap<String, Device>) -> Vec<DeviceLayout> {
+fn convert_devices(
+    devices: &std::collections::HashMap<String, Device>,
+    double_tap_window_ms: u64,
+) -> Vec<DeviceLayout> {
     let mut list: Vec<_> = devices.iter().collect();
-    list.sort_by(|(a, _), (b, _)| a.cmp(b));
+    list.sort_by_key(|(id, _)| *id);
 
     list.into_iter()
         .map(|(id, device)| DeviceLayout {
             id: id.clone(),
             hardware_id: device.hardware_id.clone(),
-            pages: convert_pages(&device.pages),
+            pages: convert_pages(&device.pages, double_tap_window_ms),
+            reserved_notes: device.reserved_notes.clone(),
         })
         .collect()
 }
 
-fn convert_pages(pages: &[Page]) -> Vec<LayoutPage> {
+fn convert_pages(pages: &[Page], double_tap_window_ms: u64) -> Vec<LayoutPage> {
     pages
         .iter()
         .map(|page| LayoutPage {
             name: page.name.clone(),
-            widgets: convert_widgets(&page.widgets),
+            widgets: convert_widgets(&page.widgets, double_tap_window_ms),
         })
         .collect()
 }
 
-fn convert_widgets(widgets: &[SchemaWidget]) -> Vec<LayoutWidget> {
+fn convert_widgets(widgets: &[SchemaWidget], double_tap_window_ms: u64) -> Vec<LayoutWidget> {
     widgets
         .iter()
         .map(|widget| LayoutWidget {
             id: widget.id.clone(),
             tap_behavior: widget.tap_behavior.clone(),
+            behavior: parse_tap_behavior(widget.tap_behavior.as_deref()),
             action: widget.action.as_ref().map(convert_action),
+            mode: widget.mode.map(|mode| match mode {
+                SchemaActionMode::Momentary => ActionMode::Momentary,
+                SchemaActionMode::Latching => ActionMode::Latching,
+            }),
+            off_action: widget.off_action.as_ref().map(convert_action),
+            double_tap_action: widget.double_tap_action.as_ref().map(convert_action),
+            double_tap_window_ms,
         })
         .collect()
 }
 
+/// Unknown values are rejected by validation, so they only reach here from
+/// unvalidated sources and fall back to `Tap`.
+pub fn parse_tap_behavior(value: Option<&str>) -> TapBehavior {
+    match value {
+        Some("hold") => TapBehavior::Hold,
+        Some("toggle") => TapBehavior::Toggle,
+        _ => TapBehavior::Tap,
+    }
+}
+
 fn convert_action(action: &Action) -> WidgetAction {
     match action {
         Action::Macro { ref_ } => WidgetAction::Macro { id: ref_.clone() },
         Action::Script { ref_ } => WidgetAction::Script { id: ref_.clone() },
+        Action::PageSwitch { device, page } => WidgetAction::PageSwitch {
+            device: device.clone(),
+            page: page.clone(),
+        },
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use config_validator::Severity;
 
     #[test]
     fn builds_ready_macros_only() {
@@ -192,6 +787,365 @@ scripts: {}
             }
             _ => panic!("unexpected step"),
         }
-        assert!(output.diagnostics.is_empty());
+        let notes = config_validator::without_lints(output.diagnostics);
+        let paths: Vec<_> = notes.iter().map(|d| d.path.as_str()).collect();
+        assert_eq!(paths, vec!["macros.draft"]);
+        assert_eq!(notes[0].severity, Severity::Info);
+    }
+
+    #[test]
+    fn trigger_alias_resolves_to_note_number() {
+        let yaml = r#"version: 1
+note_aliases:
+  kick: 36
+  snare: 38
+devices: {}
+macros:
+  drum:
+    status: ready
+    trigger:
+      type: note
+      number: snare
+    steps:
+      - type: keystroke
+        keys: ["D"]
+scripts: {}
+"#;
+        let output = build_from_str(yaml).expect("build");
+        assert_eq!(output.bundle.macros[0].trigger.as_ref().unwrap().number, 38);
+    }
+
+    #[test]
+    fn script_language_reaches_cache() {
+        let yaml = r#"version: 1
+devices:
+  pad:
+    hardware_id: "usb:pad"
+    pages:
+      - name: main
+        widgets:
+          - id: fader
+            action: { type: script, ref: fade }
+macros:
+  hello:
+    status: ready
+    steps:
+      - type: run_script
+        id: legacy
+scripts:
+  fade:
+    language: lua
+    body: "fade(0.5)"
+  legacy: "echo hi"
+  unused: "echo never"
+"#;
+        let output = build_from_str(yaml).expect("build");
+        assert!(
+            output
+                .diagnostics
+                .iter()
+                .any(|issue| issue.path == "scripts.unused" && issue.severity == Severity::Info)
+        );
+        assert_eq!(
+            output.bundle.scripts,
+            vec![
+                ScriptEntry {
+                    id: "fade".into(),
+                    language: Some("lua".into()),
+                    body: "fade(0.5)".into(),
+                },
+                ScriptEntry {
+                    id: "legacy".into(),
+                    language: None,
+                    body: "echo hi".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn summary_counts_steps_of_ready_macros() {
+        let yaml = r#"version: 1
+devices:
+  pad:
+    hardware_id: "usb:pad"
+macros:
+  copy:
+    status: ready
+    trigger: { type: note, number: 60 }
+    steps:
+      - type: keystroke
+        keys: ["Ctrl", "C"]
+      - type: repeat
+        count: 2
+        steps:
+          - type: text
+            text: "x"
+          - type: text
+            text: "y"
+  paste:
+    status: ready
+    trigger: { type: note, number: 60 }
+    steps:
+      - type: keystroke
+        keys: ["Ctrl", "V"]
+  draft:
+    status: draft
+    steps:
+      - type: keystroke
+        keys: ["X"]
+"#;
+        let output = build_from_str(yaml).expect("build");
+        let expected: usize = output.bundle.macros.iter().map(|m| m.steps.len()).sum();
+        assert_eq!(output.summary.total_steps, expected);
+        assert_eq!(
+            output.summary,
+            BuildSummary {
+                macro_count: 2,
+                device_count: 1,
+                total_steps: 3,
+                // Both ready macros share note 60.
+                warnings: output
+                    .diagnostics
+                    .iter()
+                    .filter(|d| d.severity == Severity::Warning)
+                    .count(),
+                errors: 0,
+            }
+        );
+        assert!(output.summary.warnings > 0);
+        assert_eq!(
+            output.summary.to_string(),
+            format!(
+                "2 macros (3 steps), 1 devices, {} warnings, 0 errors",
+                output.summary.warnings
+            )
+        );
+    }
+
+    #[test]
+    fn build_hook_injects_macro_and_revalidates() {
+        let yaml = r#"version: 1
+devices: {}
+macros: {}
+"#;
+        let config = parse_config_str(yaml).expect("parse");
+        let generated = |id: &str| MacroEntry {
+            id: id.into(),
+            description: Some("generated".into()),
+            tags: vec![],
+            trigger: None,
+            steps: vec![MacroStep::Keystroke {
+                keys: vec!["F13".into()],
+            }],
+            max_duration_ms: None,
+            on_error: None,
+            cooldown_ms: None,
+        };
+
+        let output = build_from_config_with(&config, yaml, |bundle| {
+            bundle.macros.push(generated("synthetic"));
+        })
+        .expect("build");
+        assert!(output.bundle.macros.iter().any(|m| m.id == "synthetic"));
+        assert_eq!(output.summary.macro_count, 1);
+
+        let err = build_from_config_with(&config, yaml, |bundle| {
+            bundle.macros.push(generated("twin"));
+            bundle.macros.push(generated("twin"));
+        })
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            BuildError::Inconsistent(ref issues)
+                if issues == &[CacheInconsistency::DuplicateMacroId { id: "twin".into() }]
+        ));
+    }
+
+    #[test]
+    fn stdin_source_builds() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  copy:
+    status: ready
+    trigger: { type: note, number: 60 }
+    steps:
+      - type: keystroke
+        keys: ["Ctrl", "C"]
+"#;
+        let content =
+            read_source(Path::new(config_validator::STDIN_PATH), yaml.as_bytes()).expect("read");
+        let output = build_from_str(&content).expect("build");
+        assert_eq!(output.bundle.macros[0].id, "copy");
+    }
+
+    #[test]
+    fn build_report_size_matches_bytes() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  copy:
+    status: ready
+    trigger: { type: note, number: 60 }
+    steps:
+      - type: keystroke
+        keys: ["Ctrl", "C"]
+"#;
+        let output = build_from_str(yaml).expect("build");
+        let bytes = bincode::serialize(&output.bundle).expect("serialize");
+        let report = BuildReport::new(&output.bundle, &bytes);
+        assert_eq!(report.uncompressed_bytes, bytes.len());
+        assert_eq!(report.compressed_bytes, None);
+        assert_eq!(report.compression_ratio(), None);
+        assert_eq!(report.summary(), format!("1 macros, {} bytes", bytes.len()));
+
+        let compressed = BuildReport {
+            macro_count: 1,
+            uncompressed_bytes: 200,
+            compressed_bytes: Some(50),
+        };
+        assert_eq!(compressed.compression_ratio(), Some(0.25));
+        assert_eq!(
+            compressed.summary(),
+            "1 macros, 200 bytes, 50 bytes compressed (25%)"
+        );
+    }
+
+    #[test]
+    fn pinned_build_time_gives_identical_bytes() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  copy:
+    status: ready
+    trigger: { type: note, number: 60 }
+    steps:
+      - type: keystroke
+        keys: ["Ctrl", "C"]
+  paste:
+    status: ready
+    trigger: { type: note, number: 61 }
+    steps:
+      - type: keystroke
+        keys: ["Ctrl", "V"]
+  undo:
+    status: ready
+    steps: []
+"#;
+        let build = || {
+            let output = build_from_str_with_time(yaml, 1_700_000_000).expect("build");
+            assert_eq!(output.bundle.header.generated_at, 1_700_000_000);
+            encode_bundle(&output.bundle).expect("encode")
+        };
+        assert_eq!(build(), build());
+    }
+
+    #[test]
+    fn canonicalize_sorts_keys_and_is_idempotent() {
+        let messy = r#"scripts: {}
+macros:
+  zeta:
+    steps:
+      - keys: ['Ctrl', "S"]
+        type: keystroke
+    trigger: { number: 61, type: note }
+    status: ready
+  alpha:
+    status: draft
+version: 1
+devices: {}
+"#;
+        let canonical = canonicalize_str(messy).expect("canonicalize");
+        assert_eq!(
+            canonical,
+            r#"devices: {}
+macros:
+  alpha:
+    status: draft
+    steps: []
+    tags: []
+  zeta:
+    status: ready
+    steps:
+    - keys:
+      - Ctrl
+      - S
+      type: keystroke
+    tags: []
+    trigger:
+      number: 61
+      type: note
+scripts: {}
+version: 1
+"#
+        );
+        assert_eq!(
+            canonicalize_str(&canonical).expect("re-canonicalize"),
+            canonical
+        );
+    }
+
+    #[test]
+    fn later_paths_override_earlier_entries() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let base = dir.path().join("base.yaml");
+        let local = dir.path().join("local.yaml");
+        fs::write(
+            &base,
+            "version: 1\nmacros:\n  copy:\n    status: ready\n    steps:\n      - type: keystroke\n        keys: [\"Ctrl\", \"C\"]\n  paste:\n    status: ready\n    steps: []\n",
+        )
+        .expect("write base");
+        fs::write(
+            &local,
+            "version: 1\nmacros:\n  copy:\n    status: ready\n    steps:\n      - type: keystroke\n        keys: [\"Cmd\", \"C\"]\n",
+        )
+        .expect("write override");
+
+        let (output, _) = build_from_paths(&[base, local]).expect("build");
+        let ids: Vec<_> = output.bundle.macros.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["copy", "paste"]);
+        match &output.bundle.macros[0].steps[0] {
+            MacroStep::Keystroke { keys } => assert_eq!(keys, &vec!["Cmd", "C"]),
+            other => panic!("unexpected step: {other:?}"),
+        }
+        let overrides: Vec<_> = output
+            .diagnostics
+            .iter()
+            .filter(|issue| issue.message.starts_with("Overridden"))
+            .collect();
+        assert_eq!(overrides.len(), 1);
+        assert_eq!(overrides[0].path, "macros.copy");
+        assert_eq!(overrides[0].severity, Severity::Info);
+    }
+
+    #[test]
+    fn overriding_one_device_field_keeps_the_rest() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let base = dir.path().join("base.yaml");
+        let local = dir.path().join("local.yaml");
+        fs::write(
+            &base,
+            "version: 1\ndevices:\n  pad:\n    hardware_id: \"usb:pad-a\"\n    pages:\n      - name: main\n      - name: mix\nmacros: {}\n",
+        )
+        .expect("write base");
+        fs::write(
+            &local,
+            "version: 1\ndevices:\n  pad:\n    hardware_id: \"usb:pad-b\"\n",
+        )
+        .expect("write override");
+
+        let (output, _) = build_from_paths(&[base, local]).expect("build");
+        let pad = &output.bundle.devices[0];
+        assert_eq!(pad.hardware_id.as_deref(), Some("usb:pad-b"));
+        let pages: Vec<_> = pad.pages.iter().map(|page| page.name.as_str()).collect();
+        assert_eq!(pages, ["main", "mix"]);
+        let overrides: Vec<_> = output
+            .diagnostics
+            .iter()
+            .filter(|issue| issue.message.starts_with("Overridden"))
+            .map(|issue| issue.path.as_str())
+            .collect();
+        assert_eq!(overrides, ["devices.pad.hardware_id"]);
     }
 }
@@ -1,39 +1,108 @@
 use std::fs;
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
 
-use anyhow::{Context, Result};
-use cache_builder::{BuildError, build_from_path};
-use clap::Parser;
-use config_validator::Severity;
+use anyhow::{Context, Result, bail};
+use cache_builder::triggers::{render_trigger_report, trigger_report};
+use cache_builder::{
+    BuildError, BuildReport, build_from_paths_with_time, canonicalize_str, encode_bundle,
+    load_bundle, unix_now,
+};
+use cache_format::CompressionKind;
+use clap::{Parser, ValueEnum};
+use config_validator::{STDIN_PATH, format_diagnostic, read_source};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "Compile configs into cache files", long_about = None)]
 struct Cli {
-    /// Path to YAML configuration bundle
-    config: PathBuf,
-    /// Output cache file path (defaults to config path with .cache)
+    /// Path to YAML configuration bundle, or `-` to read from stdin. Further paths
+    /// are merged over the first in order, later files winning
+    #[arg(required = true)]
+    configs: Vec<PathBuf>,
+    /// Output cache file path (defaults to config path with .cache; required with `-`
+    /// or several configs)
     #[arg(long)]
     out: Option<PathBuf>,
+    /// Print the config in canonical form (or write it to `--out`) instead of building a cache
+    #[arg(long)]
+    format: bool,
+    /// Print every macro trigger, flagging conflicts; accepts a config or a built `.cache`
+    #[arg(long)]
+    triggers: bool,
+    /// Zstd-compress the cache payload (bincode output only)
+    #[arg(long)]
+    compress: bool,
+    /// Encoding of the written cache; `json` is pretty-printed for diffing
+    #[arg(long, value_enum, default_value_t = OutputFormat::Bincode)]
+    output_format: OutputFormat,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Bincode,
+    Json,
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
-    let out_path = cli
-        .out
-        .clone()
-        .or_else(|| Some(default_output_path(&cli.config)));
-    let out_path = out_path.expect("output path");
+    let [config] = cli.configs.as_slice() else {
+        if cli.format || cli.triggers {
+            bail!("--format and --triggers take a single config");
+        }
+        let Some(out) = cli.out.clone() else {
+            bail!("--out is required when merging several configs");
+        };
+        return build(&cli, out);
+    };
+    if cli.format {
+        return format_config(config, cli.out.as_deref());
+    }
+    if cli.triggers {
+        let bundle =
+            load_bundle(config).with_context(|| format!("loading {}", config.display()))?;
+        print!("{}", render_trigger_report(&trigger_report(&bundle)));
+        return Ok(());
+    }
 
-    match build_from_path(&cli.config) {
+    let from_stdin = config == Path::new(STDIN_PATH);
+    let out_path = match cli.out.clone() {
+        Some(out) => out,
+        None if from_stdin => bail!("--out is required when reading the config from stdin"),
+        None => default_output_path(config),
+    };
+    build(&cli, out_path)
+}
+
+/// Builds `cli.configs`, merged in order, into a cache at `out_path`.
+fn build(cli: &Cli, out_path: PathBuf) -> Result<()> {
+    if cli.compress && cli.output_format == OutputFormat::Json {
+        bail!("--compress only applies to bincode output");
+    }
+    let generated_at = source_date_epoch()?.unwrap_or_else(unix_now);
+    let built = build_from_paths_with_time(&cli.configs, generated_at).and_then(|mut output| {
+        let bytes = match cli.output_format {
+            OutputFormat::Json => serde_json::to_vec_pretty(&output.bundle)?,
+            OutputFormat::Bincode => {
+                if cli.compress {
+                    output.bundle.header.compression = CompressionKind::Zstd;
+                }
+                encode_bundle(&output.bundle)?
+            }
+        };
+        Ok((output, bytes))
+    });
+    match built {
         Ok((output, bytes)) => {
             print_diagnostics(&output.diagnostics);
+            let report = BuildReport::new(&output.bundle, &bytes);
             fs::write(&out_path, bytes)
                 .with_context(|| format!("writing cache to {}", out_path.display()))?;
             println!(
-                "Cache generated at {} ({} macros)",
+                "Cache generated at {} ({})",
                 out_path.display(),
-                output.bundle.macros.len()
+                report.summary()
             );
+            println!("Build summary: {}", output.summary);
             Ok(())
         }
         Err(BuildError::Validation(diags)) => {
@@ -41,12 +110,63 @@ fn main() -> Result<()> {
             eprintln!("Cache build failed due to validation errors.");
             std::process::exit(2);
         }
+        Err(BuildError::Parse(err)) => {
+            print_diagnostics(&[err.to_issue()]);
+            eprintln!("Cache build failed: config could not be parsed.");
+            std::process::exit(1);
+        }
         Err(err) => Err(err.into()),
     }
 }
 
-fn default_output_path(config_path: &PathBuf) -> PathBuf {
-    let mut out = config_path.clone();
+/// Canonical form drops comments and unknown keys, so it never overwrites the
+/// config it was read from.
+fn format_config(config_path: &Path, out_path: Option<&Path>) -> Result<()> {
+    let out_path = out_path.filter(|out| *out != Path::new(STDIN_PATH));
+    if let Some(out) = out_path
+        && same_file(config_path, out)
+    {
+        bail!(
+            "refusing to format {} in place: comments and unknown keys would be lost; \
+             pass a different --out and review the result",
+            config_path.display()
+        );
+    }
+    let content = read_source(config_path, io::stdin().lock())
+        .with_context(|| format!("reading config from {}", config_path.display()))?;
+    let canonical = canonicalize_str(&content)?;
+    let Some(out_path) = out_path else {
+        print!("{canonical}");
+        return Ok(());
+    };
+    fs::write(out_path, canonical)
+        .with_context(|| format!("writing canonical config to {}", out_path.display()))?;
+    println!("Canonical config written to {}", out_path.display());
+    Ok(())
+}
+
+/// Build time pinned by `SOURCE_DATE_EPOCH`, for reproducible caches.
+fn source_date_epoch() -> Result<Option<u64>> {
+    let Ok(value) = std::env::var("SOURCE_DATE_EPOCH") else {
+        return Ok(None);
+    };
+    let seconds = value
+        .trim()
+        .parse()
+        .with_context(|| format!("SOURCE_DATE_EPOCH must be UNIX seconds, got {value:?}"))?;
+    Ok(Some(seconds))
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    a == b
+        || matches!(
+            (fs::canonicalize(a), fs::canonicalize(b)),
+            (Ok(a), Ok(b)) if a == b
+        )
+}
+
+fn default_output_path(config_path: &Path) -> PathBuf {
+    let mut out = config_path.to_path_buf();
     out.set_extension("cache");
     out
 }
@@ -57,18 +177,6 @@ fn print_diagnostics(diags: &[config_validator::ValidationIssue]) {
     }
     eprintln!("Diagnostics:");
     for diag in diags {
-        let level = match diag.severity {
-            Severity::Error => "error",
-            Severity::Warning => "warning",
-            Severity::Info => "info",
-        };
-        if let Some(loc) = diag.location {
-            eprintln!(
-                "- [{}] {}: {} (line {}, column {})",
-                level, diag.path, diag.message, loc.line, loc.column
-            );
-        } else {
-            eprintln!("- [{}] {}: {}", level, diag.path, diag.message);
-        }
+        eprintln!("- {}", format_diagnostic(diag));
     }
 }
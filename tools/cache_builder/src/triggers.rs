@@ -0,0 +1,191 @@
+//! Pre-show report of every compiled trigger, with shared triggers flagged.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use cache_format::{CacheBundle, CrossingDirection, MidiTrigger, MidiTriggerType, TransportKind};
+
+/// Sort key: kind rank, numbers or threshold, channel.
+type SlotKey = (u8, Vec<u16>, Option<u8>);
+
+/// One trigger and every macro bound to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TriggerRow {
+    pub trigger: String,
+    pub macros: Vec<String>,
+}
+
+impl TriggerRow {
+    /// More than one macro answers this trigger.
+    pub fn is_conflict(&self) -> bool {
+        self.macros.len() > 1
+    }
+}
+
+/// Groups the bundle's macros by trigger, sorted by trigger kind, number, then channel.
+/// Macros without a trigger are left out.
+pub fn trigger_report(bundle: &CacheBundle) -> Vec<TriggerRow> {
+    let mut slots: BTreeMap<SlotKey, TriggerRow> = BTreeMap::new();
+    for entry in &bundle.macros {
+        let Some(trigger) = &entry.trigger else {
+            continue;
+        };
+        let Some((key, label)) = describe(trigger) else {
+            continue;
+        };
+        slots
+            .entry(key)
+            .or_insert_with(|| TriggerRow {
+                trigger: label,
+                macros: Vec::new(),
+            })
+            .macros
+            .push(entry.id.clone());
+    }
+    slots
+        .into_values()
+        .map(|mut row| {
+            row.macros.sort();
+            row
+        })
+        .collect()
+}
+
+/// Renders the report as an aligned table, marking conflicting rows.
+pub fn render_trigger_report(rows: &[TriggerRow]) -> String {
+    let width = rows
+        .iter()
+        .map(|row| row.trigger.len())
+        .max()
+        .unwrap_or(0)
+        .max("TRIGGER".len());
+    let mut out = format!("{:<width$}  MACROS\n", "TRIGGER");
+    for row in rows {
+        let flag = if row.is_conflict() {
+            "  [conflict]"
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            out,
+            "{:<width$}  {}{}",
+            row.trigger,
+            row.macros.join(", "),
+            flag
+        );
+    }
+    out
+}
+
+fn describe(trigger: &MidiTrigger) -> Option<(SlotKey, String)> {
+    let ((rank, value), label) = match &trigger.r#type {
+        MidiTriggerType::Note => (
+            (0, vec![u16::from(trigger.number)]),
+            format!("note {}", trigger.number),
+        ),
+        MidiTriggerType::ControlChange => (
+            (1, vec![u16::from(trigger.number)]),
+            format!("cc {}", trigger.number),
+        ),
+        MidiTriggerType::Transport => {
+            let (rank, name) = match trigger.transport? {
+                TransportKind::Start => (0, "start"),
+                TransportKind::Continue => (1, "continue"),
+                TransportKind::Stop => (2, "stop"),
+            };
+            ((2, vec![rank]), format!("transport {name}"))
+        }
+        MidiTriggerType::NoteRange { low, high } => (
+            (5, vec![u16::from(*low), u16::from(*high)]),
+            format!("notes {low}-{high}"),
+        ),
+        MidiTriggerType::Chord { notes, .. } => {
+            // Press order does not matter, so `[40, 36]` shares a row with `[36, 40]`.
+            let mut sorted: Vec<u16> = notes.iter().copied().map(u16::from).collect();
+            sorted.sort_unstable();
+            let label = sorted
+                .iter()
+                .map(u16::to_string)
+                .collect::<Vec<_>>()
+                .join("+");
+            ((6, sorted), format!("chord {label}"))
+        }
+        MidiTriggerType::ProgramChange => (
+            (7, vec![u16::from(trigger.number)]),
+            format!("program {}", trigger.number),
+        ),
+        MidiTriggerType::PitchBend => {
+            let crossing = trigger.threshold?;
+            let (rank, op) = match crossing.direction {
+                CrossingDirection::Rising => (3, ">="),
+                CrossingDirection::Falling => (4, "<="),
+            };
+            (
+                (rank, vec![crossing.value]),
+                format!("pitch_bend {op} {}", crossing.value),
+            )
+        }
+    };
+    // Channel-less triggers sort before channel-bound ones on the same number.
+    let label = match trigger.channel {
+        Some(channel) => format!("{label} ch {channel}"),
+        None => label,
+    };
+    Some(((rank, value, trigger.channel), label))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle_from_str;
+
+    #[test]
+    fn report_lists_conflicting_macros_under_shared_note() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  save:
+    status: ready
+    trigger: { type: note, number: 60 }
+    steps: []
+  export:
+    status: ready
+    trigger: { type: note, number: 60 }
+    steps: []
+  undo:
+    status: ready
+    trigger: { type: note, number: 59 }
+    steps: []
+  kick:
+    status: ready
+    trigger: { type: note, number: 60, channel: 9 }
+    steps: []
+"#;
+        let bundle = bundle_from_str(yaml).expect("bundle");
+        let rows = trigger_report(&bundle);
+        assert_eq!(
+            rows,
+            vec![
+                TriggerRow {
+                    trigger: "note 59".into(),
+                    macros: vec!["undo".into()],
+                },
+                TriggerRow {
+                    trigger: "note 60".into(),
+                    macros: vec!["export".into(), "save".into()],
+                },
+                TriggerRow {
+                    trigger: "note 60 ch 9".into(),
+                    macros: vec!["kick".into()],
+                },
+            ]
+        );
+        let table = render_trigger_report(&rows);
+        assert!(table.contains("note 60       export, save  [conflict]"));
+        assert!(
+            !table
+                .lines()
+                .any(|l| l.starts_with("note 59") && l.contains("conflict"))
+        );
+    }
+}
@@ -1,5 +1,5 @@
-use cache_builder::build_from_str;
-use cache_format::{MacroStep, WidgetAction};
+use cache_builder::{build_from_str, decode_bundle, encode_bundle};
+use cache_format::{CompressionKind, MacroStep, TapBehavior, WidgetAction};
 
 #[test]
 fn cache_bundle_matches_ready_macros() {
@@ -56,12 +56,39 @@ scripts: {}
     let widget = &page.widgets[0];
     assert_eq!(widget.id, "pad_1");
     assert_eq!(widget.tap_behavior.as_deref(), Some("tap"));
+    assert_eq!(widget.behavior, TapBehavior::Tap);
     match &widget.action {
         Some(WidgetAction::Macro { id }) => assert_eq!(id, "ready_one"),
         other => panic!("unexpected widget action: {:?}", other),
     }
 
-    match &output.bundle.macros[0].steps[0] {
-        MacroStep::Keystroke { .. } | MacroStep::Pause { .. } => {}
+    assert!(matches!(
+        &output.bundle.macros[0].steps[0],
+        MacroStep::Keystroke { .. } | MacroStep::Pause { .. }
+    ));
+}
+
+#[test]
+fn compressed_cache_round_trips() {
+    let mut yaml = String::from("version: 1\ndevices: {}\nmacros:\n");
+    for index in 0..20 {
+        yaml.push_str(&format!(
+            "  macro_{index}:\n    status: ready\n    trigger: {{ type: note, number: {} }}\n    steps:\n      - type: text\n        text: \"the same words, over and over\"\n",
+            40 + index
+        ));
     }
+
+    let mut bundle = build_from_str(&yaml).expect("build").bundle;
+    let plain = encode_bundle(&bundle).expect("encode");
+    bundle.header.compression = CompressionKind::Zstd;
+    let compressed = encode_bundle(&bundle).expect("encode compressed");
+    assert!(compressed.len() < plain.len());
+
+    assert_eq!(
+        decode_bundle(&compressed).expect("decode compressed"),
+        bundle
+    );
+    let uncompressed = decode_bundle(&plain).expect("decode");
+    assert_eq!(uncompressed.header.compression, CompressionKind::None);
+    assert_eq!(uncompressed.macros, bundle.macros);
 }
@@ -0,0 +1,66 @@
+use std::process::Command;
+
+const COMMENTED: &str = r#"# Studio rig
+version: 1
+macros:
+  save:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["Ctrl", "S"]
+"#;
+
+#[test]
+fn format_prints_to_stdout_and_leaves_the_config_alone() {
+    let dir = tempfile::tempdir().expect("temp dir");
+    let path = dir.path().join("config.yaml");
+    std::fs::write(&path, COMMENTED).expect("write config");
+    let output = Command::new(env!("CARGO_BIN_EXE_cache_builder"))
+        .arg("--format")
+        .arg(&path)
+        .output()
+        .expect("run builder");
+    assert_eq!(output.status.code(), Some(0));
+    let canonical = String::from_utf8(output.stdout).expect("utf-8");
+    assert!(canonical.contains("save:"));
+    assert!(!canonical.contains("# Studio rig"));
+    assert_eq!(
+        std::fs::read_to_string(&path).expect("read config"),
+        COMMENTED
+    );
+}
+
+#[test]
+fn format_refuses_to_overwrite_its_input() {
+    let dir = tempfile::tempdir().expect("temp dir");
+    let path = dir.path().join("config.yaml");
+    std::fs::write(&path, COMMENTED).expect("write config");
+    let output = Command::new(env!("CARGO_BIN_EXE_cache_builder"))
+        .arg("--format")
+        .arg("--out")
+        .arg(dir.path().join(".").join("config.yaml"))
+        .arg(&path)
+        .output()
+        .expect("run builder");
+    assert_ne!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("refusing to format"));
+    assert_eq!(
+        std::fs::read_to_string(&path).expect("read config"),
+        COMMENTED
+    );
+
+    let copy = dir.path().join("canonical.yaml");
+    let status = Command::new(env!("CARGO_BIN_EXE_cache_builder"))
+        .arg("--format")
+        .arg("--out")
+        .arg(&copy)
+        .arg(&path)
+        .status()
+        .expect("run builder");
+    assert!(status.success());
+    assert!(
+        std::fs::read_to_string(&copy)
+            .expect("read copy")
+            .contains("save:")
+    );
+}
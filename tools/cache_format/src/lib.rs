@@ -3,7 +3,19 @@
 use serde::{Deserialize, Serialize};
 
 /// Current cache format version.
-pub const CACHE_VERSION: u32 = 1;
+/// Version 2 added `compression` and `payload_crc` to the header; version 3
+/// appended `CacheBundle::defaults`; version 4 appended `CacheDefaults::allow_commands`
+/// and inserted `MacroStep::RunCommand` before `KeyDown`; version 5 appended
+/// `MacroEntry::cooldown_ms`; version 6 moved `KeyDown`, `KeyUp` and then
+/// `RunCommand` after `RandomPause`, so steps appear in the order they were added.
+/// Append new `MacroStep` variants at the end: bincode encodes them by index.
+pub const CACHE_VERSION: u32 = 6;
+/// Double-tap window used when `global.defaults.double_tap_window_ms` is unset.
+pub const DEFAULT_DOUBLE_TAP_WINDOW_MS: u64 = 300;
+/// Tap/hold threshold used when `global.defaults.tap_hold_timeout_ms` is unset.
+pub const DEFAULT_TAP_HOLD_TIMEOUT_MS: u64 = 200;
+/// Chord press window used when a `chord` trigger sets no `window_ms`.
+pub const DEFAULT_CHORD_WINDOW_MS: u64 = 100;
 
 /// Header stored at the beginning of every cache artifact.
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
@@ -14,6 +26,38 @@ pub struct CacheHeader {
     pub source_hash: u64,
     /// UNIX timestamp (seconds) when cache was generated.
     pub generated_at: u64,
+    /// How the payload after the header is stored.
+    pub compression: CompressionKind,
+    /// CRC-32 of the uncompressed bincode payload that follows the header.
+    pub payload_crc: u32,
+}
+
+/// Global settings resolved from `global.defaults` at build time.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct CacheDefaults {
+    pub tap_hold_timeout_ms: u64,
+    /// Console theme; `None` leaves the choice to the runtime.
+    pub theme: Option<String>,
+    /// Whether `RunCommand` steps may start programs and scripts may run.
+    pub allow_commands: bool,
+}
+
+impl Default for CacheDefaults {
+    fn default() -> Self {
+        Self {
+            tap_hold_timeout_ms: DEFAULT_TAP_HOLD_TIMEOUT_MS,
+            theme: None,
+            allow_commands: false,
+        }
+    }
+}
+
+/// Encoding of the bincode payload that follows the header in a `.cache` file.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Default)]
+pub enum CompressionKind {
+    #[default]
+    None,
+    Zstd,
 }
 
 /// Root structure serialized into cache file.
@@ -24,7 +68,240 @@ pub struct CacheBundle {
     pub devices: Vec<DeviceLayout>,
     /// Compiled macros that are safe to execute at runtime.
     pub macros: Vec<MacroEntry>,
-    // TODO: add device layouts, scripts, overlays, etc.
+    /// Script bodies available to widget actions and macro steps.
+    pub scripts: Vec<ScriptEntry>,
+    /// Named macro sets the executor can switch between.
+    pub profiles: Vec<Profile>,
+    /// Macros run around every execution (`global.on_before`/`global.on_after`).
+    pub hooks: ExecutionHooks,
+    /// `global.defaults` values the runtime honors.
+    pub defaults: CacheDefaults,
+    // TODO: add overlays, etc.
+}
+
+/// A script carried into the cache for the runtime's script host.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct ScriptEntry {
+    pub id: String,
+    pub language: Option<String>,
+    pub body: String,
+}
+
+/// A named set of macro ids that can be activated as a group.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub macros: Vec<String>,
+}
+
+/// Internal inconsistency found by [`CacheBundle::validate`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum CacheInconsistency {
+    /// Two macro entries share the same id.
+    DuplicateMacroId { id: String },
+    /// A widget action points at a macro id missing from the bundle.
+    DanglingWidgetMacro {
+        device_id: String,
+        page_name: String,
+        widget_id: String,
+        macro_id: String,
+    },
+    /// A `PageSwitch` widget action names a device or page missing from the bundle.
+    DanglingPageSwitch {
+        device_id: String,
+        page_name: String,
+        widget_id: String,
+        target_device: String,
+        target_page: String,
+    },
+    /// An execution hook points at a macro id missing from the bundle.
+    DanglingHookMacro {
+        hook: &'static str,
+        macro_id: String,
+    },
+    /// A profile lists a macro id missing from the bundle.
+    DanglingProfileMacro { profile: String, macro_id: String },
+    /// A `RunMacro` step calls a macro id missing from the bundle.
+    DanglingStepMacro { macro_id: String, called: String },
+    /// A MIDI trigger number, note-range bound or chord note is outside 0–127.
+    TriggerOutOfRange { macro_id: String, number: u8 },
+}
+
+impl CacheBundle {
+    /// Cheap structural check independent of the source config. Guards against
+    /// hand-built or migrated bundles that never went through the validator.
+    pub fn validate(&self) -> Vec<CacheInconsistency> {
+        let mut issues = Vec::new();
+        let mut ids = std::collections::HashSet::new();
+
+        for entry in &self.macros {
+            if !ids.insert(entry.id.as_str()) {
+                issues.push(CacheInconsistency::DuplicateMacroId {
+                    id: entry.id.clone(),
+                });
+            }
+            if let Some(trigger) = &entry.trigger {
+                let mut numbers = vec![trigger.number];
+                match &trigger.r#type {
+                    MidiTriggerType::NoteRange { low, high } => numbers.extend([*low, *high]),
+                    MidiTriggerType::Chord { notes, .. } => numbers.extend(notes),
+                    _ => {}
+                }
+                numbers.sort_unstable();
+                numbers.dedup();
+                for number in numbers.into_iter().filter(|number| *number > 127) {
+                    issues.push(CacheInconsistency::TriggerOutOfRange {
+                        macro_id: entry.id.clone(),
+                        number,
+                    });
+                }
+            }
+        }
+
+        for entry in &self.macros {
+            for called in entry.called_macros() {
+                if !ids.contains(called) {
+                    issues.push(CacheInconsistency::DanglingStepMacro {
+                        macro_id: entry.id.clone(),
+                        called: called.to_string(),
+                    });
+                }
+            }
+        }
+
+        for device in &self.devices {
+            for page in &device.pages {
+                for widget in &page.widgets {
+                    for action in [
+                        &widget.action,
+                        &widget.off_action,
+                        &widget.double_tap_action,
+                    ] {
+                        match action {
+                            Some(WidgetAction::Macro { id }) if !ids.contains(id.as_str()) => {
+                                issues.push(CacheInconsistency::DanglingWidgetMacro {
+                                    device_id: device.id.clone(),
+                                    page_name: page.name.clone(),
+                                    widget_id: widget.id.clone(),
+                                    macro_id: id.clone(),
+                                });
+                            }
+                            Some(WidgetAction::PageSwitch {
+                                device: target_device,
+                                page: target_page,
+                            }) if !self.has_page(target_device, target_page) => {
+                                issues.push(CacheInconsistency::DanglingPageSwitch {
+                                    device_id: device.id.clone(),
+                                    page_name: page.name.clone(),
+                                    widget_id: widget.id.clone(),
+                                    target_device: target_device.clone(),
+                                    target_page: target_page.clone(),
+                                });
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        }
+
+        for (hook, macro_id) in [
+            ("on_before", &self.hooks.on_before),
+            ("on_after", &self.hooks.on_after),
+        ] {
+            if let Some(macro_id) = macro_id
+                && !ids.contains(macro_id.as_str())
+            {
+                issues.push(CacheInconsistency::DanglingHookMacro {
+                    hook,
+                    macro_id: macro_id.clone(),
+                });
+            }
+        }
+
+        for profile in &self.profiles {
+            for macro_id in &profile.macros {
+                if !ids.contains(macro_id.as_str()) {
+                    issues.push(CacheInconsistency::DanglingProfileMacro {
+                        profile: profile.name.clone(),
+                        macro_id: macro_id.clone(),
+                    });
+                }
+            }
+        }
+
+        issues
+    }
+
+    /// True when device `device_id` has a page named `page_name`.
+    pub fn has_page(&self, device_id: &str, page_name: &str) -> bool {
+        self.devices
+            .iter()
+            .filter(|device| device.id == device_id)
+            .flat_map(|device| &device.pages)
+            .any(|page| page.name == page_name)
+    }
+
+    /// Macros a device can end up running: those its widgets reference, plus
+    /// whatever those pull in (the global hooks wrap every execution, and
+    /// `RunMacro` steps call into other macros). Note
+    /// triggers are not scoped to a device in the cache, so they are not counted.
+    /// Returned in bundle order; empty for an unknown device.
+    pub fn macros_reachable_from_device(&self, device_id: &str) -> Vec<&MacroEntry> {
+        let Some(device) = self.devices.iter().find(|device| device.id == device_id) else {
+            return Vec::new();
+        };
+        let known: std::collections::HashMap<&str, &MacroEntry> = self
+            .macros
+            .iter()
+            .map(|entry| (entry.id.as_str(), entry))
+            .collect();
+
+        let mut pending: Vec<&str> = device
+            .pages
+            .iter()
+            .flat_map(|page| &page.widgets)
+            .flat_map(|widget| {
+                [
+                    &widget.action,
+                    &widget.off_action,
+                    &widget.double_tap_action,
+                ]
+            })
+            .filter_map(|action| match action {
+                Some(WidgetAction::Macro { id }) => Some(id.as_str()),
+                _ => None,
+            })
+            .collect();
+        let mut reached = std::collections::HashSet::new();
+        while let Some(id) = pending.pop() {
+            let Some(entry) = known.get(id) else {
+                continue;
+            };
+            if !reached.insert(id) {
+                continue;
+            }
+            pending.extend(
+                [&self.hooks.on_before, &self.hooks.on_after]
+                    .into_iter()
+                    .flatten()
+                    .map(String::as_str),
+            );
+            pending.extend(entry.called_macros());
+        }
+
+        self.macros
+            .iter()
+            .filter(|entry| reached.contains(entry.id.as_str()))
+            .collect()
+    }
+}
+
+/// Macro ids the executor runs before and after every other macro.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct ExecutionHooks {
+    pub on_before: Option<String>,
+    pub on_after: Option<String>,
 }
 
 /// A compiled macro ready for runtime execution.
@@ -35,18 +312,143 @@ pub struct MacroEntry {
     pub tags: Vec<String>,
     pub trigger: Option<MidiTrigger>,
     pub steps: Vec<MacroStep>,
+    /// Upper bound on run time; the executor cancels the macro once exceeded.
+    pub max_duration_ms: Option<u64>,
+    /// Reaction to a failing step; `None` aborts the macro.
+    pub on_error: Option<ErrorPolicy>,
+    /// Minimum time between triggered starts; earlier re-triggers are ignored.
+    pub cooldown_ms: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorPolicy {
+    Continue,
+    Abort,
+    /// Stop the macro and run this fallback macro instead.
+    RunMacro(String),
+}
+
+impl MacroEntry {
+    /// Estimated run time in milliseconds: the sum of all pauses plus `per_key_ms`
+    /// for every keystroke and typed character. Useful for progress hints, not exact scheduling.
+    pub fn nominal_duration_ms(&self, per_key_ms: u64) -> u64 {
+        steps_duration_ms(&self.steps, per_key_ms)
+    }
+
+    /// Ids this macro calls through `RunMacro` steps, in step order.
+    pub fn called_macros(&self) -> Vec<&str> {
+        let mut called = Vec::new();
+        collect_called_macros(&self.steps, &mut called);
+        called
+    }
+}
+
+fn collect_called_macros<'a>(steps: &'a [MacroStep], out: &mut Vec<&'a str>) {
+    for step in steps {
+        match step {
+            MacroStep::RunMacro { id } => out.push(id),
+            MacroStep::Atomic { steps } | MacroStep::Repeat { steps, .. } => {
+                collect_called_macros(steps, out)
+            }
+            MacroStep::If { then, else_, .. } => {
+                collect_called_macros(then, out);
+                collect_called_macros(else_, out);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn steps_duration_ms(steps: &[MacroStep], per_key_ms: u64) -> u64 {
+    steps
+        .iter()
+        .map(|step| match step {
+            MacroStep::Keystroke { .. } | MacroStep::KeyDown { .. } | MacroStep::KeyUp { .. } => {
+                per_key_ms
+            }
+            MacroStep::Text { text } => per_key_ms.saturating_mul(text.chars().count() as u64),
+            MacroStep::MouseMove { .. } | MacroStep::MouseClick { .. } => per_key_ms,
+            MacroStep::MidiOut { .. }
+            | MacroStep::VelocityGate { .. }
+            | MacroStep::Reset
+            | MacroStep::SetVar { .. } => 0,
+            MacroStep::Pause { ms, .. } => *ms,
+            MacroStep::RandomPause { max_ms, .. } => *max_ms,
+            MacroStep::PauseMicros { us } => us.div_ceil(1000),
+            MacroStep::Atomic { steps } => steps_duration_ms(steps, per_key_ms),
+            MacroStep::If { then, else_, .. } => {
+                steps_duration_ms(then, per_key_ms).max(steps_duration_ms(else_, per_key_ms))
+            }
+            MacroStep::Repeat { count, steps } => {
+                steps_duration_ms(steps, per_key_ms).saturating_mul(u64::from(*count))
+            }
+            // The callee is not visible from here; count it like a single key.
+            MacroStep::RunMacro { .. }
+            | MacroStep::RunScript { .. }
+            | MacroStep::RunCommand { .. } => per_key_ms,
+            // Worst case: the awaited event never arrives.
+            MacroStep::WaitForMidi { timeout_ms, .. } => *timeout_ms,
+        })
+        .fold(0u64, u64::saturating_add)
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct MidiTrigger {
     pub r#type: MidiTriggerType,
+    /// Note or controller number; unused for transport and pitch bend triggers.
     pub number: u8,
+    pub transport: Option<TransportKind>,
+    /// Channel (0–15) to match; `None` matches any channel.
+    pub channel: Option<u8>,
+    /// Crossing point for continuous-value triggers such as pitch bend.
+    pub threshold: Option<ThresholdCrossing>,
+}
+
+/// Fires once when a continuous value passes `value` in `direction`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct ThresholdCrossing {
+    pub value: u16,
+    pub direction: CrossingDirection,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum CrossingDirection {
+    Rising,
+    Falling,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum MidiTriggerType {
     Note,
+    ControlChange,
+    Transport,
+    PitchBend,
+    /// Note-ons from `low` to `high` inclusive that no `Note` trigger claims.
+    /// `MidiTrigger::number` holds `low`.
+    NoteRange {
+        low: u8,
+        high: u8,
+    },
+    /// Fires once when every note in `notes` is held, the first and last press at
+    /// most `window_ms` apart. `MidiTrigger::number` holds the first note.
+    Chord {
+        notes: Vec<u8>,
+        window_ms: u64,
+    },
+    /// Program change message; `MidiTrigger::number` holds the program.
+    ProgramChange,
+}
+
+/// MIDI system real-time transport messages (`0xFA` start, `0xFB` continue, `0xFC` stop).
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum TransportKind {
+    Start,
+    Stop,
+    Continue,
 }
 
 /// Device/page/widget layout snapshot for runtime/VC modules.
@@ -55,6 +457,8 @@ pub struct DeviceLayout {
     pub id: String,
     pub hardware_id: Option<String>,
     pub pages: Vec<LayoutPage>,
+    /// Notes the controller keeps for system functions.
+    pub reserved_notes: Vec<u8>,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
@@ -63,25 +467,163 @@ pub struct LayoutPage {
     pub widgets: Vec<LayoutWidget>,
 }
 
+/// Press handling resolved from a widget's `tap_behavior` string.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TapBehavior {
+    /// `action` on press; releases are ignored.
+    #[default]
+    Tap,
+    /// `action` on press, `off_action` on release.
+    Hold,
+    /// Presses alternate between `action` and `off_action`.
+    Toggle,
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct LayoutWidget {
     pub id: String,
     pub tap_behavior: Option<String>,
+    /// `tap_behavior` parsed at build time; `Tap` when unset.
+    pub behavior: TapBehavior,
     pub action: Option<WidgetAction>,
+    pub mode: Option<ActionMode>,
+    /// Run on release (momentary) or on alternate presses (latching).
+    pub off_action: Option<WidgetAction>,
+    /// Run when a second press follows within `double_tap_window_ms`.
+    pub double_tap_action: Option<WidgetAction>,
+    /// `global.defaults.double_tap_window_ms` resolved at build time.
+    pub double_tap_window_ms: u64,
+}
+
+/// How a widget's press and release map onto its actions.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionMode {
+    /// `action` on press, `off_action` on release.
+    Momentary,
+    /// Presses alternate between `action` and `off_action`.
+    Latching,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum WidgetAction {
-    Macro { id: String },
-    Script { id: String },
+    Macro {
+        id: String,
+    },
+    Script {
+        id: String,
+    },
+    /// Activates the page named `page` on device `device`.
+    PageSwitch {
+        device: String,
+        page: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
 }
 
 /// Macro steps recorded in the cache.
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub enum MacroStep {
-    Keystroke { keys: Vec<String> },
-    Pause { ms: u64 },
+    Keystroke {
+        keys: Vec<String>,
+    },
+    /// Types `text` as-is, including characters with no key mapping.
+    Text {
+        text: String,
+    },
+    /// Moves the cursor to `(x, y)`, or by that offset when `relative`.
+    MouseMove {
+        x: i32,
+        y: i32,
+        relative: bool,
+    },
+    MouseClick {
+        button: MouseButton,
+    },
+    Pause {
+        ms: u64,
+        label: Option<String>,
+    },
+    /// Steps executed while holding the executor's key-send lock.
+    Atomic {
+        steps: Vec<MacroStep>,
+    },
+    /// Runs `steps` `count` times in a row.
+    Repeat {
+        count: u32,
+        steps: Vec<MacroStep>,
+    },
+    /// Runs the steps of the macro with this id.
+    RunMacro {
+        id: String,
+    },
+    /// Runs the bundled script with this id through the runtime's script runner.
+    RunScript {
+        id: String,
+    },
+    /// Raw MIDI message sent to the runtime's output port, e.g. to light a pad.
+    MidiOut {
+        message: Vec<u8>,
+    },
+    /// Waits for a note-on of `note`; a timeout aborts the macro unless
+    /// `continue_on_timeout` is set.
+    WaitForMidi {
+        note: u8,
+        timeout_ms: u64,
+        continue_on_timeout: bool,
+    },
+    /// Ends the macro quietly unless the triggering event's velocity is within
+    /// `min..=max`. Runs without a triggering event always pass.
+    VelocityGate {
+        min: u8,
+        max: u8,
+    },
+    /// Pause whose length is drawn uniformly from `min_ms..=max_ms` each run.
+    RandomPause {
+        min_ms: u64,
+        max_ms: u64,
+    },
+    /// Presses `key` without releasing it.
+    KeyDown {
+        key: String,
+    },
+    /// Releases `key`, normally held by an earlier `KeyDown`.
+    KeyUp {
+        key: String,
+    },
+    /// Starts `program` with `args` and waits for it to exit. Refused unless
+    /// `CacheDefaults::allow_commands` is set.
+    RunCommand {
+        program: String,
+        args: Vec<String>,
+    },
+    /// Pause of `us` microseconds, for sub-millisecond timing.
+    PauseMicros {
+        us: u64,
+    },
+    /// Releases every key held by `KeyDown` steps and sends all-notes-off.
+    Reset,
+    /// Stores `value` in the executor's variable `name`.
+    SetVar {
+        name: String,
+        value: i64,
+    },
+    /// Runs `then` if variable `var` (0 when unset) equals `equals`, else `else_`.
+    If {
+        var: String,
+        equals: i64,
+        then: Vec<MacroStep>,
+        else_: Vec<MacroStep>,
+    },
 }
 
 #[cfg(test)]
@@ -95,6 +637,8 @@ mod tests {
                 version: CACHE_VERSION,
                 source_hash: 42,
                 generated_at: 1_700_000_000,
+                compression: CompressionKind::None,
+                payload_crc: 0,
             },
             devices: vec![DeviceLayout {
                 id: "launchpad".into(),
@@ -104,9 +648,15 @@ mod tests {
                     widgets: vec![LayoutWidget {
                         id: "pad_1".into(),
                         tap_behavior: Some("tap".into()),
+                        behavior: TapBehavior::Tap,
                         action: Some(WidgetAction::Macro { id: "copy".into() }),
+                        mode: None,
+                        off_action: None,
+                        double_tap_action: None,
+                        double_tap_window_ms: 300,
                     }],
                 }],
+                reserved_notes: vec![],
             }],
             macros: vec![MacroEntry {
                 id: "copy".to_string(),
@@ -115,18 +665,290 @@ mod tests {
                 trigger: Some(MidiTrigger {
                     r#type: MidiTriggerType::Note,
                     number: 60,
+                    transport: None,
+                    threshold: None,
+                    channel: None,
                 }),
                 steps: vec![
                     MacroStep::Keystroke {
                         keys: vec!["Ctrl".into(), "C".into()],
                     },
-                    MacroStep::Pause { ms: 50 },
+                    MacroStep::Pause {
+                        ms: 50,
+                        label: Some("settle".into()),
+                    },
                 ],
+                max_duration_ms: None,
+                on_error: None,
+                cooldown_ms: None,
             }],
+            scripts: vec![],
+            profiles: vec![],
+            hooks: ExecutionHooks::default(),
+            defaults: CacheDefaults::default(),
         };
 
         let bytes = bincode::serialize(&bundle).expect("serialize");
         let decoded: CacheBundle = bincode::deserialize(&bytes).expect("deserialize");
         assert_eq!(bundle, decoded);
     }
+
+    #[test]
+    fn nominal_duration_sums_pauses_and_key_cost() {
+        let entry = MacroEntry {
+            id: "save".into(),
+            description: None,
+            tags: vec![],
+            trigger: None,
+            steps: vec![
+                MacroStep::Keystroke {
+                    keys: vec!["Ctrl".into(), "S".into()],
+                },
+                MacroStep::Pause {
+                    ms: 1_000,
+                    label: None,
+                },
+                MacroStep::Keystroke {
+                    keys: vec!["Enter".into()],
+                },
+                MacroStep::Pause {
+                    ms: 250,
+                    label: None,
+                },
+            ],
+            max_duration_ms: None,
+            on_error: None,
+            cooldown_ms: None,
+        };
+        assert_eq!(entry.nominal_duration_ms(0), 1_250);
+        assert_eq!(entry.nominal_duration_ms(20), 1_290);
+    }
+
+    #[test]
+    fn validate_reports_dangling_widget_macro() {
+        let bundle = CacheBundle {
+            header: CacheHeader {
+                version: CACHE_VERSION,
+                source_hash: 0,
+                generated_at: 0,
+                compression: CompressionKind::None,
+                payload_crc: 0,
+            },
+            devices: vec![DeviceLayout {
+                id: "launchpad".into(),
+                hardware_id: None,
+                pages: vec![LayoutPage {
+                    name: "Main".into(),
+                    widgets: vec![
+                        LayoutWidget {
+                            id: "pad_1".into(),
+                            tap_behavior: None,
+                            behavior: TapBehavior::Tap,
+                            action: Some(WidgetAction::Macro {
+                                id: "missing".into(),
+                            }),
+                            mode: None,
+                            off_action: None,
+                            double_tap_action: None,
+                            double_tap_window_ms: 300,
+                        },
+                        LayoutWidget {
+                            id: "pad_2".into(),
+                            tap_behavior: None,
+                            behavior: TapBehavior::Tap,
+                            action: Some(WidgetAction::PageSwitch {
+                                device: "launchpad".into(),
+                                page: "Mix".into(),
+                            }),
+                            mode: None,
+                            off_action: None,
+                            double_tap_action: None,
+                            double_tap_window_ms: 300,
+                        },
+                    ],
+                }],
+                reserved_notes: vec![],
+            }],
+            macros: vec![],
+            scripts: vec![],
+            profiles: vec![],
+            hooks: ExecutionHooks::default(),
+            defaults: CacheDefaults::default(),
+        };
+
+        assert_eq!(
+            bundle.validate(),
+            vec![
+                CacheInconsistency::DanglingWidgetMacro {
+                    device_id: "launchpad".into(),
+                    page_name: "Main".into(),
+                    widget_id: "pad_1".into(),
+                    macro_id: "missing".into(),
+                },
+                CacheInconsistency::DanglingPageSwitch {
+                    device_id: "launchpad".into(),
+                    page_name: "Main".into(),
+                    widget_id: "pad_2".into(),
+                    target_device: "launchpad".into(),
+                    target_page: "Mix".into(),
+                }
+            ]
+        );
+        assert!(bundle.has_page("launchpad", "Main"));
+        assert!(!bundle.has_page("nano", "Main"));
+    }
+
+    #[test]
+    fn validate_reports_out_of_range_trigger_notes() {
+        let entry = |id: &str, r#type: MidiTriggerType, number: u8| MacroEntry {
+            id: id.into(),
+            description: None,
+            tags: vec![],
+            trigger: Some(MidiTrigger {
+                r#type,
+                number,
+                transport: None,
+                channel: None,
+                threshold: None,
+            }),
+            steps: vec![],
+            max_duration_ms: None,
+            on_error: None,
+            cooldown_ms: None,
+        };
+        let bundle = CacheBundle {
+            header: CacheHeader {
+                version: CACHE_VERSION,
+                source_hash: 0,
+                generated_at: 0,
+                compression: CompressionKind::None,
+                payload_crc: 0,
+            },
+            devices: vec![],
+            macros: vec![
+                entry("note", MidiTriggerType::Note, 128),
+                entry(
+                    "low",
+                    MidiTriggerType::NoteRange {
+                        low: 140,
+                        high: 127,
+                    },
+                    60,
+                ),
+                entry(
+                    "high",
+                    MidiTriggerType::NoteRange { low: 60, high: 130 },
+                    60,
+                ),
+                entry(
+                    "chord",
+                    MidiTriggerType::Chord {
+                        notes: vec![60, 200],
+                        window_ms: DEFAULT_CHORD_WINDOW_MS,
+                    },
+                    60,
+                ),
+                entry("fine", MidiTriggerType::NoteRange { low: 0, high: 127 }, 0),
+            ],
+            scripts: vec![],
+            profiles: vec![],
+            hooks: ExecutionHooks::default(),
+            defaults: CacheDefaults::default(),
+        };
+
+        let out_of_range = |macro_id: &str, number| CacheInconsistency::TriggerOutOfRange {
+            macro_id: macro_id.into(),
+            number,
+        };
+        assert_eq!(
+            bundle.validate(),
+            vec![
+                out_of_range("note", 128),
+                out_of_range("low", 140),
+                out_of_range("high", 130),
+                out_of_range("chord", 200),
+            ]
+        );
+    }
+
+    #[test]
+    fn reachable_macros_follow_widgets_hooks_and_calls() {
+        let entry = |id: &str| MacroEntry {
+            id: id.into(),
+            description: None,
+            tags: vec![],
+            trigger: None,
+            steps: vec![],
+            max_duration_ms: None,
+            on_error: None,
+            cooldown_ms: None,
+        };
+        let widget = |id: &str, macro_id: &str| LayoutWidget {
+            id: id.into(),
+            tap_behavior: None,
+            behavior: TapBehavior::Tap,
+            action: Some(WidgetAction::Macro {
+                id: macro_id.into(),
+            }),
+            mode: None,
+            off_action: None,
+            double_tap_action: None,
+            double_tap_window_ms: 300,
+        };
+        let bundle = CacheBundle {
+            header: CacheHeader {
+                version: CACHE_VERSION,
+                source_hash: 0,
+                generated_at: 0,
+                compression: CompressionKind::None,
+                payload_crc: 0,
+            },
+            devices: vec![DeviceLayout {
+                id: "pad".into(),
+                hardware_id: None,
+                pages: vec![LayoutPage {
+                    name: "main".into(),
+                    widgets: vec![widget("a", "save"), widget("b", "export")],
+                }],
+                reserved_notes: vec![],
+            }],
+            macros: vec![
+                entry("save"),
+                MacroEntry {
+                    steps: vec![MacroStep::RunMacro {
+                        id: "cleanup".into(),
+                    }],
+                    ..entry("export")
+                },
+                entry("focus"),
+                entry("cleanup"),
+                entry("unused"),
+            ],
+            scripts: vec![],
+            profiles: vec![],
+            hooks: ExecutionHooks {
+                on_before: Some("focus".into()),
+                on_after: None,
+            },
+            defaults: CacheDefaults::default(),
+        };
+
+        let ids: Vec<_> = bundle
+            .macros_reachable_from_device("pad")
+            .into_iter()
+            .map(|entry| entry.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["save", "export", "focus", "cleanup"]);
+        assert!(bundle.macros_reachable_from_device("missing").is_empty());
+
+        let mut broken = bundle.clone();
+        broken.macros.retain(|entry| entry.id != "cleanup");
+        assert_eq!(
+            broken.validate(),
+            vec![CacheInconsistency::DanglingStepMacro {
+                macro_id: "export".into(),
+                called: "cleanup".into(),
+            }]
+        );
+    }
 }
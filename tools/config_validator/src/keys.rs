@@ -0,0 +1,102 @@
+//! Key tokens keystroke steps may use; the runtime's key mapping must accept all of them.
+
+/// Named keys every platform can send, lowercase; matching is case-insensitive.
+pub const KEY_NAMES: &[&str] = &[
+    "ctrl",
+    "control",
+    "alt",
+    "shift",
+    "meta",
+    "cmd",
+    "command",
+    "super",
+    "enter",
+    "return",
+    "space",
+    "spacebar",
+    "tab",
+    "esc",
+    "escape",
+    "up",
+    "down",
+    "left",
+    "right",
+    "home",
+    "end",
+    "pageup",
+    "pagedown",
+    "delete",
+    "backspace",
+    "f1",
+    "f2",
+    "f3",
+    "f4",
+    "f5",
+    "f6",
+    "f7",
+    "f8",
+    "f9",
+    "f10",
+    "f11",
+    "f12",
+    "f13",
+    "f14",
+    "f15",
+    "f16",
+    "f17",
+    "f18",
+    "f19",
+    "f20",
+    "volumeup",
+    "volumedown",
+    "mute",
+];
+
+/// Named keys only Windows and Linux can send. `play` and `pause` both send the
+/// play/pause toggle.
+pub const LIMITED_KEY_NAMES: &[&str] = &[
+    "insert",
+    "f21",
+    "f22",
+    "f23",
+    "f24",
+    "play",
+    "pause",
+    "playpause",
+    "next",
+    "prev",
+    "previous",
+    "stop",
+];
+
+/// True for a named key or a single character, which types itself.
+pub fn is_known_key(token: &str) -> bool {
+    let lower = token.to_ascii_lowercase();
+    lower.chars().count() == 1 || KEY_NAMES.contains(&lower.as_str()) || is_limited_key(&lower)
+}
+
+/// True for a key listed in [`LIMITED_KEY_NAMES`].
+pub fn is_limited_key(token: &str) -> bool {
+    LIMITED_KEY_NAMES.contains(&token.to_ascii_lowercase().as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_names_in_any_case_and_single_characters() {
+        assert!(is_known_key("Ctrl"));
+        assert!(is_known_key("ESCAPE"));
+        assert!(is_known_key("c"));
+        assert!(is_known_key("é"));
+        assert!(is_known_key("PageUp"));
+        assert!(is_known_key("F24"));
+        assert!(!is_known_key("f25"));
+        assert!(is_known_key("VolumeUp"));
+        assert!(is_limited_key("PlayPause"));
+        assert!(!is_limited_key("mute"));
+        assert!(!is_known_key("Cntrl"));
+        assert!(!is_known_key("ctrll"));
+    }
+}
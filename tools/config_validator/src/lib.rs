@@ -1,19 +1,343 @@
+pub mod keys;
 pub mod schema;
+mod spans;
 pub mod validation;
 
+use std::collections::HashSet;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
 use schema::Config;
-use serde_yaml::Error as YamlError;
+use serde::de::DeserializeOwned;
+use serde_yaml::{Error as YamlError, Mapping, Value};
 use thiserror::Error;
 
-pub use validation::{Location, Severity, ValidationIssue, validate_config};
+pub use validation::{
+    DiagnosticTarget, Location, Severity, ValidationIssue, find_location, format_diagnostic,
+    validate_config, without_lints,
+};
 
 #[derive(Debug, Error)]
 pub enum ConfigError {
     #[error("YAML parse error: {0}")]
     Parse(#[from] YamlError),
+    #[error("JSON parse error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Failed to include {}: {reason}", path.display())]
+    Include { path: PathBuf, reason: String },
+}
+
+impl ConfigError {
+    /// Source position reported by the parser, if any.
+    pub fn location(&self) -> Option<Location> {
+        match self {
+            ConfigError::Parse(err) => err.location().map(|loc| Location {
+                line: loc.line(),
+                column: loc.column(),
+            }),
+            ConfigError::Json(err) if err.line() > 0 => Some(Location {
+                line: err.line(),
+                column: err.column(),
+            }),
+            ConfigError::Json(_) | ConfigError::Include { .. } => None,
+        }
+    }
+
+    /// Presents the parse failure in the same shape as validation diagnostics.
+    pub fn to_issue(&self) -> ValidationIssue {
+        let location = self.location();
+        let mut text = match self {
+            ConfigError::Parse(err) => err.to_string(),
+            ConfigError::Json(err) => err.to_string(),
+            ConfigError::Include { .. } => {
+                return ValidationIssue::new("include".into(), self.to_string(), Severity::Error);
+            }
+        };
+        if let Some(loc) = location {
+            let suffix = format!(" at line {} column {}", loc.line, loc.column);
+            if let Some(stripped) = text.strip_suffix(&suffix) {
+                text = stripped.to_string();
+            }
+        }
+        // serde_yaml prefixes errors with the offending path (`macros.a.steps[0]: ...`).
+        let (path, message) = match text.split_once(": ") {
+            Some((path, message)) if !path.is_empty() && !path.contains(char::is_whitespace) => {
+                (path.to_string(), message.to_string())
+            }
+            _ => ("config".to_string(), text),
+        };
+        let mut issue = ValidationIssue::new(path, message, Severity::Error);
+        issue.location = location;
+        issue
+    }
+}
+
+/// Path the CLIs accept in place of a file to read the config from stdin.
+pub const STDIN_PATH: &str = "-";
+
+/// Reads a config source from `path`, or from `stdin` when `path` is [`STDIN_PATH`].
+pub fn read_source(path: &Path, mut stdin: impl Read) -> io::Result<String> {
+    if path == Path::new(STDIN_PATH) {
+        let mut content = String::new();
+        stdin.read_to_string(&mut content)?;
+        Ok(content)
+    } else {
+        std::fs::read_to_string(path)
+    }
+}
+
+/// Source format of a config file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Yaml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// `.json` files are JSON; anything else, stdin included, is YAML.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => ConfigFormat::Json,
+            _ => ConfigFormat::Yaml,
+        }
+    }
+
+    fn parse<T: DeserializeOwned>(self, src: &str) -> Result<T, ConfigError> {
+        Ok(match self {
+            ConfigFormat::Yaml => serde_yaml::from_str(src)?,
+            ConfigFormat::Json => serde_json::from_str(src)?,
+        })
+    }
 }
 
 pub fn parse_config_str(src: &str) -> Result<Config, ConfigError> {
     let config = serde_yaml::from_str::<Config>(src)?;
     Ok(config)
 }
+
+/// Parses a strict JSON config, so JSON mistakes get JSON error messages.
+pub fn parse_config_str_json(src: &str) -> Result<Config, ConfigError> {
+    let config = serde_json::from_str::<Config>(src)?;
+    Ok(config)
+}
+
+/// JSON Schema of the config file format, generated from [`schema::Config`], for
+/// editor autocompletion and inline validation of YAML configs.
+pub fn json_schema() -> serde_json::Value {
+    schemars::schema_for!(Config).to_value()
+}
+
+/// Parses `src`, read from `path`, and merges in every file its `include` list names,
+/// recursively. Include paths resolve against the including file's directory. Entries
+/// already defined by the including file win; a file reached twice is merged once, so
+/// cycles end there. Returns the merged config and every included file, in merge order.
+/// Each file is parsed as the [`ConfigFormat`] its extension names.
+pub fn parse_config_with_includes(
+    path: &Path,
+    src: &str,
+) -> Result<(Config, Vec<PathBuf>), ConfigError> {
+    let format = ConfigFormat::from_path(path);
+    let config: Config = format.parse(src)?;
+    if config.include.is_empty() {
+        return Ok((config, Vec::new()));
+    }
+    let Value::Mapping(mut root) = format.parse::<Value>(src)? else {
+        return Ok((config, Vec::new()));
+    };
+    let mut visited = HashSet::new();
+    if let Ok(canonical) = path.canonicalize() {
+        visited.insert(canonical);
+    }
+    let mut included = Vec::new();
+    merge_includes(&mut root, base_dir(path), &mut visited, &mut included)?;
+    Ok((serde_yaml::from_value(Value::Mapping(root))?, included))
+}
+
+fn base_dir(path: &Path) -> &Path {
+    path.parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+}
+
+fn merge_includes(
+    target: &mut Mapping,
+    base: &Path,
+    visited: &mut HashSet<PathBuf>,
+    included: &mut Vec<PathBuf>,
+) -> Result<(), ConfigError> {
+    let names: Vec<String> = match target.get("include") {
+        Some(value) => serde_yaml::from_value(value.clone())?,
+        None => return Ok(()),
+    };
+    for name in names {
+        let path = base.join(&name);
+        let include_error = |reason: String| ConfigError::Include {
+            path: path.clone(),
+            reason,
+        };
+        let canonical = path
+            .canonicalize()
+            .map_err(|err| include_error(err.to_string()))?;
+        if !visited.insert(canonical) {
+            continue;
+        }
+        let src = std::fs::read_to_string(&path).map_err(|err| include_error(err.to_string()))?;
+        let mut fragment = match ConfigFormat::from_path(&path).parse::<Value>(&src) {
+            Ok(Value::Mapping(fragment)) => fragment,
+            Ok(Value::Null) => Mapping::new(),
+            Ok(_) => return Err(include_error("expected a mapping".into())),
+            Err(err) => return Err(include_error(err.to_string())),
+        };
+        included.push(path.clone());
+        merge_includes(&mut fragment, base_dir(&path), visited, included)?;
+        merge_under(target, fragment);
+    }
+    Ok(())
+}
+
+/// Adds `fragment`'s entries to `target`, one level deep, without replacing any.
+fn merge_under(target: &mut Mapping, fragment: Mapping) {
+    for (key, value) in fragment {
+        if key.as_str() == Some("include") {
+            continue;
+        }
+        match (target.get_mut(&key), value) {
+            (None, value) => {
+                target.insert(key, value);
+            }
+            (Some(Value::Mapping(existing)), Value::Mapping(entries)) => {
+                for (entry_key, entry) in entries {
+                    if !existing.contains_key(&entry_key) {
+                        existing.insert(entry_key, entry);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema_accepts(yaml: &str) -> bool {
+        let validator = jsonschema::validator_for(&json_schema()).expect("valid schema");
+        let instance: serde_json::Value = serde_yaml::from_str(yaml).expect("yaml");
+        validator.is_valid(&instance)
+    }
+
+    #[test]
+    fn json_schema_accepts_the_sample_configs() {
+        assert!(schema_accepts(include_str!("../../../config/sample.yaml")));
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  held:
+    status: ready
+    trigger: { type: chord, notes: [36, 40], window_ms: 80 }
+    on_error: { run_macro: recover }
+    steps:
+      - { type: key_down, key: Shift }
+      - { type: random_pause, min_ms: 10, max_ms: 20 }
+      - { type: key_up, key: Shift }
+  recover:
+    trigger: { type: note, number: "C4" }
+    on_error: abort
+    steps: []
+"#;
+        assert!(schema_accepts(yaml));
+        assert!(!schema_accepts(
+            "version: 1\nmacros:\n  a:\n    steps:\n      - type: bogus\n"
+        ));
+        assert!(!schema_accepts(
+            "version: 1\nmacros:\n  a:\n    on_error: retry\n    steps: []\n"
+        ));
+    }
+
+    #[test]
+    fn json_configs_parse_by_extension_with_json_errors() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let root = dir.path().join("config.json");
+        let src = r#"{"version": 1, "include": ["macros.json"], "macros": {}}"#;
+        std::fs::write(&root, src).expect("write root");
+        std::fs::write(
+            dir.path().join("macros.json"),
+            r#"{"macros": {"paste": {"steps": []}}}"#,
+        )
+        .expect("write include");
+        let (config, _) = parse_config_with_includes(&root, src).expect("parse");
+        assert!(config.macros.contains_key("paste"));
+
+        let err = parse_config_str_json("{\"version\": 1,\n  \"macros\": {},}").unwrap_err();
+        assert!(matches!(err, ConfigError::Json(_)));
+        let issue = err.to_issue();
+        assert_eq!(issue.path, "config");
+        assert_eq!(issue.message, "trailing comma");
+        let loc = issue.location.expect("location");
+        assert_eq!((loc.line, loc.column), (2, 16));
+    }
+
+    #[test]
+    fn stdin_source_validates() {
+        let yaml = "version: 1\ndevices: {}\nmacros: {}\n";
+        let content = read_source(Path::new(STDIN_PATH), yaml.as_bytes()).expect("read");
+        let config = parse_config_str(&content).expect("parse");
+        assert!(validate_config(&config, &content).is_empty());
+    }
+
+    #[test]
+    fn parse_error_becomes_located_issue() {
+        let yaml = "version: 1\nmacros:\n  a:\n    steps:\n      - type: bogus\n";
+        let issue = parse_config_str(yaml).unwrap_err().to_issue();
+        assert_eq!(issue.path, "macros.a.steps[0].type");
+        assert!(issue.message.starts_with("unknown variant `bogus`"));
+        assert_eq!(issue.severity, Severity::Error);
+        let loc = issue.location.expect("location");
+        assert_eq!((loc.line, loc.column), (5, 15));
+    }
+
+    #[test]
+    fn includes_merge_recursively_and_stop_at_cycles() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let root = dir.path().join("config.yaml");
+        let src = "version: 1\ninclude: [\"macros.yaml\"]\nmacros:\n  copy:\n    steps: []\n";
+        std::fs::write(&root, src).expect("write root");
+        std::fs::write(
+            dir.path().join("macros.yaml"),
+            "include: [\"config.yaml\", \"more/scripts.yaml\"]\nmacros:\n  copy:\n    status: ready\n    steps: []\n  paste:\n    steps: []\n",
+        )
+        .expect("write include");
+        std::fs::create_dir(dir.path().join("more")).expect("mkdir");
+        std::fs::write(
+            dir.path().join("more/scripts.yaml"),
+            "scripts:\n  hello: print(1)\n",
+        )
+        .expect("write nested include");
+
+        let (config, included) = parse_config_with_includes(&root, src).expect("parse");
+        assert_eq!(
+            included,
+            vec![
+                dir.path().join("macros.yaml"),
+                dir.path().join("more/scripts.yaml")
+            ]
+        );
+        assert_eq!(config.macros.len(), 2);
+        // The including file's own definition wins.
+        assert_eq!(config.macros["copy"].status, schema::MacroStatus::Draft);
+        assert!(config.scripts.contains_key("hello"));
+    }
+
+    #[test]
+    fn missing_include_is_a_parse_error() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let root = dir.path().join("config.yaml");
+        let src = "version: 1\ninclude: [\"absent.yaml\"]\n";
+        let err = parse_config_with_includes(&root, src).unwrap_err();
+        assert!(
+            matches!(err, ConfigError::Include { ref path, .. } if path.ends_with("absent.yaml"))
+        );
+        assert_eq!(err.to_issue().path, "include");
+    }
+}
@@ -1,58 +1,130 @@
-use std::fs;
+use std::io;
 use std::path::PathBuf;
 
-use clap::Parser;
-use config_validator::Severity;
+use clap::{Parser, ValueEnum};
+use config_validator::{Severity, ValidationIssue, format_diagnostic};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "Validate MIDI Macro Studio configs", long_about = None)]
 struct Cli {
-    /// Path to YAML configuration file
-    path: PathBuf,
+    /// Path to YAML configuration file, or `-` to read from stdin
+    #[arg(required_unless_present = "json_schema")]
+    path: Option<PathBuf>,
+    /// Print the JSON Schema of the config format instead of validating a file
+    #[arg(long)]
+    json_schema: bool,
+    /// Skip style lints (missing descriptions, long pauses, ...)
+    #[arg(long)]
+    no_lints: bool,
+    /// Fail on warnings as well as errors; same as `--max-severity info`
+    #[arg(long, conflicts_with = "max_severity")]
+    strict: bool,
+    /// Most severe diagnostic that still passes; anything above it exits with status 2
+    #[arg(long, value_enum, default_value_t = MaxSeverity::Warning)]
+    max_severity: MaxSeverity,
+    /// Hide info diagnostics
+    #[arg(long)]
+    quiet: bool,
+    /// How diagnostics are printed; `json` writes an array of issues to stdout
+    #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+    format: OutputFormat,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Human,
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum MaxSeverity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl From<Severity> for MaxSeverity {
+    fn from(severity: Severity) -> Self {
+        match severity {
+            Severity::Info => MaxSeverity::Info,
+            Severity::Warning => MaxSeverity::Warning,
+            Severity::Error => MaxSeverity::Error,
+        }
+    }
 }
 
 fn main() {
     let cli = Cli::parse();
+    if cli.json_schema {
+        let schema = config_validator::json_schema();
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&schema).expect("schema is JSON")
+        );
+        return;
+    }
+    let path = cli
+        .path
+        .expect("clap requires a path without --json-schema");
 
-    let content = match fs::read_to_string(&cli.path) {
+    let content = match config_validator::read_source(&path, io::stdin().lock()) {
         Ok(c) => c,
         Err(err) => {
-            eprintln!("Failed to read {}: {err}", cli.path.display());
+            eprintln!("Failed to read {}: {err}", path.display());
             std::process::exit(1);
         }
     };
 
-    match config_validator::parse_config_str(&content) {
-        Ok(config) => {
-            let issues = config_validator::validate_config(&config, &content);
-            if issues.is_empty() {
-                println!("Validation OK: {}", cli.path.display());
+    let config = match config_validator::parse_config_with_includes(&path, &content) {
+        Ok((config, _)) => config,
+        Err(err) => {
+            if cli.format == OutputFormat::Json {
+                print_json(&[err.to_issue()]);
             } else {
-                let has_errors = issues.iter().any(|i| i.severity == Severity::Error);
-                eprintln!("Validation diagnostics:");
-                for issue in &issues {
-                    let level = match issue.severity {
-                        Severity::Error => "error",
-                        Severity::Warning => "warning",
-                        Severity::Info => "info",
-                    };
-                    if let Some(loc) = issue.location {
-                        eprintln!(
-                            "- [{}] {}: {} (line {}, column {})",
-                            level, issue.path, issue.message, loc.line, loc.column
-                        );
-                    } else {
-                        eprintln!("- [{}] {}: {}", level, issue.path, issue.message);
-                    }
-                }
-                if has_errors {
-                    std::process::exit(2);
-                }
+                eprintln!("Validation failed:");
+                print_issues(&[err.to_issue()]);
             }
-        }
-        Err(err) => {
-            eprintln!("Validation failed: {err}");
             std::process::exit(1);
         }
+    };
+
+    let mut issues = config_validator::validate_config(&config, &content);
+    if cli.no_lints {
+        issues = config_validator::without_lints(issues);
+    }
+    if cli.quiet {
+        issues.retain(|issue| issue.severity != Severity::Info);
+    }
+    let max_severity = if cli.strict {
+        MaxSeverity::Info
+    } else {
+        cli.max_severity
+    };
+    let failed = issues
+        .iter()
+        .any(|i| MaxSeverity::from(i.severity) > max_severity);
+    if cli.format == OutputFormat::Json {
+        print_json(&issues);
+    } else if issues.is_empty() {
+        println!("Validation OK: {}", path.display());
+    } else {
+        eprintln!("Validation diagnostics:");
+        print_issues(&issues);
+    }
+    if failed {
+        std::process::exit(2);
+    }
+}
+
+fn print_json(issues: &[ValidationIssue]) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(issues).expect("issues are JSON")
+    );
+}
+
+fn print_issues(issues: &[ValidationIssue]) {
+    for issue in issues {
+        eprintln!("- {}", format_diagnostic(issue));
     }
 }
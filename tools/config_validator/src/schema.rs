@@ -1,11 +1,13 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 
-use serde::Deserialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Config {
     pub version: u32,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub global: Option<Global>,
     #[serde(default)]
     pub devices: HashMap<String, Device>,
@@ -13,54 +15,364 @@ pub struct Config {
     pub macros: HashMap<String, Macro>,
     #[serde(default)]
     pub scripts: HashMap<String, Script>,
-    #[serde(default)]
-    pub virtual_console: Option<serde_yaml::Value>,
+    /// Named note numbers that triggers may reference instead of raw numbers.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub note_aliases: HashMap<String, u8>,
+    /// Named sets of macro ids; activating one enables only those macros' triggers.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub profiles: HashMap<String, Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub virtual_console: Option<VirtualConsole>,
+    /// Further config files, relative to this one, merged in by
+    /// [`crate::parse_config_with_includes`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+}
+
+/// On-screen console layout; every field is optional.
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct VirtualConsole {
+    /// Grid used by pages without their own `grid`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grid: Option<GridSize>,
+    /// Page shown when the console opens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_page: Option<ConsolePageRef>,
+    /// Per-page overrides of the console defaults.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pages: Vec<ConsolePage>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy, JsonSchema)]
+pub struct GridSize {
+    pub rows: u32,
+    pub columns: u32,
+}
+
+/// A page on one of the configured devices.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, JsonSchema)]
+pub struct ConsolePageRef {
+    pub device: String,
+    pub page: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct ConsolePage {
+    pub device: String,
+    pub page: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grid: Option<GridSize>,
+    /// Label shown instead of the page name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+impl Config {
+    /// Overlays `other` onto this config, e.g. a machine-specific file onto a shared
+    /// base. Maps merge by key with `other`'s entry winning, `version` is `other`'s,
+    /// and lists and `global`/`virtual_console` are replaced when `other` sets them.
+    /// A device present in both merges field by field (see [`Device::merge`]), so an
+    /// override can change its `hardware_id` without restating its pages.
+    /// Returns the sorted paths of entries `other` replaced, e.g. `macros.copy`.
+    pub fn merge(&mut self, other: Config) -> Vec<String> {
+        fn merge_map<V>(
+            section: &str,
+            target: &mut HashMap<String, V>,
+            overlay: HashMap<String, V>,
+            replaced: &mut Vec<String>,
+        ) {
+            for (key, value) in overlay {
+                if target.insert(key.clone(), value).is_some() {
+                    replaced.push(format!("{section}.{key}"));
+                }
+            }
+        }
+
+        let mut replaced = Vec::new();
+        self.version = other.version;
+        merge_option("global", &mut self.global, other.global, &mut replaced);
+        for (id, device) in other.devices {
+            match self.devices.get_mut(&id) {
+                Some(existing) => existing.merge(&format!("devices.{id}"), device, &mut replaced),
+                None => {
+                    self.devices.insert(id, device);
+                }
+            }
+        }
+        merge_map("macros", &mut self.macros, other.macros, &mut replaced);
+        merge_map("scripts", &mut self.scripts, other.scripts, &mut replaced);
+        merge_map(
+            "note_aliases",
+            &mut self.note_aliases,
+            other.note_aliases,
+            &mut replaced,
+        );
+        merge_map(
+            "profiles",
+            &mut self.profiles,
+            other.profiles,
+            &mut replaced,
+        );
+        merge_option(
+            "virtual_console",
+            &mut self.virtual_console,
+            other.virtual_console,
+            &mut replaced,
+        );
+        if !other.include.is_empty() {
+            self.include = other.include;
+        }
+        replaced.sort();
+        replaced
+    }
+
+    /// Ids of scripts run by a ready macro's steps or by any widget action; only
+    /// these are compiled into the cache.
+    pub fn referenced_scripts(&self) -> HashSet<&str> {
+        fn collect<'a>(steps: &'a [MacroStep], ids: &mut HashSet<&'a str>) {
+            for step in steps {
+                match step {
+                    MacroStep::RunScript { id } => {
+                        ids.insert(id.as_str());
+                    }
+                    MacroStep::Atomic { steps } | MacroStep::Repeat { steps, .. } => {
+                        collect(steps, ids)
+                    }
+                    MacroStep::If { then, else_, .. } => {
+                        collect(then, ids);
+                        collect(else_, ids);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut ids = HashSet::new();
+        for mac in self.macros.values() {
+            if mac.status == MacroStatus::Ready {
+                collect(&mac.steps, &mut ids);
+            }
+        }
+        for action in self.widget_actions() {
+            if let Action::Script { ref_ } = action {
+                ids.insert(ref_.as_str());
+            }
+        }
+        ids
+    }
+
+    /// Ids of macros something can start: a ready macro's own trigger, a widget
+    /// action, a `run_macro` step or error policy, or a global hook.
+    pub fn referenced_macros(&self) -> HashSet<&str> {
+        fn collect<'a>(steps: &'a [MacroStep], ids: &mut HashSet<&'a str>) {
+            for step in steps {
+                match step {
+                    MacroStep::RunMacro { id } => {
+                        ids.insert(id.as_str());
+                    }
+                    MacroStep::Atomic { steps } | MacroStep::Repeat { steps, .. } => {
+                        collect(steps, ids)
+                    }
+                    MacroStep::If { then, else_, .. } => {
+                        collect(then, ids);
+                        collect(else_, ids);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut ids = HashSet::new();
+        for (id, mac) in &self.macros {
+            if mac.status == MacroStatus::Ready && mac.trigger.is_some() {
+                ids.insert(id.as_str());
+            }
+            collect(&mac.steps, &mut ids);
+            if let Some(ErrorPolicy::RunMacro(fallback)) = &mac.on_error {
+                ids.insert(fallback.as_str());
+            }
+        }
+        if let Some(global) = &self.global {
+            for hook in [&global.on_before, &global.on_after].into_iter().flatten() {
+                ids.insert(hook.as_str());
+            }
+        }
+        for action in self.widget_actions() {
+            if let Action::Macro { ref_ } = action {
+                ids.insert(ref_.as_str());
+            }
+        }
+        ids
+    }
+
+    /// Every `action`, `off_action` and `double_tap_action` of every widget.
+    fn widget_actions(&self) -> impl Iterator<Item = &Action> {
+        self.devices
+            .values()
+            .flat_map(|device| &device.pages)
+            .flat_map(|page| &page.widgets)
+            .flat_map(|widget| {
+                [
+                    &widget.action,
+                    &widget.off_action,
+                    &widget.double_tap_action,
+                ]
+            })
+            .flatten()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Global {
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub defaults: Option<Defaults>,
+    /// Macro run before every macro execution.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_before: Option<String>,
+    /// Macro run after every macro execution.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_after: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Defaults {
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub tap_hold_timeout_ms: Option<u64>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub display: Option<DisplaySettings>,
+    /// Default `max_duration_ms` for macros that do not set their own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_macro_duration_ms: Option<u64>,
+    /// How soon a second press must follow the first to count as a double tap.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub double_tap_window_ms: Option<u64>,
+    /// Lets `run_command` steps start programs and scripts run; off unless set explicitly.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub allow_commands: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct DisplaySettings {
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub theme: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Device {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub hardware_id: Option<String>,
     #[serde(default)]
     pub pages: Vec<Page>,
+    /// Notes the device can physically send, inclusive on both ends.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note_range: Option<NoteRange>,
+    /// Notes the controller keeps for its own system functions.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reserved_notes: Vec<u8>,
+}
+
+/// Sets `target` to `overlay` when that is set, noting `path` if it replaced a value.
+fn merge_option<V>(
+    path: &str,
+    target: &mut Option<V>,
+    overlay: Option<V>,
+    replaced: &mut Vec<String>,
+) {
+    if let Some(value) = overlay
+        && target.replace(value).is_some()
+    {
+        replaced.push(path.to_string());
+    }
 }
 
-#[derive(Debug, Deserialize)]
+/// Like [`merge_option`], with an empty list standing for unset.
+fn merge_list<V>(path: &str, target: &mut Vec<V>, overlay: Vec<V>, replaced: &mut Vec<String>) {
+    if overlay.is_empty() {
+        return;
+    }
+    if !std::mem::replace(target, overlay).is_empty() {
+        replaced.push(path.to_string());
+    }
+}
+
+impl Device {
+    /// Overlays the fields `other` sets; unset options and empty lists keep this
+    /// device's value. `pages` is replaced whole. `path` prefixes the reported
+    /// entries, e.g. `devices.pad.hardware_id`.
+    pub fn merge(&mut self, path: &str, other: Device, replaced: &mut Vec<String>) {
+        merge_option(
+            &format!("{path}.hardware_id"),
+            &mut self.hardware_id,
+            other.hardware_id,
+            replaced,
+        );
+        merge_list(
+            &format!("{path}.pages"),
+            &mut self.pages,
+            other.pages,
+            replaced,
+        );
+        merge_option(
+            &format!("{path}.note_range"),
+            &mut self.note_range,
+            other.note_range,
+            replaced,
+        );
+        merge_list(
+            &format!("{path}.reserved_notes"),
+            &mut self.reserved_notes,
+            other.reserved_notes,
+            replaced,
+        );
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+pub struct NoteRange {
+    pub low: u8,
+    pub high: u8,
+}
+
+impl NoteRange {
+    pub fn contains(&self, note: u8) -> bool {
+        (self.low..=self.high).contains(&note)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Page {
     pub name: String,
     #[serde(default)]
     pub widgets: Vec<Widget>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Widget {
     pub id: String,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub action: Option<Action>,
-    #[serde(default)]
+    /// One of `TAP_BEHAVIORS`; `hold` and `toggle` stand in for the matching `mode`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub tap_behavior: Option<String>,
+    /// How press/release map onto `action` and `off_action`; unset runs `action` on press only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<ActionMode>,
+    /// Run on release (`momentary`) or on every second press (`latching`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub off_action: Option<Action>,
+    /// Run instead of `action` when two presses land within the double-tap window.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub double_tap_action: Option<Action>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionMode {
+    Momentary,
+    Latching,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum Action {
     #[serde(rename_all = "snake_case")]
@@ -72,23 +384,79 @@ pub enum Action {
         #[serde(rename = "ref")]
         ref_: String,
     },
+    /// Makes `page` the active page of `device`; only active-page widgets respond.
+    PageSwitch { device: String, page: String },
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Macro {
     #[serde(default = "default_status")]
     pub status: MacroStatus,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
     #[serde(default)]
     pub tags: Vec<String>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub trigger: Option<MidiTrigger>,
     #[serde(default)]
     pub steps: Vec<MacroStep>,
+    /// Cancel the macro if it runs longer than this; falls back to the global default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_duration_ms: Option<u64>,
+    /// What to do when a step fails; defaults to `abort`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_error: Option<ErrorPolicy>,
+    /// Ignore re-triggers this soon after the macro last fired, e.g. from a bouncy pad.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cooldown_ms: Option<u64>,
+}
+
+/// Reaction to a failing step, written `continue`, `abort` or `{ run_macro: <id> }`.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, JsonSchema)]
+#[serde(from = "ErrorPolicyRepr", into = "ErrorPolicyRepr")]
+pub enum ErrorPolicy {
+    Continue,
+    Abort,
+    /// Stop the macro and run the named fallback macro instead.
+    RunMacro(String),
+}
+
+/// YAML shape of [`ErrorPolicy`]: a bare keyword or a single-key `run_macro` map.
+#[derive(Deserialize, Serialize, JsonSchema)]
+#[serde(untagged)]
+enum ErrorPolicyRepr {
+    Keyword(ErrorKeyword),
+    RunMacro { run_macro: String },
 }
 
-#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[derive(Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum ErrorKeyword {
+    Continue,
+    Abort,
+}
+
+impl From<ErrorPolicyRepr> for ErrorPolicy {
+    fn from(repr: ErrorPolicyRepr) -> Self {
+        match repr {
+            ErrorPolicyRepr::Keyword(ErrorKeyword::Continue) => ErrorPolicy::Continue,
+            ErrorPolicyRepr::Keyword(ErrorKeyword::Abort) => ErrorPolicy::Abort,
+            ErrorPolicyRepr::RunMacro { run_macro } => ErrorPolicy::RunMacro(run_macro),
+        }
+    }
+}
+
+impl From<ErrorPolicy> for ErrorPolicyRepr {
+    fn from(policy: ErrorPolicy) -> Self {
+        match policy {
+            ErrorPolicy::Continue => ErrorPolicyRepr::Keyword(ErrorKeyword::Continue),
+            ErrorPolicy::Abort => ErrorPolicyRepr::Keyword(ErrorKeyword::Abort),
+            ErrorPolicy::RunMacro(run_macro) => ErrorPolicyRepr::RunMacro { run_macro },
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum MacroStatus {
     Draft,
@@ -99,28 +467,330 @@ fn default_status() -> MacroStatus {
     MacroStatus::Draft
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct MidiTrigger {
     pub r#type: MidiTriggerType,
-    pub number: u8,
+    /// Note number (or alias) for `note` triggers; controller number for `control_change`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub number: Option<NoteRef>,
+    /// Real-time message for `transport` triggers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transport: Option<TransportKind>,
+    /// MIDI channel (0–15) the trigger listens on; unset matches every channel.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<u8>,
+    /// 14-bit value (0–16383) a `pitch_bend` trigger fires on crossing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<u16>,
+    /// Which way the value must cross `threshold`; defaults to `rising`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<CrossingDirection>,
+    /// Lowest note (inclusive) of a `note_range` trigger.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub low: Option<u8>,
+    /// Highest note (inclusive) of a `note_range` trigger.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub high: Option<u8>,
+    /// Notes a `chord` trigger needs held together.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<u8>,
+    /// How close together a `chord`'s notes must be pressed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_ms: Option<u64>,
+}
+
+impl MidiTrigger {
+    /// Resolves the trigger number, looking up aliases in `note_aliases`.
+    pub fn resolve_number(&self, aliases: &HashMap<String, u8>) -> Option<u8> {
+        match self.number.as_ref()? {
+            NoteRef::Number(number) => Some(*number),
+            NoteRef::Alias(name) => aliases.get(name).copied(),
+        }
+    }
 }
 
-#[derive(Debug, Deserialize, PartialEq, Eq)]
+/// A trigger number written literally, as a note name (`"C4"`, `"F#3"`), or as a
+/// `note_aliases` key. Note names become `Number` when the config is parsed.
+#[derive(Debug, Serialize, PartialEq, Eq, Clone, JsonSchema)]
+#[serde(untagged)]
+pub enum NoteRef {
+    Number(u8),
+    Alias(String),
+}
+
+impl<'de> Deserialize<'de> for NoteRef {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(u8),
+            Text(String),
+        }
+
+        Ok(match Raw::deserialize(deserializer)? {
+            Raw::Number(number) => NoteRef::Number(number),
+            Raw::Text(text) => match parse_note_name(&text) {
+                Some(number) => NoteRef::Number(number),
+                None => NoteRef::Alias(text),
+            },
+        })
+    }
+}
+
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// Parses a note name such as `C4`, `F#3` or `Bb-1` into a MIDI note number,
+/// with middle C (`C4`) as 60. Returns `None` for anything outside 0–127.
+pub fn parse_note_name(name: &str) -> Option<u8> {
+    let mut chars = name.chars();
+    let pitch: i32 = match chars.next()?.to_ascii_uppercase() {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+    let rest = chars.as_str();
+    let (accidental, octave) = match rest.as_bytes().first()? {
+        b'#' => (1, &rest[1..]),
+        b'b' => (-1, &rest[1..]),
+        _ => (0, rest),
+    };
+    let octave: i32 = octave.parse().ok()?;
+    let number = (octave + 1) * 12 + pitch + accidental;
+    u8::try_from(number).ok().filter(|number| *number <= 127)
+}
+
+/// Formats a MIDI note number as a note name using sharps, e.g. 60 as `C4`.
+pub fn note_name(number: u8) -> String {
+    let octave = i32::from(number / 12) - 1;
+    format!("{}{}", NOTE_NAMES[usize::from(number % 12)], octave)
+}
+
+impl fmt::Display for NoteRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NoteRef::Number(number) => write!(f, "{number}"),
+            NoteRef::Alias(name) => f.write_str(name),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum MidiTriggerType {
     Note,
+    /// Control change; `number` is the controller (0–127).
+    ControlChange,
+    Transport,
+    PitchBend,
+    /// Any note from `low` to `high`; exact `note` triggers take precedence.
+    NoteRange,
+    /// Every note in `notes` held at once, pressed within `window_ms`.
+    Chord,
+    /// Program change; `number` is the program (0–127).
+    ProgramChange,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CrossingDirection {
+    Rising,
+    Falling,
+}
+
+/// MIDI system real-time transport messages.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone, Copy, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TransportKind {
+    Start,
+    Stop,
+    Continue,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum MacroStep {
-    Keystroke { keys: Vec<String> },
-    Pause { ms: u64 },
+    Keystroke {
+        keys: Vec<String>,
+    },
+    /// Types a literal string, e.g. a Unicode snippet or a long command.
+    Text {
+        text: String,
+    },
+    /// Moves the cursor to screen coordinates, or by an offset when `relative`.
+    MouseMove {
+        x: i32,
+        y: i32,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        relative: bool,
+    },
+    MouseClick {
+        button: MouseButton,
+    },
+    Pause {
+        ms: u64,
+        /// Human-readable note shown in action logs (e.g. "wait for dialog").
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        label: Option<String>,
+    },
+    /// Steps whose key output must not interleave with other running macros.
+    Atomic {
+        steps: Vec<MacroStep>,
+    },
+    /// Runs the nested steps `count` times, e.g. for drum rolls.
+    Repeat {
+        count: u32,
+        steps: Vec<MacroStep>,
+    },
+    /// Runs another macro's steps in place.
+    RunMacro {
+        id: String,
+    },
+    /// Runs an entry of the top-level `scripts` map.
+    RunScript {
+        id: String,
+    },
+    /// Sends raw bytes (status byte first) to the MIDI output, e.g. pad LED feedback.
+    MidiOut {
+        message: Vec<u8>,
+    },
+    /// Blocks until a note-on for `note` arrives or `timeout_ms` elapses.
+    WaitForMidi {
+        note: u8,
+        timeout_ms: u64,
+        /// Keep running the macro after a timeout instead of aborting it.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        continue_on_timeout: bool,
+    },
+    /// Stops the macro here unless the triggering velocity is within `min..=max`.
+    VelocityGate {
+        min: u8,
+        max: u8,
+    },
+    /// Starts `program` directly (no shell) with `args` and waits for it to exit.
+    /// Only runs when `global.defaults.allow_commands` is set.
+    RunCommand {
+        program: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        args: Vec<String>,
+    },
+    /// Presses `key` and keeps it held until a matching `key_up` step.
+    KeyDown {
+        key: String,
+    },
+    /// Releases a key held by an earlier `key_down` step.
+    KeyUp {
+        key: String,
+    },
+    /// Pauses for a random duration within `min_ms..=max_ms`, to humanize timing.
+    RandomPause {
+        min_ms: u64,
+        max_ms: u64,
+    },
+    /// Pauses for `us` microseconds, for timing finer than `pause` can express.
+    PauseMicros {
+        us: u64,
+    },
+    /// Releases every key still held by `key_down` steps and silences MIDI output.
+    Reset,
+    /// Stores `value` in the runtime variable `name`, for later `if` steps.
+    SetVar {
+        name: String,
+        value: i64,
+    },
+    /// Runs `then` when variable `var` equals `equals`, otherwise `else`. Variables
+    /// nothing has set read as 0.
+    If {
+        var: String,
+        equals: i64,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        then: Vec<MacroStep>,
+        #[serde(default, rename = "else", skip_serializing_if = "Vec::is_empty")]
+        else_: Vec<MacroStep>,
+    },
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 #[serde(untagged)]
 pub enum Script {
-    Body { body: String },
+    Body {
+        body: String,
+        /// Interpreter the body is written for (see `SCRIPT_LANGUAGES`).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        language: Option<String>,
+    },
     Inline(String),
 }
+
+/// Accepted `tap_behavior` values.
+pub const TAP_BEHAVIORS: &[&str] = &["tap", "hold", "toggle"];
+
+/// Script languages the runtime knows how to host.
+pub const SCRIPT_LANGUAGES: &[&str] = &["python", "lua", "rhai", "shell"];
+
+impl Script {
+    pub fn body(&self) -> &str {
+        match self {
+            Script::Body { body, .. } => body,
+            Script::Inline(body) => body,
+        }
+    }
+
+    pub fn language(&self) -> Option<&str> {
+        match self {
+            Script::Body { language, .. } => language.as_deref(),
+            Script::Inline(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn note_names_round_trip_at_the_boundaries() {
+        assert_eq!(parse_note_name("C-1"), Some(0));
+        assert_eq!(parse_note_name("G9"), Some(127));
+        assert_eq!(note_name(0), "C-1");
+        assert_eq!(note_name(127), "G9");
+        for number in 0..=127 {
+            assert_eq!(parse_note_name(&note_name(number)), Some(number));
+        }
+    }
+
+    #[test]
+    fn parses_accidentals_and_rejects_out_of_range_names() {
+        assert_eq!(parse_note_name("C4"), Some(60));
+        assert_eq!(parse_note_name("F#3"), Some(54));
+        assert_eq!(parse_note_name("Bb3"), Some(58));
+        assert_eq!(parse_note_name("G#9"), None);
+        assert_eq!(parse_note_name("Cb-1"), None);
+        assert_eq!(parse_note_name("H2"), None);
+        assert_eq!(parse_note_name("kick"), None);
+    }
+
+    #[test]
+    fn trigger_number_accepts_note_names() {
+        let trigger: MidiTrigger =
+            serde_yaml::from_str("type: note\nnumber: \"D#2\"\n").expect("parse");
+        assert_eq!(trigger.number, Some(NoteRef::Number(39)));
+        let trigger: MidiTrigger =
+            serde_yaml::from_str("type: note\nnumber: kick\n").expect("parse");
+        assert_eq!(trigger.number, Some(NoteRef::Alias("kick".into())));
+    }
+}
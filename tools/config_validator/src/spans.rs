@@ -0,0 +1,187 @@
+//! Source positions of config nodes, so diagnostics point at the node their path names.
+
+use yaml_rust2::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust2::scanner::Marker;
+
+use crate::validation::Location;
+
+/// A parsed YAML (or JSON) document that keeps where each node starts.
+pub(crate) struct SourceMap {
+    root: Option<Node>,
+}
+
+struct Node {
+    mark: Marker,
+    kind: NodeKind,
+}
+
+enum NodeKind {
+    Scalar(String),
+    /// Entries keep the position of their key, which is what diagnostics point at.
+    Mapping(Vec<(String, Marker, Node)>),
+    Sequence(Vec<Node>),
+}
+
+enum Open {
+    Mapping {
+        mark: Marker,
+        entries: Vec<(String, Marker, Node)>,
+        key: Option<(String, Marker)>,
+    },
+    Sequence {
+        mark: Marker,
+        items: Vec<Node>,
+    },
+}
+
+#[derive(Default)]
+struct TreeBuilder {
+    open: Vec<Open>,
+    root: Option<Node>,
+}
+
+impl TreeBuilder {
+    fn push(&mut self, node: Node) {
+        match self.open.last_mut() {
+            None => {
+                // Only the first document is located against.
+                self.root.get_or_insert(node);
+            }
+            Some(Open::Sequence { items, .. }) => items.push(node),
+            Some(Open::Mapping { entries, key, .. }) => match key.take() {
+                Some((name, mark)) => entries.push((name, mark, node)),
+                None => {
+                    let name = match node.kind {
+                        NodeKind::Scalar(name) => name,
+                        // Complex keys never match a path segment.
+                        _ => String::new(),
+                    };
+                    *key = Some((name, node.mark));
+                }
+            },
+        }
+    }
+}
+
+impl MarkedEventReceiver for TreeBuilder {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        match event {
+            Event::Scalar(value, ..) => self.push(Node {
+                mark,
+                kind: NodeKind::Scalar(value),
+            }),
+            Event::Alias(_) => self.push(Node {
+                mark,
+                kind: NodeKind::Scalar(String::new()),
+            }),
+            Event::MappingStart(..) => self.open.push(Open::Mapping {
+                mark,
+                entries: Vec::new(),
+                key: None,
+            }),
+            Event::SequenceStart(..) => self.open.push(Open::Sequence {
+                mark,
+                items: Vec::new(),
+            }),
+            Event::MappingEnd | Event::SequenceEnd => {
+                let node = match self.open.pop() {
+                    // Block mappings are only recognised at their first `:`, so
+                    // their start event lands there; the first key is where they begin.
+                    Some(Open::Mapping { mark, entries, .. }) => Node {
+                        mark: entries.first().map_or(mark, |(_, key, _)| *key),
+                        kind: NodeKind::Mapping(entries),
+                    },
+                    Some(Open::Sequence { mark, items }) => Node {
+                        mark,
+                        kind: NodeKind::Sequence(items),
+                    },
+                    None => return,
+                };
+                self.push(node);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl SourceMap {
+    /// Parses `source`; a source that fails to parse locates nothing.
+    pub(crate) fn parse(source: &str) -> Self {
+        let mut builder = TreeBuilder::default();
+        let root = match Parser::new_from_str(source).load(&mut builder, false) {
+            Ok(()) => builder.root,
+            Err(_) => None,
+        };
+        Self { root }
+    }
+
+    /// Position of the node a dotted diagnostic path such as `macros.copy.steps[1]`
+    /// names. Mapping entries resolve to their key; sequence items may be named by
+    /// index or by their `id`. When the path runs past the source, the deepest node
+    /// found is returned instead.
+    pub(crate) fn locate(&self, path: &str) -> Option<Location> {
+        let mut node = self.root.as_ref()?;
+        let mut found = None;
+        for segment in path_segments(path) {
+            let next = match (&node.kind, segment) {
+                (NodeKind::Mapping(entries), Segment::Key(key)) => entries
+                    .iter()
+                    .find(|(name, ..)| name == key)
+                    .map(|(_, mark, value)| (*mark, value)),
+                (NodeKind::Sequence(items), Segment::Key(id)) => items
+                    .iter()
+                    .find(|item| item.scalar_field("id") == Some(id))
+                    .map(|item| (item.mark, item)),
+                (NodeKind::Sequence(items), Segment::Index(index)) => {
+                    items.get(index).map(|item| (item.mark, item))
+                }
+                _ => None,
+            };
+            let Some((mark, child)) = next else { break };
+            found = Some(mark);
+            node = child;
+        }
+        found.map(|mark| Location {
+            line: mark.line(),
+            column: mark.col() + 1,
+        })
+    }
+}
+
+impl Node {
+    fn scalar_field(&self, field: &str) -> Option<&str> {
+        let NodeKind::Mapping(entries) = &self.kind else {
+            return None;
+        };
+        entries
+            .iter()
+            .find_map(|(name, _, value)| match &value.kind {
+                NodeKind::Scalar(text) if name == field => Some(text.as_str()),
+                _ => None,
+            })
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Segment<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+/// Splits `devices.pad.pages[0].widgets` into keys and indices.
+fn path_segments(path: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    for part in path.split('.') {
+        let mut pieces = part.split('[');
+        if let Some(key) = pieces.next().filter(|key| !key.is_empty()) {
+            segments.push(Segment::Key(key));
+        }
+        for index in pieces {
+            match index.trim_end_matches(']').parse() {
+                Ok(index) => segments.push(Segment::Index(index)),
+                Err(_) => return segments,
+            }
+        }
+    }
+    segments
+}
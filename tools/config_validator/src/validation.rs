@@ -1,22 +1,60 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-use crate::schema::{Action, Config, MacroStatus, MacroStep, MidiTriggerType, Script};
+use crate::keys::{is_known_key, is_limited_key};
+use crate::schema::{
+    Action, Config, CrossingDirection, ErrorPolicy, GridSize, MacroStatus, MacroStep, MidiTrigger,
+    MidiTriggerType, SCRIPT_LANGUAGES, TAP_BEHAVIORS, TransportKind, VirtualConsole,
+};
+use crate::spans::SourceMap;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Location {
     pub line: usize,
     pub column: usize,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ValidationIssue {
+    /// Dotted path for display; logic should match on `target` instead.
     pub path: String,
     pub message: String,
     pub location: Option<Location>,
     pub severity: Severity,
+    pub target: DiagnosticTarget,
+    /// Style-only hint (always [`Severity::Info`]) that strict builds may drop.
+    pub lint: bool,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The config entity an issue is about, as known when the issue was raised.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DiagnosticTarget {
+    /// Anything without a more specific target: globals, profiles, aliases,
+    /// the virtual console and parse failures.
+    #[default]
+    Other,
+    Version,
+    Device {
+        id: String,
+    },
+    Widget {
+        device: String,
+        page_index: usize,
+        widget_id: String,
+    },
+    /// `step` is the index of the top-level step the issue arose in, if any.
+    Macro {
+        name: String,
+        step: Option<usize>,
+    },
+    Script {
+        name: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Severity {
     Error,
     Warning,
@@ -30,10 +68,133 @@ impl ValidationIssue {
             message,
             location: None,
             severity,
+            target: DiagnosticTarget::Other,
+            lint: false,
+        }
+    }
+
+    pub fn with_target(mut self, target: DiagnosticTarget) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// An info-level style hint; see [`ValidationIssue::lint`].
+    fn lint(path: String, message: &str, target: DiagnosticTarget) -> Self {
+        Self {
+            lint: true,
+            ..Self::new(path, message.into(), Severity::Info).with_target(target)
+        }
+    }
+}
+
+/// Drops style lints, keeping every diagnostic that concerns correctness.
+pub fn without_lints(issues: Vec<ValidationIssue>) -> Vec<ValidationIssue> {
+    issues.into_iter().filter(|issue| !issue.lint).collect()
+}
+
+/// Targets the issues raised since `start` that do not have a target yet, so an
+/// enclosing scope only fills in what an inner scope left open.
+fn target_issues(issues: &mut [ValidationIssue], start: usize, target: DiagnosticTarget) {
+    for issue in &mut issues[start..] {
+        if issue.target == DiagnosticTarget::Other {
+            issue.target = target.clone();
+        }
+    }
+}
+
+impl Severity {
+    pub fn label(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+        }
+    }
+}
+
+/// Renders an issue in the canonical one-line form shared by the CLIs:
+/// `[level] path: message (line L, column C)`.
+pub fn format_diagnostic(issue: &ValidationIssue) -> String {
+    let mut text = format!(
+        "[{}] {}: {}",
+        issue.severity.label(),
+        issue.path,
+        issue.message
+    );
+    if let Some(loc) = issue.location {
+        text.push_str(&format!(" (line {}, column {})", loc.line, loc.column));
+    }
+    text
+}
+
+fn validate_action(
+    action: &Action,
+    path: &str,
+    config: &Config,
+    issues: &mut Vec<ValidationIssue>,
+) {
+    match action {
+        Action::Macro { ref_ } => {
+            if !config.macros.contains_key(ref_) {
+                issues.push(ValidationIssue::new(
+                    path.to_string(),
+                    format!("References undefined macro `{}`", ref_),
+                    Severity::Error,
+                ));
+            } else if let Some(mac) = config.macros.get(ref_)
+                && mac.status != MacroStatus::Ready
+            {
+                issues.push(ValidationIssue::new(
+                    path.to_string(),
+                    format!(
+                        "References macro `{}` that is not marked ready and will not be compiled",
+                        ref_
+                    ),
+                    Severity::Warning,
+                ));
+            }
+        }
+        Action::Script { ref_ } => {
+            if !config.scripts.contains_key(ref_) {
+                issues.push(ValidationIssue::new(
+                    path.to_string(),
+                    format!("References undefined script `{}`", ref_),
+                    Severity::Error,
+                ));
+            }
+            if !commands_allowed(config) {
+                issues.push(ValidationIssue::new(
+                    path.to_string(),
+                    SCRIPTS_REFUSED.into(),
+                    Severity::Warning,
+                ));
+            }
+        }
+        Action::PageSwitch { device, page } => {
+            validate_page_ref(device, page, path, config, issues);
         }
     }
 }
 
+/// Whether `global.defaults.allow_commands` lets commands and scripts run.
+fn commands_allowed(config: &Config) -> bool {
+    config
+        .global
+        .as_ref()
+        .and_then(|global| global.defaults.as_ref())
+        .is_some_and(|defaults| defaults.allow_commands)
+}
+
+/// Scripts run as shell commands, so they are gated like `run_command` steps.
+const SCRIPTS_REFUSED: &str = "Scripts are refused unless `global.defaults.allow_commands` is set";
+
+/// Largest 14-bit pitch bend value.
+const PITCH_BEND_MAX: u16 = 16383;
+/// Cooldowns longer than this are more likely a unit mistake than a debounce.
+const COOLDOWN_WARN_MS: u64 = 60_000;
+/// Pauses longer than this get a lint, as they usually hide a missing `wait_for_midi`.
+const LONG_PAUSE_MS: u64 = 5_000;
+
 fn adjust_severity_for_macro(status: MacroStatus, severity: Severity) -> Severity {
     if status == MacroStatus::Draft && severity == Severity::Error {
         Severity::Warning
@@ -46,16 +207,100 @@ pub fn validate_config(config: &Config, source: &str) -> Vec<ValidationIssue> {
     let mut issues = Vec::new();
 
     if config.version != 1 {
+        issues.push(
+            ValidationIssue::new(
+                "version".into(),
+                format!("Unsupported schema version {} (expected 1)", config.version),
+                Severity::Error,
+            )
+            .with_target(DiagnosticTarget::Version),
+        );
+    }
+
+    if let Some(global) = &config.global {
+        for (field, hook) in [
+            ("on_before", &global.on_before),
+            ("on_after", &global.on_after),
+        ] {
+            let Some(ref_) = hook else { continue };
+            match config.macros.get(ref_) {
+                None => issues.push(ValidationIssue::new(
+                    format!("global.{field}"),
+                    format!("References undefined macro `{}`", ref_),
+                    Severity::Error,
+                )),
+                Some(mac) if mac.status != MacroStatus::Ready => issues.push(ValidationIssue::new(
+                    format!("global.{field}"),
+                    format!(
+                        "References macro `{}` that is not marked ready and will not be compiled",
+                        ref_
+                    ),
+                    Severity::Error,
+                )),
+                Some(_) => {}
+            }
+        }
+    }
+
+    for (profile, members) in &config.profiles {
+        for (idx, member) in members.iter().enumerate() {
+            let path = format!("profiles.{profile}[{idx}]");
+            match config.macros.get(member) {
+                None => issues.push(ValidationIssue::new(
+                    path,
+                    format!("References undefined macro `{}`", member),
+                    Severity::Error,
+                )),
+                Some(mac) if mac.status != MacroStatus::Ready => issues.push(ValidationIssue::new(
+                    path,
+                    format!(
+                        "Macro `{}` is not marked ready and will be left out of the profile",
+                        member
+                    ),
+                    Severity::Warning,
+                )),
+                Some(_) => {}
+            }
+        }
+    }
+
+    let defaults = config
+        .global
+        .as_ref()
+        .and_then(|global| global.defaults.as_ref());
+    if let Some(ms) = defaults.and_then(|defaults| defaults.max_macro_duration_ms)
+        && ms == 0
+    {
+        issues.push(ValidationIssue::new(
+            "global.defaults.max_macro_duration_ms".into(),
+            "max_macro_duration_ms must be greater than zero".into(),
+            Severity::Error,
+        ));
+    }
+    if let Some(ms) = defaults.and_then(|defaults| defaults.double_tap_window_ms)
+        && ms == 0
+    {
         issues.push(ValidationIssue::new(
-            "version".into(),
-            format!("Unsupported schema version {} (expected 1)", config.version),
+            "global.defaults.double_tap_window_ms".into(),
+            "double_tap_window_ms must be greater than zero".into(),
             Severity::Error,
         ));
     }
 
+    for (alias, number) in &config.note_aliases {
+        if *number > 127 {
+            issues.push(ValidationIssue::new(
+                format!("note_aliases.{alias}"),
+                "Note alias must map to a note between 0 and 127".into(),
+                Severity::Error,
+            ));
+        }
+    }
+
     let mut hardware_ids: HashMap<String, String> = HashMap::new();
     for (device_name, device) in &config.devices {
         let path = format!("devices.{device_name}");
+        let device_start = issues.len();
 
         match device.hardware_id.as_deref() {
             Some(id) if !id.trim().is_empty() => {
@@ -88,10 +333,37 @@ pub fn validate_config(config: &Config, source: &str) -> Vec<ValidationIssue> {
             }
         }
 
+        if let Some(range) = device.note_range
+            && range.low > range.high
+        {
+            issues.push(ValidationIssue::new(
+                format!("{path}.note_range"),
+                format!(
+                    "note_range low ({}) must not exceed high ({})",
+                    range.low, range.high
+                ),
+                Severity::Error,
+            ));
+        }
+
+        let mut page_names = HashSet::new();
+        // Pages each widget id appears on, for the device-wide uniqueness warning.
+        let mut widget_pages: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
         for (page_index, page) in device.pages.iter().enumerate() {
+            if !page_names.insert(page.name.as_str()) {
+                issues.push(ValidationIssue::new(
+                    format!("{path}.pages[{page_index}]"),
+                    format!(
+                        "Duplicate page name `{}`; lookups by name only reach the first such page",
+                        page.name
+                    ),
+                    Severity::Error,
+                ));
+            }
             let mut widget_ids = HashSet::new();
             for widget in &page.widgets {
                 let widget_path = format!("{path}.pages[{page_index}].widgets.{}", widget.id);
+                let widget_start = issues.len();
 
                 if !widget_ids.insert(widget.id.clone()) {
                     issues.push(ValidationIssue::new(
@@ -100,71 +372,334 @@ pub fn validate_config(config: &Config, source: &str) -> Vec<ValidationIssue> {
                         Severity::Error,
                     ));
                 }
+                let pages = widget_pages.entry(widget.id.as_str()).or_default();
+                if pages.last() != Some(&page_index) {
+                    pages.push(page_index);
+                }
 
                 if let Some(action) = &widget.action {
-                    match action {
-                        Action::Macro { ref_ } => {
-                            if !config.macros.contains_key(ref_) {
+                    validate_action(action, &widget_path, config, &mut issues);
+                    if let Action::Macro { ref_ } = action
+                        && let Some(number) = config
+                            .macros
+                            .get(ref_)
+                            .and_then(|mac| mac.trigger.as_ref())
+                            .filter(|trigger| trigger.r#type == MidiTriggerType::Note)
+                            .and_then(|trigger| trigger.resolve_number(&config.note_aliases))
+                        && device.reserved_notes.contains(&number)
+                    {
+                        issues.push(ValidationIssue::new(
+                            widget_path.clone(),
+                            format!(
+                                "Macro `{}` is triggered by note {}, which this device reserves for system functions",
+                                ref_, number
+                            ),
+                            Severity::Warning,
+                        ));
+                    }
+                }
+                let tap_behavior = widget.tap_behavior.as_deref();
+                if let Some(behavior) = tap_behavior
+                    && !TAP_BEHAVIORS.contains(&behavior)
+                {
+                    issues.push(ValidationIssue::new(
+                        format!("{widget_path}.tap_behavior"),
+                        format!(
+                            "Unknown tap_behavior `{}` (expected one of: {})",
+                            behavior,
+                            TAP_BEHAVIORS.join(", ")
+                        ),
+                        Severity::Error,
+                    ));
+                }
+                if let Some(off_action) = &widget.off_action {
+                    let off_path = format!("{widget_path}.off_action");
+                    let paired = matches!(tap_behavior, Some("hold" | "toggle"));
+                    if widget.mode.is_none() && !paired {
+                        issues.push(ValidationIssue::new(
+                            off_path.clone(),
+                            "off_action is ignored unless `mode` is momentary or latching (or tap_behavior is hold or toggle)".into(),
+                            Severity::Warning,
+                        ));
+                    }
+                    validate_action(off_action, &off_path, config, &mut issues);
+                }
+                if let Some(double_tap_action) = &widget.double_tap_action {
+                    let double_tap_path = format!("{widget_path}.double_tap_action");
+                    validate_action(double_tap_action, &double_tap_path, config, &mut issues);
+                }
+                target_issues(
+                    &mut issues,
+                    widget_start,
+                    DiagnosticTarget::Widget {
+                        device: device_name.clone(),
+                        page_index,
+                        widget_id: widget.id.clone(),
+                    },
+                );
+            }
+        }
+        for (widget_id, pages) in widget_pages.iter().filter(|(_, pages)| pages.len() > 1) {
+            let names: Vec<String> = pages
+                .iter()
+                .map(|index| format!("`{}`", device.pages[*index].name))
+                .collect();
+            issues.push(ValidationIssue::new(
+                path.clone(),
+                format!(
+                    "Widget id `{}` appears on pages {}; console lookups by widget id are ambiguous",
+                    widget_id,
+                    names.join(", ")
+                ),
+                Severity::Warning,
+            ));
+        }
+        target_issues(
+            &mut issues,
+            device_start,
+            DiagnosticTarget::Device {
+                id: device_name.clone(),
+            },
+        );
+    }
+
+    let mut note_map: HashMap<(u8, Option<u8>), String> = HashMap::new();
+    let mut cc_map: HashMap<(u8, Option<u8>), String> = HashMap::new();
+    let mut program_map: HashMap<(u8, Option<u8>), String> = HashMap::new();
+    let mut transport_map: HashMap<TransportKind, String> = HashMap::new();
+    // Only meaningful when every device declares a range; an undeclared device may send any note.
+    let device_ranges: Option<Vec<_>> = if config.devices.is_empty() {
+        None
+    } else {
+        config.devices.values().map(|d| d.note_range).collect()
+    };
+    let mut reserving_devices: Vec<_> = config
+        .devices
+        .iter()
+        .filter(|(_, device)| !device.reserved_notes.is_empty())
+        .collect();
+    reserving_devices.sort_by_key(|(name, _)| *name);
+
+    for (macro_name, macro_def) in &config.macros {
+        let macro_path = format!("macros.{macro_name}");
+        let macro_start = issues.len();
+
+        if let Some(trigger) = &macro_def.trigger {
+            let trigger_path = format!("{macro_path}.trigger");
+            if let Some(channel) = trigger.channel
+                && channel > 15
+            {
+                issues.push(ValidationIssue::new(
+                    trigger_path.clone(),
+                    format!("MIDI channel {} must be between 0 and 15", channel),
+                    adjust_severity_for_macro(macro_def.status, Severity::Error),
+                ));
+            }
+            match trigger.r#type {
+                MidiTriggerType::Note => {
+                    let number = resolve_trigger_number(
+                        trigger,
+                        config,
+                        macro_def.status,
+                        &trigger_path,
+                        &mut issues,
+                    );
+                    if let Some(number) = number {
+                        if number > 127 {
+                            issues.push(ValidationIssue::new(
+                                trigger_path.clone(),
+                                "Note trigger number must be between 0 and 127".into(),
+                                adjust_severity_for_macro(macro_def.status, Severity::Error),
+                            ));
+                        } else {
+                            if let Some(existing) =
+                                note_map.insert((number, trigger.channel), macro_name.clone())
+                            {
                                 issues.push(ValidationIssue::new(
-                                    widget_path.clone(),
-                                    format!("References undefined macro `{}`", ref_),
-                                    Severity::Error,
+                                    trigger_path.clone(),
+                                    format!(
+                                        "Note {} already assigned to macro `{}`",
+                                        number, existing
+                                    ),
+                                    Severity::Warning,
                                 ));
-                            } else if let Some(mac) = config.macros.get(ref_) {
-                                if mac.status != MacroStatus::Ready {
+                            }
+                            for (device_name, device) in &reserving_devices {
+                                if device.reserved_notes.contains(&number) {
                                     issues.push(ValidationIssue::new(
-                                        widget_path.clone(),
+                                        trigger_path.clone(),
                                         format!(
-                                            "References macro `{}` that is not marked ready and will not be compiled",
-                                            ref_
+                                            "Note {} is reserved for system functions on device `{}`",
+                                            number, device_name
                                         ),
                                         Severity::Warning,
                                     ));
                                 }
                             }
-                        }
-                        Action::Script { ref_ } => {
-                            if !config.scripts.contains_key(ref_) {
+                            if let Some(ranges) = &device_ranges
+                                && !ranges.iter().any(|r| r.contains(number))
+                            {
                                 issues.push(ValidationIssue::new(
-                                    widget_path.clone(),
-                                    format!("References undefined script `{}`", ref_),
-                                    Severity::Error,
+                                    trigger_path.clone(),
+                                    format!(
+                                        "Note {} is outside the declared note range of every device",
+                                        number
+                                    ),
+                                    Severity::Info,
                                 ));
                             }
                         }
                     }
                 }
-            }
-        }
-    }
-
-    let mut note_map: HashMap<u8, String> = HashMap::new();
-
-    for (macro_name, macro_def) in &config.macros {
-        let macro_path = format!("macros.{macro_name}");
-
-        if let Some(trigger) = &macro_def.trigger {
-            match trigger.r#type {
-                MidiTriggerType::Note => {
-                    if trigger.number > 127 {
+                MidiTriggerType::Transport => match trigger.transport {
+                    None => issues.push(ValidationIssue::new(
+                        trigger_path.clone(),
+                        "Transport trigger requires `transport` (start, stop or continue)".into(),
+                        adjust_severity_for_macro(macro_def.status, Severity::Error),
+                    )),
+                    Some(kind) => {
+                        if let Some(existing) = transport_map.insert(kind, macro_name.clone()) {
+                            issues.push(ValidationIssue::new(
+                                trigger_path.clone(),
+                                format!(
+                                    "Transport {:?} already assigned to macro `{}`",
+                                    kind, existing
+                                ),
+                                Severity::Warning,
+                            ));
+                        }
+                    }
+                },
+                MidiTriggerType::ControlChange => {
+                    let number = resolve_trigger_number(
+                        trigger,
+                        config,
+                        macro_def.status,
+                        &trigger_path,
+                        &mut issues,
+                    );
+                    if let Some(number) = number {
+                        if number > 127 {
+                            issues.push(ValidationIssue::new(
+                                trigger_path.clone(),
+                                "Control change number must be between 0 and 127".into(),
+                                adjust_severity_for_macro(macro_def.status, Severity::Error),
+                            ));
+                        } else if let Some(existing) =
+                            cc_map.insert((number, trigger.channel), macro_name.clone())
+                        {
+                            issues.push(ValidationIssue::new(
+                                trigger_path.clone(),
+                                format!("CC {} already assigned to macro `{}`", number, existing),
+                                Severity::Warning,
+                            ));
+                        }
+                    }
+                }
+                MidiTriggerType::ProgramChange => {
+                    let number = resolve_trigger_number(
+                        trigger,
+                        config,
+                        macro_def.status,
+                        &trigger_path,
+                        &mut issues,
+                    );
+                    if let Some(number) = number {
+                        if number > 127 {
+                            issues.push(ValidationIssue::new(
+                                trigger_path.clone(),
+                                "Program change number must be between 0 and 127".into(),
+                                adjust_severity_for_macro(macro_def.status, Severity::Error),
+                            ));
+                        } else if let Some(existing) =
+                            program_map.insert((number, trigger.channel), macro_name.clone())
+                        {
+                            issues.push(ValidationIssue::new(
+                                trigger_path.clone(),
+                                format!(
+                                    "Program {} already assigned to macro `{}`",
+                                    number, existing
+                                ),
+                                Severity::Warning,
+                            ));
+                        }
+                    }
+                }
+                MidiTriggerType::NoteRange => {
+                    let message = match (trigger.low, trigger.high) {
+                        (Some(low), Some(high)) if low > 127 || high > 127 => Some(format!(
+                            "Note range {low}-{high} must stay between 0 and 127"
+                        )),
+                        (Some(low), Some(high)) if low > high => Some(format!(
+                            "Note range low ({low}) must not exceed high ({high})"
+                        )),
+                        (Some(_), Some(_)) => None,
+                        _ => Some("Note range trigger requires `low` and `high`".to_string()),
+                    };
+                    if let Some(message) = message {
                         issues.push(ValidationIssue::new(
-                            format!("{macro_path}.trigger"),
-                            "Note trigger number must be between 0 and 127".into(),
+                            trigger_path.clone(),
+                            message,
                             adjust_severity_for_macro(macro_def.status, Severity::Error),
                         ));
-                    } else if let Some(existing) =
-                        note_map.insert(trigger.number, macro_name.clone())
+                    }
+                }
+                MidiTriggerType::Chord => {
+                    let mut seen = HashSet::new();
+                    let message = if trigger.notes.is_empty() {
+                        Some("Chord trigger requires at least one note in `notes`".to_string())
+                    } else if let Some(note) = trigger.notes.iter().find(|note| **note > 127) {
+                        Some(format!("Chord note {note} must be between 0 and 127"))
+                    } else if let Some(note) =
+                        trigger.notes.iter().find(|note| !seen.insert(**note))
                     {
+                        Some(format!("Chord lists note {note} more than once"))
+                    } else if trigger.window_ms == Some(0) {
+                        Some("Chord window_ms must be greater than zero".to_string())
+                    } else {
+                        None
+                    };
+                    if let Some(message) = message {
                         issues.push(ValidationIssue::new(
-                            format!("{macro_path}.trigger"),
-                            format!(
-                                "Note {} already assigned to macro `{}`",
-                                trigger.number, existing
-                            ),
-                            Severity::Warning,
+                            trigger_path.clone(),
+                            message,
+                            adjust_severity_for_macro(macro_def.status, Severity::Error),
                         ));
                     }
                 }
+                MidiTriggerType::PitchBend => match trigger.threshold {
+                    None => issues.push(ValidationIssue::new(
+                        trigger_path.clone(),
+                        "Pitch bend trigger requires `threshold`".into(),
+                        adjust_severity_for_macro(macro_def.status, Severity::Error),
+                    )),
+                    Some(threshold) if threshold > PITCH_BEND_MAX => {
+                        issues.push(ValidationIssue::new(
+                            trigger_path.clone(),
+                            format!(
+                                "Pitch bend threshold {} must be between 0 and {}",
+                                threshold, PITCH_BEND_MAX
+                            ),
+                            adjust_severity_for_macro(macro_def.status, Severity::Error),
+                        ))
+                    }
+                    Some(threshold) => {
+                        let unreachable =
+                            match trigger.direction.unwrap_or(CrossingDirection::Rising) {
+                                CrossingDirection::Rising => threshold == 0,
+                                CrossingDirection::Falling => threshold == PITCH_BEND_MAX,
+                            };
+                        if unreachable {
+                            issues.push(ValidationIssue::new(
+                                trigger_path.clone(),
+                                format!(
+                                    "Pitch bend threshold {threshold} is at the end of the wheel, so it can never be crossed"
+                                ),
+                                adjust_severity_for_macro(macro_def.status, Severity::Warning),
+                            ))
+                        }
+                    }
+                },
             }
         } else if macro_def.status == MacroStatus::Ready {
             issues.push(ValidationIssue::new(
@@ -174,94 +709,740 @@ pub fn validate_config(config: &Config, source: &str) -> Vec<ValidationIssue> {
             ));
         }
 
-        for (idx, step) in macro_def.steps.iter().enumerate() {
-            match step {
-                MacroStep::Keystroke { keys } => {
-                    if keys.is_empty() || keys.iter().any(|k| k.trim().is_empty()) {
-                        issues.push(ValidationIssue::new(
-                            format!("macros.{macro_name}.steps[{idx}]"),
-                            "Keystroke step must define at least one non-empty key".into(),
-                            adjust_severity_for_macro(macro_def.status, Severity::Error),
-                        ));
-                    }
-                }
-                MacroStep::Pause { ms } => {
-                    if *ms == 0 {
-                        issues.push(ValidationIssue::new(
-                            format!("macros.{macro_name}.steps[{idx}]"),
-                            "Pause duration must be greater than zero".into(),
-                            adjust_severity_for_macro(macro_def.status, Severity::Error),
-                        ));
-                    }
-                }
+        if macro_def.max_duration_ms == Some(0) {
+            issues.push(ValidationIssue::new(
+                format!("{macro_path}.max_duration_ms"),
+                "max_duration_ms must be greater than zero".into(),
+                adjust_severity_for_macro(macro_def.status, Severity::Error),
+            ));
+        }
+
+        if let Some(cooldown) = macro_def.cooldown_ms
+            && cooldown > COOLDOWN_WARN_MS
+        {
+            issues.push(ValidationIssue::new(
+                format!("{macro_path}.cooldown_ms"),
+                format!(
+                    "cooldown_ms of {cooldown} ignores re-triggers for over a minute; \
+                     is it meant to be in milliseconds?"
+                ),
+                Severity::Warning,
+            ));
+        }
+
+        for (index, tag) in macro_def.tags.iter().enumerate() {
+            if tag.trim().is_empty() {
+                issues.push(ValidationIssue::new(
+                    format!("{macro_path}.tags[{index}]"),
+                    "Tag must not be empty".into(),
+                    adjust_severity_for_macro(macro_def.status, Severity::Error),
+                ));
             }
         }
+
+        if let Some(ErrorPolicy::RunMacro(fallback)) = &macro_def.on_error {
+            let message = match config.macros.get(fallback) {
+                None => Some(format!("Fallback macro `{}` is not defined", fallback)),
+                Some(mac) if mac.status != MacroStatus::Ready => Some(format!(
+                    "Fallback macro `{}` is not marked ready and will not be compiled",
+                    fallback
+                )),
+                Some(_) => None,
+            };
+            if let Some(message) = message {
+                issues.push(ValidationIssue::new(
+                    format!("{macro_path}.on_error"),
+                    message,
+                    adjust_severity_for_macro(macro_def.status, Severity::Error),
+                ));
+            }
+        }
+
+        validate_steps(
+            &macro_def.steps,
+            &macro_path,
+            Some(macro_name),
+            macro_def.status,
+            config,
+            &mut issues,
+        );
+        validate_held_keys(&macro_def.steps, &macro_path, &mut issues);
+        target_issues(&mut issues, macro_start, macro_target(macro_name, None));
+    }
+
+    validate_call_cycles(config, &mut issues);
+
+    if let Some(console) = &config.virtual_console {
+        validate_virtual_console(console, config, &mut issues);
+    }
+
+    let referenced_macros = config.referenced_macros();
+    for macro_name in config.macros.keys() {
+        if !referenced_macros.contains(macro_name.as_str()) {
+            issues.push(ValidationIssue::new(
+                format!("macros.{macro_name}"),
+                "Macro has no trigger and is not referenced by any widget, step, hook or error policy".into(),
+                Severity::Info,
+            )
+            .with_target(macro_target(macro_name, None)));
+        }
     }
 
+    lint_macros(config, &mut issues);
+
+    let referenced_scripts = config.referenced_scripts();
     for (script_name, script) in &config.scripts {
-        let empty = match script {
-            Script::Body { body } => body.trim().is_empty(),
-            Script::Inline(body) => body.trim().is_empty(),
-        };
-        if empty {
+        let script_start = issues.len();
+        if !referenced_scripts.contains(script_name.as_str()) {
+            issues.push(ValidationIssue::new(
+                format!("scripts.{script_name}"),
+                "Script is not used by any ready macro or widget and will not be compiled".into(),
+                Severity::Info,
+            ));
+        }
+        if let Some(language) = script.language()
+            && !SCRIPT_LANGUAGES.contains(&language)
+        {
+            issues.push(ValidationIssue::new(
+                format!("scripts.{script_name}.language"),
+                format!(
+                    "Unknown script language `{}` (expected one of: {})",
+                    language,
+                    SCRIPT_LANGUAGES.join(", ")
+                ),
+                Severity::Error,
+            ));
+        }
+        if script.body().trim().is_empty() {
             issues.push(ValidationIssue::new(
                 format!("scripts.{script_name}"),
                 "Script body must not be empty".into(),
                 Severity::Error,
             ));
         }
+        target_issues(
+            &mut issues,
+            script_start,
+            DiagnosticTarget::Script {
+                name: script_name.clone(),
+            },
+        );
     }
 
     attach_locations(source, issues)
 }
 
-fn attach_locations(source: &str, mut issues: Vec<ValidationIssue>) -> Vec<ValidationIssue> {
-    for issue in &mut issues {
-        issue.location = find_location(source, &issue.path);
+fn validate_virtual_console(
+    console: &VirtualConsole,
+    config: &Config,
+    issues: &mut Vec<ValidationIssue>,
+) {
+    if let Some(grid) = &console.grid {
+        validate_grid(grid, "virtual_console.grid", issues);
+    }
+    if let Some(default_page) = &console.default_page {
+        validate_page_ref(
+            &default_page.device,
+            &default_page.page,
+            "virtual_console.default_page",
+            config,
+            issues,
+        );
+    }
+    for (index, page) in console.pages.iter().enumerate() {
+        let path = format!("virtual_console.pages[{index}]");
+        validate_page_ref(&page.device, &page.page, &path, config, issues);
+        if let Some(grid) = &page.grid {
+            validate_grid(grid, &format!("{path}.grid"), issues);
+        }
     }
-    issues
 }
 
-fn find_location(source: &str, path: &str) -> Option<Location> {
-    let needle = path.split('.').last()?;
-    for (idx, line) in source.lines().enumerate() {
-        if line.contains(needle) {
-            let column = line.find(needle).map(|c| c + 1).unwrap_or(1);
-            return Some(Location {
-                line: idx + 1,
-                column,
-            });
+fn validate_grid(grid: &GridSize, path: &str, issues: &mut Vec<ValidationIssue>) {
+    if grid.rows == 0 || grid.columns == 0 {
+        issues.push(ValidationIssue::new(
+            path.to_string(),
+            "Grid needs at least one row and one column".into(),
+            Severity::Error,
+        ));
+    }
+}
+
+/// Reports a console page reference whose device or page does not exist.
+fn validate_page_ref(
+    device_id: &str,
+    page_name: &str,
+    path: &str,
+    config: &Config,
+    issues: &mut Vec<ValidationIssue>,
+) {
+    let message = match config.devices.get(device_id) {
+        None => format!("References undefined device `{device_id}`"),
+        Some(device) if !device.pages.iter().any(|page| page.name == page_name) => {
+            format!("Device `{device_id}` has no page named `{page_name}`")
+        }
+        Some(_) => return,
+    };
+    issues.push(ValidationIssue::new(
+        path.to_string(),
+        message,
+        Severity::Error,
+    ));
+}
+
+/// Style lints: undocumented or untagged macros, long pauses, and macros that do
+/// nothing but pause.
+fn lint_macros(config: &Config, issues: &mut Vec<ValidationIssue>) {
+    for (name, macro_def) in &config.macros {
+        let macro_path = format!("macros.{name}");
+        if macro_def
+            .description
+            .as_deref()
+            .is_none_or(|text| text.trim().is_empty())
+        {
+            issues.push(ValidationIssue::lint(
+                format!("{macro_path}.description"),
+                "Macro has no description",
+                macro_target(name, None),
+            ));
+        }
+        if macro_def.tags.is_empty() {
+            issues.push(ValidationIssue::lint(
+                format!("{macro_path}.tags"),
+                "Macro has no tags",
+                macro_target(name, None),
+            ));
+        }
+        if let [MacroStep::Pause { .. }] = macro_def.steps.as_slice() {
+            issues.push(ValidationIssue::lint(
+                format!("{macro_path}.steps"),
+                "Macro does nothing but pause",
+                macro_target(name, Some(0)),
+            ));
+        }
+        for (index, step) in macro_def.steps.iter().enumerate() {
+            lint_long_pauses(
+                step,
+                &format!("{macro_path}.steps[{index}]"),
+                &macro_target(name, Some(index)),
+                issues,
+            );
         }
     }
-    None
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::parse_config_str;
+fn lint_long_pauses(
+    step: &MacroStep,
+    path: &str,
+    target: &DiagnosticTarget,
+    issues: &mut Vec<ValidationIssue>,
+) {
+    match step {
+        MacroStep::Pause { ms, .. } if *ms > LONG_PAUSE_MS => {
+            issues.push(ValidationIssue::lint(
+                path.to_string(),
+                &format!("Pause of {ms} ms is longer than {LONG_PAUSE_MS} ms"),
+                target.clone(),
+            ));
+        }
+        MacroStep::Atomic { steps } | MacroStep::Repeat { steps, .. } => {
+            for (index, nested) in steps.iter().enumerate() {
+                lint_long_pauses(nested, &format!("{path}.steps[{index}]"), target, issues);
+            }
+        }
+        MacroStep::If { then, else_, .. } => {
+            for (branch, steps) in [("then", then), ("else", else_)] {
+                for (index, nested) in steps.iter().enumerate() {
+                    lint_long_pauses(nested, &format!("{path}.{branch}[{index}]"), target, issues);
+                }
+            }
+        }
+        _ => {}
+    }
+}
 
-    #[test]
-    fn valid_config_with_trigger_passes() {
-        let yaml = r#"version: 1
-devices: {}
-macros:
-  ready:
-    status: ready
-    trigger:
-      type: note
-      number: 60
-    steps:
-      - type: keystroke
-        keys: ["Ctrl", "C"]
-scripts: {}
-"#;
-        let cfg = parse_config_str(yaml).expect("parse");
-        let issues = validate_config(&cfg, yaml);
-        assert!(issues.iter().all(|i| i.severity != Severity::Error));
+fn macro_target(name: &str, step: Option<usize>) -> DiagnosticTarget {
+    DiagnosticTarget::Macro {
+        name: name.to_string(),
+        step,
     }
+}
 
-    #[test]
+/// Warns about `KeyDown` steps with no later `KeyUp` of the same key in the macro,
+/// and `KeyUp` steps releasing a key nothing pressed. Nested groups are walked once,
+/// in step order; called macros are not followed.
+fn validate_held_keys(steps: &[MacroStep], macro_path: &str, issues: &mut Vec<ValidationIssue>) {
+    /// `list` is the path of the step list, e.g. `macros.x.steps`.
+    fn walk(
+        steps: &[MacroStep],
+        list: &str,
+        held: &mut Vec<(String, String)>,
+        issues: &mut Vec<ValidationIssue>,
+    ) {
+        for (idx, step) in steps.iter().enumerate() {
+            let step_path = format!("{list}[{idx}]");
+            match step {
+                MacroStep::KeyDown { key } => held.push((key.clone(), step_path)),
+                MacroStep::KeyUp { key } => {
+                    match held
+                        .iter()
+                        .rposition(|(held_key, _)| held_key.eq_ignore_ascii_case(key))
+                    {
+                        Some(index) => {
+                            held.remove(index);
+                        }
+                        None => issues.push(ValidationIssue::new(
+                            step_path,
+                            format!("KeyUp `{key}` has no earlier KeyDown in this macro"),
+                            Severity::Warning,
+                        )),
+                    }
+                }
+                MacroStep::Atomic { steps } | MacroStep::Repeat { steps, .. } => {
+                    walk(steps, &format!("{step_path}.steps"), held, issues);
+                }
+                MacroStep::If { then, else_, .. } => {
+                    walk(then, &format!("{step_path}.then"), held, issues);
+                    walk(else_, &format!("{step_path}.else"), held, issues);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut held = Vec::new();
+    walk(steps, &format!("{macro_path}.steps"), &mut held, issues);
+    for (key, step_path) in held {
+        issues.push(ValidationIssue::new(
+            step_path,
+            format!("KeyDown `{key}` is never released by a KeyUp in this macro"),
+            Severity::Warning,
+        ));
+    }
+}
+
+/// Validates `steps` (recursing into nested step groups); `parent` is the owning path.
+/// `macro_name` is set for a macro's top-level steps, whose issues target their index.
+fn validate_steps(
+    steps: &[MacroStep],
+    parent: &str,
+    macro_name: Option<&str>,
+    status: MacroStatus,
+    config: &Config,
+    issues: &mut Vec<ValidationIssue>,
+) {
+    for (idx, step) in steps.iter().enumerate() {
+        let step_path = format!("{parent}.steps[{idx}]");
+        let step_start = issues.len();
+        validate_step(step, step_path, status, config, issues);
+        if let Some(name) = macro_name {
+            target_issues(issues, step_start, macro_target(name, Some(idx)));
+        }
+    }
+}
+
+fn validate_step(
+    step: &MacroStep,
+    step_path: String,
+    status: MacroStatus,
+    config: &Config,
+    issues: &mut Vec<ValidationIssue>,
+) {
+    match step {
+        MacroStep::Keystroke { keys } => {
+            if keys.is_empty() || keys.iter().any(|k| k.trim().is_empty()) {
+                issues.push(ValidationIssue::new(
+                    step_path.clone(),
+                    "Keystroke step must define at least one non-empty key".into(),
+                    adjust_severity_for_macro(status, Severity::Error),
+                ));
+            }
+            let unknown: Vec<_> = keys
+                .iter()
+                .filter(|key| !key.trim().is_empty() && !is_known_key(key))
+                .map(|key| format!("`{key}`"))
+                .collect();
+            if !unknown.is_empty() {
+                issues.push(ValidationIssue::new(
+                    step_path.clone(),
+                    format!("Unrecognized key {} will not be sent", unknown.join(", ")),
+                    adjust_severity_for_macro(status, Severity::Error),
+                ));
+            }
+            let limited: Vec<_> = keys
+                .iter()
+                .filter(|key| is_limited_key(key))
+                .map(|key| format!("`{key}`"))
+                .collect();
+            if !limited.is_empty() {
+                issues.push(ValidationIssue::new(
+                    step_path,
+                    format!(
+                        "Key {} can only be sent on Windows and Linux",
+                        limited.join(", ")
+                    ),
+                    Severity::Warning,
+                ));
+            }
+        }
+        MacroStep::KeyDown { key } | MacroStep::KeyUp { key } => {
+            let message = if key.trim().is_empty() {
+                Some("Key step must name a key".to_string())
+            } else if !is_known_key(key) {
+                Some(format!("Unrecognized key `{key}` will not be sent"))
+            } else {
+                None
+            };
+            if let Some(message) = message {
+                issues.push(ValidationIssue::new(
+                    step_path.clone(),
+                    message,
+                    adjust_severity_for_macro(status, Severity::Error),
+                ));
+            }
+            if is_limited_key(key) {
+                issues.push(ValidationIssue::new(
+                    step_path,
+                    format!("Key `{key}` can only be sent on Windows and Linux"),
+                    Severity::Warning,
+                ));
+            }
+        }
+        MacroStep::Text { text } => {
+            if text.is_empty() {
+                issues.push(ValidationIssue::new(
+                    step_path,
+                    "Text step must define non-empty text".into(),
+                    adjust_severity_for_macro(status, Severity::Error),
+                ));
+            }
+        }
+        MacroStep::MouseMove { x, y, relative } => {
+            if !relative && (*x < 0 || *y < 0) {
+                issues.push(ValidationIssue::new(
+                    step_path,
+                    format!("Absolute mouse position ({x}, {y}) is off-screen on most setups"),
+                    Severity::Warning,
+                ));
+            }
+        }
+        // Unknown button names are rejected when the config is parsed.
+        MacroStep::MouseClick { .. } | MacroStep::Reset => {}
+        MacroStep::Pause { ms, .. } => {
+            if *ms == 0 {
+                issues.push(ValidationIssue::new(
+                    step_path,
+                    "Pause duration must be greater than zero".into(),
+                    adjust_severity_for_macro(status, Severity::Error),
+                ));
+            }
+        }
+        MacroStep::PauseMicros { us } => {
+            if *us == 0 {
+                issues.push(ValidationIssue::new(
+                    step_path,
+                    "Pause duration must be greater than zero".into(),
+                    adjust_severity_for_macro(status, Severity::Error),
+                ));
+            }
+        }
+        MacroStep::RandomPause { min_ms, max_ms } => {
+            let message = if *max_ms == 0 {
+                Some("Random pause max_ms must be greater than zero".to_string())
+            } else if min_ms > max_ms {
+                Some(format!(
+                    "Random pause min_ms ({min_ms}) must not exceed max_ms ({max_ms})"
+                ))
+            } else {
+                None
+            };
+            if let Some(message) = message {
+                issues.push(ValidationIssue::new(
+                    step_path,
+                    message,
+                    adjust_severity_for_macro(status, Severity::Error),
+                ));
+            }
+        }
+        MacroStep::Atomic { steps } => {
+            validate_steps(steps, &step_path, None, status, config, issues);
+        }
+        MacroStep::Repeat { count, steps } => {
+            if *count == 0 {
+                issues.push(ValidationIssue::new(
+                    step_path.clone(),
+                    "Repeat count must be greater than zero".into(),
+                    adjust_severity_for_macro(status, Severity::Error),
+                ));
+            }
+            validate_steps(steps, &step_path, None, status, config, issues);
+        }
+        MacroStep::SetVar { name, .. } => {
+            if name.trim().is_empty() {
+                issues.push(ValidationIssue::new(
+                    step_path,
+                    "Variable name must not be empty".into(),
+                    adjust_severity_for_macro(status, Severity::Error),
+                ));
+            }
+        }
+        MacroStep::If {
+            var, then, else_, ..
+        } => {
+            if var.trim().is_empty() {
+                issues.push(ValidationIssue::new(
+                    step_path.clone(),
+                    "Variable name must not be empty".into(),
+                    adjust_severity_for_macro(status, Severity::Error),
+                ));
+            }
+            for (branch, steps) in [("then", then), ("else", else_)] {
+                for (idx, nested) in steps.iter().enumerate() {
+                    let nested_path = format!("{step_path}.{branch}[{idx}]");
+                    validate_step(nested, nested_path, status, config, issues);
+                }
+            }
+        }
+        MacroStep::RunMacro { id } => match config.macros.get(id) {
+            None => issues.push(ValidationIssue::new(
+                step_path,
+                format!("References undefined macro `{}`", id),
+                adjust_severity_for_macro(status, Severity::Error),
+            )),
+            Some(mac) if mac.status != MacroStatus::Ready => issues.push(ValidationIssue::new(
+                step_path,
+                format!(
+                    "References macro `{}` that is not marked ready and will not be compiled",
+                    id
+                ),
+                Severity::Warning,
+            )),
+            Some(_) => {}
+        },
+        MacroStep::RunScript { id } => {
+            if !config.scripts.contains_key(id) {
+                issues.push(ValidationIssue::new(
+                    step_path.clone(),
+                    format!("References undefined script `{}`", id),
+                    adjust_severity_for_macro(status, Severity::Error),
+                ));
+            }
+            if !commands_allowed(config) {
+                issues.push(ValidationIssue::new(
+                    step_path,
+                    SCRIPTS_REFUSED.into(),
+                    Severity::Warning,
+                ));
+            }
+        }
+        MacroStep::RunCommand { program, .. } => {
+            if program.trim().is_empty() {
+                issues.push(ValidationIssue::new(
+                    step_path.clone(),
+                    "RunCommand step must name a program".into(),
+                    adjust_severity_for_macro(status, Severity::Error),
+                ));
+            }
+            if !commands_allowed(config) {
+                issues.push(ValidationIssue::new(
+                    step_path,
+                    "RunCommand steps are refused unless `global.defaults.allow_commands` is set"
+                        .into(),
+                    Severity::Warning,
+                ));
+            }
+        }
+        MacroStep::MidiOut { message } => {
+            if message.is_empty() {
+                issues.push(ValidationIssue::new(
+                    step_path,
+                    "MidiOut step must define at least one byte".into(),
+                    adjust_severity_for_macro(status, Severity::Error),
+                ));
+            }
+        }
+        MacroStep::WaitForMidi {
+            note, timeout_ms, ..
+        } => {
+            if *note > 127 {
+                issues.push(ValidationIssue::new(
+                    step_path.clone(),
+                    "WaitForMidi note must be between 0 and 127".into(),
+                    adjust_severity_for_macro(status, Severity::Error),
+                ));
+            }
+            if *timeout_ms == 0 {
+                issues.push(ValidationIssue::new(
+                    step_path,
+                    "WaitForMidi timeout must be greater than zero".into(),
+                    adjust_severity_for_macro(status, Severity::Error),
+                ));
+            }
+        }
+        MacroStep::VelocityGate { min, max } => {
+            let message = if *min > 127 || *max > 127 {
+                Some(format!(
+                    "Velocity gate bounds must be between 0 and 127 (got {min}..{max})"
+                ))
+            } else if min > max {
+                Some(format!(
+                    "Velocity gate min ({min}) must not exceed max ({max})"
+                ))
+            } else {
+                None
+            };
+            if let Some(message) = message {
+                issues.push(ValidationIssue::new(
+                    step_path,
+                    message,
+                    adjust_severity_for_macro(status, Severity::Error),
+                ));
+            }
+        }
+    }
+}
+
+/// Ids called by `run_macro` steps, including those nested in step groups.
+fn called_macros<'a>(steps: &'a [MacroStep], out: &mut Vec<&'a str>) {
+    for step in steps {
+        match step {
+            MacroStep::RunMacro { id } => out.push(id),
+            MacroStep::Atomic { steps } | MacroStep::Repeat { steps, .. } => {
+                called_macros(steps, out)
+            }
+            MacroStep::If { then, else_, .. } => {
+                called_macros(then, out);
+                called_macros(else_, out);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Reports every `run_macro` cycle once, at the first macro (by name) found on it.
+fn validate_call_cycles(config: &Config, issues: &mut Vec<ValidationIssue>) {
+    let mut names: Vec<&str> = config.macros.keys().map(String::as_str).collect();
+    names.sort_unstable();
+    let mut finished = HashSet::new();
+    for name in names {
+        visit_calls(name, config, &mut Vec::new(), &mut finished, issues);
+    }
+}
+
+fn visit_calls<'a>(
+    id: &'a str,
+    config: &'a Config,
+    stack: &mut Vec<&'a str>,
+    finished: &mut HashSet<&'a str>,
+    issues: &mut Vec<ValidationIssue>,
+) {
+    if finished.contains(id) {
+        return;
+    }
+    if let Some(start) = stack.iter().position(|on_stack| *on_stack == id) {
+        let cycle = &stack[start..];
+        // A cycle only among drafts cannot run yet, so it is not fatal.
+        let all_draft = cycle
+            .iter()
+            .all(|name| config.macros[*name].status == MacroStatus::Draft);
+        let severity = if all_draft {
+            Severity::Warning
+        } else {
+            Severity::Error
+        };
+        issues.push(
+            ValidationIssue::new(
+                format!("macros.{}", cycle[0]),
+                format!("Macro call cycle: {} -> {}", cycle.join(" -> "), id),
+                severity,
+            )
+            .with_target(macro_target(cycle[0], None)),
+        );
+        return;
+    }
+    let Some(macro_def) = config.macros.get(id) else {
+        return;
+    };
+    let mut callees = Vec::new();
+    called_macros(&macro_def.steps, &mut callees);
+    stack.push(id);
+    for callee in callees {
+        visit_calls(callee, config, stack, finished, issues);
+    }
+    stack.pop();
+    finished.insert(id);
+}
+
+/// Resolves a trigger's `number`, reporting a missing number or an undefined alias.
+fn resolve_trigger_number(
+    trigger: &MidiTrigger,
+    config: &Config,
+    status: MacroStatus,
+    path: &str,
+    issues: &mut Vec<ValidationIssue>,
+) -> Option<u8> {
+    let Some(number) = &trigger.number else {
+        issues.push(ValidationIssue::new(
+            path.to_string(),
+            "Trigger requires a `number`".into(),
+            adjust_severity_for_macro(status, Severity::Error),
+        ));
+        return None;
+    };
+    let resolved = trigger.resolve_number(&config.note_aliases);
+    if resolved.is_none() {
+        issues.push(ValidationIssue::new(
+            path.to_string(),
+            format!(
+                "References undefined note alias `{}` (note names look like `C4` or `F#3`)",
+                number
+            ),
+            adjust_severity_for_macro(status, Severity::Error),
+        ));
+    }
+    resolved
+}
+
+fn attach_locations(source: &str, mut issues: Vec<ValidationIssue>) -> Vec<ValidationIssue> {
+    let spans = SourceMap::parse(source);
+    for issue in &mut issues {
+        issue.location = spans.locate(&issue.path);
+    }
+    issues
+}
+
+/// Line and column in `source` of the node a dotted issue path names, e.g. the key
+/// `copy` under `macros` for `macros.copy`.
+pub fn find_location(source: &str, path: &str) -> Option<Location> {
+    SourceMap::parse(source).locate(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_config_str;
+
+    #[test]
+    fn valid_config_with_trigger_passes() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  ready:
+    status: ready
+    trigger:
+      type: note
+      number: 60
+    steps:
+      - type: keystroke
+        keys: ["Ctrl", "C"]
+scripts: {}
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        assert!(issues.iter().all(|i| i.severity != Severity::Error));
+    }
+
+    #[test]
     fn missing_trigger_warns() {
         let yaml = r#"version: 1
 devices: {}
@@ -333,32 +1514,1545 @@ scripts: {}
     }
 
     #[test]
-    fn widget_referencing_draft_macro_warns() {
+    fn empty_text_step_is_error_for_ready_and_warning_for_draft() {
         let yaml = r#"version: 1
-devices:
-  controller:
-    hardware_id: "usb:test"
-    pages:
-      - name: "Main"
-        widgets:
-          - id: pad_1
-            action:
-              type: macro
-              ref: draft_macro
+devices: {}
 macros:
-  draft_macro:
+  ready:
+    status: ready
+    steps:
+      - type: text
+        text: ""
+  draft:
+    status: draft
+    steps:
+      - type: text
+        text: ""
+  ok:
+    status: ready
+    steps:
+      - type: text
+        text: "héllo ✓"
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        let severity_at = |path: &str| issues.iter().find(|i| i.path == path).map(|i| i.severity);
+        assert_eq!(severity_at("macros.ready.steps[0]"), Some(Severity::Error));
+        assert_eq!(
+            severity_at("macros.draft.steps[0]"),
+            Some(Severity::Warning)
+        );
+        assert_eq!(severity_at("macros.ok.steps[0]"), None);
+    }
+
+    #[test]
+    fn mouse_steps_reject_unknown_buttons_and_flag_negative_positions() {
+        let bad_button = r#"version: 1
+devices: {}
+macros:
+  click:
+    status: ready
+    steps:
+      - type: mouse_click
+        button: fourth
+"#;
+        assert!(parse_config_str(bad_button).is_err());
+
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  moves:
+    status: ready
+    steps:
+      - type: mouse_move
+        x: -10
+        y: 20
+      - type: mouse_move
+        x: -10
+        y: 20
+        relative: true
+      - type: mouse_click
+        button: middle
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        let step_issues: Vec<_> = issues
+            .iter()
+            .filter(|i| i.path.starts_with("macros.moves.steps"))
+            .collect();
+        assert_eq!(step_issues.len(), 1, "{issues:?}");
+        assert_eq!(step_issues[0].path, "macros.moves.steps[0]");
+        assert_eq!(step_issues[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn run_macro_steps_check_references_and_cycles() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  a:
+    status: ready
+    steps:
+      - type: run_macro
+        id: b
+  b:
+    status: ready
+    steps:
+      - type: atomic
+        steps:
+          - type: run_macro
+            id: a
+  c:
+    status: ready
+    steps:
+      - type: run_macro
+        id: missing
+      - type: run_macro
+        id: sketch
+  sketch:
     status: draft
     steps:
       - type: keystroke
-        keys: ["A"]
-scripts: {}
+        keys: ["S"]
 "#;
         let cfg = parse_config_str(yaml).expect("parse");
         let issues = validate_config(&cfg, yaml);
-        assert!(issues.iter().any(|i| {
-            i.path.ends_with("widgets.pad_1")
-                && matches!(i.severity, Severity::Warning)
-                && i.message.contains("not marked ready")
-        }));
+        let find = |path: &str| issues.iter().find(|i| i.path == path);
+
+        let cycle = find("macros.a").expect("cycle issue");
+        assert_eq!(cycle.severity, Severity::Error);
+        assert_eq!(cycle.message, "Macro call cycle: a -> b -> a");
+        assert!(find("macros.b").is_none(), "cycle reported twice");
+        assert_eq!(
+            find("macros.c.steps[0]").map(|i| i.severity),
+            Some(Severity::Error)
+        );
+        assert_eq!(
+            find("macros.c.steps[1]").map(|i| i.severity),
+            Some(Severity::Warning)
+        );
+    }
+
+    #[test]
+    fn repeat_count_is_checked_and_nested_steps_validated() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  roll:
+    status: ready
+    steps:
+      - type: repeat
+        count: 0
+        steps:
+          - type: pause
+            ms: 0
+  draft_roll:
+    status: draft
+    steps:
+      - type: repeat
+        count: 0
+        steps: []
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        let severity_at = |path: &str| issues.iter().find(|i| i.path == path).map(|i| i.severity);
+        assert_eq!(severity_at("macros.roll.steps[0]"), Some(Severity::Error));
+        assert_eq!(
+            severity_at("macros.roll.steps[0].steps[0]"),
+            Some(Severity::Error)
+        );
+        assert_eq!(
+            severity_at("macros.draft_roll.steps[0]"),
+            Some(Severity::Warning)
+        );
+    }
+
+    #[test]
+    fn run_script_step_must_reference_defined_script() {
+        let yaml = r#"version: 1
+global:
+  defaults:
+    allow_commands: true
+devices: {}
+macros:
+  deploy:
+    status: ready
+    steps:
+      - type: run_script
+        id: build
+      - type: run_script
+        id: missing
+scripts:
+  build: "make all"
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        let step_issues: Vec<_> = issues
+            .iter()
+            .filter(|i| i.path.starts_with("macros.deploy.steps"))
+            .collect();
+        assert_eq!(step_issues.len(), 1, "{issues:?}");
+        assert_eq!(step_issues[0].path, "macros.deploy.steps[1]");
+        assert_eq!(step_issues[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn midi_out_requires_bytes() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  flash:
+    status: ready
+    steps:
+      - type: midi_out
+        message: []
+      - type: midi_out
+        message: [0x90, 36, 127]
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        let step_issues: Vec<_> = issues
+            .iter()
+            .filter(|i| i.path.starts_with("macros.flash.steps"))
+            .collect();
+        assert_eq!(step_issues.len(), 1, "{issues:?}");
+        assert_eq!(step_issues[0].path, "macros.flash.steps[0]");
+        assert_eq!(step_issues[0].severity, Severity::Error);
+
+        let too_big = yaml.replace("[0x90, 36, 127]", "[256]");
+        assert!(parse_config_str(&too_big).is_err());
+    }
+
+    #[test]
+    fn unknown_tap_behavior_is_error_and_hold_pairs_off_action() {
+        let yaml = r#"version: 1
+devices:
+  pad:
+    hardware_id: "usb:pad"
+    pages:
+      - name: main
+        widgets:
+          - id: a
+            tap_behavior: squeeze
+          - id: b
+            tap_behavior: hold
+            action: { type: macro, ref: on }
+            off_action: { type: macro, ref: off }
+macros:
+  on:
+    status: ready
+    steps: []
+  off:
+    status: ready
+    steps: []
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        let widget_issues: Vec<_> = issues
+            .iter()
+            .filter(|i| i.path.contains(".widgets."))
+            .collect();
+        assert_eq!(widget_issues.len(), 1, "{issues:?}");
+        assert_eq!(
+            widget_issues[0].path,
+            "devices.pad.pages[0].widgets.a.tap_behavior"
+        );
+        assert_eq!(widget_issues[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn double_tap_action_must_resolve() {
+        let yaml = r#"version: 1
+global:
+  defaults:
+    double_tap_window_ms: 0
+devices:
+  pad:
+    hardware_id: "usb:pad"
+    pages:
+      - name: main
+        widgets:
+          - id: a
+            action: { type: macro, ref: on }
+            double_tap_action: { type: script, ref: missing }
+macros:
+  on:
+    status: ready
+    steps: []
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        let double_tap = issues
+            .iter()
+            .find(|i| i.path == "devices.pad.pages[0].widgets.a.double_tap_action")
+            .expect("double tap issue");
+        assert_eq!(double_tap.severity, Severity::Error);
+        assert!(issues.iter().any(|i| {
+            i.path == "global.defaults.double_tap_window_ms" && i.severity == Severity::Error
+        }));
+    }
+
+    #[test]
+    fn virtual_console_references_must_exist() {
+        let yaml = r#"version: 1
+devices:
+  pad:
+    hardware_id: "usb:pad"
+    pages:
+      - name: main
+virtual_console:
+  grid: { rows: 4, columns: 0 }
+  default_page: { device: pad, page: main }
+  pages:
+    - device: pad
+      page: mixer
+    - device: keys
+      page: main
+      grid: { rows: 2, columns: 8 }
+macros: {}
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        let mut console: Vec<_> = issues
+            .iter()
+            .filter(|i| i.path.starts_with("virtual_console"))
+            .map(|i| (i.path.as_str(), i.message.as_str(), i.severity))
+            .collect();
+        console.sort_by_key(|(path, _, _)| *path);
+        assert_eq!(
+            console,
+            vec![
+                (
+                    "virtual_console.grid",
+                    "Grid needs at least one row and one column",
+                    Severity::Error
+                ),
+                (
+                    "virtual_console.pages[0]",
+                    "Device `pad` has no page named `mixer`",
+                    Severity::Error
+                ),
+                (
+                    "virtual_console.pages[1]",
+                    "References undefined device `keys`",
+                    Severity::Error
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn untriggered_unreferenced_definitions_are_noted() {
+        let yaml = r#"version: 1
+devices:
+  pad:
+    hardware_id: "usb:pad"
+    pages:
+      - name: main
+        widgets:
+          - id: a
+            action: { type: macro, ref: from_widget }
+macros:
+  triggered:
+    status: ready
+    trigger: { type: note, number: 60 }
+    steps:
+      - type: run_macro
+        id: called
+  from_widget:
+    status: ready
+    steps: []
+  called:
+    status: ready
+    steps: []
+  orphan:
+    status: ready
+    steps:
+      - type: run_script
+        id: used
+scripts:
+  used: "echo used"
+  stale: "echo stale"
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = without_lints(validate_config(&cfg, yaml));
+        let mut unused: Vec<_> = issues
+            .iter()
+            .filter(|i| i.severity == Severity::Info)
+            .map(|i| i.path.as_str())
+            .collect();
+        unused.sort();
+        assert_eq!(unused, vec!["macros.orphan", "scripts.stale"]);
+    }
+
+    #[test]
+    fn unrecognized_keys_error_in_ready_macros_and_warn_in_drafts() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  copy:
+    status: ready
+    trigger: { type: note, number: 60 }
+    steps:
+      - type: keystroke
+        keys: ["Cntrl", "C"]
+  sketch:
+    status: draft
+    steps:
+      - type: keystroke
+        keys: ["Shift", "ctrll"]
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        let copy = issues
+            .iter()
+            .find(|i| i.path == "macros.copy.steps[0]")
+            .expect("ready macro issue");
+        assert_eq!(copy.severity, Severity::Error);
+        assert!(copy.message.contains("`Cntrl`"), "{}", copy.message);
+        let sketch = issues
+            .iter()
+            .find(|i| i.path == "macros.sketch.steps[0]")
+            .expect("draft macro issue");
+        assert_eq!(sketch.severity, Severity::Warning);
+    }
+
+    #[test]
+    fn platform_limited_keys_warn() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  media:
+    status: ready
+    trigger: { type: note, number: 60 }
+    steps:
+      - type: keystroke
+        keys: ["PlayPause"]
+      - type: keystroke
+        keys: ["VolumeUp"]
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = without_lints(validate_config(&cfg, yaml));
+        assert_eq!(issues.len(), 1, "{issues:?}");
+        assert_eq!(issues[0].path, "macros.media.steps[0]");
+        assert_eq!(issues[0].severity, Severity::Warning);
+        assert!(issues[0].message.contains("`PlayPause`"));
+    }
+
+    #[test]
+    fn empty_virtual_console_is_accepted() {
+        let yaml = "version: 1\ndevices: {}\nmacros: {}\nvirtual_console: {}\n";
+        let cfg = parse_config_str(yaml).expect("parse");
+        assert!(validate_config(&cfg, yaml).is_empty());
+    }
+
+    #[test]
+    fn widget_referencing_draft_macro_warns() {
+        let yaml = r#"version: 1
+devices:
+  controller:
+    hardware_id: "usb:test"
+    pages:
+      - name: "Main"
+        widgets:
+          - id: pad_1
+            action:
+              type: macro
+              ref: draft_macro
+macros:
+  draft_macro:
+    status: draft
+    steps:
+      - type: keystroke
+        keys: ["A"]
+scripts: {}
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        assert!(issues.iter().any(|i| {
+            i.path.ends_with("widgets.pad_1")
+                && matches!(i.severity, Severity::Warning)
+                && i.message.contains("not marked ready")
+        }));
+    }
+
+    #[test]
+    fn issues_carry_structured_targets() {
+        let yaml = r#"version: 2
+devices:
+  deck.v2:
+    pages:
+      - name: "Main"
+        widgets:
+          - id: pad.1
+            action:
+              type: macro
+              ref: missing
+macros:
+  copy:
+    status: ready
+    trigger: { type: note, number: 60 }
+    steps:
+      - type: keystroke
+        keys: ["C"]
+      - type: repeat
+        count: 0
+        steps: []
+scripts:
+  empty: ""
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        let target_of = |path: &str| {
+            issues
+                .iter()
+                .find(|i| i.path == path)
+                .map(|i| i.target.clone())
+                .unwrap_or_else(|| panic!("no issue at {path}"))
+        };
+        assert_eq!(target_of("version"), DiagnosticTarget::Version);
+        assert_eq!(
+            target_of("devices.deck.v2.hardware_id"),
+            DiagnosticTarget::Device {
+                id: "deck.v2".into()
+            }
+        );
+        assert_eq!(
+            target_of("devices.deck.v2.pages[0].widgets.pad.1"),
+            DiagnosticTarget::Widget {
+                device: "deck.v2".into(),
+                page_index: 0,
+                widget_id: "pad.1".into(),
+            }
+        );
+        assert_eq!(
+            target_of("macros.copy.steps[1]"),
+            DiagnosticTarget::Macro {
+                name: "copy".into(),
+                step: Some(1),
+            }
+        );
+        assert_eq!(
+            target_of("scripts.empty"),
+            DiagnosticTarget::Script {
+                name: "empty".into()
+            }
+        );
+    }
+
+    #[test]
+    fn velocity_gate_bounds_are_checked() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  hits:
+    status: ready
+    trigger: { type: note, number: 60 }
+    steps:
+      - type: velocity_gate
+        min: 100
+        max: 90
+      - type: velocity_gate
+        min: 0
+        max: 200
+      - type: velocity_gate
+        min: 64
+        max: 127
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = without_lints(validate_config(&cfg, yaml));
+        let paths: Vec<_> = issues.iter().map(|i| i.path.as_str()).collect();
+        assert_eq!(paths, vec!["macros.hits.steps[0]", "macros.hits.steps[1]"]);
+        assert!(issues[0].message.contains("must not exceed"));
+        assert!(issues.iter().all(|i| i.severity == Severity::Error));
+    }
+
+    #[test]
+    fn unbalanced_key_down_and_up_warn() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  held:
+    status: ready
+    trigger: { type: note, number: 60 }
+    steps:
+      - type: key_down
+        key: Shift
+      - type: repeat
+        count: 2
+        steps:
+          - type: key_down
+            key: W
+          - type: key_up
+            key: w
+      - type: key_up
+        key: Ctrl
+      - type: key_down
+        key: Alt
+      - type: key_up
+        key: shift
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        let held: Vec<_> = issues
+            .iter()
+            .filter(|i| i.path.contains(".steps["))
+            .map(|i| (i.path.as_str(), i.message.as_str(), i.severity))
+            .collect();
+        assert_eq!(
+            held,
+            vec![
+                (
+                    "macros.held.steps[2]",
+                    "KeyUp `Ctrl` has no earlier KeyDown in this macro",
+                    Severity::Warning
+                ),
+                (
+                    "macros.held.steps[3]",
+                    "KeyDown `Alt` is never released by a KeyUp in this macro",
+                    Severity::Warning
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn run_command_needs_allow_commands() {
+        let steps = r#"devices: {}
+macros:
+  launch:
+    status: ready
+    trigger: { type: note, number: 60 }
+    steps:
+      - type: run_command
+        program: "obs"
+        args: ["--startrecording"]
+      - type: run_command
+        program: ""
+"#;
+        let messages = |yaml: &str| -> Vec<(String, Severity)> {
+            let cfg = parse_config_str(yaml).expect("parse");
+            validate_config(&cfg, yaml)
+                .into_iter()
+                .filter(|i| i.path.contains(".steps["))
+                .map(|i| (i.path, i.severity))
+                .collect()
+        };
+
+        let denied = format!("version: 1\n{steps}");
+        assert_eq!(
+            messages(&denied),
+            vec![
+                ("macros.launch.steps[0]".to_string(), Severity::Warning),
+                ("macros.launch.steps[1]".to_string(), Severity::Error),
+                ("macros.launch.steps[1]".to_string(), Severity::Warning),
+            ]
+        );
+        let allowed =
+            format!("version: 1\nglobal:\n  defaults:\n    allow_commands: true\n{steps}");
+        assert_eq!(
+            messages(&allowed),
+            vec![("macros.launch.steps[1]".to_string(), Severity::Error)]
+        );
+    }
+
+    #[test]
+    fn scripts_need_allow_commands() {
+        let body = r#"devices:
+  pad:
+    pages:
+      - name: main
+        widgets:
+          - id: build_button
+            action: { type: script, ref: build }
+macros:
+  deploy:
+    status: ready
+    trigger: { type: note, number: 60 }
+    steps:
+      - type: run_script
+        id: build
+scripts:
+  build: "make all"
+"#;
+        let refusals = |yaml: &str| -> Vec<String> {
+            let cfg = parse_config_str(yaml).expect("parse");
+            validate_config(&cfg, yaml)
+                .into_iter()
+                .filter(|i| i.message == SCRIPTS_REFUSED)
+                .map(|i| i.path)
+                .collect()
+        };
+
+        assert_eq!(
+            refusals(&format!("version: 1\n{body}")),
+            [
+                "devices.pad.pages[0].widgets.build_button",
+                "macros.deploy.steps[0]"
+            ]
+        );
+        let allowed = format!("version: 1\nglobal:\n  defaults:\n    allow_commands: true\n{body}");
+        assert!(refusals(&allowed).is_empty());
+    }
+
+    #[test]
+    fn note_range_bounds_are_checked() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  reversed:
+    status: ready
+    trigger: { type: note_range, low: 50, high: 40 }
+  too_high:
+    status: ready
+    trigger: { type: note_range, low: 100, high: 130 }
+  open:
+    status: ready
+    trigger: { type: note_range, low: 36 }
+  row:
+    status: ready
+    trigger: { type: note_range, low: 36, high: 43 }
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        let mut errors: Vec<_> = issues
+            .iter()
+            .filter(|i| i.severity == Severity::Error)
+            .map(|i| (i.path.as_str(), i.message.as_str()))
+            .collect();
+        errors.sort();
+        assert_eq!(
+            errors,
+            vec![
+                (
+                    "macros.open.trigger",
+                    "Note range trigger requires `low` and `high`"
+                ),
+                (
+                    "macros.reversed.trigger",
+                    "Note range low (50) must not exceed high (40)"
+                ),
+                (
+                    "macros.too_high.trigger",
+                    "Note range 100-130 must stay between 0 and 127"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn chord_notes_must_be_listed_once() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  empty:
+    status: ready
+    trigger: { type: chord, notes: [] }
+  doubled:
+    status: ready
+    trigger: { type: chord, notes: [36, 40, 36] }
+  pair:
+    status: ready
+    trigger: { type: chord, notes: [36, 40], window_ms: 80 }
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        let mut errors: Vec<_> = issues
+            .iter()
+            .filter(|i| i.severity == Severity::Error)
+            .map(|i| (i.path.as_str(), i.message.as_str()))
+            .collect();
+        errors.sort();
+        assert_eq!(
+            errors,
+            vec![
+                (
+                    "macros.doubled.trigger",
+                    "Chord lists note 36 more than once"
+                ),
+                (
+                    "macros.empty.trigger",
+                    "Chord trigger requires at least one note in `notes`"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn random_pause_bounds_are_checked() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  ready:
+    status: ready
+    trigger: { type: note, number: 60 }
+    steps:
+      - type: random_pause
+        min_ms: 80
+        max_ms: 20
+      - type: random_pause
+        min_ms: 0
+        max_ms: 0
+      - type: random_pause
+        min_ms: 20
+        max_ms: 80
+  sketch:
+    status: draft
+    steps:
+      - type: random_pause
+        min_ms: 0
+        max_ms: 0
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        let mut steps: Vec<_> = issues
+            .iter()
+            .filter(|i| i.path.contains(".steps["))
+            .map(|i| (i.path.as_str(), i.severity))
+            .collect();
+        steps.sort_by_key(|(path, _)| *path);
+        assert_eq!(
+            steps,
+            vec![
+                ("macros.ready.steps[0]", Severity::Error),
+                ("macros.ready.steps[1]", Severity::Error),
+                ("macros.sketch.steps[0]", Severity::Warning),
+            ]
+        );
+    }
+
+    #[test]
+    fn page_switch_must_name_an_existing_page() {
+        let yaml = r#"version: 1
+devices:
+  pad:
+    hardware_id: "usb:pad"
+    pages:
+      - name: "Main"
+        widgets:
+          - id: ok
+            action: { type: page_switch, device: pad, page: Main }
+          - id: bad
+            action: { type: page_switch, device: pad, page: Mix }
+macros: {}
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        assert_eq!(issues.len(), 1, "{issues:?}");
+        assert_eq!(issues[0].path, "devices.pad.pages[0].widgets.bad");
+        assert_eq!(issues[0].message, "Device `pad` has no page named `Mix`");
+    }
+
+    #[test]
+    fn trigger_outside_device_note_range_is_info() {
+        let yaml = r#"version: 1
+devices:
+  pads:
+    hardware_id: "usb:pads"
+    note_range: { low: 36, high: 99 }
+macros:
+  high:
+    status: ready
+    trigger:
+      type: note
+      number: 120
+    steps:
+      - type: keystroke
+        keys: ["A"]
+  in_range:
+    status: ready
+    trigger:
+      type: note
+      number: 40
+    steps:
+      - type: keystroke
+        keys: ["B"]
+scripts: {}
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        let range_issues: Vec<_> = issues
+            .iter()
+            .filter(|i| i.message.contains("outside the declared note range"))
+            .collect();
+        assert_eq!(range_issues.len(), 1);
+        assert_eq!(range_issues[0].path, "macros.high.trigger");
+        assert_eq!(range_issues[0].severity, Severity::Info);
+    }
+
+    #[test]
+    fn undefined_note_alias_errors() {
+        let yaml = r#"version: 1
+note_aliases:
+  kick: 36
+devices: {}
+macros:
+  drum:
+    status: ready
+    trigger:
+      type: note
+      number: snare
+    steps:
+      - type: keystroke
+        keys: ["D"]
+scripts: {}
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        assert!(issues.iter().any(|i| i.path == "macros.drum.trigger"
+            && i.severity == Severity::Error
+            && i.message.contains("undefined note alias `snare`")));
+    }
+
+    #[test]
+    fn note_name_triggers_resolve_and_bad_names_error() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  low:
+    status: ready
+    trigger: { type: note, number: "C4" }
+    steps: []
+  high:
+    status: ready
+    trigger: { type: note, number: "A9" }
+    steps: []
+scripts: {}
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        let trigger_errors: Vec<_> = issues
+            .iter()
+            .filter(|i| i.path.ends_with(".trigger") && i.severity == Severity::Error)
+            .collect();
+        assert_eq!(trigger_errors.len(), 1, "{issues:?}");
+        assert_eq!(trigger_errors[0].path, "macros.high.trigger");
+    }
+
+    #[test]
+    fn transport_trigger_requires_kind_and_warns_on_duplicates() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  on_start:
+    status: ready
+    trigger: { type: transport, transport: start }
+    steps:
+      - type: keystroke
+        keys: ["F5"]
+  also_start:
+    status: ready
+    trigger: { type: transport, transport: start }
+    steps:
+      - type: keystroke
+        keys: ["F6"]
+  missing_kind:
+    status: ready
+    trigger: { type: transport }
+    steps:
+      - type: keystroke
+        keys: ["F7"]
+scripts: {}
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        assert!(issues.iter().any(|i| i.severity == Severity::Warning
+            && i.message.contains("Transport Start already assigned")));
+        assert!(
+            issues
+                .iter()
+                .any(|i| i.path == "macros.missing_kind.trigger" && i.severity == Severity::Error)
+        );
+    }
+
+    #[test]
+    fn atomic_inner_steps_are_validated() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  chord:
+    status: ready
+    trigger:
+      type: note
+      number: 60
+    steps:
+      - type: atomic
+        steps:
+          - type: keystroke
+            keys: ["Ctrl", "K"]
+          - type: pause
+            ms: 0
+scripts: {}
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        assert!(
+            issues.iter().any(
+                |i| i.path == "macros.chord.steps[0].steps[1]" && i.severity == Severity::Error
+            )
+        );
+    }
+
+    #[test]
+    fn profile_members_must_exist() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  save:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["Ctrl", "S"]
+profiles:
+  daw: [save, missing]
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        assert!(
+            issues
+                .iter()
+                .any(|i| i.path == "profiles.daw[1]" && i.severity == Severity::Error)
+        );
+        assert!(!issues.iter().any(|i| i.path == "profiles.daw[0]"));
+    }
+
+    #[test]
+    fn pitch_bend_threshold_must_fit_fourteen_bits() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  bend_up:
+    status: ready
+    trigger: { type: pitch_bend, threshold: 16384 }
+    steps:
+      - type: keystroke
+        keys: ["Up"]
+  bend_down:
+    status: ready
+    trigger: { type: pitch_bend, threshold: 2000, direction: falling }
+    steps:
+      - type: keystroke
+        keys: ["Down"]
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        assert!(
+            issues
+                .iter()
+                .any(|i| i.path == "macros.bend_up.trigger" && i.severity == Severity::Error)
+        );
+        assert!(!issues.iter().any(|i| i.path == "macros.bend_down.trigger"));
+    }
+
+    #[test]
+    fn pitch_bend_threshold_at_the_end_stop_warns() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  rise_from_zero:
+    status: ready
+    trigger: { type: pitch_bend, threshold: 0 }
+    steps:
+      - type: keystroke
+        keys: ["Up"]
+  fall_from_top:
+    status: ready
+    trigger: { type: pitch_bend, threshold: 16383, direction: falling }
+    steps:
+      - type: keystroke
+        keys: ["Down"]
+  fall_to_zero:
+    status: ready
+    trigger: { type: pitch_bend, threshold: 0, direction: falling }
+    steps:
+      - type: keystroke
+        keys: ["Left"]
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        for path in [
+            "macros.rise_from_zero.trigger",
+            "macros.fall_from_top.trigger",
+        ] {
+            assert!(
+                issues
+                    .iter()
+                    .any(|i| i.path == path && i.severity == Severity::Warning),
+                "{path}"
+            );
+        }
+        assert!(
+            !issues
+                .iter()
+                .any(|i| i.path == "macros.fall_to_zero.trigger")
+        );
+    }
+
+    #[test]
+    fn binding_reserved_note_warns() {
+        let yaml = r#"version: 1
+devices:
+  launchpad:
+    hardware_id: "lp-1"
+    reserved_notes: [104, 105]
+    pages:
+      - name: main
+        widgets:
+          - id: top_left
+            action: { type: macro, ref: session }
+macros:
+  session:
+    status: ready
+    trigger: { type: note, number: 104 }
+    steps:
+      - type: keystroke
+        keys: ["F1"]
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        assert!(issues.iter().any(|i| i.path == "macros.session.trigger"
+            && i.severity == Severity::Warning
+            && i.message.contains("reserved")));
+        assert!(issues.iter().any(|i| i.path.ends_with("widgets.top_left")
+            && i.severity == Severity::Warning
+            && i.message.contains("reserves")));
+    }
+
+    #[test]
+    fn wait_for_midi_requires_positive_timeout() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  call:
+    status: ready
+    steps:
+      - type: wait_for_midi
+        note: 64
+        timeout_ms: 0
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        assert!(issues.iter().any(|i| i.path == "macros.call.steps[0]"
+            && i.severity == Severity::Error
+            && i.message.contains("timeout")));
+    }
+
+    #[test]
+    fn duplicate_page_names_are_errors() {
+        let yaml = r#"version: 1
+devices:
+  pad:
+    hardware_id: "pad-1"
+    pages:
+      - name: main
+        widgets: []
+      - name: main
+        widgets: []
+macros: {}
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        assert!(
+            issues
+                .iter()
+                .any(|i| i.path == "devices.pad.pages[1]" && i.severity == Severity::Error)
+        );
+        assert!(!issues.iter().any(|i| i.path == "devices.pad.pages[0]"));
+    }
+
+    #[test]
+    fn widget_ids_repeated_across_pages_warn() {
+        let yaml = r#"version: 1
+devices:
+  pad:
+    hardware_id: "pad-1"
+    pages:
+      - name: main
+        widgets:
+          - id: pad_1
+          - id: pad_2
+      - name: mix
+        widgets:
+          - id: pad_1
+macros: {}
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        let repeated: Vec<_> = issues
+            .iter()
+            .filter(|i| i.path == "devices.pad")
+            .map(|i| (i.message.as_str(), i.severity))
+            .collect();
+        assert_eq!(
+            repeated,
+            vec![(
+                "Widget id `pad_1` appears on pages `main`, `mix`; console lookups by widget id are ambiguous",
+                Severity::Warning
+            )]
+        );
+        assert!(!issues.iter().any(|i| i.message.contains("within page")));
+    }
+
+    #[test]
+    fn style_lints_are_info_and_filterable() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  tidy:
+    status: ready
+    description: "Copies the selection"
+    tags: [edit]
+    steps:
+      - type: keystroke
+        keys: ["Ctrl", "C"]
+  sleepy:
+    status: ready
+    description: "Waits for the render"
+    tags: [render]
+    steps:
+      - type: pause
+        ms: 8000
+  bare:
+    status: ready
+    description: "  "
+    steps:
+      - type: repeat
+        count: 2
+        steps:
+          - type: pause
+            ms: 6000
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        let mut lints: Vec<_> = issues
+            .iter()
+            .filter(|i| i.lint)
+            .map(|i| (i.path.as_str(), i.message.as_str()))
+            .collect();
+        lints.sort();
+        assert_eq!(
+            lints,
+            vec![
+                ("macros.bare.description", "Macro has no description"),
+                (
+                    "macros.bare.steps[0].steps[0]",
+                    "Pause of 6000 ms is longer than 5000 ms"
+                ),
+                ("macros.bare.tags", "Macro has no tags"),
+                ("macros.sleepy.steps", "Macro does nothing but pause"),
+                (
+                    "macros.sleepy.steps[0]",
+                    "Pause of 8000 ms is longer than 5000 ms"
+                ),
+            ]
+        );
+        assert!(
+            issues
+                .iter()
+                .filter(|i| i.lint)
+                .all(|i| i.severity == Severity::Info)
+        );
+        assert!(without_lints(issues).iter().all(|i| !i.lint));
+    }
+
+    #[test]
+    fn long_cooldowns_warn() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  debounced:
+    status: ready
+    cooldown_ms: 250
+    steps: []
+  sleepy:
+    status: ready
+    cooldown_ms: 90000
+    steps: []
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        let cooldowns: Vec<_> = issues
+            .iter()
+            .filter(|i| i.path.ends_with("cooldown_ms"))
+            .map(|i| (i.path.as_str(), i.severity))
+            .collect();
+        assert_eq!(
+            cooldowns,
+            vec![("macros.sleepy.cooldown_ms", Severity::Warning)]
+        );
+    }
+
+    #[test]
+    fn on_error_fallback_must_exist() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  risky:
+    status: ready
+    on_error: { run_macro: rescue }
+    steps:
+      - type: keystroke
+        keys: ["F5"]
+  careful:
+    status: ready
+    on_error: continue
+    steps:
+      - type: keystroke
+        keys: ["F6"]
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        assert!(
+            issues
+                .iter()
+                .any(|i| i.path == "macros.risky.on_error" && i.severity == Severity::Error)
+        );
+        assert!(!issues.iter().any(|i| i.path == "macros.careful.on_error"));
+    }
+
+    #[test]
+    fn control_change_duplicates_are_tracked_apart_from_notes() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  filter:
+    status: ready
+    trigger: { type: control_change, number: 74 }
+    steps:
+      - type: keystroke
+        keys: ["F"]
+  note_74:
+    status: ready
+    trigger: { type: note, number: 74 }
+    steps:
+      - type: keystroke
+        keys: ["N"]
+  resonance:
+    status: ready
+    trigger: { type: control_change, number: 200 }
+    steps:
+      - type: keystroke
+        keys: ["R"]
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        assert!(
+            !issues
+                .iter()
+                .any(|i| i.message.contains("already assigned"))
+        );
+        assert!(
+            issues
+                .iter()
+                .any(|i| i.path == "macros.resonance.trigger" && i.severity == Severity::Error)
+        );
+    }
+
+    #[test]
+    fn blank_tags_are_errors() {
+        let yaml = r#"version: 1
+macros:
+  stop:
+    status: ready
+    tags: [live, "  "]
+    steps: []
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        let blank: Vec<_> = issues
+            .iter()
+            .filter(|i| i.message == "Tag must not be empty")
+            .collect();
+        assert_eq!(blank.len(), 1);
+        assert_eq!(blank[0].path, "macros.stop.tags[1]");
+        assert_eq!(blank[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn steps_inside_if_branches_are_validated() {
+        let yaml = r#"version: 1
+macros:
+  branch:
+    status: ready
+    steps:
+      - type: if
+        var: mode
+        equals: 1
+        then:
+          - type: pause
+            ms: 0
+        else:
+          - type: set_var
+            name: ""
+            value: 2
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        let paths: Vec<_> = issues
+            .iter()
+            .filter(|i| i.severity == Severity::Error)
+            .map(|i| (i.path.as_str(), i.message.as_str()))
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                (
+                    "macros.branch.steps[0].then[0]",
+                    "Pause duration must be greater than zero"
+                ),
+                (
+                    "macros.branch.steps[0].else[0]",
+                    "Variable name must not be empty"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn program_change_triggers_are_range_checked_and_warn_on_duplicates() {
+        let yaml = r#"version: 1
+macros:
+  bank_a:
+    status: ready
+    trigger: { type: program_change, number: 5 }
+    steps: []
+  bank_b:
+    status: ready
+    trigger: { type: program_change, number: 5 }
+    steps: []
+  note_5:
+    status: ready
+    trigger: { type: note, number: 5 }
+    steps: []
+  bank_z:
+    status: ready
+    trigger: { type: program_change, number: 128 }
+    steps: []
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        let duplicates: Vec<_> = issues
+            .iter()
+            .filter(|i| i.message.contains("already assigned"))
+            .collect();
+        assert_eq!(duplicates.len(), 1);
+        assert!(
+            duplicates[0]
+                .message
+                .starts_with("Program 5 already assigned")
+        );
+        assert_eq!(duplicates[0].severity, Severity::Warning);
+        assert!(
+            issues
+                .iter()
+                .any(|i| i.path == "macros.bank_z.trigger" && i.severity == Severity::Error)
+        );
+    }
+
+    #[test]
+    fn trigger_channel_is_range_checked_and_separates_duplicates() {
+        let yaml = r#"version: 1
+devices: {}
+macros:
+  drums:
+    status: ready
+    trigger: { type: note, number: 36, channel: 9 }
+    steps:
+      - type: keystroke
+        keys: ["D"]
+  keys:
+    status: ready
+    trigger: { type: note, number: 36, channel: 0 }
+    steps:
+      - type: keystroke
+        keys: ["K"]
+  bad:
+    status: ready
+    trigger: { type: note, number: 37, channel: 16 }
+    steps:
+      - type: keystroke
+        keys: ["B"]
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        assert!(
+            !issues
+                .iter()
+                .any(|i| i.message.contains("already assigned"))
+        );
+        assert!(issues.iter().any(|i| i.path == "macros.bad.trigger"
+            && i.severity == Severity::Error
+            && i.message.contains("channel")));
+    }
+
+    #[test]
+    fn format_diagnostic_with_and_without_location() {
+        let mut issue = ValidationIssue::new(
+            "macros.a.trigger".into(),
+            "Trigger missing".into(),
+            Severity::Warning,
+        );
+        assert_eq!(
+            format_diagnostic(&issue),
+            "[warning] macros.a.trigger: Trigger missing"
+        );
+        issue.location = Some(Location { line: 4, column: 5 });
+        assert_eq!(
+            format_diagnostic(&issue),
+            "[warning] macros.a.trigger: Trigger missing (line 4, column 5)"
+        );
+    }
+
+    #[test]
+    fn locations_follow_the_path_past_repeated_key_names() {
+        let yaml = r#"version: 1
+macros:
+  paste:
+    status: ready
+    description: "copy first"
+    steps:
+      - type: run_macro
+        id: copy
+  copy:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["Ctrl", "C"]
+      - type: text
+        text: ""
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        let empty_text = issues
+            .iter()
+            .find(|issue| issue.path == "macros.copy.steps[1]")
+            .expect("empty text issue");
+        let loc = empty_text.location.expect("location");
+        assert_eq!((loc.line, loc.column), (14, 9));
+
+        let loc = find_location(yaml, "macros.copy").expect("location");
+        assert_eq!((loc.line, loc.column), (9, 3));
+        let loc = find_location(yaml, "macros.copy.trigger").expect("location");
+        assert_eq!((loc.line, loc.column), (9, 3));
+        assert!(find_location(yaml, "scripts.copy").is_none());
+    }
+
+    #[test]
+    fn unknown_script_language_errors() {
+        let yaml = r#"version: 1
+devices: {}
+macros: {}
+scripts:
+  fade:
+    language: cobol
+    body: "DISPLAY 'HI'"
+"#;
+        let cfg = parse_config_str(yaml).expect("parse");
+        let issues = validate_config(&cfg, yaml);
+        assert!(
+            issues
+                .iter()
+                .any(|i| i.path == "scripts.fade.language" && i.severity == Severity::Error)
+        );
     }
 }
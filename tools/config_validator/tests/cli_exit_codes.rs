@@ -0,0 +1,59 @@
+use config_validator::{DiagnosticTarget, Severity, ValidationIssue};
+use std::process::Command;
+
+const WARNING_ONLY: &str = r#"version: 1
+macros:
+  untriggered:
+    status: ready
+    steps:
+      - type: keystroke
+        keys: ["A"]
+"#;
+
+fn validate(args: &[&str]) -> Option<i32> {
+    let dir = tempfile::tempdir().expect("temp dir");
+    let path = dir.path().join("config.yaml");
+    std::fs::write(&path, WARNING_ONLY).expect("write config");
+    Command::new(env!("CARGO_BIN_EXE_config_validator"))
+        .args(args)
+        .arg(&path)
+        .output()
+        .expect("run validator")
+        .status
+        .code()
+}
+
+#[test]
+fn strict_fails_on_warnings() {
+    assert_eq!(validate(&[]), Some(0));
+    assert_eq!(validate(&["--strict"]), Some(2));
+    assert_eq!(validate(&["--max-severity", "info"]), Some(2));
+    assert_eq!(validate(&["--quiet", "--max-severity", "warning"]), Some(0));
+}
+
+#[test]
+fn json_format_prints_issues_to_stdout() {
+    let dir = tempfile::tempdir().expect("temp dir");
+    let path = dir.path().join("config.yaml");
+    std::fs::write(&path, WARNING_ONLY).expect("write config");
+    let output = Command::new(env!("CARGO_BIN_EXE_config_validator"))
+        .args(["--format", "json", "--quiet"])
+        .arg(&path)
+        .output()
+        .expect("run validator");
+    assert_eq!(output.status.code(), Some(0));
+
+    let issues: Vec<ValidationIssue> =
+        serde_json::from_slice(&output.stdout).expect("JSON diagnostics");
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].path, "macros.untriggered.trigger");
+    assert_eq!(issues[0].severity, Severity::Warning);
+    assert_eq!(
+        issues[0].target,
+        DiagnosticTarget::Macro {
+            name: "untriggered".into(),
+            step: None,
+        }
+    );
+    assert_eq!(issues[0].location.map(|loc| loc.line), Some(3));
+}